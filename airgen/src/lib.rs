@@ -6,7 +6,9 @@ use powdr_ast::{
     asm_analysis::{self, combine_flags, AnalysisASMFile, LinkDefinition, MachineDegree},
     object::{Link, LinkFrom, LinkTo, Location, Machine, MachineInstanceGraph, Object, Operation},
     parsed::{
-        asm::{parse_absolute_path, AbsoluteSymbolPath, CallableRef, MachineParams},
+        asm::{
+            parse_absolute_path, AbsoluteSymbolPath, CallableRef, LinkConnection, MachineParams,
+        },
         Expression, PilStatement,
     },
 };
@@ -372,6 +374,7 @@ impl<'a> ASMPILConverter<'a> {
                     params,
                 },
             is_permutation,
+            connection,
         }: LinkDefinition,
     ) -> Link {
         let from = LinkFrom {
@@ -436,6 +439,7 @@ impl<'a> ASMPILConverter<'a> {
                 .unwrap()
                 .clone(),
             is_permutation,
+            connection,
         }
     }
 
@@ -444,6 +448,7 @@ impl<'a> ASMPILConverter<'a> {
     /// - they originate from the same machine instance
     /// - they target the same instance.operation
     /// - they are of the same kind (permutation/lookup)
+    /// - they have the same connection override, if any
     /// - their flags are mutually exclusive
     ///
     /// Right now we only consider links from different instructions,
@@ -457,6 +462,7 @@ impl<'a> ASMPILConverter<'a> {
             to: Location,
             operation: Operation,
             is_permutation: bool,
+            connection: Option<LinkConnection>,
         }
 
         // process links, partitioning them into (mergeable, non-mergeable)
@@ -467,6 +473,7 @@ impl<'a> ASMPILConverter<'a> {
                 to: link.to.machine.location.clone(),
                 operation: link.to.operation.clone(),
                 is_permutation: link.is_permutation,
+                connection: link.connection,
             };
 
             if link.from.instr_flag.is_none() {
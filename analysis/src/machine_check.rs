@@ -70,6 +70,7 @@ impl TypeChecker {
                         flag,
                         link,
                         is_permutation,
+                        connection,
                     },
                 ) => links.push(LinkDefinition {
                     source,
@@ -77,6 +78,7 @@ impl TypeChecker {
                     link_flag: flag,
                     to: link,
                     is_permutation,
+                    connection,
                 }),
                 MachineStatement::Pil(_source, statement) => {
                     pil.push(statement);
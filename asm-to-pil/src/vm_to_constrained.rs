@@ -276,6 +276,7 @@ impl<T: FieldElement> VMConverter<T> {
                 },
             },
             is_permutation: false,
+            connection: None,
         });
 
         if !self.pil.is_empty() {
@@ -624,6 +625,7 @@ impl<T: FieldElement> VMConverter<T> {
             link_flag: link_decl.flag,
             to: callable,
             is_permutation: link_decl.is_permutation,
+            connection: link_decl.connection,
         }
     }
 
@@ -268,7 +268,12 @@ impl<T> Analyzed<T> {
             }
         });
 
-        // Now re-assign the IDs to be contiguous and in source order again.
+        self.reassign_ids();
+    }
+
+    /// Re-assigns the IDs of all polynomials to be contiguous and in source order,
+    /// and updates all references to them.
+    pub fn reassign_ids(&mut self) {
         let mut replacements: BTreeMap<PolyID, PolyID> = Default::default();
 
         let mut handle_symbol = |new_id: u64, symbol: &Symbol| -> u64 {
@@ -314,6 +319,14 @@ impl<T> Analyzed<T> {
             }
         };
         self.post_visit_expressions_in_identities_mut(algebraic_visitor);
+        // The accumulator columns are not children of the bus interactions.
+        for identity in &mut self.identities {
+            if let Identity::PhantomBusInteraction(interaction) = identity {
+                for column in &mut interaction.accumulator_columns {
+                    column.poly_id = replacements[&column.poly_id];
+                }
+            }
+        }
     }
 
     /// Removes the given set of trait impls, identified by their index
@@ -1092,6 +1105,9 @@ pub struct PhantomBusInteractionIdentity<T> {
     pub source: SourceRef,
     pub multiplicity: AlgebraicExpression<T>,
     pub tuple: ExpressionList<T>,
+    /// The columns of the accumulator of this interaction, one per extension
+    /// field component. Their values in the last row are the sum over all rows.
+    pub accumulator_columns: Vec<AlgebraicReference>,
 }
 
 impl<T> Children<AlgebraicExpression<T>> for PhantomBusInteractionIdentity<T> {
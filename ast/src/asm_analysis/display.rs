@@ -119,7 +119,8 @@ impl Display for LinkDefinition {
         let flag = combine_flags(self.instr_flag.clone(), self.link_flag.clone());
         write!(
             f,
-            "link {}{} {};",
+            "link {}{}{} {};",
+            self.connection.map(|c| format!("{c} ")).unwrap_or_default(),
             if flag == 1.into() {
                 "".to_string()
             } else {
@@ -16,7 +16,7 @@ use powdr_parser_util::SourceRef;
 use crate::parsed::{
     asm::{
         AbsoluteSymbolPath, AssignmentRegister, CallableRef, FunctionParams, Instruction,
-        MachineParams, OperationId, OperationParams,
+        LinkConnection, MachineParams, OperationId, OperationParams,
     },
     visitor::{ExpressionVisitable, VisitOrder},
     NamespacedPolynomialReference, PilStatement,
@@ -77,6 +77,8 @@ pub struct LinkDefinition {
     pub to: CallableRef,
     /// true if this is a permutation link
     pub is_permutation: bool,
+    /// how the link is connected, if it overrides the choice of the linker
+    pub connection: Option<LinkConnection>,
 }
 
 /// Helper function to multiply optional instruction flag with link flag
@@ -5,7 +5,7 @@ use powdr_number::BigUint;
 use crate::{
     asm_analysis::MachineDegree,
     parsed::{
-        asm::{AbsoluteSymbolPath, CallableParams, OperationParams},
+        asm::{AbsoluteSymbolPath, CallableParams, LinkConnection, OperationParams},
         Expression, PilStatement,
     },
 };
@@ -73,6 +73,8 @@ pub struct Link {
     pub to: LinkTo,
     /// true if this is a permutation link
     pub is_permutation: bool,
+    /// how the link is connected, if it overrides the choice of the linker
+    pub connection: Option<LinkConnection>,
 }
 
 #[derive(Clone, Debug)]
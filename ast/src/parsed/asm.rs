@@ -606,6 +606,39 @@ pub struct LinkDeclaration {
     pub flag: Expression,
     pub link: CallableRef,
     pub is_permutation: bool,
+    /// How the link is connected, if it overrides the choice of the linker.
+    pub connection: Option<LinkConnection>,
+}
+
+/// The way a link is turned into constraints.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum LinkConnection {
+    /// A native lookup or permutation between the two machines.
+    Native,
+    /// A send in the calling machine and a receive in the called machine,
+    /// connected via the global bus.
+    Bus,
+}
+
+impl LinkConnection {
+    pub fn try_from_name(source_ref: SourceRef, name: &str) -> Result<Self, Error> {
+        match name {
+            "native" => Ok(LinkConnection::Native),
+            "bus" => Ok(LinkConnection::Bus),
+            _ => Err(source_ref.with_error(format!(
+                "unknown link connection `{name}`, expected `native` or `bus`"
+            ))),
+        }
+    }
+}
+
+impl Display for LinkConnection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkConnection::Native => write!(f, "native"),
+            LinkConnection::Bus => write!(f, "bus"),
+        }
+    }
 }
 
 impl Children<Expression> for LinkDeclaration {
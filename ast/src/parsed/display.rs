@@ -171,7 +171,8 @@ impl Display for LinkDeclaration {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(
             f,
-            "link {}{} {}",
+            "link {}{}{} {}",
+            self.connection.map(|c| format!("{c} ")).unwrap_or_default(),
             if self.flag == 1.into() {
                 "".to_string()
             } else {
@@ -1,17 +1,25 @@
 use std::{
     collections::{btree_map::Entry, BTreeMap},
-    io::{self, Cursor, Read},
+    fs,
+    io::{self, BufReader, Cursor, Read},
     marker::PhantomData,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread,
 };
 
 use itertools::Itertools;
-use powdr_ast::analyzed::Analyzed;
+use powdr_ast::analyzed::{
+    AlgebraicReference, Analyzed, Identity, PolynomialReference, PublicDeclaration,
+    StatementIdentifier,
+};
 use powdr_backend_utils::{machine_fixed_columns, machine_witness_columns};
-use powdr_executor::{constant_evaluator::VariablySizedColumn, witgen::WitgenCallback};
+use powdr_executor::{
+    constant_evaluator::VariablySizedColumn,
+    witgen::{WitgenCallback, WitnessSink},
+};
 use powdr_number::{DegreeType, FieldElement};
+use powdr_parser_util::SourceRef;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
@@ -31,18 +39,34 @@ struct CompositeVerificationKey {
 
 /// A proof for a single machine.
 #[derive(Serialize, Deserialize)]
-struct MachineProof {
+struct MachineProof<F> {
     /// The (dynamic) size of the machine.
     size: DegreeType,
     /// The proof for the machine.
     proof: Vec<u8>,
+    /// The final values of the accumulator columns of the bus interactions of the
+    /// machine, in the order of their publics. Empty if the machine does not interact
+    /// with the bus.
+    bus_accumulator: Vec<F>,
+}
+
+/// A machine proof persisted to disk, together with the hashes of the witness
+/// and of the configuration (backend, options, PIL and setup) it was computed from.
+#[derive(Serialize, Deserialize)]
+struct MachineProofCheckpoint<F> {
+    witness_hash: String,
+    config_hash: String,
+    proof: MachineProof<F>,
 }
 
 /// A composite proof that contains a proof for each machine separately, sorted by machine name.
 #[derive(Serialize, Deserialize)]
-struct CompositeProof {
+struct CompositeProof<F> {
     /// Machine proofs by machine name.
-    proofs: BTreeMap<String, MachineProof>,
+    proofs: BTreeMap<String, MachineProof<F>>,
+    /// The challenges shared by all machines, for each stage after the first one.
+    /// They are the sum of the challenges each machine derived from its own commitments.
+    challenges: Vec<BTreeMap<u64, F>>,
 }
 
 pub(crate) struct CompositeBackendFactory<F: FieldElement, B: BackendFactory<F>> {
@@ -59,6 +83,30 @@ impl<F: FieldElement, B: BackendFactory<F>> CompositeBackendFactory<F, B> {
     }
 }
 
+impl<F: FieldElement, B: BackendFactory<F>> CompositeBackendFactory<F, B> {
+    /// Returns the hex-encoded hash of everything a machine proof depends on,
+    /// apart from the witness: the backend, its options, the machine PIL and the setup.
+    fn hash_machine_config(
+        pil: &Analyzed<F>,
+        backend_options: &BackendOptions,
+        setup: Option<&[u8]>,
+    ) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for part in [
+            std::any::type_name::<B>().as_bytes(),
+            backend_options.as_bytes(),
+            pil.to_string().as_bytes(),
+            setup.unwrap_or_default(),
+        ] {
+            // Prefix with the length, so that different splits of the same
+            // bytes result in different hashes.
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
 impl<F: FieldElement, B: BackendFactory<F>> BackendFactory<F> for CompositeBackendFactory<F, B> {
     fn create(
         &self,
@@ -103,6 +151,20 @@ impl<F: FieldElement, B: BackendFactory<F>> BackendFactory<F> for CompositeBacke
             log_machine_stats(machine_name, pil)
         }
 
+        let publics = pil
+            .public_declarations_in_source_order()
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let config_hashes = pils
+            .iter()
+            .map(|(machine_name, pil)| {
+                let config_hash =
+                    Self::hash_machine_config(pil, &backend_options, setup_bytes.as_deref());
+                (machine_name.clone(), config_hash)
+            })
+            .collect();
+
         let machine_data = pils
             .into_iter()
             .zip_eq(verification_keys.into_iter())
@@ -116,6 +178,7 @@ impl<F: FieldElement, B: BackendFactory<F>> BackendFactory<F> for CompositeBacke
                             .map(|(name, values)| (name, values.to_vec().into()))
                             .collect();
                         let pil = set_size(pil.clone(), size as DegreeType);
+                        let pil = add_bus_accumulator_publics(pil, size as DegreeType);
                         // Set up readers for the setup and verification key
                         let mut setup_cursor = setup_bytes.as_ref().map(Cursor::new);
                         let setup = setup_cursor.as_mut().map(|cursor| cursor as &mut dyn Read);
@@ -160,7 +223,12 @@ impl<F: FieldElement, B: BackendFactory<F>> BackendFactory<F> for CompositeBacke
             })
             .collect::<Result<BTreeMap<_, _>, _>>()?;
 
-        Ok(Box::new(CompositeBackend { machine_data }))
+        Ok(Box::new(CompositeBackend {
+            machine_data,
+            publics,
+            checkpoint_dir: None,
+            config_hashes,
+        }))
     }
 
     fn generate_setup(&self, size: DegreeType, output: &mut dyn io::Write) -> Result<(), Error> {
@@ -212,6 +280,314 @@ pub(crate) struct CompositeBackend<F> {
     /// Note that it is essential that we use BTreeMap here to ensure that the machines are
     /// deterministically ordered.
     machine_data: BTreeMap<String, BTreeMap<DegreeType, MachineData<F>>>,
+    /// The names of the publics of the whole PIL, in source order.
+    publics: Vec<String>,
+    /// The directory where the proof of each machine is checkpointed, if enabled.
+    checkpoint_dir: Option<PathBuf>,
+    /// For each machine, the hash of the configuration its proofs are computed with.
+    config_hashes: BTreeMap<String, String>,
+}
+
+impl<F: FieldElement> CompositeBackend<F> {
+    fn checkpoint_path(dir: &Path, machine_name: &str) -> PathBuf {
+        dir.join(format!("{machine_name}.bin"))
+    }
+
+    /// Returns the checkpointed proof of a machine, if it was computed from
+    /// a witness with the given hash and with the current configuration.
+    fn load_machine_proof(
+        &self,
+        machine_name: &str,
+        witness_hash: &str,
+    ) -> Option<MachineProof<F>> {
+        let path = Self::checkpoint_path(self.checkpoint_dir.as_ref()?, machine_name);
+        let file = fs::File::open(path).ok()?;
+        let checkpoint: MachineProofCheckpoint<F> =
+            bincode::deserialize_from(BufReader::new(file)).ok()?;
+        (checkpoint.witness_hash == witness_hash
+            && checkpoint.config_hash == self.config_hashes[machine_name])
+            .then_some(checkpoint.proof)
+    }
+
+    /// Persists the proof of a machine, together with the hash of its witness.
+    fn store_machine_proof(
+        &self,
+        machine_name: &str,
+        checkpoint: &MachineProofCheckpoint<F>,
+    ) -> Result<(), Error> {
+        let Some(dir) = &self.checkpoint_dir else {
+            return Ok(());
+        };
+        // Write to a temporary file first, so that an interruption never
+        // leaves a truncated checkpoint behind.
+        let path = Self::checkpoint_path(dir, machine_name);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bincode::serialize(checkpoint).unwrap())?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+impl<F: FieldElement> CompositeBackend<F> {
+    /// Maps the names of the publics of the whole PIL to their values, given in
+    /// source order as the only instance column.
+    fn public_values(&self, instances: &[Vec<F>]) -> Result<BTreeMap<&str, F>, Error> {
+        let values = instances.first().map(Vec::as_slice).unwrap_or_default();
+        if values.len() != self.publics.len() {
+            return Err(Error::BackendError(format!(
+                "Expected {} public values, got {}",
+                self.publics.len(),
+                values.len()
+            )));
+        }
+        Ok(self
+            .publics
+            .iter()
+            .map(String::as_str)
+            .zip(values.iter().copied())
+            .collect())
+    }
+
+    /// Proves all machines. `produce_witness` receives a function that starts
+    /// proving a machine, which it must call exactly once for each machine with
+    /// the (stage-0) witness and the size of that machine. The first stage of
+    /// each machine is proven as soon as its witness is provided, which allows
+    /// dropping the witness of single-stage machines right away.
+    fn prove_machines(
+        &self,
+        produce_witness: impl FnOnce(&mut dyn FnMut(&String, Vec<(String, Vec<F>)>, DegreeType)),
+        witgen_callback: WitgenCallback<F>,
+    ) -> Result<Proof, Error> {
+        // We use scoped threads to be able to share non-'static references.
+        thread::scope(|scope| {
+            let mut proof_results = BTreeMap::new();
+            let mut proofs_status = vec![];
+            // The current witness of the machines that need further stages.
+            let mut witness_by_machine = BTreeMap::new();
+
+            produce_witness(&mut |machine, witness, size| {
+                let machine_entry = self.machine_data.get_key_value(machine).unwrap();
+                let (machine, machine_data) = machine_entry;
+                if size == 0 {
+                    // If a machine has no rows, remove it entirely.
+                    return;
+                }
+
+                let witness_hash = self
+                    .checkpoint_dir
+                    .is_some()
+                    .then(|| hash_witness(&witness));
+                if let Some(proof) = witness_hash
+                    .as_ref()
+                    .and_then(|hash| self.load_machine_proof(machine, hash))
+                {
+                    log::info!("== Reusing checkpointed proof of machine: {machine} (size {size})");
+                    proof_results.insert(machine, (Ok(proof.proof), size, proof.bus_accumulator));
+                    return;
+                }
+
+                let inner_machine_data = machine_data
+                    .get(&size)
+                    .expect("Machine does not support the given size");
+
+                // Later stages are computed from the current witness, so
+                // only those machines need to keep it around.
+                if inner_machine_data.pil.stage_count() > 1 {
+                    witness_by_machine.insert(machine, witness.clone());
+                }
+
+                let status = time_stage(machine, size, 0, || {
+                    sub_prover::run(scope, &inner_machine_data.backend, witness)
+                });
+
+                proofs_status.push((status, machine_entry, size, witness_hash));
+            });
+
+            // The challenges shared by all machines, for each stage after the first one.
+            let mut shared_challenges = vec![];
+            for stage in 1.. {
+                // Filter out proofs that have completed and accumulate the
+                // challenges.
+                let mut challenges = BTreeMap::new();
+                let waiting_provers = std::mem::take(&mut proofs_status)
+                    .into_iter()
+                    .filter_map(|(status, machine_entry, size, witness_hash)| match status {
+                        sub_prover::RunStatus::Completed(result) => {
+                            let (machine_name, machine_data) = machine_entry;
+                            let bus_accumulator = witness_by_machine
+                                .remove(machine_name)
+                                .map(|witness| bus_accumulator(&machine_data[&size].pil, &witness))
+                                .unwrap_or_default();
+                            // Only proofs that did not depend on challenges
+                            // (which are shared between all machines) can
+                            // be reused on their own.
+                            if let (Ok(proof), Some(witness_hash)) = (&result, witness_hash) {
+                                let checkpoint = MachineProofCheckpoint {
+                                    witness_hash,
+                                    config_hash: self.config_hashes[machine_name.as_str()].clone(),
+                                    proof: MachineProof {
+                                        size,
+                                        proof: proof.clone(),
+                                        bus_accumulator: bus_accumulator.clone(),
+                                    },
+                                };
+                                if let Err(e) = self.store_machine_proof(machine_name, &checkpoint)
+                                {
+                                    log::warn!("Could not checkpoint machine proof: {e}");
+                                }
+                            }
+                            assert!(proof_results
+                                .insert(machine_name, (result, size, bus_accumulator))
+                                .is_none());
+                            None
+                        }
+                        sub_prover::RunStatus::Challenged(sub_prover, c) => {
+                            // Accumulate the challenges
+                            accumulate_challenges(&mut challenges, c);
+                            Some((sub_prover, machine_entry, size))
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                if waiting_provers.is_empty() {
+                    break;
+                }
+                shared_challenges.push(challenges.clone());
+
+                // Compute next-stage witness for each waiting machine in parallel.
+                let waiting_machines = waiting_provers
+                    .iter()
+                    .map(|(_, (machine_name, machine_data), size)| {
+                        (*machine_name, machine_data.get(size).unwrap())
+                    })
+                    .collect::<Vec<_>>();
+                let new_witnesses = waiting_machines
+                    .par_iter()
+                    .map(|(machine_name, machine_data)| {
+                        witgen_callback.next_stage_witness(
+                            &machine_data.pil,
+                            &witness_by_machine[machine_name],
+                            challenges.clone(),
+                            stage,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                // Resume the waiting provers with the new witness
+                proofs_status = waiting_provers
+                    .into_iter()
+                    .zip_eq(new_witnesses)
+                    .map(|((prover, machine_entry, size), witness)| {
+                        let (machine_name, _) = machine_entry;
+                        witness_by_machine.insert(machine_name, witness.clone());
+
+                        let response = (challenges.clone(), witness);
+                        let status =
+                            time_stage(machine_name, size, stage, move || prover.resume(response));
+
+                        (status, machine_entry, size, None)
+                    })
+                    .collect();
+            }
+
+            let proofs = proof_results
+                .into_iter()
+                .map(
+                    |(machine_name, (proof, size, bus_accumulator))| match proof {
+                        Ok(proof) => Ok((
+                            machine_name.clone(),
+                            MachineProof {
+                                size,
+                                proof,
+                                bus_accumulator,
+                            },
+                        )),
+                        Err(e) => {
+                            log::error!("==> Machine proof failed: {:?}", e);
+                            Err(e)
+                        }
+                    },
+                )
+                .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+            let proof = CompositeProof {
+                proofs,
+                challenges: shared_challenges,
+            };
+            Ok(bincode::serialize(&proof).unwrap())
+        })
+    }
+}
+
+/// A witness sink that collects the columns of each machine and starts
+/// proving the machine as soon as all of its columns are available.
+struct MachineWitnessSink<'a, F: FieldElement, S> {
+    machine_data: &'a BTreeMap<String, BTreeMap<DegreeType, MachineData<F>>>,
+    /// The columns received so far that belong to machines not started yet.
+    columns: BTreeMap<String, Vec<F>>,
+    pending_machines: Vec<&'a String>,
+    start_machine: S,
+}
+
+impl<'a, F: FieldElement, S: FnMut(&'a String, Vec<(String, Vec<F>)>, DegreeType)>
+    MachineWitnessSink<'a, F, S>
+{
+    /// Starts the machines that are still pending, using the columns received
+    /// so far. Should be called after the whole witness has been received.
+    fn finish(mut self) {
+        for machine_name in std::mem::take(&mut self.pending_machines) {
+            self.start(machine_name);
+        }
+    }
+
+    fn start(&mut self, machine_name: &'a String) {
+        let machine_data = &self.machine_data[machine_name];
+        let columns = witness_column_names(machine_data)
+            .filter_map(|name| self.columns.remove_entry(&name))
+            .collect::<Vec<_>>();
+        let (witness, size) = process_witness_for_machine(machine_name, machine_data, &columns);
+        // Drop our copy of the columns before proving.
+        drop(columns);
+        (self.start_machine)(machine_name, witness, size);
+    }
+}
+
+impl<'a, F: FieldElement, S: FnMut(&'a String, Vec<(String, Vec<F>)>, DegreeType)> WitnessSink<F>
+    for MachineWitnessSink<'a, F, S>
+{
+    fn consume(&mut self, columns: Vec<(String, Vec<F>)>) {
+        self.columns.extend(columns);
+        let (complete, pending) = std::mem::take(&mut self.pending_machines)
+            .into_iter()
+            .partition::<Vec<_>, _>(|machine_name| {
+                witness_column_names(&self.machine_data[*machine_name])
+                    .all(|name| self.columns.contains_key(&name))
+            });
+        self.pending_machines = pending;
+        for machine_name in complete {
+            self.start(machine_name);
+        }
+    }
+}
+
+/// The names of the first-stage witness columns of a machine.
+fn witness_column_names<F: FieldElement>(
+    machine_data: &BTreeMap<DegreeType, MachineData<F>>,
+) -> impl Iterator<Item = String> + '_ {
+    // Pick any available PIL; they all contain the same witness columns
+    let any_pil = &machine_data.values().next().unwrap().pil;
+    any_pil
+        .committed_polys_in_source_order()
+        .filter(|(symbol, _)| symbol.stage.unwrap_or_default() == 0)
+        .flat_map(|(symbol, _)| symbol.array_elements())
+        .map(|(name, _)| name)
+}
+
+/// Returns the hex-encoded hash of a machine witness.
+fn hash_witness<F: FieldElement>(witness: &[(String, Vec<F>)]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    bincode::serialize_into(&mut hasher, witness).unwrap();
+    hasher.finalize().to_hex().to_string()
 }
 
 /// Makes sure that all columns in the machine PIL have the provided degree, cloning
@@ -257,6 +633,69 @@ fn accumulate_challenges<F: FieldElement>(into: &mut BTreeMap<u64, F>, from: BTr
     }
 }
 
+/// The accumulator columns of each bus interaction of a machine.
+fn bus_accumulator_columns<F>(pil: &Analyzed<F>) -> impl Iterator<Item = &[AlgebraicReference]> {
+    pil.identities.iter().filter_map(|identity| match identity {
+        Identity::PhantomBusInteraction(interaction) => {
+            Some(interaction.accumulator_columns.as_slice())
+        }
+        _ => None,
+    })
+}
+
+/// The name of the public exposing the final value of an accumulator column.
+fn bus_accumulator_public_name(column: &AlgebraicReference) -> String {
+    format!("{}_final", column.name)
+}
+
+/// Exposes the value of each bus accumulator column of a machine in the last row
+/// as a public, so that the machine proof binds the value the bus balance is
+/// checked against.
+fn add_bus_accumulator_publics<F: Clone>(
+    pil: Arc<Analyzed<F>>,
+    degree: DegreeType,
+) -> Arc<Analyzed<F>> {
+    let columns = bus_accumulator_columns(&pil)
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    if columns.is_empty() {
+        return pil;
+    }
+    let mut pil = (*pil).clone();
+    for column in columns {
+        let name = bus_accumulator_public_name(&column);
+        let declaration = PublicDeclaration {
+            id: pil.public_declarations.len() as u64,
+            source: SourceRef::unknown(),
+            name: name.clone(),
+            polynomial: PolynomialReference {
+                name: column.name,
+                type_args: Some(vec![]),
+            },
+            array_index: None,
+            index: degree - 1,
+        };
+        pil.public_declarations.insert(name.clone(), declaration);
+        pil.source_order
+            .push(StatementIdentifier::PublicDeclaration(name));
+    }
+    Arc::new(pil)
+}
+
+/// Returns the values of the bus accumulator columns of a machine in the last row,
+/// in the order of their publics.
+fn bus_accumulator<F: FieldElement>(pil: &Analyzed<F>, witness: &[(String, Vec<F>)]) -> Vec<F> {
+    let witness = witness
+        .iter()
+        .map(|(name, values)| (name, values))
+        .collect::<BTreeMap<_, _>>();
+    bus_accumulator_columns(pil)
+        .flatten()
+        .map(|column| *witness[&column.name].last().unwrap())
+        .collect()
+}
+
 fn process_witness_for_machine<F: FieldElement>(
     machine: &str,
     machine_data: &BTreeMap<DegreeType, MachineData<F>>,
@@ -307,127 +746,148 @@ impl<F: FieldElement> Backend<F> for CompositeBackend<F> {
             unimplemented!();
         }
 
-        // Compute next-stage witness for each machine in parallel.
-        let mut witness_by_machine = self
-            .machine_data
-            .par_iter()
-            .map(|(machine_name, machine_data)| {
-                let (witness, size) =
-                    process_witness_for_machine(machine_name, machine_data, witness);
-                (machine_name.clone(), (witness, size))
-            })
-            .collect::<BTreeMap<_, _>>();
-
-        // We use scoped threads to be able to share non-'static references.
-        thread::scope(|scope| {
-            let mut proofs_status = self
-                .machine_data
-                .iter()
-                .filter_map(|machine_entry| {
-                    let (machine, machine_data) = machine_entry;
-                    let (witness, size) = witness_by_machine[machine].clone();
-                    if size == 0 {
-                        // If a machine has no rows, remove it entirely.
-                        return None;
-                    }
-                    let inner_machine_data = machine_data
-                        .get(&size)
-                        .expect("Machine does not support the given size");
-
-                    let status = time_stage(machine, size, 0, || {
-                        sub_prover::run(scope, &inner_machine_data.backend, witness)
-                    });
-
-                    Some((status, machine_entry, size))
-                })
-                .collect::<Vec<_>>();
-
-            let mut proof_results = BTreeMap::new();
-            for stage in 1.. {
-                // Filter out proofs that have completed and accumulate the
-                // challenges.
-                let mut challenges = BTreeMap::new();
-                let waiting_provers = std::mem::take(&mut proofs_status)
-                    .into_iter()
-                    .filter_map(|(status, machine_data, size)| match status {
-                        sub_prover::RunStatus::Completed(result) => {
-                            let (machine_name, _) = machine_data;
-                            assert!(proof_results.insert(machine_name, (result, size)).is_none());
-                            None
-                        }
-                        sub_prover::RunStatus::Challenged(sub_prover, c) => {
-                            // Accumulate the challenges
-                            accumulate_challenges(&mut challenges, c);
-                            Some((sub_prover, machine_data))
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                if waiting_provers.is_empty() {
-                    break;
-                }
-
-                witness_by_machine = self
+        self.prove_machines(
+            |start_machine| {
+                // Select the witness of each machine in parallel.
+                let witness_by_machine = self
                     .machine_data
                     .par_iter()
                     .map(|(machine_name, machine_data)| {
-                        let (machine_witness, size) = witness_by_machine.get(machine_name).unwrap();
-                        let machine_data = machine_data.get(size).unwrap();
-                        let new_witness = witgen_callback.next_stage_witness(
-                            &machine_data.pil,
-                            machine_witness,
-                            challenges.clone(),
-                            stage,
-                        );
-                        (machine_name.clone(), (new_witness, *size))
-                    })
-                    .collect();
-
-                // Resume the waiting provers with the new witness
-                proofs_status = waiting_provers
-                    .into_iter()
-                    .map(|(prover, machine_entry)| {
-                        let (machine_name, _) = machine_entry;
                         let (witness, size) =
-                            witness_by_machine.get(machine_name).cloned().unwrap();
-
-                        let status =
-                            time_stage(machine_name, size, stage, move || prover.resume(witness));
-
-                        (status, machine_entry, size)
+                            process_witness_for_machine(machine_name, machine_data, witness);
+                        (machine_name, (witness, size))
                     })
-                    .collect();
-            }
+                    .collect::<Vec<_>>();
+                for (machine_name, (witness, size)) in witness_by_machine {
+                    start_machine(machine_name, witness, size);
+                }
+            },
+            witgen_callback,
+        )
+    }
 
-            let proofs = proof_results
-                .into_iter()
-                .map(|(machine_name, (proof, size))| match proof {
-                    Ok(proof) => Ok((machine_name.clone(), MachineProof { size, proof })),
-                    Err(e) => {
-                        log::error!("==> Machine proof failed: {:?}", e);
-                        Err(e)
-                    }
-                })
-                .collect::<Result<BTreeMap<_, _>, _>>()?;
+    fn prove_streaming(
+        &self,
+        generate_witness: &mut dyn FnMut(&mut dyn WitnessSink<F>),
+        prev_proof: Option<Proof>,
+        witgen_callback: WitgenCallback<F>,
+    ) -> Result<Proof, Error> {
+        if prev_proof.is_some() {
+            unimplemented!();
+        }
 
-            let proof = CompositeProof { proofs };
-            Ok(bincode::serialize(&proof).unwrap())
-        })
+        self.prove_machines(
+            |start_machine| {
+                let mut sink = MachineWitnessSink {
+                    machine_data: &self.machine_data,
+                    columns: BTreeMap::new(),
+                    pending_machines: self.machine_data.keys().collect(),
+                    start_machine,
+                };
+                generate_witness(&mut sink);
+                sink.finish();
+            },
+            witgen_callback,
+        )
     }
-
     fn verify(&self, proof: &[u8], instances: &[Vec<F>]) -> Result<(), Error> {
-        let proof: CompositeProof = bincode::deserialize(proof).unwrap();
+        let proof: CompositeProof<F> = bincode::deserialize(proof).unwrap();
+        let publics = self.public_values(instances)?;
+        let mut challenges: Vec<BTreeMap<u64, F>> = vec![];
+        let mut bus_balance: Vec<F> = vec![];
         for (machine_name, machine_data) in self.machine_data.iter() {
             if let Some(machine_proof) = proof.proofs.get(machine_name) {
-                machine_data
-                    .get(&machine_proof.size)
-                    .unwrap()
-                    .backend
-                    .lock()
-                    .unwrap()
-                    .verify(&machine_proof.proof, instances)?;
+                let machine_data = machine_data.get(&machine_proof.size).unwrap();
+                let pil = &machine_data.pil;
+
+                // The final values of the bus accumulators are publics of the machine proof.
+                let accumulator_names = bus_accumulator_columns(pil)
+                    .flatten()
+                    .map(bus_accumulator_public_name)
+                    .collect::<Vec<_>>();
+                if accumulator_names.len() != machine_proof.bus_accumulator.len() {
+                    return Err(Error::BackendError(format!(
+                        "Expected {} bus accumulator values for machine {machine_name}, got {}",
+                        accumulator_names.len(),
+                        machine_proof.bus_accumulator.len()
+                    )));
+                }
+                let accumulators = accumulator_names
+                    .iter()
+                    .map(String::as_str)
+                    .zip_eq(machine_proof.bus_accumulator.iter().copied())
+                    .collect::<BTreeMap<_, _>>();
+                let machine_instances = [pil
+                    .public_declarations_in_source_order()
+                    .map(|(name, _)| {
+                        publics
+                            .get(name.as_str())
+                            .or_else(|| accumulators.get(name.as_str()))
+                            .copied()
+                            .ok_or_else(|| {
+                                Error::BackendError(format!("Missing value of public {name}"))
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?];
+
+                let backend = machine_data.backend.lock().unwrap();
+                if pil.stage_count() > 1 {
+                    // Later stages were computed with the challenges shared by all machines.
+                    backend.verify_with_challenges(
+                        &machine_proof.proof,
+                        &machine_instances,
+                        &proof.challenges,
+                    )?;
+
+                    // Re-derive the challenges this machine contributed from its commitments.
+                    // The machine takes part in the rounds of challenges after
+                    // all its stages but the last one, even if it draws none.
+                    let machine_challenges =
+                        backend.stage_challenges(&machine_proof.proof, &machine_instances)?;
+                    for (stage, machine_challenges) in machine_challenges
+                        .into_iter()
+                        .enumerate()
+                        .take(pil.stage_count() - 1)
+                    {
+                        if challenges.len() <= stage {
+                            challenges.resize(stage + 1, BTreeMap::new());
+                        }
+                        accumulate_challenges(&mut challenges[stage], machine_challenges);
+                    }
+                } else {
+                    backend.verify(&machine_proof.proof, &machine_instances)?;
+                }
+
+                for columns in bus_accumulator_columns(pil) {
+                    if bus_balance.len() < columns.len() {
+                        bus_balance.resize(columns.len(), F::zero());
+                    }
+                    for (balance, column) in bus_balance.iter_mut().zip(columns) {
+                        *balance += accumulators[bus_accumulator_public_name(column).as_str()];
+                    }
+                }
+            } else if machine_data
+                .values()
+                .any(|machine_data| bus_accumulator_columns(&machine_data.pil).next().is_some())
+            {
+                // Otherwise, the bus interactions of the machine would be
+                // missing from the balance.
+                return Err(Error::BackendError(format!(
+                    "Missing proof of machine {machine_name}, which has bus interactions"
+                )));
             }
         }
+
+        if challenges != proof.challenges {
+            return Err(Error::BackendError(
+                "The shared challenges do not match the commitments of the machines".to_string(),
+            ));
+        }
+        if bus_balance.iter().any(|value| !value.is_zero()) {
+            return Err(Error::BackendError(
+                "The bus accumulators of the machines do not sum to zero".to_string(),
+            ));
+        }
         Ok(())
     }
 
@@ -478,4 +938,10 @@ impl<F: FieldElement> Backend<F> for CompositeBackend<F> {
     fn export_ethereum_verifier(&self, _output: &mut dyn io::Write) -> Result<(), Error> {
         unimplemented!();
     }
+
+    fn enable_checkpoints(&mut self, dir: PathBuf) -> Result<(), Error> {
+        fs::create_dir_all(&dir)?;
+        self.checkpoint_dir = Some(dir);
+        Ok(())
+    }
 }
@@ -27,17 +27,26 @@ where
         // We must ensure the backend can call the callback from multiple
         // threads (needed by Plonky3, as it requires WitgenCallback to be
         // Sync). So we wrap the data in a Mutex.
-        let callback_data = Mutex::new((challenge_sender, response_receiver, 1u8));
+        let callback_data = Arc::new(Mutex::new(CallbackData {
+            challenge_sender,
+            response_receiver,
+            expected_stage: 1,
+            next_witness: None,
+        }));
+        let share_data = callback_data.clone();
         let callback = WitgenCallback::<F>::new(Arc::new(move |_, _, challenge, stage| {
-            // Locking guarantees the sequencing of the stages:
-            let mut receiver_guard = callback_data.lock().unwrap();
-            let (challenge_sender, response_receiver, expected_stage) = &mut *receiver_guard;
-
-            assert_eq!(stage, *expected_stage);
-            *expected_stage += 1;
-
-            challenge_sender.send(challenge).unwrap();
-            response_receiver.recv().unwrap()
+            let mut data = callback_data.lock().unwrap();
+            // The witness was already received when the challenges were shared.
+            match data.next_witness.take() {
+                Some(witness) => witness,
+                None => data.exchange(challenge, stage).1,
+            }
+        }))
+        .with_share_challenges(Arc::new(move |challenge, stage| {
+            let mut data = share_data.lock().unwrap();
+            let (challenges, witness) = data.exchange(challenge, stage);
+            data.next_witness = Some(witness);
+            challenges
         }));
 
         // TODO: this witness will be held in memory until the end of the
@@ -55,6 +64,31 @@ where
     .wait()
 }
 
+/// The state of the callback a sub-prover uses to communicate with the caller.
+struct CallbackData<F> {
+    challenge_sender: SyncSender<BTreeMap<u64, F>>,
+    response_receiver: Receiver<Response<F>>,
+    expected_stage: u8,
+    /// The next-stage witness, if it was received but not requested yet.
+    next_witness: Option<Vec<(String, Vec<F>)>>,
+}
+
+impl<F> CallbackData<F> {
+    /// Sends the challenges of the prover and waits for the shared challenges
+    /// and the witness of the next stage.
+    fn exchange(&mut self, challenge: BTreeMap<u64, F>, stage: u8) -> Response<F> {
+        // Locking guarantees the sequencing of the stages:
+        assert_eq!(stage, self.expected_stage);
+        self.expected_stage += 1;
+
+        self.challenge_sender.send(challenge).unwrap();
+        self.response_receiver.recv().unwrap()
+    }
+}
+
+/// The challenges shared by all machines and the next-stage witness.
+pub type Response<F> = (BTreeMap<u64, F>, Vec<(String, Vec<F>)>);
+
 pub enum RunStatus<'s, F: FieldElement> {
     Completed(Result<Vec<u8>, Error>),
     Challenged(SubProver<'s, F>, BTreeMap<u64, F>),
@@ -65,11 +99,11 @@ pub enum RunStatus<'s, F: FieldElement> {
 pub struct SubProver<'s, F: FieldElement> {
     pub thread: ScopedJoinHandle<'s, Result<Vec<u8>, Error>>,
     pub challenge_receiver: Receiver<BTreeMap<u64, F>>,
-    pub response_sender: SyncSender<Vec<(String, Vec<F>)>>,
+    pub response_sender: SyncSender<Response<F>>,
 }
 
 impl<'s, F: FieldElement> SubProver<'s, F> {
-    pub fn resume(self, response: Vec<(String, Vec<F>)>) -> RunStatus<'s, F> {
+    pub fn resume(self, response: Response<F>) -> RunStatus<'s, F> {
         self.response_sender.send(response).unwrap();
         self.wait()
     }
@@ -1,4 +1,5 @@
 use halo2_proofs::{
+    arithmetic::CurveAffine,
     circuit::{Layouter, SimpleFloorPlanner, Value},
     halo2curves::{
         bn256::{Bn256, Fq, Fr, G1Affine},
@@ -34,7 +35,7 @@ use snark_verifier::{
         self,
         halo2::{compile, transcript::evm::EvmTranscript, Config},
     },
-    util::arithmetic::{fe_to_limbs, PrimeField},
+    util::arithmetic::{fe_from_limbs, fe_to_limbs, PrimeField},
     verifier::{self, plonk::PlonkProtocol, SnarkVerifier},
 };
 
@@ -64,6 +65,7 @@ const R_P: usize = 60;
 type Svk = KzgSuccinctVerifyingKey<G1Affine>;
 type BaseFieldEccChip = halo2_wrong_ecc::BaseFieldEccChip<G1Affine, LIMBS, BITS>;
 type Halo2Loader<'a> = loader::halo2::Halo2Loader<'a, G1Affine, BaseFieldEccChip>;
+type Halo2Scalar<'a> = loader::halo2::Scalar<'a, G1Affine, BaseFieldEccChip>;
 pub type PoseidonTranscript<L, S> =
     system::halo2::transcript::halo2::PoseidonTranscript<G1Affine, L, S, T, RATE, R_F, R_P>;
 
@@ -136,12 +138,17 @@ impl SnarkWitness {
     }
 }
 
+/// Verifies the snarks and accumulates their KZG accumulators.
+/// Returns the accumulator and the assigned instances of all snarks.
 pub fn aggregate<'a>(
     svk: &Svk,
     loader: &Rc<Halo2Loader<'a>>,
     snarks: &[SnarkWitness],
     as_proof: Value<&'_ [u8]>,
-) -> KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>> {
+) -> (
+    KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>>,
+    Vec<Halo2Scalar<'a>>,
+) {
     let assign_instances = |instances: &[Vec<Value<Fr>>]| {
         instances
             .iter()
@@ -154,9 +161,9 @@ pub fn aggregate<'a>(
             .collect_vec()
     };
 
-    let accumulators = snarks
+    let (accumulators, instances): (Vec<_>, Vec<_>) = snarks
         .iter()
-        .flat_map(|snark| {
+        .map(|snark| {
             let protocol = snark.protocol.loaded(loader);
             let instances = assign_instances(&snark.instances);
             let mut transcript =
@@ -164,9 +171,13 @@ pub fn aggregate<'a>(
             let proof =
                 PlonkSuccinctVerifier::read_proof(svk, &protocol, &instances, &mut transcript)
                     .unwrap();
-            PlonkSuccinctVerifier::verify(svk, &protocol, &instances, &proof).unwrap()
+            let accumulators =
+                PlonkSuccinctVerifier::verify(svk, &protocol, &instances, &proof).unwrap();
+            (accumulators, instances)
         })
-        .collect_vec();
+        .unzip();
+    let accumulators = accumulators.into_iter().flatten().collect_vec();
+    let instances = instances.into_iter().flatten().flatten().collect_vec();
 
     let accumulator = {
         let mut transcript = PoseidonTranscript::<Rc<Halo2Loader>, _>::new(loader, as_proof);
@@ -174,7 +185,7 @@ pub fn aggregate<'a>(
         As::verify(&Default::default(), &accumulators, &proof).unwrap()
     };
 
-    accumulator
+    (accumulator, instances)
 }
 
 #[derive(Clone)]
@@ -258,9 +269,13 @@ impl AggregationCircuit {
         )
         .expect("Aggregated proof should be valid");
         let KzgAccumulator { lhs, rhs } = accumulator;
+        // the accumulator, followed by the instances of the snarks
         let instances = [lhs.x, lhs.y, rhs.x, rhs.y]
             .map(fe_to_limbs::<_, _, LIMBS, BITS>)
-            .concat();
+            .concat()
+            .into_iter()
+            .chain(snarks.iter().flat_map(|snark| snark.instances.concat()))
+            .collect_vec();
 
         Self {
             svk,
@@ -286,11 +301,18 @@ impl AggregationCircuit {
     }
 
     pub fn accumulator_indices() -> Vec<(usize, usize)> {
-        (0..4 * LIMBS).map(|idx| (0, idx)).collect()
+        (0..Self::accumulator_len()).map(|idx| (0, idx)).collect()
     }
 
-    pub fn num_instance() -> Vec<usize> {
-        vec![4 * LIMBS]
+    /// The number of cells of the instance column holding the accumulator.
+    pub fn accumulator_len() -> usize {
+        4 * LIMBS
+    }
+
+    /// The instance column holds the accumulator, followed by the
+    /// `app_instance_count` instances of the aggregated snark.
+    pub fn num_instance(app_instance_count: usize) -> Vec<usize> {
+        vec![Self::accumulator_len() + app_instance_count]
     }
 
     pub fn instances(&self) -> Vec<Vec<Fr>> {
@@ -302,6 +324,35 @@ impl AggregationCircuit {
     }
 }
 
+/// Runs the KZG decider on the accumulator exposed by an aggregated proof,
+/// given as the limbs of the coordinates of its two points.
+/// The aggregation circuit only checks that the accumulator is computed correctly,
+/// so an aggregated proof is only valid if the accumulator passes the pairing check.
+pub fn decide(params: &ParamsKZG<Bn256>, accumulator: &[Fr]) -> Result<(), String> {
+    if accumulator.len() != AggregationCircuit::accumulator_len() {
+        return Err(format!(
+            "Expected an accumulator of {} limbs, but got {}",
+            AggregationCircuit::accumulator_len(),
+            accumulator.len()
+        ));
+    }
+    let mut coordinates = accumulator
+        .chunks(LIMBS)
+        .map(|limbs| fe_from_limbs::<_, Fq, LIMBS, BITS>(limbs.try_into().unwrap()));
+    let mut point = || {
+        let (x, y) = (coordinates.next().unwrap(), coordinates.next().unwrap());
+        Option::<G1Affine>::from(G1Affine::from_xy(x, y))
+            .ok_or_else(|| "The accumulator is not on the curve".to_string())
+    };
+    let (lhs, rhs) = (point()?, point()?);
+
+    As::decide(
+        &(params.get_g()[0], params.g2(), params.s_g2()).into(),
+        KzgAccumulator::new(lhs, rhs),
+    )
+    .map_err(|e| format!("Invalid accumulator: {e:?}"))
+}
+
 impl Circuit<Fr> for AggregationCircuit {
     type Config = AggregationConfig;
     type FloorPlanner = SimpleFloorPlanner;
@@ -338,14 +389,15 @@ impl Circuit<Fr> for AggregationCircuit {
 
         range_chip.load_table(&mut layouter)?;
 
-        let accumulator_limbs = layouter.assign_region(
+        let (accumulator_limbs, app_instances) = layouter.assign_region(
             || "",
             |region| {
                 let ctx = RegionCtx::new(region, 0);
 
                 let ecc_chip = config.ecc_chip();
                 let loader = Halo2Loader::new(ecc_chip, ctx);
-                let accumulator = aggregate(&self.svk, &loader, &self.snarks, self.as_proof());
+                let (accumulator, instances) =
+                    aggregate(&self.svk, &loader, &self.snarks, self.as_proof());
 
                 let accumulator_limbs = [accumulator.lhs, accumulator.rhs]
                     .iter()
@@ -356,14 +408,24 @@ impl Circuit<Fr> for AggregationCircuit {
                     })
                     .collect::<Result<Vec<_>, Error>>()?
                     .into_iter()
-                    .flatten();
+                    .flatten()
+                    .collect_vec();
+                let app_instances = instances
+                    .into_iter()
+                    .map(|instance| instance.into_assigned())
+                    .collect_vec();
 
-                Ok(accumulator_limbs)
+                Ok((accumulator_limbs, app_instances))
             },
         )?;
 
-        for (row, limb) in accumulator_limbs.enumerate() {
-            main_gate.expose_public(layouter.namespace(|| ""), limb, row)?;
+        // expose the accumulator, followed by the public inputs of the aggregated snarks
+        for (row, cell) in accumulator_limbs
+            .into_iter()
+            .chain(app_instances)
+            .enumerate()
+        {
+            main_gate.expose_public(layouter.namespace(|| ""), cell, row)?;
         }
 
         Ok(())
@@ -404,3 +466,31 @@ pub fn gen_aggregation_evm_verifier(
     let sol = gen_aggregation_solidity_verifier(params, vk, num_instance, accumulator_indices);
     evm::compile_solidity(&sol)
 }
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use snark_verifier::util::arithmetic::fe_to_limbs;
+
+    use super::{decide, AggregationCircuit, BITS, LIMBS};
+    use crate::halo2::prover::generate_setup;
+
+    #[test]
+    fn decide_invalid_accumulator() {
+        let params = generate_setup(8);
+
+        // the wrong number of limbs
+        let limbs = vec![Fr::from(1u64); AggregationCircuit::accumulator_len()];
+        assert!(decide(&params, &limbs[1..]).is_err());
+
+        // not a pair of curve points
+        assert!(decide(&params, &limbs).is_err());
+
+        // the generator twice, which does not pass the pairing check
+        let g = params.get_g()[0];
+        let limbs = [g.x, g.y, g.x, g.y]
+            .map(fe_to_limbs::<_, _, LIMBS, BITS>)
+            .concat();
+        assert!(decide(&params, &limbs).is_err());
+    }
+}
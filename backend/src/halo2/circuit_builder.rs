@@ -83,19 +83,15 @@ pub(crate) struct PowdrCircuit<'a, T> {
 }
 
 fn get_publics<T: FieldElement>(analyzed: &Analyzed<T>) -> Vec<(String, usize)> {
-    let mut publics = analyzed
-        .public_declarations
-        .values()
-        .map(|public_declaration| {
+    // Use the source order, which is also the order of the instances passed to the verifier
+    analyzed
+        .public_declarations_in_source_order()
+        .map(|(_, public_declaration)| {
             let witness_name = public_declaration.referenced_poly_name();
             let witness_offset = public_declaration.index as usize;
             (witness_name, witness_offset)
         })
-        .collect::<Vec<_>>();
-
-    // Sort, so that the order is deterministic
-    publics.sort();
-    publics
+        .collect()
 }
 
 impl<'a, T: FieldElement> PowdrCircuit<'a, T> {
@@ -130,10 +126,6 @@ impl<'a, T: FieldElement> PowdrCircuit<'a, T> {
         }
     }
 
-    pub(crate) fn has_publics(&self) -> bool {
-        !self.publics.is_empty()
-    }
-
     /// Computes the instance column from the witness
     pub(crate) fn instance_column<F: PrimeField<Repr = [u8; 32]>>(&self) -> Vec<F> {
         let witness = self
@@ -423,12 +415,14 @@ impl<'a, T: FieldElement, F: PrimeField<Repr = [u8; 32]>> Circuit<F> for PowdrCi
                     )?;
                 }
 
-                let publics = self
-                    .publics
-                    .iter()
-                    .enumerate()
-                    .map(|(i, p)| (p, i))
-                    .collect::<BTreeMap<_, _>>();
+                // The same cell can be exposed by several public declarations
+                let publics = self.publics.iter().enumerate().fold(
+                    BTreeMap::<_, Vec<_>>::new(),
+                    |mut acc, (i, p)| {
+                        acc.entry(p).or_default().push(i);
+                        acc
+                    },
+                );
 
                 // Set witness values
                 let mut public_cells = Vec::new();
@@ -454,8 +448,10 @@ impl<'a, T: FieldElement, F: PrimeField<Repr = [u8; 32]>> Circuit<F> for PowdrCi
 
                             // Collect public cells, which are later copy-constrained to equal
                             // a cell in the instance column.
-                            if let Some(&instance_index) = publics.get(&(name.clone(), i)) {
-                                public_cells.push((instance_index, assigned_cell));
+                            for &instance_index in
+                                publics.get(&(name.clone(), i)).into_iter().flatten()
+                            {
+                                public_cells.push((instance_index, assigned_cell.clone()));
                             }
                         }
                     }
@@ -52,6 +52,9 @@ struct Halo2Proof {
     )]
     proof: Vec<u8>,
     publics: Vec<String>,
+    /// For aggregated proofs, the accumulator, which precedes the public inputs
+    /// in the instance column of the proof. Empty otherwise.
+    accumulator: Vec<String>,
 }
 
 fn serialize_as_hex<S>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
@@ -135,8 +138,15 @@ impl Backend<Bn254Field> for Halo2Prover {
         }
         match self.proof_type() {
             ProofType::Poseidon => Ok(self.verify_poseidon(&proof.proof, instances)?),
-            ProofType::SnarkSingle | ProofType::SnarkAggr => {
-                Ok(self.verify_snark(&proof.proof, instances)?)
+            ProofType::SnarkSingle => Ok(self.verify_snark(&proof.proof, instances)?),
+            ProofType::SnarkAggr => {
+                let accumulator = proof
+                    .accumulator
+                    .iter()
+                    .map(|x| x.parse())
+                    .collect::<Result<Vec<Bn254Field>, _>>()
+                    .map_err(Error::BackendError)?;
+                Ok(self.verify_snark_aggr(&proof.proof, &accumulator, &instances[0])?)
             }
         }
     }
@@ -148,8 +158,12 @@ impl Backend<Bn254Field> for Halo2Prover {
         witgen_callback: WitgenCallback<Bn254Field>,
     ) -> Result<Proof, Error> {
         let proof_and_publics = match self.proof_type() {
-            ProofType::Poseidon => self.prove_poseidon(witness, witgen_callback),
-            ProofType::SnarkSingle => self.prove_snark_single(witness, witgen_callback),
+            ProofType::Poseidon => self
+                .prove_poseidon(witness, witgen_callback)
+                .map(|(proof, publics)| (proof, vec![], publics)),
+            ProofType::SnarkSingle => self
+                .prove_snark_single(witness, witgen_callback)
+                .map(|(proof, publics)| (proof, vec![], publics)),
             ProofType::SnarkAggr => match prev_proof {
                 Some(proof) => {
                     let proof: Halo2Proof = bincode::deserialize(&proof).unwrap();
@@ -158,9 +172,14 @@ impl Backend<Bn254Field> for Halo2Prover {
                 None => Err("Aggregated proof requires a previous proof".to_string()),
             },
         };
-        let (proof, publics) = proof_and_publics?;
+        let (proof, accumulator, publics) = proof_and_publics?;
         let publics = fe_slice_to_string(&publics);
-        let proof = Halo2Proof { proof, publics };
+        let accumulator = fe_slice_to_string(&accumulator);
+        let proof = Halo2Proof {
+            proof,
+            publics,
+            accumulator,
+        };
         let proof = bincode::serialize(&proof).unwrap();
         Ok(proof)
     }
@@ -113,6 +113,11 @@ impl Halo2Prover {
         self.params.write(output)
     }
 
+    /// The number of public inputs, i.e. the size of the instance column of the app circuit.
+    fn num_publics(&self) -> usize {
+        self.analyzed.public_declarations.len()
+    }
+
     fn prove<
         E: EncodedChallenge<G1Affine>,
         TW: TranscriptWriterBuffer<Vec<u8>, G1Affine, E>,
@@ -226,12 +231,14 @@ impl Halo2Prover {
 
     /// Generate a recursive proof that compresses one or more Poseidon proofs.
     /// These proofs can be verified directly on Ethereum.
+    /// Returns the proof, the accumulator and the public inputs of the app,
+    /// which together form the instance column of the proof.
     pub fn prove_snark_aggr(
         &self,
         witness: &[(String, Vec<Bn254Field>)],
         witgen_callback: WitgenCallback<Bn254Field>,
         proof: Vec<u8>,
-    ) -> Result<(Vec<u8>, Vec<Bn254Field>), String> {
+    ) -> Result<(Vec<u8>, Vec<Bn254Field>, Vec<Bn254Field>), String> {
         assert!(matches!(self.proof_type, ProofType::SnarkAggr));
 
         log::info!("Starting proof aggregation...");
@@ -242,11 +249,6 @@ impl Halo2Prover {
             .with_witgen_callback(witgen_callback)
             .with_witness(witness);
 
-        // TODO Support publics in the app snark
-        if circuit_app.has_publics() {
-            unimplemented!("Public inputs are not supported yet");
-        }
-
         log::info!("Generating VK for app snark...");
 
         let mut params_app = self.params.clone();
@@ -256,8 +258,7 @@ impl Halo2Prover {
         let protocol_app = compile(
             &params_app,
             self.vkey_app.as_ref().unwrap(),
-            // TODO change this once we accept publics in the app snark
-            Config::kzg().with_num_instance(vec![0]),
+            Config::kzg().with_num_instance(vec![self.num_publics()]),
         );
         let empty_snark = aggregation::Snark::new_without_witness(protocol_app.clone());
         let agg_circuit =
@@ -272,15 +273,15 @@ impl Halo2Prover {
         let deployment_code = aggregation::gen_aggregation_evm_verifier(
             &self.params,
             &vk_aggr,
-            aggregation::AggregationCircuit::num_instance(),
+            aggregation::AggregationCircuit::num_instance(self.num_publics()),
             aggregation::AggregationCircuit::accumulator_indices(),
         );
 
         log::info!("Generating aggregated proof...");
         let start = Instant::now();
 
-        // TODO change this once we accept publics in the app snark
-        let snark = aggregation::Snark::new(protocol_app, vec![vec![]], proof);
+        let snark =
+            aggregation::Snark::new(protocol_app, vec![circuit_app.instance_column()], proof);
         let agg_circuit_with_proof = aggregation::AggregationCircuit::new(&self.params, [snark]);
         let agg_instances = agg_circuit_with_proof.instances();
         let proof = gen_proof::<_, _, EvmTranscript<G1Affine, _, _, _>>(
@@ -307,17 +308,18 @@ impl Halo2Prover {
         log::info!("Verifying aggregated proof in the EVM...");
         evm_verify(deployment_code, agg_instances.clone(), &proof);
 
-        // Our Halo2 integration always has one instance column `publics[0]`
-        // containing the public inputs.
-        let publics: Vec<Bn254Field> = agg_instances[0]
-            .clone()
-            .into_iter()
+        // The aggregation circuit has one instance column `agg_instances[0]`
+        // containing the accumulator, followed by the public inputs of the app.
+        let instances: Vec<Bn254Field> = agg_instances[0]
+            .iter()
             .map(|x| Bn254Field::from_bytes_le(&x.to_repr()))
             .collect();
+        let (accumulator, publics) =
+            instances.split_at(aggregation::AggregationCircuit::accumulator_len());
 
         log::info!("Proof aggregation done.");
 
-        Ok((proof, publics))
+        Ok((proof, accumulator.to_vec(), publics.to_vec()))
     }
 
     pub fn add_verification_key(&mut self, mut vkey: &mut dyn io::Read) {
@@ -381,7 +383,7 @@ impl Halo2Prover {
         let protocol_app = compile(
             &params_app,
             vkey_app,
-            Config::kzg().with_num_instance(vec![0]),
+            Config::kzg().with_num_instance(vec![self.num_publics()]),
         );
         let empty_snark = aggregation::Snark::new_without_witness(protocol_app.clone());
         let agg_circuit =
@@ -468,6 +470,26 @@ impl Halo2Prover {
         self.verify_common::<_, EvmTranscript<G1Affine, _, _, _>>(proof, instances)
     }
 
+    /// Verifies an aggregated proof, whose instance column holds the accumulator
+    /// followed by the public inputs of the app, and decides the accumulator.
+    pub fn verify_snark_aggr(
+        &self,
+        proof: &[u8],
+        accumulator: &[Bn254Field],
+        publics: &[Bn254Field],
+    ) -> Result<(), String> {
+        assert!(matches!(self.proof_type, ProofType::SnarkAggr));
+        aggregation::decide(
+            &self.params,
+            &accumulator
+                .iter()
+                .map(|x| convert_field(*x))
+                .collect::<Vec<_>>(),
+        )?;
+        let instances = [accumulator, publics].concat();
+        self.verify_snark(proof, &[instances])
+    }
+
     pub fn export_ethereum_verifier_snark(&self, output: &mut dyn io::Write) -> Result<(), String> {
         let verifier = match self.proof_type {
             ProofType::SnarkSingle => self.ethereum_verifier_single_snark(),
@@ -485,7 +507,12 @@ impl Halo2Prover {
         assert!(matches!(self.proof_type, ProofType::SnarkSingle));
 
         let vk = self.verification_key()?;
-        let generator = SolidityGenerator::new(&self.params, &vk, BatchOpenScheme::Gwc19, 0);
+        let generator = SolidityGenerator::new(
+            &self.params,
+            &vk,
+            BatchOpenScheme::Gwc19,
+            self.num_publics(),
+        );
         let verifier_solidity = generator.render().map_err(|e| e.to_string())?;
 
         Ok(verifier_solidity)
@@ -499,7 +526,7 @@ impl Halo2Prover {
         let verifier = aggregation::gen_aggregation_solidity_verifier(
             &self.params,
             &vk,
-            aggregation::AggregationCircuit::num_instance(),
+            aggregation::AggregationCircuit::num_instance(self.num_publics()),
             aggregation::AggregationCircuit::accumulator_indices(),
         );
 
@@ -543,3 +570,71 @@ fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: &[u8]) {
     let gas_cost = deploy_and_call(deployment_code, calldata).unwrap();
     log::info!("Gas cost: {gas_cost}");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use powdr_executor::constant_evaluator::get_uniquely_sized_cloned;
+    use powdr_number::Bn254Field;
+    use powdr_pipeline::Pipeline;
+    use test_log::test;
+
+    use super::{Halo2Prover, ProofType};
+
+    const FIBONACCI_WITH_PUBLIC: &str = r#"
+    namespace Fibonacci(8);
+        col fixed ISLAST(i) { if i == 7 { 1 } else { 0 } };
+        col witness x, y;
+        ISLAST * (y' - 1) = 0;
+        ISLAST * (x' - 1) = 0;
+        (1 - ISLAST) * (x' - y) = 0;
+        (1 - ISLAST) * (y' - (x + y)) = 0;
+        public out = y(7);
+    "#;
+
+    /// Proves the PIL with the given proof type and returns the prover, with
+    /// its verification key set, the proof and the public inputs.
+    fn prove(proof_type: ProofType) -> (Halo2Prover, Vec<u8>, Vec<Bn254Field>) {
+        let mut pipeline =
+            Pipeline::<Bn254Field>::default().from_pil_string(FIBONACCI_WITH_PUBLIC.to_string());
+        let pil = pipeline.compute_optimized_pil().unwrap();
+        let witgen_callback = pipeline.witgen_callback().unwrap();
+        let witness = pipeline.compute_witness().unwrap();
+        let fixed = pipeline.compute_fixed_cols().unwrap();
+        let fixed = Arc::new(get_uniquely_sized_cloned(&fixed).unwrap());
+
+        let mut prover = Halo2Prover::new(pil, fixed, None, proof_type.clone()).unwrap();
+        let (proof, publics) = match proof_type {
+            ProofType::Poseidon => prover.prove_poseidon(&witness, witgen_callback),
+            ProofType::SnarkSingle => prover.prove_snark_single(&witness, witgen_callback),
+            ProofType::SnarkAggr => unreachable!(),
+        }
+        .unwrap();
+        prover.vkey = Some(prover.verification_key().unwrap());
+        (prover, proof, publics)
+    }
+
+    fn other_publics(publics: &[Bn254Field]) -> Vec<Bn254Field> {
+        assert_eq!(publics.len(), 1);
+        vec![publics[0] + Bn254Field::from(1u32)]
+    }
+
+    #[test]
+    fn poseidon_with_wrong_publics() {
+        let (prover, proof, publics) = prove(ProofType::Poseidon);
+        prover.verify_poseidon(&proof, &[publics.clone()]).unwrap();
+        assert!(prover
+            .verify_poseidon(&proof, &[other_publics(&publics)])
+            .is_err());
+    }
+
+    #[test]
+    fn snark_single_with_wrong_publics() {
+        let (prover, proof, publics) = prove(ProofType::SnarkSingle);
+        prover.verify_snark(&proof, &[publics.clone()]).unwrap();
+        assert!(prover
+            .verify_snark(&proof, &[other_publics(&publics)])
+            .is_err());
+    }
+}
@@ -12,9 +12,12 @@ mod field_filter;
 mod mock;
 
 use powdr_ast::analyzed::Analyzed;
-use powdr_executor::{constant_evaluator::VariablySizedColumn, witgen::WitgenCallback};
+use powdr_executor::{
+    constant_evaluator::VariablySizedColumn,
+    witgen::{WitgenCallback, WitnessSink},
+};
 use powdr_number::{DegreeType, FieldElement};
-use std::{io, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, io, path::PathBuf, sync::Arc};
 use strum::{Display, EnumString, EnumVariantNames};
 
 #[derive(Clone, EnumString, EnumVariantNames, Display, Copy)]
@@ -71,6 +74,24 @@ pub const DEFAULT_HALO2_MOCK_OPTIONS: &str = "";
 pub const DEFAULT_ESTARK_OPTIONS: &str = "stark_gl";
 
 impl BackendType {
+    /// The maximum degree of the constraints the backend can prove, if it has one.
+    pub fn max_constraint_degree(&self) -> Option<usize> {
+        // Plonky3 splits the quotient polynomial into chunks of the trace size,
+        // as many as its FRI blowup factor of 2, so the quotient can have twice
+        // the trace degree, i.e. the constraints can have degree 3.
+        #[cfg(feature = "plonky3")]
+        if matches!(self, BackendType::Plonky3 | BackendType::Plonky3Composite) {
+            return Some(3);
+        }
+        // Stwo evaluates the constraints on a domain twice as large as the
+        // trace, which bounds their degree to 2.
+        #[cfg(feature = "stwo")]
+        if matches!(self, BackendType::Stwo | BackendType::StwoComposite) {
+            return Some(2);
+        }
+        None
+    }
+
     pub fn factory<T: FieldElement>(&self) -> Box<dyn BackendFactory<T>> {
         match self {
             BackendType::Mock => Box::new(mock::MockBackendFactory::new()),
@@ -143,6 +164,14 @@ pub enum Error {
     BackendError(String),
     #[error("the backend does not support public values which rely on later stage witnesses")]
     NoLaterStagePublicAvailable,
+    #[error("the backend does not support proving checkpoints")]
+    NoCheckpointsAvailable,
+    #[error("the backend does not support proving from a streamed witness")]
+    NoStreamingAvailable,
+    #[error("the backend does not support deriving challenges from a proof")]
+    NoChallengeDerivationAvailable,
+    #[error("the backend does not support verifying with challenges shared by several proofs")]
+    NoSharedChallengesAvailable,
 }
 
 impl From<String> for Error {
@@ -195,11 +224,47 @@ pub trait Backend<F: FieldElement>: Send {
         witgen_callback: WitgenCallback<F>,
     ) -> Result<Proof, Error>;
 
+    /// Like [Backend::prove], but the witness is produced while proving:
+    /// `generate_witness` is called at most once and passes the witness columns
+    /// to the provided sink as soon as they are complete. This allows the backend
+    /// to start proving parts of the witness and to drop them before the rest of
+    /// the witness is generated.
+    fn prove_streaming(
+        &self,
+        _generate_witness: &mut dyn FnMut(&mut dyn WitnessSink<F>),
+        _prev_proof: Option<Proof>,
+        _witgen_callback: WitgenCallback<F>,
+    ) -> Result<Proof, Error> {
+        Err(Error::NoStreamingAvailable)
+    }
+
     /// Verifies a proof.
     fn verify(&self, _proof: &[u8], _instances: &[Vec<F>]) -> Result<(), Error> {
         Err(Error::NoVerificationAvailable)
     }
 
+    /// Like [Backend::verify], but the constraints are evaluated with the given
+    /// challenges for each stage, by their IDs, instead of the ones derived from
+    /// the proof. The caller must check how these challenges were derived.
+    fn verify_with_challenges(
+        &self,
+        _proof: &[u8],
+        _instances: &[Vec<F>],
+        _challenges: &[BTreeMap<u64, F>],
+    ) -> Result<(), Error> {
+        Err(Error::NoSharedChallengesAvailable)
+    }
+
+    /// Returns the challenges drawn after each stage of a proof by their IDs,
+    /// as the verifier derives them from the commitments in the proof.
+    fn stage_challenges(
+        &self,
+        _proof: &[u8],
+        _instances: &[Vec<F>],
+    ) -> Result<Vec<BTreeMap<u64, F>>, Error> {
+        Err(Error::NoChallengeDerivationAvailable)
+    }
+
     /// Exports the setup in a backend specific format. Can be used to create a
     /// new backend object of the same kind.
     fn export_setup(&self, _output: &mut dyn io::Write) -> Result<(), Error> {
@@ -229,4 +294,10 @@ pub trait Backend<F: FieldElement>: Send {
     fn export_ethereum_verifier(&self, _output: &mut dyn io::Write) -> Result<(), Error> {
         Err(Error::NoEthereumVerifierAvailable)
     }
+
+    /// Enables persisting intermediate proving results into the given
+    /// directory, so that a later proof from the same witness can reuse them.
+    fn enable_checkpoints(&mut self, _dir: PathBuf) -> Result<(), Error> {
+        Err(Error::NoCheckpointsAvailable)
+    }
 }
@@ -1,9 +1,11 @@
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::ops::ControlFlow;
 
 use itertools::Itertools;
+use num_traits::Zero;
 use powdr_ast::analyzed::AlgebraicExpression;
 use powdr_ast::analyzed::AlgebraicReference;
 use powdr_ast::analyzed::Analyzed;
@@ -16,7 +18,7 @@ use powdr_ast::parsed::visitor::VisitOrder;
 use powdr_backend_utils::referenced_namespaces_algebraic_expression;
 use powdr_executor::witgen::evaluators::expression_evaluator::ExpressionEvaluator;
 use powdr_executor::witgen::evaluators::expression_evaluator::TraceValues;
-use powdr_number::FieldElement;
+use powdr_number::{batch, FieldElement, Fp2};
 use rayon::iter::IntoParallelIterator;
 use rayon::iter::ParallelIterator;
 
@@ -30,7 +32,7 @@ pub enum ConnectionKind {
 
 /// A connection between two machines.
 pub struct Connection<F> {
-    identity: Identity<F>,
+    pub identity: Identity<F>,
     pub left: SelectedExpressions<F>,
     pub right: SelectedExpressions<F>,
     /// For [ConnectionKind::Permutation], rows of `left` are a permutation of rows of `right`. For [ConnectionKind::Lookup], all rows in `left` are in `right`.
@@ -155,7 +157,7 @@ pub struct ConnectionConstraintChecker<'a, F: FieldElement> {
 }
 
 #[derive(Debug, PartialEq, Eq)]
-enum ConnectionPart {
+pub(super) enum ConnectionPart {
     Caller,
     Callee,
 }
@@ -233,8 +235,84 @@ impl<'a, F: FieldElement> ConnectionConstraintChecker<'a, F> {
         }
     }
 
+    /// Checks that the bus interactions of all machines balance, i.e. that the sum of
+    /// `multiplicity / (beta - fingerprint(tuple))` over all interactions and rows is zero,
+    /// for `alpha` and `beta` drawn from the quadratic extension, where the fingerprint
+    /// is the tuple evaluated as a polynomial at `alpha`.
+    /// The bus accumulators of the machines are not used, since they are computed by
+    /// the witgen callback and are already checked by the polynomial constraints.
+    pub fn check_bus_balance(&self) -> Result<(), String> {
+        assert!(
+            Fp2::<F>::is_irreducible(),
+            "The quadratic extension of the field is not a field"
+        );
+        let [alpha, beta] = ["alpha", "beta"].map(|name| {
+            let [c0, c1] = [0, 1].map(|i| {
+                let mut hasher = DefaultHasher::new();
+                ("bus", name, i).hash(&mut hasher);
+                F::from(hasher.finish())
+            });
+            Fp2::new(c0, c1)
+        });
+
+        let sum = self
+            .machines
+            .values()
+            .map(|machine| self.bus_sum(machine, alpha, beta))
+            .sum::<Fp2<F>>();
+        if sum.is_zero() {
+            Ok(())
+        } else {
+            Err(format!(
+                "The bus interactions do not balance, the sum of their fractions is {sum}"
+            ))
+        }
+    }
+
+    /// Returns the sum of the fractions of all bus interactions of a machine,
+    /// see [Self::check_bus_balance].
+    fn bus_sum(&self, machine: &Machine<'a, F>, alpha: Fp2<F>, beta: Fp2<F>) -> Fp2<F> {
+        let (multiplicities, mut denominators): (Vec<_>, Vec<_>) = machine
+            .pil
+            .identities
+            .iter()
+            .filter_map(|identity| match identity {
+                Identity::PhantomBusInteraction(interaction) => Some(interaction),
+                _ => None,
+            })
+            .flat_map(|interaction| {
+                (0..machine.size).filter_map(move |row| {
+                    let mut evaluator = ExpressionEvaluator::new(
+                        machine.trace_values.row(row),
+                        &machine.intermediate_definitions,
+                        self.challenges,
+                    );
+                    let multiplicity = evaluator.evaluate(&interaction.multiplicity);
+                    (!multiplicity.is_zero()).then(|| {
+                        let tuple = interaction
+                            .tuple
+                            .0
+                            .iter()
+                            .map(|e| Fp2::from(evaluator.evaluate(e)))
+                            .collect::<Vec<_>>();
+                        (
+                            multiplicity,
+                            beta - batch::evaluate_polynomial(&tuple, alpha),
+                        )
+                    })
+                })
+            })
+            .unzip();
+        batch::inverse_in_place(&mut denominators);
+        denominators
+            .into_iter()
+            .zip(multiplicities)
+            .map(|(inverse, multiplicity)| inverse * multiplicity)
+            .sum()
+    }
+
     /// Returns the set of all selected tuples for a given machine.
-    fn selected_tuples(
+    pub(super) fn selected_tuples(
         &self,
         connection: &Connection<F>,
         connection_part: ConnectionPart,
@@ -344,10 +422,10 @@ fn to_multi_set<T: Ord>(a: &[T]) -> BTreeMap<&T, usize> {
 /// A tuple of field elements.
 pub struct Tuple<F> {
     /// The values of the tuple.
-    values: Vec<F>,
+    pub(super) values: Vec<F>,
     /// The row in the machine where this tuple is located.
     /// Note that this value is only informational and is *not* used for equality or ordering.
-    row: usize,
+    pub(super) row: usize,
 }
 
 impl<F: PartialEq> PartialEq for Tuple<F> {
@@ -384,14 +462,14 @@ impl<F: fmt::Display> fmt::Display for Tuple<F> {
 
 pub struct FailingConnectionConstraint<'a, F> {
     /// The connection that failed.
-    connection: &'a Connection<F>,
+    pub(super) connection: &'a Connection<F>,
 
     /// Tuples that are in the callee, but not in the caller.
     /// For [ConnectionKind::Lookup], this is irrelevant and we'll store an empty vector here.
-    not_in_caller: Vec<Tuple<F>>,
+    pub(super) not_in_caller: Vec<Tuple<F>>,
 
     /// Tuples that are in the caller, but not in the callee.
-    not_in_callee: Vec<Tuple<F>>,
+    pub(super) not_in_callee: Vec<Tuple<F>>,
 }
 
 const MAX_TUPLES: usize = 5;
@@ -438,7 +516,7 @@ const MAX_ERRORS: usize = 5;
 
 pub struct FailingConnectionConstraints<'a, F> {
     connection_count: usize,
-    errors: Vec<FailingConnectionConstraint<'a, F>>,
+    pub(super) errors: Vec<FailingConnectionConstraint<'a, F>>,
 }
 
 impl<F: FieldElement> fmt::Display for FailingConnectionConstraints<'_, F> {
@@ -1,5 +1,6 @@
 use std::{
     collections::BTreeMap,
+    fs,
     hash::{DefaultHasher, Hash, Hasher},
     io,
     marker::PhantomData,
@@ -17,12 +18,17 @@ use powdr_ast::{
 use powdr_executor::{constant_evaluator::VariablySizedColumn, witgen::WitgenCallback};
 use powdr_number::{DegreeType, FieldElement};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use report::{ConstraintReport, ReportFormat};
 
 use crate::{Backend, BackendFactory, BackendOptions, Error, Proof};
 
 mod connection_constraint_checker;
 mod machine;
 mod polynomial_constraint_checker;
+mod report;
+
+/// Name of the JSON report file, relative to the output directory.
+const REPORT_FILE: &str = "constraint_report.json";
 
 pub(crate) struct MockBackendFactory<F: FieldElement> {
     _marker: PhantomData<F>,
@@ -41,12 +47,12 @@ impl<F: FieldElement> BackendFactory<F> for MockBackendFactory<F> {
         &self,
         pil: Arc<Analyzed<F>>,
         fixed: Arc<Vec<(String, VariablySizedColumn<F>)>>,
-        _output_dir: Option<PathBuf>,
+        output_dir: Option<PathBuf>,
         _setup: Option<&mut dyn std::io::Read>,
         proving_key: Option<&mut dyn std::io::Read>,
         _verification_key: Option<&mut dyn std::io::Read>,
         verification_app_key: Option<&mut dyn std::io::Read>,
-        _backend_options: BackendOptions,
+        backend_options: BackendOptions,
     ) -> Result<Box<dyn Backend<F>>, Error> {
         if proving_key.is_some() {
             unimplemented!();
@@ -54,6 +60,7 @@ impl<F: FieldElement> BackendFactory<F> for MockBackendFactory<F> {
         if verification_app_key.is_some() {
             unimplemented!();
         }
+        let report_format = ReportFormat::from_options(&backend_options);
         let machine_to_pil = powdr_backend_utils::split_pil(&pil);
         let connections = Connection::get_all(&pil, &machine_to_pil);
 
@@ -61,6 +68,8 @@ impl<F: FieldElement> BackendFactory<F> for MockBackendFactory<F> {
             machine_to_pil,
             fixed,
             connections,
+            output_dir,
+            report_format,
         }))
     }
 
@@ -73,6 +82,33 @@ pub(crate) struct MockBackend<F> {
     machine_to_pil: BTreeMap<String, Analyzed<F>>,
     fixed: Arc<Vec<(String, VariablySizedColumn<F>)>>,
     connections: Vec<Connection<F>>,
+    output_dir: Option<PathBuf>,
+    /// If set, a detailed report of the failing constraints is emitted.
+    report_format: Option<ReportFormat>,
+}
+
+impl<F: FieldElement> MockBackend<F> {
+    /// Logs the text report, or writes the JSON report into the output
+    /// directory (or logs it if there is no output directory).
+    fn emit_report(&self, report: &ConstraintReport, format: ReportFormat) -> Result<(), Error> {
+        match format {
+            ReportFormat::Text => {
+                log::error!("Constraint report:\n{report}");
+            }
+            ReportFormat::Json => {
+                let json = serde_json::to_string_pretty(report).unwrap();
+                match &self.output_dir {
+                    Some(output_dir) => {
+                        let path = output_dir.join(REPORT_FILE);
+                        fs::write(&path, json)?;
+                        log::error!("Wrote constraint report to {}", path.display());
+                    }
+                    None => log::error!("Constraint report:\n{json}"),
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<F: FieldElement> Backend<F> for MockBackend<F> {
@@ -128,17 +164,37 @@ impl<F: FieldElement> Backend<F> for MockBackend<F> {
             );
         }
 
-        let is_ok = machines.values().all(|machine| {
-            !PolynomialConstraintChecker::new(machine, &challenges)
-                .check()
-                .has_errors()
-        }) && ConnectionConstraintChecker {
+        let mut report = ConstraintReport::default();
+        let mut is_ok = true;
+        for machine in machines.values() {
+            let result = PolynomialConstraintChecker::new(machine, &challenges).check();
+            if result.has_errors() {
+                is_ok = false;
+                if self.report_format.is_some() {
+                    report.add_polynomial_failures(machine, &result, &challenges);
+                }
+            }
+        }
+
+        let connection_checker = ConnectionConstraintChecker {
             connections: &self.connections,
             machines,
             challenges: &challenges,
+        };
+        if let Err(failures) = connection_checker.check() {
+            is_ok = false;
+            if self.report_format.is_some() {
+                report.add_connection_failures(&connection_checker, &failures);
+            }
+        }
+        if let Err(e) = connection_checker.check_bus_balance() {
+            log::error!("{e}");
+            is_ok = false;
+        }
+
+        if let (false, Some(format)) = (is_ok, self.report_format) {
+            self.emit_report(&report, format)?;
         }
-        .check()
-        .is_ok();
 
         match is_ok {
             true => Ok(Vec::new()),
@@ -1,7 +1,11 @@
 use std::{collections::BTreeMap, fmt};
 
 use powdr_ast::{
-    analyzed::{AlgebraicExpression, Identity, PolynomialIdentity},
+    analyzed::{
+        AlgebraicBinaryOperation, AlgebraicBinaryOperator, AlgebraicExpression,
+        AlgebraicReferenceThin, AlgebraicUnaryOperation, AlgebraicUnaryOperator, Identity,
+        PolynomialIdentity, PolynomialType,
+    },
     parsed::visitor::AllChildren,
 };
 use powdr_executor::witgen::evaluators::expression_evaluator::ExpressionEvaluator;
@@ -32,13 +36,18 @@ impl<'a, F: FieldElement> PolynomialConstraintChecker<'a, F> {
             .pil
             .identities
             .iter()
-            .filter(|identity| matches!(identity, Identity::Polynomial(_)))
+            .filter_map(|identity| match identity {
+                Identity::Polynomial(polynomial_identity) => Some(polynomial_identity),
+                _ => None,
+            })
             .collect::<Vec<_>>();
 
-        let errors = (0..self.machine.size)
+        let mut errors = polynomial_identities
             .into_par_iter()
-            .flat_map(|row| self.check_row(row, &polynomial_identities))
-            .collect();
+            .flat_map(|identity| self.check_identity(identity))
+            .collect::<Vec<_>>();
+        // The sort is stable, so the errors of a row stay in the order of the identities.
+        errors.sort_by_key(|error| error.row);
 
         let result = MachineResult {
             machine_name: self.machine.machine_name.clone(),
@@ -48,52 +57,127 @@ impl<'a, F: FieldElement> PolynomialConstraintChecker<'a, F> {
         result
     }
 
-    fn check_row(
+    /// Evaluates the identity on all rows at once and collects the rows on
+    /// which it fails.
+    fn check_identity(
         &self,
-        row: usize,
-        identities: &[&'a Identity<F>],
+        identity: &'a PolynomialIdentity<F>,
     ) -> Vec<FailingPolynomialConstraint<'a, F>> {
-        let mut evaluator = ExpressionEvaluator::new(
-            self.machine.trace_values.row(row),
-            &self.machine.intermediate_definitions,
-            self.challenges,
-        );
-        identities
+        let values =
+            ColumnEvaluator::new(self.machine, self.challenges).evaluate(&identity.expression);
+        values
             .iter()
-            .filter_map(|identity| {
-                let identity = match identity {
-                    Identity::Polynomial(polynomial_identity) => polynomial_identity,
-                    _ => unreachable!("Unexpected identity: {}", identity),
-                };
-                let result = evaluator.evaluate(&identity.expression);
-
-                if result != F::zero() {
-                    let used_variables = identity.all_children().filter(|expr| match expr {
-                        AlgebraicExpression::Reference(_)
-                        | AlgebraicExpression::PublicReference(_)
-                        | AlgebraicExpression::Challenge(_) => true,
-                        AlgebraicExpression::Number(_)
-                        | AlgebraicExpression::BinaryOperation(_)
-                        | AlgebraicExpression::UnaryOperation(_) => false,
-                    });
-                    Some(FailingPolynomialConstraint {
-                        row,
-                        identity,
-                        assignments: used_variables
-                            .map(|variable| (variable, evaluator.evaluate(variable)))
-                            .collect(),
-                    })
-                } else {
-                    None
+            .enumerate()
+            .filter(|(_, value)| **value != F::zero())
+            .map(|(row, _)| {
+                let mut evaluator = ExpressionEvaluator::new(
+                    self.machine.trace_values.row(row),
+                    &self.machine.intermediate_definitions,
+                    self.challenges,
+                );
+                let used_variables = identity.all_children().filter(|expr| match expr {
+                    AlgebraicExpression::Reference(_)
+                    | AlgebraicExpression::PublicReference(_)
+                    | AlgebraicExpression::Challenge(_) => true,
+                    AlgebraicExpression::Number(_)
+                    | AlgebraicExpression::BinaryOperation(_)
+                    | AlgebraicExpression::UnaryOperation(_) => false,
+                });
+                FailingPolynomialConstraint {
+                    row,
+                    identity,
+                    assignments: used_variables
+                        .map(|variable| (variable, evaluator.evaluate(variable)))
+                        .collect(),
                 }
             })
             .collect()
     }
 }
 
-struct FailingPolynomialConstraint<'a, F> {
-    row: usize,
-    identity: &'a PolynomialIdentity<F>,
+/// Evaluates an algebraic expression on all rows of a machine at once, using
+/// the slice arithmetic of the field.
+struct ColumnEvaluator<'a, F> {
+    machine: &'a Machine<'a, F>,
+    challenges: &'a BTreeMap<u64, F>,
+    /// The values of the intermediate columns evaluated so far.
+    intermediates_cache: BTreeMap<AlgebraicReferenceThin, Vec<F>>,
+}
+
+impl<'a, F: FieldElement> ColumnEvaluator<'a, F> {
+    fn new(machine: &'a Machine<'a, F>, challenges: &'a BTreeMap<u64, F>) -> Self {
+        Self {
+            machine,
+            challenges,
+            intermediates_cache: Default::default(),
+        }
+    }
+
+    fn evaluate(&mut self, expr: &'a AlgebraicExpression<F>) -> Vec<F> {
+        match expr {
+            AlgebraicExpression::Reference(reference) => match reference.poly_id.ptype {
+                PolynomialType::Committed | PolynomialType::Constant => {
+                    let column = &self.machine.trace_values.values[&reference.poly_id];
+                    let mut values = column.clone();
+                    if reference.next {
+                        values.rotate_left(1);
+                    }
+                    values
+                }
+                PolynomialType::Intermediate => {
+                    let reference = reference.to_thin();
+                    if let Some(values) = self.intermediates_cache.get(&reference) {
+                        return values.clone();
+                    }
+                    let definition = &self.machine.intermediate_definitions[&reference];
+                    let values = self.evaluate(definition);
+                    self.intermediates_cache.insert(reference, values.clone());
+                    values
+                }
+            },
+            AlgebraicExpression::PublicReference(_) => unimplemented!(),
+            AlgebraicExpression::Number(n) => vec![*n; self.machine.size],
+            AlgebraicExpression::Challenge(challenge) => {
+                vec![self.challenges[&challenge.id]; self.machine.size]
+            }
+            AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation { left, op, right }) => {
+                let mut left = self.evaluate(left);
+                let mut right = self.evaluate(right);
+                match op {
+                    AlgebraicBinaryOperator::Add => F::add_assign_slice(&mut left, &right),
+                    AlgebraicBinaryOperator::Sub => {
+                        negate(&mut right);
+                        F::add_assign_slice(&mut left, &right);
+                    }
+                    AlgebraicBinaryOperator::Mul => F::mul_assign_slice(&mut left, &right),
+                    AlgebraicBinaryOperator::Pow => {
+                        for (left, right) in left.iter_mut().zip(right) {
+                            *left = left.pow(right.to_integer());
+                        }
+                    }
+                }
+                left
+            }
+            AlgebraicExpression::UnaryOperation(AlgebraicUnaryOperation { op, expr }) => {
+                let mut values = self.evaluate(expr);
+                match op {
+                    AlgebraicUnaryOperator::Minus => negate(&mut values),
+                }
+                values
+            }
+        }
+    }
+}
+
+fn negate<F: FieldElement>(values: &mut [F]) {
+    for value in values {
+        *value = -*value;
+    }
+}
+
+pub(super) struct FailingPolynomialConstraint<'a, F> {
+    pub(super) row: usize,
+    pub(super) identity: &'a PolynomialIdentity<F>,
     assignments: BTreeMap<&'a AlgebraicExpression<F>, F>,
 }
 
@@ -108,8 +192,8 @@ impl<F: fmt::Display> fmt::Display for FailingPolynomialConstraint<'_, F> {
 }
 
 pub struct MachineResult<'a, F> {
-    machine_name: String,
-    errors: Vec<FailingPolynomialConstraint<'a, F>>,
+    pub(super) machine_name: String,
+    pub(super) errors: Vec<FailingPolynomialConstraint<'a, F>>,
 }
 
 const MAX_ERRORS: usize = 5;
@@ -0,0 +1,346 @@
+//! Detailed reports of the constraints rejected by the mock backend.
+//!
+//! For each failing identity, the report contains the failing rows, the values
+//! of the referenced columns on that row and the next, and the source location
+//! of the identity. For failing connections, it also lists the tuples of the
+//! other side that are closest to the missing tuples.
+
+use std::{collections::BTreeMap, fmt};
+
+use itertools::Itertools;
+use powdr_ast::{
+    analyzed::{AlgebraicExpression, AlgebraicReference},
+    parsed::{visitor::AllChildren, SourceReference},
+};
+use powdr_executor::witgen::evaluators::expression_evaluator::ExpressionEvaluator;
+use powdr_number::FieldElement;
+use powdr_parser_util::SourceRef;
+use serde::Serialize;
+
+use super::{
+    connection_constraint_checker::{
+        ConnectionConstraintChecker, ConnectionKind, ConnectionPart, FailingConnectionConstraints,
+        Tuple,
+    },
+    machine::Machine,
+    polynomial_constraint_checker::MachineResult,
+};
+
+/// The maximum number of failing rows / missing tuples reported per identity.
+const MAX_REPORTED_FAILURES: usize = 10;
+/// The maximum number of nearest tuples reported per missing tuple.
+const MAX_NEAREST_TUPLES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl ReportFormat {
+    /// Parses the mock backend options, a comma-separated list: `report` or
+    /// `report=text` enable a human-readable report, `report=json` a JSON
+    /// report. Other options, e.g. those of the backend the mock backend
+    /// stands in for, are ignored.
+    pub fn from_options(options: &str) -> Option<Self> {
+        options
+            .split(',')
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .fold(None, |format, option| match option {
+                "report" | "report=text" => Some(ReportFormat::Text),
+                "report=json" => Some(ReportFormat::Json),
+                _ => {
+                    log::warn!("Ignoring unsupported mock backend option: {option}");
+                    format
+                }
+            })
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct ConstraintReport {
+    pub polynomial_failures: Vec<PolynomialFailure>,
+    pub connection_failures: Vec<ConnectionFailure>,
+}
+
+#[derive(Serialize)]
+pub struct PolynomialFailure {
+    pub machine: String,
+    pub identity: String,
+    pub source: Option<String>,
+    pub row: usize,
+    pub columns: Vec<ColumnValues>,
+}
+
+#[derive(Serialize)]
+pub struct ConnectionFailure {
+    pub kind: &'static str,
+    pub caller: Option<String>,
+    pub callee: Option<String>,
+    pub identity: String,
+    pub source: Option<String>,
+    pub missing_tuples: Vec<MissingTuple>,
+}
+
+/// A tuple that appears on one side of a connection, but not on the other.
+#[derive(Serialize)]
+pub struct MissingTuple {
+    /// The machine the tuple appears in.
+    pub machine: Option<String>,
+    /// The machine the tuple is missing from.
+    pub missing_from: Option<String>,
+    pub row: usize,
+    pub values: Vec<String>,
+    pub columns: Vec<ColumnValues>,
+    /// The tuples of the other machine with the fewest mismatching values.
+    pub nearest: Vec<NearestTuple>,
+}
+
+#[derive(Serialize)]
+pub struct NearestTuple {
+    pub row: usize,
+    pub values: Vec<String>,
+    pub mismatches: usize,
+}
+
+/// The values of a column on the failing row and the next.
+#[derive(Serialize)]
+pub struct ColumnValues {
+    pub column: String,
+    pub row: String,
+    pub next_row: String,
+}
+
+impl ConstraintReport {
+    pub fn add_polynomial_failures<F: FieldElement>(
+        &mut self,
+        machine: &Machine<F>,
+        result: &MachineResult<F>,
+        challenges: &BTreeMap<u64, F>,
+    ) {
+        let mut reported_per_identity = BTreeMap::<u64, usize>::new();
+        for error in &result.errors {
+            let reported = reported_per_identity.entry(error.identity.id).or_default();
+            if *reported == MAX_REPORTED_FAILURES {
+                continue;
+            }
+            *reported += 1;
+
+            self.polynomial_failures.push(PolynomialFailure {
+                machine: result.machine_name.clone(),
+                identity: error.identity.to_string(),
+                source: source_location(&error.identity.source),
+                row: error.row,
+                columns: column_values(
+                    machine,
+                    challenges,
+                    error.identity.expression.all_children(),
+                    error.row,
+                ),
+            });
+        }
+    }
+
+    pub fn add_connection_failures<F: FieldElement>(
+        &mut self,
+        checker: &ConnectionConstraintChecker<F>,
+        failures: &FailingConnectionConstraints<F>,
+    ) {
+        for failure in &failures.errors {
+            let connection = failure.connection;
+            let (caller, callee) = (connection.caller(), connection.callee());
+
+            let missing_from = |present: ConnectionPart, tuples: &[Tuple<F>]| {
+                let (machine, other_machine, expressions, other) = match present {
+                    ConnectionPart::Caller => {
+                        (&caller, &callee, &connection.left, ConnectionPart::Callee)
+                    }
+                    ConnectionPart::Callee => {
+                        (&callee, &caller, &connection.right, ConnectionPart::Caller)
+                    }
+                };
+                let other_tuples = checker.selected_tuples(connection, other);
+                let machine_data = machine.as_ref().and_then(|m| checker.machines.get(m));
+                tuples
+                    .iter()
+                    .take(MAX_REPORTED_FAILURES)
+                    .map(|tuple| MissingTuple {
+                        machine: machine.clone(),
+                        missing_from: other_machine.clone(),
+                        row: tuple.row,
+                        values: tuple.values.iter().map(|v| v.to_string()).collect(),
+                        columns: machine_data
+                            .map(|m| {
+                                column_values(
+                                    m,
+                                    checker.challenges,
+                                    expressions.all_children(),
+                                    tuple.row,
+                                )
+                            })
+                            .unwrap_or_default(),
+                        nearest: nearest_tuples(tuple, &other_tuples),
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let missing_tuples = missing_from(ConnectionPart::Caller, &failure.not_in_callee)
+                .into_iter()
+                .chain(missing_from(ConnectionPart::Callee, &failure.not_in_caller))
+                .collect();
+
+            self.connection_failures.push(ConnectionFailure {
+                kind: match connection.kind {
+                    ConnectionKind::Lookup => "lookup",
+                    ConnectionKind::Permutation => "permutation",
+                },
+                caller,
+                callee,
+                identity: connection.identity.to_string(),
+                source: source_location(connection.identity.source_reference()),
+                missing_tuples,
+            });
+        }
+    }
+}
+
+/// Returns the values of all columns referenced by the given expressions, on
+/// the given row and the next.
+fn column_values<'a, F: FieldElement>(
+    machine: &Machine<F>,
+    challenges: &BTreeMap<u64, F>,
+    expressions: impl Iterator<Item = &'a AlgebraicExpression<F>>,
+    row: usize,
+) -> Vec<ColumnValues> {
+    let columns = expressions
+        .filter_map(|expr| match expr {
+            AlgebraicExpression::Reference(reference) => Some((
+                reference.poly_id,
+                AlgebraicExpression::Reference(AlgebraicReference {
+                    next: false,
+                    ..reference.clone()
+                }),
+            )),
+            _ => None,
+        })
+        .collect::<BTreeMap<_, _>>();
+    columns
+        .values()
+        .map(|column| ColumnValues {
+            column: column.to_string(),
+            row: evaluate_on_row(machine, challenges, column, row).to_string(),
+            next_row: evaluate_on_row(machine, challenges, column, row + 1).to_string(),
+        })
+        .collect()
+}
+
+fn evaluate_on_row<F: FieldElement>(
+    machine: &Machine<F>,
+    challenges: &BTreeMap<u64, F>,
+    expression: &AlgebraicExpression<F>,
+    row: usize,
+) -> F {
+    ExpressionEvaluator::new(
+        machine.trace_values.row(row % machine.size),
+        &machine.intermediate_definitions,
+        challenges,
+    )
+    .evaluate(expression)
+}
+
+/// Returns the distinct tuples with the fewest values differing from `tuple`.
+fn nearest_tuples<F: FieldElement>(tuple: &Tuple<F>, candidates: &[Tuple<F>]) -> Vec<NearestTuple> {
+    candidates
+        .iter()
+        .map(|candidate| {
+            let mismatches = tuple
+                .values
+                .iter()
+                .zip_eq(&candidate.values)
+                .filter(|(a, b)| a != b)
+                .count();
+            (mismatches, candidate)
+        })
+        .sorted_by_key(|(mismatches, candidate)| (*mismatches, candidate.row))
+        .unique_by(|(_, candidate)| &candidate.values)
+        .take(MAX_NEAREST_TUPLES)
+        .map(|(mismatches, candidate)| NearestTuple {
+            row: candidate.row,
+            values: candidate.values.iter().map(|v| v.to_string()).collect(),
+            mismatches,
+        })
+        .collect()
+}
+
+/// Formats the location of a source reference as `file:line:column`, if the
+/// source is known.
+fn source_location(source: &SourceRef) -> Option<String> {
+    let prefix = source.file_contents.as_ref()?.get(..source.start)?;
+    let line = prefix.matches('\n').count() + 1;
+    let column = prefix.len() - prefix.rfind('\n').map_or(0, |i| i + 1) + 1;
+    let file_name = source.file_name.as_deref().unwrap_or("<input>");
+    Some(format!("{file_name}:{line}:{column}"))
+}
+
+fn fmt_columns(f: &mut fmt::Formatter<'_>, columns: &[ColumnValues]) -> fmt::Result {
+    for column in columns {
+        writeln!(
+            f,
+            "      {} = {} (next row: {})",
+            column.column, column.row, column.next_row
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ConstraintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.polynomial_failures {
+            writeln!(
+                f,
+                "Identity fails in machine {} on row {}:",
+                failure.machine, failure.row
+            )?;
+            writeln!(f, "    {}", failure.identity)?;
+            if let Some(source) = &failure.source {
+                writeln!(f, "    at {source}")?;
+            }
+            fmt_columns(f, &failure.columns)?;
+        }
+        for failure in &self.connection_failures {
+            writeln!(
+                f,
+                "The {} between {} and {} fails:",
+                failure.kind,
+                failure.caller.as_deref().unwrap_or("???"),
+                failure.callee.as_deref().unwrap_or("???")
+            )?;
+            writeln!(f, "    {}", failure.identity)?;
+            if let Some(source) = &failure.source {
+                writeln!(f, "    at {source}")?;
+            }
+            for tuple in &failure.missing_tuples {
+                writeln!(
+                    f,
+                    "  Tuple ({}) on row {} of {} is not in {}:",
+                    tuple.values.join(", "),
+                    tuple.row,
+                    tuple.machine.as_deref().unwrap_or("???"),
+                    tuple.missing_from.as_deref().unwrap_or("???")
+                )?;
+                fmt_columns(f, &tuple.columns)?;
+                for nearest in &tuple.nearest {
+                    writeln!(
+                        f,
+                        "      nearest: ({}) on row {}, {} mismatching value(s)",
+                        nearest.values.join(", "),
+                        nearest.row,
+                        nearest.mismatches
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 mod stark;
 
-use std::{io, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, io, path::PathBuf, sync::Arc};
 
 use powdr_ast::analyzed::Analyzed;
 use powdr_executor::{constant_evaluator::VariablySizedColumn, witgen::WitgenCallback};
@@ -38,7 +38,7 @@ where
             return Err(Error::NoAggregationAvailable);
         }
 
-        let mut p3 = Box::new(Plonky3Prover::new(pil.clone(), fixed));
+        let mut p3 = Box::new(Plonky3Prover::new(pil.clone(), fixed)?);
 
         match (proving_key, verification_key) {
             (Some(pk), Some(vk)) => {
@@ -68,6 +68,29 @@ where
         Ok(self.verify(proof, instances)?)
     }
 
+    fn verify_with_challenges(
+        &self,
+        proof: &[u8],
+        instances: &[Vec<T>],
+        challenges: &[BTreeMap<u64, T>],
+    ) -> Result<(), Error> {
+        assert_eq!(instances.len(), 1);
+        let instances = &instances[0];
+
+        Ok(self.verify_with_challenges(proof, instances, challenges)?)
+    }
+
+    fn stage_challenges(
+        &self,
+        proof: &[u8],
+        instances: &[Vec<T>],
+    ) -> Result<Vec<BTreeMap<u64, T>>, Error> {
+        assert_eq!(instances.len(), 1);
+        let instances = &instances[0];
+
+        Ok(self.stage_challenges(proof, instances)?)
+    }
+
     fn prove(
         &self,
         witness: &[(String, Vec<T>)],
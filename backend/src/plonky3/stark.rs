@@ -16,8 +16,9 @@ use powdr_ast::analyzed::Analyzed;
 use powdr_executor::witgen::WitgenCallback;
 
 use powdr_plonky3::{
-    prove, verify, Challenger, Commitment, ConstraintSystem, FieldElementMap, PowdrCircuit, Proof,
-    ProverData, StarkProvingKey, StarkVerifyingKey, TableProvingKey, TableProvingKeyCollection,
+    prove, stage_challenges, verify, verify_with_challenges, Challenger, Commitment,
+    ConstraintSystem, FieldElementMap, PowdrCircuit, Proof, ProverData, StarkProvingKey,
+    StarkVerifyingKey, TableProvingKey, TableProvingKeyCollection,
 };
 
 use p3_uni_stark::StarkGenericConfig;
@@ -58,23 +59,37 @@ where
     ProverData<T>: Send + Serialize + for<'a> Deserialize<'a>,
     Commitment<T>: Send,
 {
+    /// Fails if the lookups and permutations of a machine cannot be proven.
     pub fn new(
         analyzed: Arc<Analyzed<T>>,
         fixed: Arc<Vec<(String, VariablySizedColumn<T>)>>,
-    ) -> Self {
-        Self {
-            split: powdr_backend_utils::split_pil(&analyzed)
-                .into_iter()
-                .map(|(name, pil)| {
-                    let constraint_system = ConstraintSystem::from(&pil);
-                    (name, (pil, constraint_system))
-                })
-                .collect(),
+    ) -> Result<Self, String> {
+        let mut split = powdr_backend_utils::split_pil(&analyzed)
+            .into_iter()
+            .map(|(name, pil)| {
+                let constraint_system = ConstraintSystem::try_from(&pil)?;
+                Ok((name, (pil, constraint_system)))
+            })
+            .collect::<Result<BTreeMap<_, _>, String>>()?;
+
+        // lookups and permutations add a stage to the tables which contain them,
+        // but all tables are proven with the same number of stages
+        let stage_count = split
+            .values()
+            .map(|(_, constraint_system)| constraint_system.stage_count())
+            .max()
+            .unwrap_or_default();
+        for (_, constraint_system) in split.values_mut() {
+            constraint_system.set_stage_count(stage_count);
+        }
+
+        Ok(Self {
+            split,
             analyzed,
             fixed,
             proving_key: None,
             verifying_key: None,
-        }
+        })
     }
 
     pub fn set_proving_key(&mut self, rdr: &mut dyn std::io::Read) {
@@ -225,18 +240,20 @@ where
             })
             .collect::<BTreeMap<_, _>>();
 
-        let circuit = PowdrCircuit::new(&self.split).with_witgen_callback(witgen_callback);
+        let circuit = PowdrCircuit::new(&self.split)
+            .with_witgen_callback(witgen_callback)
+            .with_fixed(&self.fixed);
 
         let mut challenger = T::get_challenger();
 
         let proving_key = self.proving_key.as_ref();
 
-        let proof = prove(
+        let (proof, challenges) = prove(
             proving_key,
             &circuit,
             &mut witness_by_machine,
             &mut challenger,
-        );
+        )?;
 
         let mut challenger = T::get_challenger();
 
@@ -264,7 +281,8 @@ where
             })
             .collect();
 
-        verify(
+        // the challenges might have been shared with other proofs
+        verify_with_challenges(
             verifying_key,
             &circuit
                 .split
@@ -274,8 +292,9 @@ where
             &mut challenger,
             &proof,
             public_values,
+            &challenges,
         )
-        .unwrap();
+        .map_err(|e| format!("Generated proof does not verify: {e:?}"))?;
         Ok(bincode::serialize(&proof).unwrap())
     }
 
@@ -288,7 +307,82 @@ where
 
         let verifying_key = self.verifying_key.as_ref();
 
-        let stage_count = self.analyzed.stage_count();
+        verify(
+            verifying_key,
+            &self
+                .split
+                .iter()
+                .map(|(name, (_, constraints))| (name, constraints))
+                .collect(),
+            &mut challenger,
+            &proof,
+            self.instance_map(instances),
+        )
+        .map_err(|e| format!("Failed to verify proof: {e:?}"))
+    }
+
+    /// Like [Self::verify], but the constraints are evaluated with the given challenges
+    /// for each stage, by challenge ID.
+    pub fn verify_with_challenges(
+        &self,
+        proof: &[u8],
+        instances: &[T],
+        challenges: &[BTreeMap<u64, T>],
+    ) -> Result<(), String> {
+        let proof: Proof<_> =
+            bincode::deserialize(proof).map_err(|e| format!("Failed to deserialize proof: {e}"))?;
+
+        let mut challenger = T::get_challenger();
+
+        verify_with_challenges(
+            self.verifying_key.as_ref(),
+            &self
+                .split
+                .iter()
+                .map(|(name, (_, constraints))| (name, constraints))
+                .collect(),
+            &mut challenger,
+            &proof,
+            self.instance_map(instances),
+            challenges,
+        )
+        .map_err(|e| format!("Failed to verify proof: {e:?}"))
+    }
+
+    /// Derives the challenges drawn after each stage of the proof, by challenge ID.
+    pub fn stage_challenges(
+        &self,
+        proof: &[u8],
+        instances: &[T],
+    ) -> Result<Vec<BTreeMap<u64, T>>, String> {
+        let proof: Proof<_> =
+            bincode::deserialize(proof).map_err(|e| format!("Failed to deserialize proof: {e}"))?;
+
+        let mut challenger = T::get_challenger();
+
+        stage_challenges(
+            self.verifying_key.as_ref(),
+            &self
+                .split
+                .iter()
+                .map(|(name, (_, constraints))| (name, constraints))
+                .collect(),
+            &mut challenger,
+            &proof,
+            self.instance_map(instances),
+        )
+        .map_err(|e| format!("Failed to derive challenges: {e:?}"))
+    }
+
+    /// Assigns the instances, given in the source order of the public declarations,
+    /// to their tables and stages.
+    fn instance_map(&self, instances: &[T]) -> BTreeMap<String, Vec<Vec<T>>> {
+        let stage_count = self
+            .split
+            .values()
+            .map(|(_, constraint_system)| constraint_system.stage_count())
+            .max()
+            .unwrap_or_default();
 
         let mut instance_map: BTreeMap<String, Vec<Vec<T>>> = self
             .split
@@ -296,30 +390,26 @@ where
             .map(|name| (name.clone(), vec![vec![]; stage_count]))
             .collect();
 
+        let instances: BTreeMap<_, _> = self
+            .analyzed
+            .public_declarations_in_source_order()
+            .map(|(name, _)| name)
+            .zip_eq(instances)
+            .collect();
+
+        // within a stage, the publics of a table are ordered by name
         self.analyzed
             .get_publics()
             .iter()
-            .zip_eq(instances.iter())
-            .map(|((_, poly_name, _, _, stage), value)| {
+            .map(|(name, poly_name, _, _, stage)| {
                 let namespace = poly_name.split("::").next().unwrap();
-                (namespace, stage, value)
+                (namespace, stage, instances[name])
             })
             .for_each(|(namespace, stage, value)| {
                 instance_map.get_mut(namespace).unwrap()[*stage as usize].push(*value);
             });
 
-        verify(
-            verifying_key,
-            &self
-                .split
-                .iter()
-                .map(|(name, (_, constraints))| (name, constraints))
-                .collect(),
-            &mut challenger,
-            &proof,
-            instance_map,
-        )
-        .map_err(|e| format!("Failed to verify proof: {e:?}"))
+        instance_map
     }
 }
 
@@ -356,7 +446,7 @@ mod tests {
         let witness = &mut pipeline.compute_witness().unwrap();
         let fixed = pipeline.compute_fixed_cols().unwrap();
 
-        let mut prover = Plonky3Prover::new(pil, fixed);
+        let mut prover = Plonky3Prover::new(pil, fixed).unwrap();
         prover.setup();
         let proof = prover.prove(witness, witness_callback);
 
@@ -526,9 +616,102 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "not implemented"]
     fn lookup() {
         let content = "namespace Global(8); pol fixed z = [0, 1]*; pol witness a; [a] in [z];";
         run_test(content);
     }
+
+    #[test]
+    fn lookup_with_selector() {
+        let content = r#"
+        namespace Global(8);
+            col fixed SEL = [1, 1, 1, 1, 0, 0, 0, 0];
+            col fixed VALUE(i) { i };
+            col witness a;
+            a = VALUE * 2;
+            SEL $ [a] in [VALUE];
+        "#;
+        run_test(content);
+    }
+
+    #[test]
+    fn permutation() {
+        let content = r#"
+        namespace Global(8);
+            col fixed z = [1, 2, 3, 4, 5, 6, 7, 8];
+            col fixed z_rev = [8, 7, 6, 5, 4, 3, 2, 1];
+            col witness a;
+            a = z_rev;
+            [a] is [z];
+        "#;
+        run_test(content);
+    }
+
+    #[test]
+    fn lookup_and_multi_stage() {
+        let content = r#"
+        namespace Global(8);
+            let alpha: expr = std::prelude::challenge(0, 41);
+            col fixed z = [0, 1]*;
+            col witness stage(0) x;
+            col witness stage(1) y;
+            y = x * alpha;
+            [x] in [z];
+        "#;
+        run_test(content);
+    }
+
+    #[test]
+    fn lookup_with_challenge() {
+        let content = r#"
+        namespace Global(8);
+            let alpha: expr = std::prelude::challenge(0, 41);
+            col fixed z = [0, 1]*;
+            col witness stage(0) x;
+            [x * alpha] in [z];
+        "#;
+        let mut pipeline =
+            Pipeline::<GoldilocksField>::default().from_pil_string(content.to_string());
+        let pil = pipeline.compute_optimized_pil().unwrap();
+        let fixed = pipeline.compute_fixed_cols().unwrap();
+        assert!(Plonky3Prover::new(pil, fixed).is_err());
+    }
+
+    /// Proves the PIL with the value of `column` in row 0 replaced by `value`,
+    /// after witness generation.
+    fn prove_tampered(pil: &str, column: &str, value: u64) -> Result<Vec<u8>, String> {
+        let mut pipeline = Pipeline::<GoldilocksField>::default().from_pil_string(pil.to_string());
+        let pil = pipeline.compute_optimized_pil().unwrap();
+        let witness_callback = pipeline.witgen_callback().unwrap();
+        let mut witness = pipeline.compute_witness().unwrap().as_ref().clone();
+        let fixed = pipeline.compute_fixed_cols().unwrap();
+
+        let (_, values) = witness.iter_mut().find(|(name, _)| name == column).unwrap();
+        values[0] = GoldilocksField::from(value);
+
+        let mut prover = Plonky3Prover::new(pil, fixed).unwrap();
+        prover.setup();
+        prover.prove(&witness, witness_callback)
+    }
+
+    #[test]
+    fn lookup_not_satisfied() {
+        let content = "namespace Global(8); pol fixed z = [0, 1]*; pol witness a; [a] in [z];";
+        let err = prove_tampered(content, "Global::a", 5).unwrap_err();
+        assert!(err.contains("not satisfied"), "{err}");
+    }
+
+    #[test]
+    fn permutation_not_satisfied() {
+        let content = r#"
+        namespace Global(8);
+            col fixed z = [1, 2, 3, 4, 5, 6, 7, 8];
+            col fixed z_rev = [8, 7, 6, 5, 4, 3, 2, 1];
+            col witness a;
+            a = z_rev;
+            [a] is [z];
+        "#;
+        let err = prove_tampered(content, "Global::a", 9).unwrap_err();
+        assert!(err.contains("does not verify"), "{err}");
+    }
 }
@@ -9,7 +9,7 @@ extern crate alloc;
 use alloc::collections::btree_map::BTreeMap;
 use powdr_ast::analyzed::{
     AlgebraicBinaryOperation, AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReference,
-    Analyzed, Identity,
+    Analyzed, Challenge, Identity, SelectedExpressions,
 };
 use powdr_number::{FieldElement, LargeInt};
 
@@ -22,11 +22,14 @@ use stwo_prover::constraint_framework::{
 };
 use stwo_prover::core::backend::{Column, ColumnOps};
 use stwo_prover::core::fields::m31::{BaseField, M31};
+use stwo_prover::core::fields::qm31::SECURE_EXTENSION_DEGREE;
 use stwo_prover::core::fields::{ExtensionOf, FieldExpOps, FieldOps};
 use stwo_prover::core::poly::circle::{CircleDomain, CircleEvaluation};
 use stwo_prover::core::poly::BitReversedOrder;
 use stwo_prover::core::utils::{bit_reverse_index, coset_index_to_circle_domain_index};
 
+use crate::stwo::logup::{logup_identities, try_to_m31, LogupElements, LogupIdentity, LogupKind};
+
 pub type PowdrComponent<'a, F> = FrameworkComponent<PowdrEval<F>>;
 
 pub fn gen_stwo_circle_column<T, B, F>(
@@ -60,19 +63,31 @@ where
 
 pub struct PowdrEval<T> {
     analyzed: Arc<Analyzed<T>>,
-    witness_columns: BTreeMap<PolyID, usize>,
+    log_size: u32,
+    /// The witness columns of each stage, in source order
+    stage_witness_columns: Vec<Vec<PolyID>>,
     constant_shifted: BTreeMap<PolyID, usize>,
     constant_columns: BTreeMap<PolyID, usize>,
+    /// The name and the witness column of each public, in the order of their selector columns
+    publics: Vec<(String, PolyID)>,
+    /// The values of the publics, by name
+    public_values: BTreeMap<String, M31>,
+    /// The values of the challenges drawn after each stage
+    challenges: BTreeMap<u64, M31>,
+    logup_identities: Vec<LogupIdentity<T>>,
+    logup_elements: LogupElements,
 }
 
 impl<T: FieldElement> PowdrEval<T> {
-    pub fn new(analyzed: Arc<Analyzed<T>>) -> Self {
-        let witness_columns: BTreeMap<PolyID, usize> = analyzed
-            .definitions_in_source_order(PolynomialType::Committed)
-            .flat_map(|(symbol, _)| symbol.array_elements())
-            .enumerate()
-            .map(|(index, (_, id))| (id, index))
-            .collect();
+    /// Fails if a public value or a challenge is not a Mersenne31 value.
+    pub fn new(
+        analyzed: Arc<Analyzed<T>>,
+        log_size: u32,
+        public_values: BTreeMap<String, T>,
+        challenges: BTreeMap<u64, T>,
+        logup_elements: LogupElements,
+    ) -> Result<Self, String> {
+        let stage_witness_columns = stage_witness_columns(&analyzed);
 
         let constant_with_next_list = get_constant_with_next_list(&analyzed);
 
@@ -91,36 +106,65 @@ impl<T: FieldElement> PowdrEval<T> {
             .map(|(index, (_, id))| (id, index))
             .collect();
 
-        Self {
+        let publics = analyzed
+            .get_publics()
+            .into_iter()
+            .map(|(name, _, poly_id, _, _)| (name, poly_id))
+            .collect();
+
+        let logup_identities = logup_identities(&analyzed);
+
+        let public_values = public_values
+            .into_iter()
+            .map(|(name, value)| Ok((name, try_to_m31(value)?)))
+            .collect::<Result<_, String>>()?;
+        let challenges = challenges
+            .into_iter()
+            .map(|(id, value)| Ok((id, try_to_m31(value)?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
             analyzed,
-            witness_columns,
+            log_size,
+            stage_witness_columns,
             constant_shifted,
             constant_columns,
-        }
+            publics,
+            public_values,
+            challenges,
+            logup_identities,
+            logup_elements,
+        })
     }
 }
 
 impl<T: FieldElement> FrameworkEval for PowdrEval<T> {
     fn log_size(&self) -> u32 {
-        self.analyzed.degree().ilog2()
+        self.log_size
     }
     fn max_constraint_log_degree_bound(&self) -> u32 {
-        self.analyzed.degree().ilog2() + 1
+        self.log_size + 1
     }
     fn evaluate<E: EvalAtRow>(&self, mut eval: E) -> E {
-        assert!(
-            self.analyzed.publics_count() == 0,
-            "Error: Expected no public inputs, as they are not supported yet.",
-        );
-
-        let witness_eval: BTreeMap<PolyID, [<E as EvalAtRow>::F; 2]> = self
-            .witness_columns
-            .keys()
-            .map(|poly_id| {
-                (
+        // The witness columns of stage `i` are committed in the tree `ORIGINAL_TRACE_IDX + i`.
+        let mut witness_eval: BTreeMap<PolyID, [<E as EvalAtRow>::F; 2]> = BTreeMap::new();
+        for (stage, columns) in self.stage_witness_columns.iter().enumerate() {
+            for poly_id in columns {
+                witness_eval.insert(
                     *poly_id,
-                    eval.next_interaction_mask(ORIGINAL_TRACE_IDX, [0, 1]),
-                )
+                    eval.next_interaction_mask(ORIGINAL_TRACE_IDX + stage, [0, 1]),
+                );
+            }
+        }
+
+        // The multiplicities of the lookups follow the first-stage witness columns.
+        let multiplicities_eval: Vec<<E as EvalAtRow>::F> = self
+            .logup_identities
+            .iter()
+            .filter(|identity| identity.kind == LogupKind::Lookup)
+            .map(|_| {
+                let [multiplicity] = eval.next_interaction_mask(ORIGINAL_TRACE_IDX, [0]);
+                multiplicity
             })
             .collect();
 
@@ -151,6 +195,18 @@ impl<T: FieldElement> FrameworkEval for PowdrEval<T> {
             })
             .collect();
 
+        // The selector of each public is 1 only in the row of the public, so
+        // selector * (column - public) = 0 constrains the column in that row.
+        for (i, (name, poly_id)) in self.publics.iter().enumerate() {
+            let selector = eval.get_preprocessed_column(PreprocessedColumn::Plonk(
+                i + constant_eval.len() + constant_shifted_eval.len(),
+            ));
+            let column = witness_eval[poly_id][0].clone();
+            eval.add_constraint(
+                selector * (column - <E as EvalAtRow>::F::from(self.public_values[name])),
+            );
+        }
+
         for id in self
             .analyzed
             .identities_with_inlined_intermediate_polynomials()
@@ -162,32 +218,104 @@ impl<T: FieldElement> FrameworkEval for PowdrEval<T> {
                         &witness_eval,
                         &constant_shifted_eval,
                         &constant_eval,
+                        &self.public_values,
+                        &self.challenges,
                     );
                     eval.add_constraint(expr);
                 }
                 Identity::Connect(..) => {
                     unimplemented!("Connect is not implemented in stwo yet")
                 }
-                Identity::Lookup(..) => {
-                    unimplemented!("Lookup is not implemented in stwo yet")
-                }
-                Identity::Permutation(..) => {
-                    unimplemented!("Permutation is not implemented in stwo yet")
-                }
+                // Handled through the interaction trace below.
+                Identity::Lookup(..) | Identity::Permutation(..) => {}
                 Identity::PhantomPermutation(..)
                 | Identity::PhantomLookup(..)
                 | Identity::PhantomBusInteraction(..) => {}
             }
         }
+
+        if self.logup_identities.is_empty() {
+            return eval;
+        }
+
+        // The interaction trace is committed after the trees of all stages.
+        let interaction = ORIGINAL_TRACE_IDX + self.stage_witness_columns.len();
+        let mut multiplicities_eval = multiplicities_eval.into_iter();
+        let mut sum = <E as EvalAtRow>::EF::zero();
+        for identity in &self.logup_identities {
+            let mut fraction = |selected: &SelectedExpressions<T>| {
+                let q = E::combine_ef(std::array::from_fn(|_| {
+                    let [value] = eval.next_interaction_mask(interaction, [0]);
+                    value
+                }));
+                let selector = to_stwo_expression(
+                    &selected.selector,
+                    &witness_eval,
+                    &constant_shifted_eval,
+                    &constant_eval,
+                    &self.public_values,
+                    &self.challenges,
+                );
+                let alpha_powers = self.logup_elements.alpha_powers(selected.expressions.len());
+                let denominator = selected.expressions.iter().zip(alpha_powers).fold(
+                    <E as EvalAtRow>::EF::from(
+                        self.logup_elements.constant_denominator(identity.id),
+                    ),
+                    |denominator, (e, power)| {
+                        denominator
+                            - <E as EvalAtRow>::EF::from(power)
+                                * to_stwo_expression(
+                                    e,
+                                    &witness_eval,
+                                    &constant_shifted_eval,
+                                    &constant_eval,
+                                    &self.public_values,
+                                    &self.challenges,
+                                )
+                    },
+                );
+                // q = selector / (beta - fingerprint)
+                eval.add_constraint(q.clone() * denominator - <E as EvalAtRow>::EF::from(selector));
+                q
+            };
+            let left = fraction(&identity.left);
+            let right = fraction(&identity.right);
+            sum = sum
+                + match identity.kind {
+                    LogupKind::Lookup => left - right * multiplicities_eval.next().unwrap(),
+                    LogupKind::Permutation => left - right,
+                };
+        }
+
+        // acc' = acc + sum, including the wrap-around from the last row to the
+        // first one, so the fractions of all rows sum to zero.
+        let accumulator: [[<E as EvalAtRow>::F; 2]; SECURE_EXTENSION_DEGREE] =
+            std::array::from_fn(|_| eval.next_interaction_mask(interaction, [0, 1]));
+        let acc = E::combine_ef(accumulator.clone().map(|[current, _]| current));
+        let acc_next = E::combine_ef(accumulator.map(|[_, next]| next));
+        eval.add_constraint(acc_next - acc - sum);
+
         eval
     }
 }
 
+/// Returns the witness columns of each stage, in source order.
+fn stage_witness_columns<T>(analyzed: &Analyzed<T>) -> Vec<Vec<PolyID>> {
+    let mut columns = vec![vec![]; analyzed.stage_count()];
+    for (symbol, _) in analyzed.definitions_in_source_order(PolynomialType::Committed) {
+        let stage = symbol.stage.unwrap_or_default() as usize;
+        columns[stage].extend(symbol.array_elements().map(|(_, id)| id));
+    }
+    columns
+}
+
 fn to_stwo_expression<T: FieldElement, F>(
     expr: &AlgebraicExpression<T>,
     witness_eval: &BTreeMap<PolyID, [F; 2]>,
     constant_shifted_eval: &BTreeMap<PolyID, F>,
     constant_eval: &BTreeMap<PolyID, F>,
+    public_values: &BTreeMap<String, M31>,
+    challenges: &BTreeMap<u64, M31>,
 ) -> F
 where
     F: FieldExpOps
@@ -218,13 +346,15 @@ where
                     true => constant_shifted_eval[&poly_id].clone(),
                 },
                 PolynomialType::Intermediate => {
-                    unimplemented!("Intermediate polynomials are not supported in stwo yet")
+                    unreachable!("Intermediate polynomials have been inlined")
                 }
             }
         }
-        AlgebraicExpression::PublicReference(..) => {
-            unimplemented!("Public references are not supported in stwo yet")
-        }
+        AlgebraicExpression::PublicReference(name) => F::from(
+            *public_values
+                .get(name)
+                .expect("Referenced public value does not exist"),
+        ),
         AlgebraicExpression::Number(n) => F::from(M31::from(n.try_into_i32().unwrap())),
         AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
             left,
@@ -232,17 +362,36 @@ where
             right,
         }) => match **right {
             AlgebraicExpression::Number(n) => {
-                let left =
-                    to_stwo_expression(left, witness_eval, constant_shifted_eval, constant_eval);
+                let left = to_stwo_expression(
+                    left,
+                    witness_eval,
+                    constant_shifted_eval,
+                    constant_eval,
+                    public_values,
+                    challenges,
+                );
                 (0u32..n.to_integer().try_into_u32().unwrap())
                     .fold(F::one(), |acc, _| acc * left.clone())
             }
             _ => unimplemented!("pow with non-constant exponent"),
         },
         AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation { left, op, right }) => {
-            let left = to_stwo_expression(left, witness_eval, constant_shifted_eval, constant_eval);
-            let right =
-                to_stwo_expression(right, witness_eval, constant_shifted_eval, constant_eval);
+            let left = to_stwo_expression(
+                left,
+                witness_eval,
+                constant_shifted_eval,
+                constant_eval,
+                public_values,
+                challenges,
+            );
+            let right = to_stwo_expression(
+                right,
+                witness_eval,
+                constant_shifted_eval,
+                constant_eval,
+                public_values,
+                challenges,
+            );
 
             match op {
                 Add => left + right,
@@ -252,15 +401,20 @@ where
             }
         }
         AlgebraicExpression::UnaryOperation(AlgebraicUnaryOperation { op, expr }) => {
-            let expr = to_stwo_expression(expr, witness_eval, constant_shifted_eval, constant_eval);
+            let expr = to_stwo_expression(
+                expr,
+                witness_eval,
+                constant_shifted_eval,
+                constant_eval,
+                public_values,
+                challenges,
+            );
 
             match op {
                 AlgebraicUnaryOperator::Minus => -expr,
             }
         }
-        AlgebraicExpression::Challenge(_challenge) => {
-            unimplemented!("challenges are not supported in stwo yet")
-        }
+        AlgebraicExpression::Challenge(Challenge { id, .. }) => F::from(challenges[id]),
     }
 }
 
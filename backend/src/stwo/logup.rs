@@ -0,0 +1,240 @@
+//! Lookups and permutations proven with LogUp.
+//!
+//! For each lookup `sel_l $ [a] in sel_r $ [b]` and permutation
+//! `sel_l $ [a] is sel_r $ [b]`, the interaction trace contains the extension
+//! field columns `q_l = sel_l / (beta - fp(a))` and `q_r = sel_r / (beta - fp(b))`,
+//! where `fp` is the fingerprint of the tuple prefixed with the identity ID.
+//! A single running sum column `acc` with `acc' = acc + sum(q_l - m * q_r)`
+//! (with `m = 1` for permutations) is constrained on all rows, including the
+//! wrap-around from the last to the first row, which is only satisfiable if
+//! the fractions sum to zero. The multiplicities `m` of the lookups are
+//! committed together with the first-stage witness.
+
+use std::collections::BTreeMap;
+
+use num_traits::{One, Zero};
+use powdr_ast::analyzed::{
+    AlgebraicExpression, Analyzed, Identity, LookupIdentity, PermutationIdentity,
+    SelectedExpressions,
+};
+use powdr_executor::witgen::evaluators::expression_evaluator::{
+    ExpressionEvaluator, OwnedTraceValues,
+};
+use powdr_executor_utils::lookup_multiplicities;
+use powdr_number::{batch, FieldElement};
+use stwo_prover::core::channel::Channel;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::SecureField;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogupKind {
+    Lookup,
+    Permutation,
+}
+
+/// A lookup or permutation identity, with intermediate polynomials inlined.
+pub struct LogupIdentity<T> {
+    pub id: u64,
+    pub kind: LogupKind,
+    pub left: SelectedExpressions<T>,
+    pub right: SelectedExpressions<T>,
+}
+
+/// Returns the lookups and permutations of the PIL.
+pub fn logup_identities<T: FieldElement>(analyzed: &Analyzed<T>) -> Vec<LogupIdentity<T>> {
+    analyzed
+        .identities_with_inlined_intermediate_polynomials()
+        .into_iter()
+        .filter_map(|identity| match identity {
+            Identity::Lookup(LookupIdentity {
+                id, left, right, ..
+            }) => Some(LogupIdentity {
+                id,
+                kind: LogupKind::Lookup,
+                left,
+                right,
+            }),
+            Identity::Permutation(PermutationIdentity {
+                id, left, right, ..
+            }) => Some(LogupIdentity {
+                id,
+                kind: LogupKind::Permutation,
+                left,
+                right,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The random elements used to fingerprint the tuples.
+#[derive(Clone, Copy)]
+pub struct LogupElements {
+    pub alpha: SecureField,
+    pub beta: SecureField,
+}
+
+impl LogupElements {
+    pub fn draw(channel: &mut impl Channel) -> Self {
+        Self {
+            alpha: channel.draw_felt(),
+            beta: channel.draw_felt(),
+        }
+    }
+
+    /// Placeholder elements, for when only the shape of the constraints is needed.
+    pub fn dummy() -> Self {
+        Self {
+            alpha: SecureField::zero(),
+            beta: SecureField::zero(),
+        }
+    }
+
+    /// Returns `[alpha, alpha^2, ..., alpha^n]`.
+    pub fn alpha_powers(&self, n: usize) -> Vec<SecureField> {
+        (0..n)
+            .scan(SecureField::one(), |power, _| {
+                *power *= self.alpha;
+                Some(*power)
+            })
+            .collect()
+    }
+
+    /// Returns `beta - id` for the given identity ID, i.e. the constant part of
+    /// the denominator `beta - fp(tuple)`.
+    pub fn constant_denominator(&self, id: u64) -> SecureField {
+        self.beta - SecureField::from(M31::from(id as u32))
+    }
+}
+
+pub fn to_m31<T: FieldElement>(value: T) -> M31 {
+    try_to_m31(value).unwrap()
+}
+
+/// Converts the value to M31, failing if it is not a Mersenne31 value.
+pub fn try_to_m31<T: FieldElement>(value: T) -> Result<M31, String> {
+    value
+        .try_into_i32()
+        .map(M31::from)
+        .ok_or_else(|| format!("{value} is not a Mersenne31 value."))
+}
+
+/// Computes the multiplicity column of every lookup, i.e. the number of times
+/// each row of the right-hand side is looked up.
+pub fn multiplicities<T: FieldElement>(
+    analyzed: &Analyzed<T>,
+    identities: &[LogupIdentity<T>],
+    witness: &[(String, Vec<T>)],
+    fixed: &[(String, Vec<T>)],
+) -> Result<Vec<Vec<T>>, String> {
+    let trace_values = OwnedTraceValues::new(analyzed, witness.to_vec(), fixed.to_vec());
+    let intermediate_definitions = BTreeMap::new();
+    let challenges = BTreeMap::new();
+    let evaluate = |e: &AlgebraicExpression<T>, row: usize| {
+        ExpressionEvaluator::new(
+            trace_values.row(row),
+            &intermediate_definitions,
+            &challenges,
+        )
+        .evaluate(e)
+    };
+
+    identities
+        .iter()
+        .filter(|identity| identity.kind == LogupKind::Lookup)
+        .map(|identity| {
+            lookup_multiplicities(
+                identity.id,
+                &identity.left,
+                &identity.right,
+                trace_values.height(),
+                &evaluate,
+            )
+        })
+        .collect()
+}
+
+/// Computes the interaction trace as base field columns: for each identity the
+/// columns of `q_l` and `q_r`, followed by the columns of the running sum.
+pub fn interaction_trace<T: FieldElement>(
+    analyzed: &Analyzed<T>,
+    identities: &[LogupIdentity<T>],
+    witness: &[(String, Vec<T>)],
+    fixed: &[(String, Vec<T>)],
+    multiplicities: &[Vec<T>],
+    elements: &LogupElements,
+) -> Vec<Vec<T>> {
+    let trace_values = OwnedTraceValues::new(analyzed, witness.to_vec(), fixed.to_vec());
+    let size = trace_values.height();
+    let intermediate_definitions = BTreeMap::new();
+    let challenges = BTreeMap::new();
+
+    // q = selector / denominator for the rows of one side of an identity
+    let fractions = |id: u64, selected: &SelectedExpressions<T>| {
+        let alpha_powers = elements.alpha_powers(selected.expressions.len());
+        let (selectors, mut denominators): (Vec<_>, Vec<_>) = (0..size)
+            .map(|row| {
+                let mut evaluator = ExpressionEvaluator::new(
+                    trace_values.row(row),
+                    &intermediate_definitions,
+                    &challenges,
+                );
+                let selector = SecureField::from(to_m31(evaluator.evaluate(&selected.selector)));
+                let denominator = selected.expressions.iter().zip(&alpha_powers).fold(
+                    elements.constant_denominator(id),
+                    |denominator, (e, power)| {
+                        denominator - *power * SecureField::from(to_m31(evaluator.evaluate(e)))
+                    },
+                );
+                (selector, denominator)
+            })
+            .unzip();
+        batch::inverse_in_place(&mut denominators);
+        selectors
+            .into_iter()
+            .zip(denominators)
+            .map(|(selector, inverse)| selector * inverse)
+            .collect::<Vec<_>>()
+    };
+
+    let mut columns = vec![];
+    let mut contributions = vec![SecureField::zero(); size];
+    let mut multiplicities = multiplicities.iter();
+    for identity in identities {
+        let left = fractions(identity.id, &identity.left);
+        let right = fractions(identity.id, &identity.right);
+        let multiplicity = match identity.kind {
+            LogupKind::Lookup => Some(multiplicities.next().unwrap()),
+            LogupKind::Permutation => None,
+        };
+        for (row, contribution) in contributions.iter_mut().enumerate() {
+            let m = multiplicity.map_or(SecureField::one(), |m| SecureField::from(to_m31(m[row])));
+            *contribution += left[row] - m * right[row];
+        }
+        columns.extend(secure_columns::<T>(&left));
+        columns.extend(secure_columns::<T>(&right));
+    }
+
+    // acc[0] = 0 and acc[i + 1] = acc[i] + contribution[i]
+    let running_sum = contributions
+        .iter()
+        .scan(SecureField::zero(), |acc, contribution| {
+            let current = *acc;
+            *acc += *contribution;
+            Some(current)
+        })
+        .collect::<Vec<_>>();
+    columns.extend(secure_columns::<T>(&running_sum));
+    columns
+}
+
+/// Splits a column of extension field elements into its base field coordinates.
+fn secure_columns<T: FieldElement>(values: &[SecureField]) -> [Vec<T>; 4] {
+    let mut columns: [Vec<T>; 4] = Default::default();
+    for value in values {
+        for (column, coordinate) in columns.iter_mut().zip(value.to_m31_array()) {
+            column.push(T::from(coordinate.0));
+        }
+    }
+    columns
+}
@@ -17,6 +17,7 @@ use stwo_prover::core::channel::{Blake2sChannel, Channel, MerkleChannel};
 use stwo_prover::core::vcs::blake2_merkle::Blake2sMerkleChannel;
 
 mod circuit_builder;
+mod logup;
 mod proof;
 mod prover;
 
@@ -39,7 +40,9 @@ impl<F: FieldElement> BackendFactory<F> for RestrictedFactory {
             return Err(Error::BackendError("Proving key unused".to_string()));
         }
 
-        if pil.degrees().len() > 1 {
+        // The size is chosen per proof, but all machines are proven in a single
+        // trace, so they need to share it.
+        if pil.degree_ranges().len() > 1 {
             return Err(Error::NoVariableDegreeAvailable);
         }
 
@@ -88,7 +91,7 @@ where
         if prev_proof.is_some() {
             return Err(Error::NoAggregationAvailable);
         }
-        Ok(StwoProver::prove(self, witness)?)
+        Ok(StwoProver::prove(self, witness, witgen_callback)?)
     }
     fn export_proving_key(&self, output: &mut dyn io::Write) -> Result<(), Error> {
         self.export_proving_key(output)
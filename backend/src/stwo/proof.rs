@@ -1,15 +1,29 @@
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use stwo_prover::core::backend::Backend;
 use stwo_prover::core::backend::Column;
 use stwo_prover::core::backend::ColumnOps;
+use stwo_prover::core::channel::MerkleChannel;
 use stwo_prover::core::fields::m31::BaseField;
 use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::poly::circle::{CanonicCoset, CircleEvaluation};
 use stwo_prover::core::poly::BitReversedOrder;
+use stwo_prover::core::prover::StarkProof;
 use stwo_prover::core::ColumnVec;
 
+/// A proof, together with the size of the trace it was computed for.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "StarkProof<MC::H>: Serialize",
+    deserialize = "StarkProof<MC::H>: DeserializeOwned"
+))]
+pub struct Proof<MC: MerkleChannel> {
+    pub stark_proof: StarkProof<MC::H>,
+    pub log_size: u32,
+}
+
 /// For each possible size, the commitment and prover data
 pub type TableProvingKeyCollection<B> = BTreeMap<usize, TableProvingKey<B>>;
 
@@ -85,14 +99,12 @@ impl<B: Backend> From<TableProvingKeyCollection<B>> for SerializableTableProving
         table_provingkey_collection
             .iter()
             .for_each(|(&size, trable_provingkey)| {
-                let mut values: BTreeMap<usize, Vec<M31>> = BTreeMap::new();
-                let log_size = size.ilog2();
-                trable_provingkey
+                let values: BTreeMap<usize, Vec<M31>> = trable_provingkey
                     .constant_trace_circle_domain
                     .iter()
-                    .for_each(|circle_eval| {
-                        values.insert(log_size as usize, circle_eval.values.to_cpu().to_vec());
-                    });
+                    .map(|circle_eval| circle_eval.values.to_cpu().to_vec())
+                    .enumerate()
+                    .collect();
 
                 constant_trace_circle_domain_collection.insert(size, values);
             });
@@ -1,10 +1,13 @@
-use powdr_ast::analyzed::Analyzed;
+use num_traits::Zero;
+use powdr_ast::analyzed::{AlgebraicExpression, Analyzed, Challenge, PolynomialType};
+use powdr_ast::parsed::visitor::AllChildren;
 use powdr_backend_utils::machine_fixed_columns;
 use powdr_executor::constant_evaluator::VariablySizedColumn;
+use powdr_executor::witgen::WitgenCallback;
 use powdr_number::FieldElement;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::marker::PhantomData;
 use std::sync::Arc;
 use std::{fmt, io};
@@ -12,24 +15,25 @@ use std::{fmt, io};
 use crate::stwo::circuit_builder::{
     gen_stwo_circle_column, get_constant_with_next_list, PowdrComponent, PowdrEval,
 };
+use crate::stwo::logup::{self, logup_identities, LogupElements};
 use crate::stwo::proof::{
-    SerializableStarkProvingKey, StarkProvingKey, TableProvingKey, TableProvingKeyCollection,
+    Proof, SerializableStarkProvingKey, StarkProvingKey, TableProvingKey, TableProvingKeyCollection,
 };
 
 use stwo_prover::constraint_framework::{
     TraceLocationAllocator, ORIGINAL_TRACE_IDX, PREPROCESSED_TRACE_IDX,
 };
-use stwo_prover::core::prover::StarkProof;
-
 use stwo_prover::core::air::{Component, ComponentProver};
 use stwo_prover::core::backend::{Backend, BackendForChannel};
 use stwo_prover::core::channel::{Channel, MerkleChannel};
 use stwo_prover::core::fields::m31::{BaseField, M31};
+use stwo_prover::core::fields::qm31::SecureField;
 use stwo_prover::core::fri::FriConfig;
 use stwo_prover::core::pcs::{CommitmentSchemeProver, CommitmentSchemeVerifier, PcsConfig};
-use stwo_prover::core::poly::circle::{CanonicCoset, CircleDomain, CircleEvaluation};
+use stwo_prover::core::poly::circle::{CanonicCoset, CircleEvaluation};
 use stwo_prover::core::poly::twiddles::TwiddleTree;
 use stwo_prover::core::poly::BitReversedOrder;
+use stwo_prover::core::vcs::ops::MerkleHasher;
 use stwo_prover::core::ColumnVec;
 
 const FRI_LOG_BLOWUP: usize = 1;
@@ -112,29 +116,21 @@ where
     }
 
     pub fn setup(&mut self) {
-        // machines with varying sizes are not supported yet, and it is checked in backendfactory create function.
-        //TODO: support machines with varying sizes
-        let domain_map: BTreeMap<usize, CircleDomain> = self
-            .analyzed
-            .degrees()
-            .iter()
-            .map(|size| {
-                (
-                    (size.ilog2() as usize),
-                    CanonicCoset::new(size.ilog2()).circle_domain(),
-                )
-            })
-            .collect();
-
         let preprocessed: BTreeMap<String, TableProvingKeyCollection<B>> = self
             .split
             .iter()
             .filter_map(|(namespace, pil)| {
-                // if we have no fixed columns, we don't need to commit to anything.
-                if pil.constant_count() == 0 {
+                // if we have neither fixed columns nor publics, we don't need to commit to anything.
+                if pil.constant_count() + pil.publics_count() == 0 {
                     None
                 } else {
                     let fixed_columns = machine_fixed_columns(&self.fixed, pil);
+                    let constant_with_next_list = get_constant_with_next_list(pil);
+                    let public_rows = pil
+                        .get_publics()
+                        .into_iter()
+                        .map(|(_, _, _, row, _)| row)
+                        .collect::<Vec<_>>();
 
                     Some((
                         namespace.to_string(),
@@ -143,48 +139,38 @@ where
                             .unwrap()
                             .iter()
                             .map(|size| {
-                                let mut constant_trace: ColumnVec<
-                                    CircleEvaluation<B, BaseField, BitReversedOrder>,
-                                > = fixed_columns
-                                    .values()
-                                    .flat_map(|vec| {
-                                        vec.iter().map(|(_name, values)| {
-                                            gen_stwo_circle_column::<F, B, M31>(
-                                                *domain_map
-                                                    .get(&(values.len().ilog2() as usize))
-                                                    .unwrap(),
-                                                values,
-                                            )
-                                        })
-                                    })
-                                    .collect();
-
-                                let constant_with_next_list = get_constant_with_next_list(pil);
-
-                                let constant_shifted_trace: ColumnVec<
+                                let domain = CanonicCoset::new(size.ilog2()).circle_domain();
+                                let fixed_columns = &fixed_columns[&size];
+
+                                let constant_trace =
+                                    fixed_columns.iter().map(|(_, values)| values.to_vec());
+
+                                let constant_shifted_trace = fixed_columns
+                                    .iter()
+                                    .filter(|(name, _)| constant_with_next_list.contains(name))
+                                    .map(|(_, values)| {
+                                        let mut rotated_values = values.to_vec();
+                                        rotated_values.rotate_left(1);
+                                        rotated_values
+                                    });
+
+                                // selector columns for the public values, which are 1 only in the row of the public
+                                let public_selectors = public_rows.iter().map(|row| {
+                                    (0..size as usize)
+                                        .map(|i| F::from(i == *row))
+                                        .collect::<Vec<_>>()
+                                });
+
+                                let constant_trace: ColumnVec<
                                     CircleEvaluation<B, BaseField, BitReversedOrder>,
-                                > = fixed_columns
-                                    .values()
-                                    .flat_map(|vec| {
-                                        vec.iter()
-                                            .filter(|(name, _)| {
-                                                constant_with_next_list.contains(name)
-                                            })
-                                            .map(|(_, values)| {
-                                                let mut rotated_values = values.to_vec();
-                                                rotated_values.rotate_left(1);
-                                                gen_stwo_circle_column::<F, B, M31>(
-                                                    *domain_map
-                                                        .get(&(values.len().ilog2() as usize))
-                                                        .unwrap(),
-                                                    &rotated_values,
-                                                )
-                                            })
+                                > = constant_trace
+                                    .chain(constant_shifted_trace)
+                                    .chain(public_selectors)
+                                    .map(|values| {
+                                        gen_stwo_circle_column::<F, B, M31>(domain, &values)
                                     })
                                     .collect();
 
-                                constant_trace.extend(constant_shifted_trace);
-
                                 (
                                     size as usize,
                                     TableProvingKey {
@@ -203,92 +189,119 @@ where
         self.proving_key = proving_key;
     }
 
-    pub fn prove(&self, witness: &[(String, Vec<F>)]) -> Result<Vec<u8>, String> {
-        assert!(
-            witness
-                .iter()
-                .all(|(_name, vec)| vec.len() == witness[0].1.len()),
-            "All Vec<T> in witness must have the same length. Mismatch found!"
-        );
+    pub fn prove(
+        &self,
+        witness: &[(String, Vec<F>)],
+        witgen_callback: WitgenCallback<F>,
+    ) -> Result<Vec<u8>, String> {
+        let size = witness[0].1.len();
+        if witness.iter().any(|(_, values)| values.len() != size) {
+            // machines of different sizes are proven separately by the composite backend
+            return Err("All witness columns must have the same size".to_string());
+        }
 
         let config = get_config();
-        let domain_map: BTreeMap<usize, CircleDomain> = self
-            .analyzed
-            .degrees()
+        let log_size = size.ilog2();
+        let domain = CanonicCoset::new(log_size).circle_domain();
+        let twiddles = twiddles::<B>(log_size);
+        let prover_channel = &mut <MC as MerkleChannel>::C::default();
+        let mut commitment_scheme = CommitmentSchemeProver::<'_, B, MC>::new(config, &twiddles);
+
+        //commit to the constant and shifted constant polynomials, and the public selectors
+        let mut tree_builder = commitment_scheme.tree_builder();
+        tree_builder.extend_evals(self.constant_trace(size));
+        tree_builder.commit(prover_channel);
+
+        let fixed: Vec<(String, Vec<F>)> = self
+            .fixed
             .iter()
-            .map(|size| {
+            .map(|(name, values)| {
                 (
-                    (size.ilog2() as usize),
-                    CanonicCoset::new(size.ilog2()).circle_domain(),
+                    name.clone(),
+                    values.get_by_size(size as u64).unwrap().to_vec(),
                 )
             })
             .collect();
-        let twiddles_map: BTreeMap<usize, TwiddleTree<B>> = self
-            .split
-            .values()
-            .flat_map(|pil| {
-                // Precompute twiddles for all sizes in the PIL
-                pil.committed_polys_in_source_order()
-                    .flat_map(|(s, _)| {
-                        s.degree.iter().flat_map(|range| {
-                            range
-                                .iter()
-                                .filter(|&size| size.is_power_of_two())
-                                .map(|size| {
-                                    let twiddles = B::precompute_twiddles(
-                                        CanonicCoset::new(size.ilog2() + 1 + FRI_LOG_BLOWUP as u32)
-                                            .circle_domain()
-                                            .half_coset,
-                                    );
-                                    (size as usize, twiddles)
-                                })
-                                .collect::<Vec<_>>()
-                        })
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-        // only the first one is used, machines with varying sizes are not supported yet, and it is checked in backendfactory create function.
-        let prover_channel = &mut <MC as MerkleChannel>::C::default();
-        let mut commitment_scheme =
-            CommitmentSchemeProver::<'_, B, MC>::new(config, twiddles_map.iter().next().unwrap().1);
+        let identities = logup_identities(&self.analyzed);
+        let multiplicities = logup::multiplicities(&self.analyzed, &identities, witness, &fixed)?;
+
+        let stage_witness_names = stage_witness_names(&self.analyzed);
+        let challenges_by_stage = challenges_by_stage(&self.analyzed);
+        let publics = self.analyzed.get_publics();
+        let mut witness = witness.to_vec();
+        let mut challenges = BTreeMap::new();
+        let mut public_values = BTreeMap::new();
+        for (stage, names) in stage_witness_names.iter().enumerate() {
+            if stage > 0 {
+                witness = witgen_callback.next_stage_witness(
+                    &self.analyzed,
+                    &witness,
+                    challenges.clone(),
+                    stage as u8,
+                );
+            }
+            let columns_by_name: BTreeMap<&String, &Vec<F>> = witness
+                .iter()
+                .map(|(name, values)| (name, values))
+                .collect();
 
-        let mut tree_builder = commitment_scheme.tree_builder();
+            // the multiplicities of the lookups are committed with the first stage
+            let trace: ColumnVec<CircleEvaluation<B, BaseField, BitReversedOrder>> = names
+                .iter()
+                .map(|name| columns_by_name[name])
+                .chain(multiplicities.iter().filter(|_| stage == 0))
+                .map(|values| gen_stwo_circle_column::<F, B, M31>(domain, values))
+                .collect();
+
+            let mut tree_builder = commitment_scheme.tree_builder();
+            tree_builder.extend_evals(trace);
+            tree_builder.commit(prover_channel);
 
-        //commit to the constant and shifted constant polynomials
-        if let Some((_, table_proving_key)) =
-            self.proving_key
-                .preprocessed
-                .as_ref()
-                .and_then(|preprocessed| {
-                    preprocessed
-                        .iter()
-                        .find_map(|(_, table_collection)| table_collection.iter().next())
+            let stage_public_values = publics
+                .iter()
+                .filter(|(_, _, _, _, public_stage)| *public_stage as usize == stage)
+                .map(|(name, column_name, _, row, _)| {
+                    (name.clone(), columns_by_name[column_name][*row])
                 })
-        {
-            tree_builder.extend_evals(table_proving_key.constant_trace_circle_domain.clone());
-        } else {
-            tree_builder.extend_evals([]);
+                .collect::<Vec<_>>();
+            mix_public_values(&stage_public_values, prover_channel)?;
+            public_values.extend(stage_public_values);
+
+            challenges.extend(draw_challenges(&challenges_by_stage[stage], prover_channel));
         }
-        tree_builder.commit(prover_channel);
 
-        let trace: ColumnVec<CircleEvaluation<B, BaseField, BitReversedOrder>> = witness
-            .iter()
-            .map(|(_name, values)| {
-                gen_stwo_circle_column::<F, B, M31>(
-                    *domain_map.get(&(values.len().ilog2() as usize)).unwrap(),
-                    values,
+        let logup_elements = if identities.is_empty() {
+            LogupElements::dummy()
+        } else {
+            let logup_elements = LogupElements::draw(prover_channel);
+            let interaction_trace: ColumnVec<CircleEvaluation<B, BaseField, BitReversedOrder>> =
+                logup::interaction_trace(
+                    &self.analyzed,
+                    &identities,
+                    &witness,
+                    &fixed,
+                    &multiplicities,
+                    &logup_elements,
                 )
-            })
-            .collect();
+                .iter()
+                .map(|values| gen_stwo_circle_column::<F, B, M31>(domain, values))
+                .collect();
 
-        let mut tree_builder = commitment_scheme.tree_builder();
-        tree_builder.extend_evals(trace);
-        tree_builder.commit(prover_channel);
+            let mut tree_builder = commitment_scheme.tree_builder();
+            tree_builder.extend_evals(interaction_trace);
+            tree_builder.commit(prover_channel);
+            logup_elements
+        };
 
         let component = PowdrComponent::new(
             &mut TraceLocationAllocator::default(),
-            PowdrEval::new(self.analyzed.clone()),
+            PowdrEval::new(
+                self.analyzed.clone(),
+                log_size,
+                public_values,
+                challenges,
+                logup_elements,
+            )?,
         );
 
         let proof_result = stwo_prover::core::prover::prove::<B, MC>(
@@ -297,52 +310,233 @@ where
             &mut commitment_scheme,
         );
 
-        let proof = match proof_result {
+        let stark_proof = match proof_result {
             Ok(value) => value,
             Err(e) => return Err(e.to_string()), // Propagate the error instead of panicking
         };
 
+        let proof: Proof<MC> = Proof {
+            stark_proof,
+            log_size,
+        };
         Ok(bincode::serialize(&proof).unwrap())
     }
 
-    pub fn verify(&self, proof: &[u8], _instances: &[F]) -> Result<(), String> {
-        assert!(
-            _instances.is_empty(),
-            "Expected _instances slice to be empty, but it has {} elements.",
-            _instances.len()
-        );
+    pub fn verify(&self, proof: &[u8], instances: &[F]) -> Result<(), String> {
+        let publics = self.analyzed.get_publics();
+        if publics.len() != instances.len() {
+            return Err(format!(
+                "Expected {} public values, but got {}.",
+                publics.len(),
+                instances.len()
+            ));
+        }
 
         let config = get_config();
-        let proof: StarkProof<MC::H> =
+        let Proof {
+            stark_proof: proof,
+            log_size,
+        }: Proof<MC> =
             bincode::deserialize(proof).map_err(|e| format!("Failed to deserialize proof: {e}"))?;
 
+        // The public values are only bound through the preprocessed selector columns,
+        // so the size and the preprocessed commitment have to match the setup.
+        let degree_range = self
+            .analyzed
+            .committed_polys_in_source_order()
+            .find_map(|(symbol, _)| symbol.degree)
+            .unwrap();
+        let Some(size) = degree_range
+            .iter()
+            .find(|size| size.ilog2() == log_size)
+            .map(|size| size as usize)
+        else {
+            return Err(format!(
+                "Invalid trace size 2^{log_size}, expected a size in {}..={}.",
+                degree_range.min, degree_range.max
+            ));
+        };
+        if proof.commitments.get(PREPROCESSED_TRACE_IDX)
+            != Some(&self.preprocessed_commitment(size))
+        {
+            return Err("The preprocessed commitment does not match the setup.".to_string());
+        }
+
         let verifier_channel = &mut <MC as MerkleChannel>::C::default();
         let commitment_scheme = &mut CommitmentSchemeVerifier::<MC>::new(config);
 
-        //Constraints that are to be proved
-        let component = PowdrComponent::new(
-            &mut TraceLocationAllocator::default(),
-            PowdrEval::new(self.analyzed.clone()),
-        );
+        let challenges_by_stage = challenges_by_stage(&self.analyzed);
+        let has_logup = !logup_identities(&self.analyzed).is_empty();
 
         // Retrieve the expected column sizes in each commitment interaction, from the AIR.
-        // the sizes include the degrees of the constant, witness, native lookups. Native lookups are not used yet.
-        let sizes = component.trace_log_degree_bounds();
+        // The sizes do not depend on the public and random values, so placeholders are used for them.
+        let sizes = PowdrComponent::new(
+            &mut TraceLocationAllocator::default(),
+            PowdrEval::new(
+                self.analyzed.clone(),
+                log_size,
+                publics
+                    .iter()
+                    .map(|(name, _, _, _, _)| (name.clone(), F::zero()))
+                    .collect(),
+                challenges_by_stage
+                    .iter()
+                    .flatten()
+                    .map(|id| (*id, F::zero()))
+                    .collect(),
+                LogupElements::dummy(),
+            )?,
+        )
+        .trace_log_degree_bounds();
+
+        // A proof with too few commitments is rejected rather than indexed.
+        let commitment = |tree: usize| {
+            proof
+                .commitments
+                .get(tree)
+                .copied()
+                .ok_or_else(|| format!("The proof is missing commitment {tree}."))
+        };
 
         commitment_scheme.commit(
-            proof.commitments[PREPROCESSED_TRACE_IDX],
+            commitment(PREPROCESSED_TRACE_IDX)?,
             &sizes[PREPROCESSED_TRACE_IDX],
             verifier_channel,
         );
-        commitment_scheme.commit(
-            proof.commitments[ORIGINAL_TRACE_IDX],
-            &sizes[ORIGINAL_TRACE_IDX],
-            verifier_channel,
+        let mut challenges = BTreeMap::new();
+        let mut public_values = BTreeMap::new();
+        for (stage, stage_challenges) in challenges_by_stage.iter().enumerate() {
+            commitment_scheme.commit(
+                commitment(ORIGINAL_TRACE_IDX + stage)?,
+                &sizes[ORIGINAL_TRACE_IDX + stage],
+                verifier_channel,
+            );
+
+            let stage_public_values = publics
+                .iter()
+                .zip(instances)
+                .filter(|((_, _, _, _, public_stage), _)| *public_stage as usize == stage)
+                .map(|((name, _, _, _, _), value)| (name.clone(), *value))
+                .collect::<Vec<_>>();
+            mix_public_values(&stage_public_values, verifier_channel)?;
+            public_values.extend(stage_public_values);
+
+            challenges.extend(draw_challenges(stage_challenges, verifier_channel));
+        }
+
+        let logup_elements = if has_logup {
+            let logup_elements = LogupElements::draw(verifier_channel);
+            let interaction = ORIGINAL_TRACE_IDX + challenges_by_stage.len();
+            commitment_scheme.commit(
+                commitment(interaction)?,
+                &sizes[interaction],
+                verifier_channel,
+            );
+            logup_elements
+        } else {
+            LogupElements::dummy()
+        };
+
+        //Constraints that are to be proved
+        let component = PowdrComponent::new(
+            &mut TraceLocationAllocator::default(),
+            PowdrEval::new(
+                self.analyzed.clone(),
+                log_size,
+                public_values,
+                challenges,
+                logup_elements,
+            )?,
         );
 
         stwo_prover::core::prover::verify(&[&component], verifier_channel, commitment_scheme, proof)
             .map_err(|e| e.to_string())
     }
+
+    /// Returns the constant, shifted constant and public selector columns for the given size.
+    fn constant_trace(
+        &self,
+        size: usize,
+    ) -> ColumnVec<CircleEvaluation<B, BaseField, BitReversedOrder>> {
+        self.proving_key
+            .preprocessed
+            .as_ref()
+            .and_then(|preprocessed| {
+                preprocessed
+                    .values()
+                    .find_map(|table_collection| table_collection.get(&size))
+            })
+            .map(|table_proving_key| table_proving_key.constant_trace_circle_domain.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the commitment to the preprocessed columns for the given size, as the prover computes it.
+    fn preprocessed_commitment(&self, size: usize) -> <MC::H as MerkleHasher>::Hash {
+        let twiddles = twiddles::<B>(size.ilog2());
+        let mut commitment_scheme =
+            CommitmentSchemeProver::<'_, B, MC>::new(get_config(), &twiddles);
+        let mut tree_builder = commitment_scheme.tree_builder();
+        tree_builder.extend_evals(self.constant_trace(size));
+        tree_builder.commit(&mut <MC as MerkleChannel>::C::default());
+        commitment_scheme.roots()[PREPROCESSED_TRACE_IDX]
+    }
+}
+
+/// Precomputes the twiddles for committing to a trace of size `2^log_size`.
+fn twiddles<B: Backend>(log_size: u32) -> TwiddleTree<B> {
+    B::precompute_twiddles(
+        CanonicCoset::new(log_size + 1 + FRI_LOG_BLOWUP as u32)
+            .circle_domain()
+            .half_coset,
+    )
+}
+
+/// Returns the names of the witness columns of each stage, in source order.
+fn stage_witness_names<T>(analyzed: &Analyzed<T>) -> Vec<Vec<String>> {
+    let mut names = vec![vec![]; analyzed.stage_count()];
+    for (symbol, _) in analyzed.definitions_in_source_order(PolynomialType::Committed) {
+        let stage = symbol.stage.unwrap_or_default() as usize;
+        names[stage].extend(symbol.array_elements().map(|(name, _)| name));
+    }
+    names
+}
+
+/// Returns the IDs of the challenges drawn after each stage.
+fn challenges_by_stage<T>(analyzed: &Analyzed<T>) -> Vec<BTreeSet<u64>> {
+    let mut challenges = vec![BTreeSet::new(); analyzed.stage_count()];
+    analyzed.all_children().for_each(|e| {
+        if let AlgebraicExpression::Challenge(Challenge { id, stage }) = e {
+            challenges[*stage as usize].insert(*id);
+        }
+    });
+    challenges
+}
+
+/// Draws the values of the given challenges from the channel, as base field elements.
+fn draw_challenges<F: FieldElement>(
+    ids: &BTreeSet<u64>,
+    channel: &mut impl Channel,
+) -> BTreeMap<u64, F> {
+    ids.iter()
+        .map(|id| (*id, F::from(channel.draw_felt().to_m31_array()[0].0)))
+        .collect()
+}
+
+/// Mixes the values of the public inputs of a stage into the channel.
+/// Fails if a value is not a Mersenne31 value.
+fn mix_public_values<F: FieldElement>(
+    values: &[(String, F)],
+    channel: &mut impl Channel,
+) -> Result<(), String> {
+    if !values.is_empty() {
+        channel.mix_felts(
+            &values
+                .iter()
+                .map(|(_, value)| Ok(SecureField::from(logup::try_to_m31(*value)?)))
+                .collect::<Result<Vec<_>, String>>()?,
+        );
+    }
+    Ok(())
 }
 
 fn get_config() -> PcsConfig {
@@ -11,7 +11,9 @@ use powdr::number::{
     BabyBearField, BigUint, Bn254Field, FieldElement, GoldilocksField, KnownField, KoalaBearField,
 };
 use powdr::riscv::{CompilerOptions, RuntimeLibs};
-use powdr::riscv_executor::{write_executor_csv, ProfilerOptions};
+use powdr::riscv_executor::{
+    step_trace, write_executor_csv, ProfilerOptions, StepTraceFormat, StepTraceOptions,
+};
 use powdr::Pipeline;
 
 use std::ffi::OsStr;
@@ -45,6 +47,23 @@ impl FieldArgument {
     }
 }
 
+#[derive(Clone, EnumString, EnumVariantNames, Display)]
+pub enum TraceFormatArgument {
+    #[strum(serialize = "jsonl")]
+    Jsonl,
+    #[strum(serialize = "binary")]
+    Binary,
+}
+
+impl TraceFormatArgument {
+    pub fn as_step_trace_format(&self) -> StepTraceFormat {
+        match self {
+            TraceFormatArgument::Jsonl => StepTraceFormat::Jsonl,
+            TraceFormatArgument::Binary => StepTraceFormat::Binary,
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "powdr-rs", author, version, about, long_about = None)]
 struct Cli {
@@ -149,6 +168,29 @@ enum Commands {
         #[arg(long)]
         #[arg(default_value_t = false)]
         generate_callgrind: bool,
+
+        /// Wait for GDB to connect on the given local port and run the execution under its control
+        #[arg(long)]
+        gdb: Option<u16>,
+
+        /// Write a per-step execution trace to the given file
+        #[arg(long)]
+        trace: Option<String>,
+
+        /// The format of the execution trace
+        #[arg(long)]
+        #[arg(default_value_t = TraceFormatArgument::Jsonl)]
+        #[arg(value_parser = clap_enum_variants!(TraceFormatArgument))]
+        trace_format: TraceFormatArgument,
+    },
+    /// Compare two execution traces written by `execute --trace` and report
+    /// the first step at which they differ.
+    TraceDiff {
+        /// The first trace file
+        left: String,
+
+        /// The second trace file
+        right: String,
     },
     /// Execute and generate a valid witness for a RISCV powdr-asm file with the given inputs.
     Witgen {
@@ -280,6 +322,9 @@ fn run_command(command: Commands) {
             output_directory,
             generate_flamegraph,
             generate_callgrind,
+            gdb,
+            trace,
+            trace_format,
         } => {
             let profiling = if generate_callgrind || generate_flamegraph {
                 Some(ProfilerOptions {
@@ -298,9 +343,15 @@ fn run_command(command: Commands) {
                 Path::new(&file),
                 split_inputs(&inputs),
                 Path::new(&output_directory),
-                profiling
+                profiling,
+                gdb,
+                trace.map(|path| StepTraceOptions {
+                    path: path.into(),
+                    format: trace_format.as_step_trace_format(),
+                })
             ))
         }
+        Commands::TraceDiff { left, right } => trace_diff(Path::new(&left), Path::new(&right)),
         Commands::Witgen {
             file,
             field,
@@ -382,6 +433,8 @@ fn execute_fast<F: FieldElement>(
     inputs: Vec<F>,
     output_dir: &Path,
     profiling: Option<ProfilerOptions>,
+    gdb_port: Option<u16>,
+    step_trace: Option<StepTraceOptions>,
 ) -> Result<(), Vec<String>> {
     let mut pipeline = Pipeline::<F>::default()
         .from_asm_file(file_name.to_path_buf())
@@ -392,13 +445,36 @@ fn execute_fast<F: FieldElement>(
 
     let start = Instant::now();
 
-    let trace_len = powdr::riscv_executor::execute_fast::<F>(
-        &asm,
-        powdr::riscv_executor::MemoryState::new(),
-        pipeline.data_callback().unwrap(),
-        &[],
-        profiling,
-    );
+    let trace_len = match gdb_port {
+        Some(port) => {
+            if profiling.is_some() {
+                return Err(vec![
+                    "Profiling is not supported when debugging with GDB".to_string()
+                ]);
+            }
+            if step_trace.is_some() {
+                return Err(vec![
+                    "Execution tracing is not supported when debugging with GDB".to_string(),
+                ]);
+            }
+            powdr::riscv_executor::execute_fast_with_gdb::<F>(
+                &asm,
+                powdr::riscv_executor::MemoryState::new(),
+                pipeline.data_callback().unwrap(),
+                &[],
+                port,
+            )
+            .map_err(|e| vec![format!("Error while debugging with GDB: {e}")])?
+        }
+        None => powdr::riscv_executor::execute_fast::<F>(
+            &asm,
+            powdr::riscv_executor::MemoryState::new(),
+            pipeline.data_callback().unwrap(),
+            &[],
+            profiling,
+            step_trace,
+        ),
+    };
 
     let duration = start.elapsed();
     log::info!("Executor done in: {:?}", duration);
@@ -406,6 +482,24 @@ fn execute_fast<F: FieldElement>(
     Ok(())
 }
 
+fn trace_diff(left: &Path, right: &Path) -> Result<(), Vec<String>> {
+    let divergence = step_trace::first_divergence_in_files(left, right)
+        .map_err(|e| vec![format!("Error reading execution traces: {e}")])?;
+    match divergence {
+        None => log::info!("The execution traces are identical."),
+        Some(divergence) => {
+            let describe = |step: Option<step_trace::TraceStep>| match step {
+                Some(step) => format!("{step:?}"),
+                None => "<end of trace>".to_string(),
+            };
+            log::info!("The execution traces diverge at step {}:", divergence.step);
+            log::info!("  {}: {}", left.display(), describe(divergence.left));
+            log::info!("  {}: {}", right.display(), describe(divergence.right));
+        }
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 fn execute<F: FieldElement>(
     file_name: &Path,
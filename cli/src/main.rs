@@ -152,6 +152,7 @@ enum Commands {
 
         /// Backend options. Halo2: "poseidon", "snark_single" or "snark_aggr".
         /// EStark and PilStarkCLI: "stark_gl", "stark_bn" or "snark_bn".
+        /// Mock: "report" or "report=json" to explain failing constraints.
         #[arg(long)]
         backend_options: Option<String>,
 
@@ -202,6 +203,7 @@ enum Commands {
 
         /// Backend options. Halo2: "poseidon", "snark_single" or "snark_aggr".
         /// EStark and PilStarkCLI: "stark_gl", "stark_bn" or "snark_bn".
+        /// Mock: "report" or "report=json" to explain failing constraints.
         #[arg(long)]
         backend_options: Option<String>,
 
@@ -244,6 +246,7 @@ enum Commands {
 
         /// Backend options. Halo2: "poseidon", "snark_single" or "snark_aggr".
         /// EStark and PilStarkCLI: "stark_gl", "stark_bn" or "snark_bn".
+        /// Mock: "report" or "report=json" to explain failing constraints.
         #[arg(long)]
         backend_options: Option<String>,
 
@@ -287,6 +290,7 @@ enum Commands {
 
         /// Backend options. Halo2: "poseidon", "snark_single" or "snark_aggr".
         /// EStark and PilStarkCLI: "stark_gl", "stark_bn" or "snark_bn".
+        /// Mock: "report" or "report=json" to explain failing constraints.
         #[arg(long)]
         backend_options: Option<String>,
 
@@ -323,6 +327,7 @@ enum Commands {
 
         /// Backend options. Halo2: "poseidon", "snark_single" or "snark_aggr".
         /// EStark and PilStarkCLI: "stark_gl", "stark_bn" or "snark_bn".
+        /// Mock: "report" or "report=json" to explain failing constraints.
         #[arg(long)]
         backend_options: Option<String>,
 
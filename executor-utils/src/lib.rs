@@ -6,6 +6,10 @@ use std::sync::Arc;
 
 use powdr_number::{DegreeType, FieldElement};
 
+mod multiplicities;
+
+pub use multiplicities::lookup_multiplicities;
+
 /// A callback that computes an updated witness, given:
 /// - The PIL for the current machine.
 /// - The current witness.
@@ -16,12 +20,41 @@ pub type WitgenCallbackFn<T> = Arc<
         + Sync,
 >;
 
+/// A function that maps the challenges drawn after stage `stage - 1` to the
+/// challenges the next stage is computed with.
+pub type ShareChallengesFn<T> = Arc<dyn Fn(BTreeMap<u64, T>, u8) -> BTreeMap<u64, T> + Send + Sync>;
+
 #[derive(Clone)]
-pub struct WitgenCallback<T>(WitgenCallbackFn<T>);
+pub struct WitgenCallback<T> {
+    next_stage_witness: WitgenCallbackFn<T>,
+    share_challenges: Option<ShareChallengesFn<T>>,
+}
 
 impl<T: FieldElement> WitgenCallback<T> {
     pub fn new(f: WitgenCallbackFn<T>) -> Self {
-        WitgenCallback(f)
+        WitgenCallback {
+            next_stage_witness: f,
+            share_challenges: None,
+        }
+    }
+
+    /// Sets the function that replaces the challenges a prover draws by the ones
+    /// it computes the next stage with, e.g. when they are shared by several proofs.
+    pub fn with_share_challenges(self, f: ShareChallengesFn<T>) -> Self {
+        WitgenCallback {
+            share_challenges: Some(f),
+            ..self
+        }
+    }
+
+    /// Returns the challenges to compute stage `stage` with, given the ones the
+    /// prover drew after the previous stage. These are the drawn challenges,
+    /// unless they are shared with other proofs.
+    pub fn share_challenges(&self, challenges: BTreeMap<u64, T>, stage: u8) -> BTreeMap<u64, T> {
+        match &self.share_challenges {
+            Some(f) => f(challenges, stage),
+            None => challenges,
+        }
     }
 
     /// Computes the next-stage witness, given the current witness and challenges.
@@ -32,7 +65,7 @@ impl<T: FieldElement> WitgenCallback<T> {
         challenges: BTreeMap<u64, T>,
         stage: u8,
     ) -> Vec<(String, Vec<T>)> {
-        (self.0)(pil, current_witness, challenges, stage)
+        (self.next_stage_witness)(pil, current_witness, challenges, stage)
     }
 }
 
@@ -0,0 +1,52 @@
+use powdr_ast::analyzed::{AlgebraicExpression, SelectedExpressions};
+use powdr_number::FieldElement;
+use std::collections::BTreeMap;
+
+/// Computes the multiplicity column of the lookup `left in right` on a trace
+/// with `size` rows, i.e. for each row of the right-hand side, the sum of the
+/// selectors of the rows of the left-hand side looking it up.
+/// `evaluate` returns the value of an expression in a row.
+/// Fails if a tuple of the left-hand side is not found in the right-hand side.
+pub fn lookup_multiplicities<T: FieldElement>(
+    id: u64,
+    left: &SelectedExpressions<T>,
+    right: &SelectedExpressions<T>,
+    size: usize,
+    evaluate: impl Fn(&AlgebraicExpression<T>, usize) -> T,
+) -> Result<Vec<T>, String> {
+    // the row, the selector value and the tuple of all rows with a non-zero selector
+    let selected_tuples = |selected: &SelectedExpressions<T>| {
+        (0..size).filter_map(|row| {
+            let selector = evaluate(&selected.selector, row);
+            (!selector.is_zero()).then(|| {
+                let tuple = selected
+                    .expressions
+                    .iter()
+                    .map(|e| evaluate(e, row))
+                    .collect::<Vec<_>>();
+                (row, selector, tuple)
+            })
+        })
+    };
+
+    let mut rows_by_tuple = BTreeMap::new();
+    for (row, _, tuple) in selected_tuples(right) {
+        rows_by_tuple.entry(tuple).or_insert(row);
+    }
+
+    let mut multiplicities = vec![T::zero(); size];
+    for (row, selector, tuple) in selected_tuples(left) {
+        let Some(target) = rows_by_tuple.get(&tuple) else {
+            return Err(format!(
+                "Lookup {id} is not satisfied in row {row}: ({}) not found",
+                tuple
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        };
+        multiplicities[*target] += selector;
+    }
+    Ok(multiplicities)
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
 use powdr_ast::analyzed::{Analyzed, PolyID};
 use powdr_number::FieldElement;
@@ -11,23 +11,28 @@ use super::VariablySizedColumn;
 /// Ignores all columns where the compilation fails.
 pub fn generate_values<T: FieldElement>(
     analyzed: &Analyzed<T>,
+    cache_dir: Option<&Path>,
 ) -> HashMap<(String, PolyID), VariablySizedColumn<T>> {
-    let compiled_pil = match powdr_jit_compiler::compile(analyzed, &symbols_to_compile(analyzed)) {
-        Err(err) => {
-            // TODO this should be changed back to Error after the introduction of the ToCol trait.
-            log::debug!("Failed to compile some constant columns: {}", err);
-            return HashMap::new();
-        }
-        Ok(fun_map) => fun_map,
-    };
+    let compiled_pil =
+        match powdr_jit_compiler::compile(analyzed, &symbols_to_compile(analyzed), cache_dir) {
+            Err(err) => {
+                // TODO this should be changed back to Error after the introduction of the ToCol trait.
+                log::debug!("Failed to compile some constant columns: {}", err);
+                return HashMap::new();
+            }
+            Ok(fun_map) => fun_map,
+        };
 
     analyzed
         .constant_polys_in_source_order()
-        .filter_map(|(symbol, _)| {
-            let fun = compiled_pil.get_fixed_column(symbol.absolute_name.as_str())?;
-            Some((symbol, fun))
+        .flat_map(|(symbol, _)| {
+            let compiled_pil = &compiled_pil;
+            symbol.array_elements().filter_map(move |(name, poly_id)| {
+                let fun = compiled_pil.get_fixed_column(&name)?;
+                Some((symbol, name, poly_id, fun))
+            })
         })
-        .map(|(symbol, fun)| {
+        .map(|(symbol, name, poly_id, fun)| {
             let column_values: Vec<Vec<T>> = symbol
                 .degree
                 .unwrap()
@@ -44,10 +49,7 @@ pub fn generate_values<T: FieldElement>(
                 })
                 .collect();
 
-            (
-                (symbol.absolute_name.clone(), symbol.into()),
-                column_values.into(),
-            )
+            ((name, poly_id), column_values.into())
         })
         .collect()
 }
@@ -55,8 +57,6 @@ pub fn generate_values<T: FieldElement>(
 fn symbols_to_compile<T>(analyzed: &Analyzed<T>) -> Vec<&str> {
     analyzed
         .constant_polys_in_source_order()
-        .filter_map(|(symbol, value)| {
-            (!symbol.is_array() && value.is_some()).then_some(symbol.absolute_name.as_str())
-        })
+        .filter_map(|(symbol, value)| value.is_some().then_some(symbol.absolute_name.as_str()))
         .collect()
 }
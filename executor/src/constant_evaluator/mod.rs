@@ -1,3 +1,5 @@
+use std::path::Path;
+
 pub use data_structures::{get_uniquely_sized, get_uniquely_sized_cloned, VariablySizedColumn};
 use itertools::Itertools;
 use powdr_ast::analyzed::Analyzed;
@@ -13,6 +15,15 @@ mod jit_compiler;
 /// Arrays of columns are flattened, the name of the `i`th array element
 /// is `name[i]`.
 pub fn generate<T: FieldElement>(analyzed: &Analyzed<T>) -> Vec<(String, VariablySizedColumn<T>)> {
+    generate_with_jit_cache_dir(analyzed, None)
+}
+
+/// Like [generate], but caches JIT-compiled code in the given directory
+/// (see [powdr_jit_compiler::call_cargo]).
+pub fn generate_with_jit_cache_dir<T: FieldElement>(
+    analyzed: &Analyzed<T>,
+    jit_cache_dir: Option<&Path>,
+) -> Vec<(String, VariablySizedColumn<T>)> {
     let max_degree = analyzed
         .constant_polys_in_source_order()
         .map(|(poly, _)| poly.degree.unwrap().max)
@@ -21,7 +32,7 @@ pub fn generate<T: FieldElement>(analyzed: &Analyzed<T>) -> Vec<(String, Variabl
 
     let mut fixed_cols = Default::default();
     if max_degree > (1 << 18) {
-        fixed_cols = jit_compiler::generate_values(analyzed);
+        fixed_cols = jit_compiler::generate_values(analyzed, jit_cache_dir);
     }
     let mut used_interpreter = false;
     for (poly, value) in analyzed.constant_polys_in_source_order() {
@@ -75,7 +86,7 @@ pub fn generate<T: FieldElement>(analyzed: &Analyzed<T>) -> Vec<(String, Variabl
 pub fn generate_only_via_jit<T: FieldElement>(
     analyzed: &Analyzed<T>,
 ) -> Vec<(String, VariablySizedColumn<T>)> {
-    jit_compiler::generate_values(analyzed)
+    jit_compiler::generate_values(analyzed, None)
         .into_iter()
         .sorted_by_key(|((_, id), _)| *id)
         .map(|((name, _), values)| (name, values))
@@ -105,13 +105,43 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> BlockProcessor<'a, 'c, T, Q>
 
         match self.processor.finished_outer_query() {
             true => Ok(EvalValue::complete(outer_assignments)),
-            false => Ok(EvalValue::incomplete_with_constraints(
-                outer_assignments,
-                IncompleteCause::BlockMachineLookupIncomplete,
-            )),
+            false => {
+                if log::log_enabled!(log::Level::Debug) {
+                    self.log_first_incomplete_row(&is_identity_complete);
+                }
+                Ok(EvalValue::incomplete_with_constraints(
+                    outer_assignments,
+                    IncompleteCause::BlockMachineLookupIncomplete,
+                ))
+            }
         }
     }
 
+    /// Logs diagnostics for the first row of the block that has incomplete identities.
+    fn log_first_incomplete_row(&self, is_identity_complete: &[Vec<bool>]) {
+        // The first and last rows belong to the neighboring blocks.
+        let Some((row_index, complete)) = is_identity_complete
+            .iter()
+            .enumerate()
+            .take(self.processor.len() - 1)
+            .skip(1)
+            .find(|(_, complete)| complete.contains(&false))
+        else {
+            return;
+        };
+        let incomplete_identities = self
+            .identities
+            .iter()
+            .zip(complete)
+            .filter(|(_, is_complete)| !**is_complete)
+            .map(|(identity, _)| *identity);
+        log::debug!(
+            "Could not complete the block:\n{}",
+            self.processor
+                .diagnose_row(row_index, incomplete_identities)
+        );
+    }
+
     /// Returns the updated data and values for publics
     pub fn finish(self) -> SolverState<'a, T> {
         self.processor.finish()
@@ -252,4 +282,52 @@ mod tests {
 
         solve_and_assert::<GoldilocksField>(src, &[(7, "Fibonacci::y", 34)]);
     }
+
+    #[test]
+    fn diagnose_stuck_row() {
+        let src = r#"
+            let N: int = 4;
+
+            namespace Stuck(N);
+                col witness x, y, z;
+
+                // Neither x nor y can be determined.
+                x + y = 3;
+                // z cannot be solved for, because the identity is not affine.
+                z * z = 4;
+        "#;
+
+        do_with_processor(
+            src,
+            unused_query_callback(),
+            |processor: BlockProcessor<GoldilocksField, _>, _, _, _| {
+                let identities = processor.identities.to_vec();
+                let diagnostics = processor.processor.diagnose_row(1, identities);
+
+                let unknown_cells = diagnostics
+                    .identities
+                    .iter()
+                    .map(|identity| {
+                        identity
+                            .unknown_cells
+                            .iter()
+                            .map(|cell| cell.to_string())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    unknown_cells,
+                    vec![vec!["Stuck::x", "Stuck::y"], vec!["Stuck::z"]]
+                );
+
+                // z has to be provided, and one of x and y.
+                let missing_inputs = diagnostics
+                    .missing_inputs
+                    .iter()
+                    .map(|input| input.cell.to_string())
+                    .collect::<Vec<_>>();
+                assert_eq!(missing_inputs, vec!["Stuck::x", "Stuck::z"]);
+            },
+        )
+    }
 }
@@ -1,25 +1,23 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use fp2::Fp2;
 use itertools::Itertools;
 use num_traits::{One, Zero};
 use powdr_ast::analyzed::{Analyzed, Identity, PhantomBusInteractionIdentity};
 use powdr_executor_utils::VariablySizedColumn;
-use powdr_number::{DegreeType, FieldElement};
+use powdr_number::{batch, DegreeType, FieldElement, Fp2};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::witgen::evaluators::expression_evaluator::ExpressionEvaluator;
 
 use super::evaluators::expression_evaluator::OwnedTraceValues;
 
-mod fp2;
-
 /// Witness generator for the second-stage bus accumulator.
 pub struct BusAccumulatorGenerator<'a, T> {
     pil: &'a Analyzed<T>,
     bus_interactions: Vec<&'a PhantomBusInteractionIdentity<T>>,
     trace_values: OwnedTraceValues<T>,
-    powers_of_alpha: Vec<Fp2<T>>,
+    /// The coefficients of alpha^(n-1), ..., alpha^0, where n is the maximal tuple size.
+    reversed_powers_of_alpha: [Vec<T>; 2],
     beta: Fp2<T>,
 }
 
@@ -68,15 +66,26 @@ impl<'a, T: FieldElement> BusAccumulatorGenerator<'a, T> {
             .max()
             .unwrap();
 
+        assert!(
+            Fp2::<T>::is_irreducible(),
+            "The quadratic extension of the field is not a field"
+        );
         let alpha = Fp2::new(challenges[&1], challenges[&2]);
         let beta = Fp2::new(challenges[&3], challenges[&4]);
         let powers_of_alpha = powers_of_alpha(alpha, max_tuple_size);
+        let reversed_powers_of_alpha = [0, 1].map(|c| {
+            powers_of_alpha
+                .iter()
+                .rev()
+                .map(|power| power.0[c])
+                .collect::<Vec<_>>()
+        });
 
         Self {
             pil,
             bus_interactions,
             trace_values,
-            powers_of_alpha,
+            reversed_powers_of_alpha,
             beta,
         }
     }
@@ -104,10 +113,8 @@ impl<'a, T: FieldElement> BusAccumulatorGenerator<'a, T> {
         let empty_challenges = BTreeMap::new();
 
         let size = self.trace_values.height();
-        let mut folded1 = vec![T::zero(); size];
-        let mut folded2 = vec![T::zero(); size];
-        let mut acc1 = vec![T::zero(); size];
-        let mut acc2 = vec![T::zero(); size];
+        let mut folded = Vec::with_capacity(size);
+        let mut multiplicities = Vec::with_capacity(size);
 
         for i in 0..size {
             let mut evaluator = ExpressionEvaluator::new(
@@ -115,12 +122,7 @@ impl<'a, T: FieldElement> BusAccumulatorGenerator<'a, T> {
                 &intermediate_definitions,
                 &empty_challenges,
             );
-            let current_acc = if i == 0 {
-                Fp2::zero()
-            } else {
-                Fp2::new(acc1[i - 1], acc2[i - 1])
-            };
-            let multiplicity = evaluator.evaluate(&bus_interaction.multiplicity);
+            multiplicities.push(evaluator.evaluate(&bus_interaction.multiplicity));
 
             let tuple = bus_interaction
                 .tuple
@@ -128,17 +130,33 @@ impl<'a, T: FieldElement> BusAccumulatorGenerator<'a, T> {
                 .iter()
                 .map(|r| evaluator.evaluate(r))
                 .collect::<Vec<_>>();
-            let folded = self.beta - self.fingerprint(&tuple);
+            folded.push(self.beta - self.fingerprint(&tuple));
+        }
 
-            let new_acc = match multiplicity.is_zero() {
-                true => current_acc,
-                false => current_acc + folded.inverse() * multiplicity,
-            };
+        // Only the rows with a non-zero multiplicity contribute to the accumulator,
+        // so only their inverses are needed.
+        let mut inverses = folded
+            .iter()
+            .zip_eq(&multiplicities)
+            .filter(|(_, multiplicity)| !multiplicity.is_zero())
+            .map(|(folded, _)| *folded)
+            .collect::<Vec<_>>();
+        batch::inverse_in_place(&mut inverses);
+        let mut inverses = inverses.into_iter();
+
+        let mut folded1 = vec![T::zero(); size];
+        let mut folded2 = vec![T::zero(); size];
+        let mut acc1 = vec![T::zero(); size];
+        let mut acc2 = vec![T::zero(); size];
 
-            folded1[i] = folded.0;
-            folded2[i] = folded.1;
-            acc1[i] = new_acc.0;
-            acc2[i] = new_acc.1;
+        let mut acc = Fp2::zero();
+        for (i, (folded, multiplicity)) in folded.into_iter().zip(multiplicities).enumerate() {
+            if !multiplicity.is_zero() {
+                acc += inverses.next().unwrap() * multiplicity;
+            }
+
+            [folded1[i], folded2[i]] = folded.0;
+            [acc1[i], acc2[i]] = acc.0;
         }
 
         vec![folded1, folded2, acc1, acc2]
@@ -146,11 +164,14 @@ impl<'a, T: FieldElement> BusAccumulatorGenerator<'a, T> {
 
     /// Fingerprints a tuples of field elements, using the pre-computed powers of alpha.
     fn fingerprint(&self, tuple: &[T]) -> Fp2<T> {
-        tuple
-            .iter()
-            .zip_eq(self.powers_of_alpha.iter().take(tuple.len()).rev())
-            .map(|(a, b)| (*b) * (*a))
-            .sum()
+        let [powers1, powers2] = self
+            .reversed_powers_of_alpha
+            .each_ref()
+            .map(|powers| &powers[powers.len() - tuple.len()..]);
+        Fp2::new(
+            T::dot_product(tuple, powers1),
+            T::dot_product(tuple, powers2),
+        )
     }
 }
 
@@ -1,9 +1,11 @@
 use std::{
     cell::{RefCell, RefMut},
     collections::{BTreeMap, HashMap},
+    iter,
 };
 
-use powdr_number::FieldElement;
+use powdr_number::{DegreeType, FieldElement};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::witgen::{
     machines::{KnownMachine, LookupCell, Machine},
@@ -40,13 +42,17 @@ impl<'a, T: FieldElement, Q: QueryCallback<T>> MutableState<'a, T, Q> {
         }
     }
 
-    /// Runs the first machine (unless there are no machines) end returns the generated columns.
+    /// Runs the first machine (unless there are no machines) and passes the generated
+    /// columns of each machine to `consume`, one machine at a time, so that the
+    /// columns of all machines never need to be kept in memory at once.
     /// The first machine might call other machines, which is handled automatically.
-    pub fn run(self) -> HashMap<String, Vec<T>> {
+    /// Returns the number of rows each namespace used before padding, for the
+    /// machines that are sized to fit their used rows.
+    pub fn run(self, consume: impl FnMut(HashMap<String, Vec<T>>)) -> BTreeMap<String, DegreeType> {
         if let Some(first_machine) = self.machines.first() {
             first_machine.try_borrow_mut().unwrap().run_timed(&self);
         }
-        self.take_witness_col_values()
+        self.take_witness_col_values(consume)
     }
 
     /// Call the machine responsible for the right-hand-side of an identity given its ID
@@ -84,25 +90,69 @@ impl<'a, T: FieldElement, Q: QueryCallback<T>> MutableState<'a, T, Q> {
     }
 
     /// Extracts the witness column values from the machines.
-    fn take_witness_col_values(self) -> HashMap<String, Vec<T>> {
+    /// Machines whose finalization might call other machines are finalized
+    /// sequentially and passed to `consume` one at a time. The remaining machines
+    /// are finalized in parallel afterwards and passed to `consume` in their
+    /// original order, so that the result does not depend on the scheduling.
+    /// Returns the number of rows each namespace used before padding.
+    fn take_witness_col_values(
+        self,
+        mut consume: impl FnMut(HashMap<String, Vec<T>>),
+    ) -> BTreeMap<String, DegreeType> {
+        let mut used_rows = BTreeMap::new();
+        let mut record_used_rows =
+            |machine: &KnownMachine<'a, T>, columns: &HashMap<String, Vec<T>>| {
+                let namespace = columns
+                    .keys()
+                    .min()
+                    .and_then(|name| name.rsplit_once("::"))
+                    .map(|(namespace, _)| namespace.to_string());
+                if let (Some(namespace), Some(rows)) = (namespace, machine.used_rows()) {
+                    let entry = used_rows.entry(namespace).or_default();
+                    *entry = rows.max(*entry);
+                }
+            };
+
         // We keep the already processed machines mutably borrowed so that
         // "later" machines do not try to create new rows in already processed
         // machines.
         let mut processed = vec![];
-        self.machines
-            .iter()
-            .flat_map(|machine| {
-                let mut machine = machine
-                    .try_borrow_mut()
-                    .map_err(|_| {
-                        panic!("Recursive machine dependencies while finishing machines.");
-                    })
-                    .unwrap();
-                let columns = machine.take_witness_col_values(&self).into_iter();
+        let mut independent = vec![];
+        for machine in &self.machines {
+            let mut machine = machine
+                .try_borrow_mut()
+                .map_err(|_| {
+                    panic!("Recursive machine dependencies while finishing machines.");
+                })
+                .unwrap();
+            if machine.finalizes_independently() {
+                independent.push(machine);
+            } else {
+                let columns = machine.take_witness_col_values(&self);
+                record_used_rows(&machine, &columns);
+                consume(columns);
                 processed.push(machine);
-                columns
+            }
+        }
+
+        // Independent machines do not call other machines, so each of them
+        // gets a mutable state without any machines.
+        let query_callback = self.query_callback;
+        let columns = independent
+            .iter_mut()
+            .map(|machine| &mut **machine)
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|machine| {
+                let mutable_state = MutableState::new(iter::empty(), query_callback);
+                machine.take_witness_col_values(&mutable_state)
             })
-            .collect()
+            .collect::<Vec<_>>();
+        for (machine, columns) in independent.iter().zip(columns) {
+            record_used_rows(machine, &columns);
+            consume(columns);
+        }
+        used_rows
     }
 
     pub fn query_callback(&self) -> &Q {
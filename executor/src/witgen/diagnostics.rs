@@ -0,0 +1,260 @@
+//! Diagnostics for rows on which witness generation cannot make progress.
+//!
+//! When a machine gets stuck on a row, [StuckRowDiagnostics] records which
+//! identities could not be completed, which unknown cells each of them depends
+//! on and what is known about these cells. It also proposes a small set of
+//! cells that, if they were provided (e.g. by a prover query or a free input),
+//! would allow witness generation to proceed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+};
+
+use powdr_ast::{
+    analyzed::{AlgebraicExpression, PolyID, PolynomialType},
+    indent,
+    parsed::visitor::AllChildren,
+};
+use powdr_number::{DegreeType, FieldElement};
+
+use crate::Identity;
+
+use super::{machines::MachineParts, range_constraints::RangeConstraint, rows::Row, FixedData};
+
+/// A cell of a witness column, on the current or the next row.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cell {
+    pub poly_id: PolyID,
+    pub next: bool,
+    pub name: String,
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.name, if self.next { "'" } else { "" })
+    }
+}
+
+/// An identity that could not be completed, with the unknown cells it depends on.
+pub struct IdentityDiagnostics<'a, T> {
+    pub identity: &'a Identity<T>,
+    pub unknown_cells: BTreeSet<Cell>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MissingInputKind {
+    /// The column has a prover query, but it did not provide a value.
+    QueryWithoutAnswer,
+    /// The column has no prover query.
+    NoQuery,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingInput {
+    pub cell: Cell,
+    pub kind: MissingInputKind,
+}
+
+impl Display for MissingInput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            MissingInputKind::QueryWithoutAnswer => write!(
+                f,
+                "{}: the prover query of the column did not provide a value",
+                self.cell
+            ),
+            MissingInputKind::NoQuery => write!(
+                f,
+                "{}: not determined by the identities, consider a prover query or a free input",
+                self.cell
+            ),
+        }
+    }
+}
+
+pub struct StuckRowDiagnostics<'a, T: FieldElement> {
+    /// The global index of the row.
+    pub row: DegreeType,
+    pub identities: Vec<IdentityDiagnostics<'a, T>>,
+    /// The known range constraints of the unknown cells.
+    pub range_constraints: BTreeMap<Cell, RangeConstraint<T>>,
+    /// The proposed inputs that would allow to make progress.
+    pub missing_inputs: Vec<MissingInput>,
+}
+
+impl<'a, T: FieldElement> StuckRowDiagnostics<'a, T> {
+    /// Analyzes why the given identities could not be completed on the row
+    /// pair (`current`, `next`).
+    pub fn new(
+        fixed_data: &FixedData<'a, T>,
+        parts: &MachineParts<'a, T>,
+        row: DegreeType,
+        current: &Row<T>,
+        next: &Row<T>,
+        identities: impl IntoIterator<Item = &'a Identity<T>>,
+    ) -> Self {
+        let is_unknown = |cell: &Cell| {
+            let row = if cell.next { next } else { current };
+            !row.value_is_known(&cell.poly_id)
+        };
+        let identities = identities
+            .into_iter()
+            .map(|identity| {
+                let mut cells = BTreeSet::new();
+                collect_cells(fixed_data, identity, false, &mut cells);
+                let unknown_cells = cells
+                    .into_iter()
+                    .filter(|cell| parts.witnesses.contains(&cell.poly_id) && is_unknown(cell))
+                    .collect();
+                IdentityDiagnostics {
+                    identity,
+                    unknown_cells,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let range_constraints = identities
+            .iter()
+            .flat_map(|identity| &identity.unknown_cells)
+            .filter_map(|cell| {
+                let row = if cell.next { next } else { current };
+                row.range_constraint(&cell.poly_id)
+                    .or_else(|| {
+                        fixed_data.global_range_constraints.witness_constraints[&cell.poly_id]
+                            .clone()
+                    })
+                    .map(|constraint| (cell.clone(), constraint))
+            })
+            .collect();
+
+        let missing_inputs = propose_missing_inputs(&identities)
+            .into_iter()
+            .map(|cell| {
+                let kind = match fixed_data.witness_cols[&cell.poly_id].query {
+                    Some(_) => MissingInputKind::QueryWithoutAnswer,
+                    None => MissingInputKind::NoQuery,
+                };
+                MissingInput { cell, kind }
+            })
+            .collect();
+
+        Self {
+            row,
+            identities,
+            range_constraints,
+            missing_inputs,
+        }
+    }
+}
+
+/// Collects the cells of all witness columns referenced by the expression,
+/// including those referenced via intermediate columns.
+fn collect_cells<T: FieldElement>(
+    fixed_data: &FixedData<'_, T>,
+    expr: &impl AllChildren<AlgebraicExpression<T>>,
+    next: bool,
+    cells: &mut BTreeSet<Cell>,
+) {
+    for child in expr.all_children() {
+        if let AlgebraicExpression::Reference(reference) = child {
+            match reference.poly_id.ptype {
+                PolynomialType::Committed => {
+                    cells.insert(Cell {
+                        poly_id: reference.poly_id,
+                        next: next || reference.next,
+                        name: reference.name.clone(),
+                    });
+                }
+                PolynomialType::Intermediate => collect_cells(
+                    fixed_data,
+                    &fixed_data.intermediate_definitions[&reference.to_thin()],
+                    next || reference.next,
+                    cells,
+                ),
+                PolynomialType::Constant => {}
+            }
+        }
+    }
+}
+
+/// Greedily proposes a small set of cells that, if known, would leave every
+/// identity with at most one unknown cell, which it could then be solved for.
+/// Identities that are stuck with a single unknown cell cannot be solved for it
+/// (e.g. because they are not affine in it), so that cell is always proposed.
+fn propose_missing_inputs<T>(identities: &[IdentityDiagnostics<'_, T>]) -> Vec<Cell> {
+    let mut remaining = identities
+        .iter()
+        .map(|identity| identity.unknown_cells.clone())
+        .collect::<Vec<_>>();
+    let mut proposed = remaining
+        .iter()
+        .filter(|cells| cells.len() == 1)
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    loop {
+        for cells in &mut remaining {
+            cells.retain(|cell| !proposed.contains(cell));
+        }
+        remaining.retain(|cells| cells.len() > 1);
+
+        // Propose the cell that appears in the most identities.
+        let mut occurrences = BTreeMap::<&Cell, usize>::new();
+        for cell in remaining.iter().flatten() {
+            *occurrences.entry(cell).or_default() += 1;
+        }
+        let Some((cell, _)) = occurrences
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+        else {
+            break;
+        };
+        proposed.insert(cell.clone());
+    }
+    proposed.into_iter().collect()
+}
+
+impl<T: FieldElement> Display for StuckRowDiagnostics<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Witness generation cannot make progress on row {}.",
+            self.row
+        )?;
+        writeln!(
+            f,
+            "Incomplete identities and the unknown cells they depend on:"
+        )?;
+        for identity in &self.identities {
+            writeln!(f, "{}", indent(identity.identity.to_string(), 1))?;
+            if identity.unknown_cells.is_empty() {
+                writeln!(f, "        (no unknown cells)")?;
+            } else {
+                let cells = identity
+                    .unknown_cells
+                    .iter()
+                    .map(|cell| cell.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(f, "        unknown: {}", cells.join(", "))?;
+            }
+        }
+        if !self.range_constraints.is_empty() {
+            writeln!(f, "Known range constraints of the unknown cells:")?;
+            for (cell, constraint) in &self.range_constraints {
+                writeln!(f, "    {cell}: {constraint}")?;
+            }
+        }
+        if !self.missing_inputs.is_empty() {
+            writeln!(
+                f,
+                "Providing the following cells would allow to make progress:"
+            )?;
+            for input in &self.missing_inputs {
+                writeln!(f, "    {input}")?;
+            }
+        }
+        Ok(())
+    }
+}
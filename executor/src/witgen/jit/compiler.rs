@@ -1,5 +1,5 @@
 #![allow(unused)]
-use std::{ffi::c_void, iter, mem, sync::Arc};
+use std::{ffi::c_void, iter, mem, path::Path, sync::Arc};
 
 use auto_enums::auto_enum;
 use itertools::Itertools;
@@ -62,17 +62,20 @@ extern "C" fn call_machine<T: FieldElement, Q: QueryCallback<T>>(
 }
 
 /// Compile the given inferred effects into machine code and load it.
+/// The library is cached in `cache_dir` (see [powdr_jit_compiler::call_cargo]),
+/// which is usually [crate::witgen::FixedData::jit_cache_dir].
 pub fn compile_effects<T: FieldElement>(
     first_column_id: u64,
     column_count: usize,
     known_inputs: &[Variable],
     effects: &[Effect<T, Variable>],
+    cache_dir: Option<&Path>,
 ) -> Result<WitgenFunction<T>, String> {
     let utils = util_code::<T>(first_column_id, column_count)?;
     let witgen_code = witgen_code(known_inputs, effects);
     let code = format!("{utils}\n//-------------------------------\n{witgen_code}");
 
-    let lib_path = powdr_jit_compiler::call_cargo(&code)
+    let lib_path = powdr_jit_compiler::call_cargo::<T>(&code, cache_dir)
         .map_err(|e| format!("Failed to compile generated code: {e}"))?;
 
     let library = Arc::new(unsafe { libloading::Library::new(&lib_path.path).unwrap() });
@@ -533,7 +536,7 @@ mod tests {
 
     #[test]
     fn compile_util_code() {
-        compile_effects::<GoldilocksField>(0, 2, &[], &[]).unwrap();
+        compile_effects::<GoldilocksField>(0, 2, &[], &[], None).unwrap();
     }
 
     fn cell(column_name: &str, id: u64, row_offset: i32) -> Variable {
@@ -633,7 +636,7 @@ extern \"C\" fn witgen(
             assignment(&x, number(7)),
             assignment(&y, symbol(&x) + number(2)),
         ];
-        let f = compile_effects(0, 1, &[], &effects).unwrap();
+        let f = compile_effects(0, 1, &[], &effects, None).unwrap();
         let mut data = vec![GoldilocksField::from(0); 2];
         let mut known = vec![0; 1];
         let params = WitgenFunctionParams {
@@ -663,8 +666,8 @@ extern \"C\" fn witgen(
         let row_count = 2;
         let column_count = 2;
         let data_len = column_count * row_count;
-        let f1 = compile_effects(0, column_count, &[], &effects1).unwrap();
-        let f2 = compile_effects(0, column_count, &[], &effects2).unwrap();
+        let f1 = compile_effects(0, column_count, &[], &effects1, None).unwrap();
+        let f2 = compile_effects(0, column_count, &[], &effects2, None).unwrap();
         let mut data = vec![GoldilocksField::from(0); data_len];
         let mut known = vec![0; row_count];
         let params1 = WitgenFunctionParams {
@@ -704,7 +707,7 @@ extern \"C\" fn witgen(
             assignment(&cell("x", 0, 3), number(8).field_div(&-number(2))),
             assignment(&cell("x", 0, 4), (-number(8)).field_div(&-number(2))),
         ];
-        let f = compile_effects(0, 1, &[], &effects).unwrap();
+        let f = compile_effects(0, 1, &[], &effects, None).unwrap();
         let mut data = vec![GoldilocksField::from(0); 5];
         let mut known = vec![0; 5];
         let params = WitgenFunctionParams {
@@ -732,7 +735,7 @@ extern \"C\" fn witgen(
         let z = cell("z", 2, 0);
         let effects = vec![assignment(&x, symbol(&y) * symbol(&z))];
         let known_inputs = vec![y.clone(), z.clone()];
-        let f = compile_effects(0, 3, &known_inputs, &effects).unwrap();
+        let f = compile_effects(0, 3, &known_inputs, &effects, None).unwrap();
         let mut data = vec![
             GoldilocksField::from(0),
             GoldilocksField::from(3),
@@ -760,7 +763,7 @@ extern \"C\" fn witgen(
             assignment(&z, symbol(&x).integer_div(&-number(10))),
         ];
         let known_inputs = vec![x.clone()];
-        let f = compile_effects(0, 3, &known_inputs, &effects).unwrap();
+        let f = compile_effects(0, 3, &known_inputs, &effects, None).unwrap();
         let mut data = vec![
             GoldilocksField::from(23),
             GoldilocksField::from(0),
@@ -787,7 +790,7 @@ extern \"C\" fn witgen(
         let x_val: GoldilocksField = 7.into();
         let mut y_val: GoldilocksField = 9.into();
         let effects = vec![assignment(&y, symbol(&x) + number(7))];
-        let f = compile_effects(0, 1, &[x], &effects).unwrap();
+        let f = compile_effects(0, 1, &[x], &effects, None).unwrap();
         let mut data = vec![];
         let mut known = vec![];
         let mut params = vec![LookupCell::Input(&x_val), LookupCell::Output(&mut y_val)];
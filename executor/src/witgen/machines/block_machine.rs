@@ -78,6 +78,8 @@ pub struct BlockMachine<'a, T: FieldElement> {
     jit_processor: JitProcessor<'a, T>,
     name: String,
     multiplicity_counter: MultiplicityCounter,
+    /// The number of rows used before padding, known after finalization.
+    used_rows: Option<DegreeType>,
 }
 
 impl<'a, T: FieldElement> BlockMachine<'a, T> {
@@ -137,6 +139,7 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
                 parts.identities.len(),
             ),
             jit_processor: JitProcessor::new(fixed_data, parts.clone(), block_size, latch_row),
+            used_rows: None,
         })
     }
 }
@@ -176,6 +179,17 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
         &self.name
     }
 
+    fn used_rows(&self) -> Option<DegreeType> {
+        self.used_rows
+    }
+
+    fn finalizes_independently(&self) -> bool {
+        // For permutations, the identities are solved on the dummy block,
+        // which might call other machines.
+        !(matches!(self.connection_type, ConnectionKind::Permutation)
+            && self.parts.calls_other_machines())
+    }
+
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         mutable_state: &'b MutableState<'a, T, Q>,
@@ -191,6 +205,7 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
                     "Machine {} is never used at runtime, so we remove it.",
                     self.name
                 );
+                self.used_rows = Some(0);
                 // Return empty columns for all witnesses.
                 return self
                     .parts
@@ -203,13 +218,11 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
                     .collect();
             }
         }
-        self.degree = compute_size_and_log(
-            &self.name,
-            // At this point, the data still contains the dummy block, which will be removed below.
-            // Therefore, we subtract the block size here.
-            self.data.len() - self.block_size,
-            self.degree_range,
-        );
+        // At this point, the data still contains the dummy block, which will be removed below.
+        // Therefore, we subtract the block size here.
+        let used_rows = self.data.len() - self.block_size;
+        self.used_rows = Some(used_rows as DegreeType);
+        self.degree = compute_size_and_log(&self.name, used_rows, self.degree_range);
 
         if matches!(self.connection_type, ConnectionKind::Permutation) {
             // We have to make sure that *all* selectors are 0 in the dummy block,
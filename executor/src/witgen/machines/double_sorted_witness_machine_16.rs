@@ -80,6 +80,8 @@ fn split_column_name(name: &str) -> (&str, &str) {
 pub struct DoubleSortedWitnesses16<'a, T: FieldElement> {
     degree_range: DegreeRange,
     degree: DegreeType,
+    /// The number of rows used before padding, known after finalization.
+    used_rows: Option<DegreeType>,
     //key_col: String,
     /// Position of the witness columns in the data.
     /// The key column has a position of usize::max
@@ -203,6 +205,7 @@ impl<'a, T: FieldElement> DoubleSortedWitnesses16<'a, T> {
             namespace,
             parts: parts.clone(), // TODO is this really unused?
             degree,
+            used_rows: None,
             diff_columns_base,
             has_bootloader_write_column,
             trace: Default::default(),
@@ -240,6 +243,14 @@ impl<'a, T: FieldElement> Machine<'a, T> for DoubleSortedWitnesses16<'a, T> {
         self.process_plookup_internal(identity_id, caller_rows)
     }
 
+    fn finalizes_independently(&self) -> bool {
+        true
+    }
+
+    fn used_rows(&self) -> Option<DegreeType> {
+        self.used_rows
+    }
+
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         _mutable_state: &'b MutableState<'a, T, Q>,
@@ -306,6 +317,7 @@ impl<'a, T: FieldElement> Machine<'a, T> for DoubleSortedWitnesses16<'a, T> {
             set_selector(None);
         }
 
+        self.used_rows = Some(addr.len() as DegreeType);
         self.degree = compute_size_and_log(&self.name, addr.len(), self.degree_range);
 
         while addr.len() < self.degree as usize {
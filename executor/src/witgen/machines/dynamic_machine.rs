@@ -32,6 +32,10 @@ pub struct DynamicMachine<'a, T: FieldElement> {
     name: String,
     degree: DegreeType,
     multiplicity_counter: MultiplicityCounter,
+    /// The number of rows used before a loop was detected, if any.
+    loop_start: Option<DegreeType>,
+    /// The number of rows used before padding, known after finalization.
+    used_rows: Option<DegreeType>,
 }
 
 impl<'a, T: FieldElement> Machine<'a, T> for DynamicMachine<'a, T> {
@@ -52,6 +56,10 @@ impl<'a, T: FieldElement> Machine<'a, T> for DynamicMachine<'a, T> {
         &self.name
     }
 
+    fn used_rows(&self) -> Option<DegreeType> {
+        self.used_rows
+    }
+
     /// Runs the machine without any arguments from the first row.
     fn run<Q: QueryCallback<T>>(&mut self, mutable_state: &MutableState<'a, T, Q>) {
         assert!(self.data.is_empty());
@@ -114,6 +122,9 @@ impl<'a, T: FieldElement> Machine<'a, T> for DynamicMachine<'a, T> {
     ) -> HashMap<String, Vec<T>> {
         log::debug!("Finalizing VM: {}", self.name());
 
+        // The last row is the first row of the next call, which is not used yet.
+        let used_rows = (self.data.len() as DegreeType).saturating_sub(1);
+        self.used_rows = Some(self.loop_start.unwrap_or(used_rows).min(used_rows));
         self.fill_remaining_rows(mutable_state);
         self.fix_first_row();
 
@@ -148,6 +159,8 @@ impl<'a, T: FieldElement> DynamicMachine<'a, T> {
             publics: Default::default(),
             latch,
             multiplicity_counter,
+            loop_start: None,
+            used_rows: None,
         }
     }
 
@@ -250,10 +263,11 @@ impl<'a, T: FieldElement> DynamicMachine<'a, T> {
             processor = processor.with_outer_query(outer_query);
         }
         let eval_value = processor.run(is_main_run);
-        let (updated_data, degree) = processor.finish();
+        let (updated_data, degree, loop_start) = processor.finish();
 
         // The processor might have detected a loop, in which case the degree has changed
         self.degree = degree;
+        self.loop_start = self.loop_start.or(loop_start);
 
         ProcessResult {
             eval_value,
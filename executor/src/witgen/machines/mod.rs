@@ -3,7 +3,9 @@ use std::fmt::Display;
 
 use dynamic_machine::DynamicMachine;
 use powdr_ast::analyzed::{
-    self, AlgebraicExpression, DegreeRange, PermutationIdentity, PhantomPermutationIdentity, PolyID,
+    self, AlgebraicBinaryOperation, AlgebraicBinaryOperator, AlgebraicExpression,
+    AlgebraicUnaryOperation, AlgebraicUnaryOperator, DegreeRange, PermutationIdentity,
+    PhantomPermutationIdentity, PolyID,
 };
 
 use powdr_number::DegreeType;
@@ -115,6 +117,19 @@ pub trait Machine<'a, T: FieldElement>: Send + Sync {
         mutable_state: &'b MutableState<'a, T, Q>,
     ) -> HashMap<String, Vec<T>>;
 
+    /// Returns true if [Machine::take_witness_col_values] does not call other machines,
+    /// which allows finalizing this machine in parallel with other machines.
+    fn finalizes_independently(&self) -> bool {
+        false
+    }
+
+    /// Returns the number of rows the machine used before its columns were padded,
+    /// if its size is chosen to fit the used rows.
+    /// Only available after [Machine::take_witness_col_values] was called.
+    fn used_rows(&self) -> Option<DegreeType> {
+        None
+    }
+
     /// Returns the identity IDs of the connecting identities that this machine is responsible for.
     fn identity_ids(&self) -> Vec<u64>;
 }
@@ -252,6 +267,32 @@ impl<'a, T: FieldElement> Machine<'a, T> for KnownMachine<'a, T> {
         }
     }
 
+    fn finalizes_independently(&self) -> bool {
+        match self {
+            KnownMachine::SecondStageMachine(m) => m.finalizes_independently(),
+            KnownMachine::SortedWitnesses(m) => m.finalizes_independently(),
+            KnownMachine::DoubleSortedWitnesses16(m) => m.finalizes_independently(),
+            KnownMachine::DoubleSortedWitnesses32(m) => m.finalizes_independently(),
+            KnownMachine::WriteOnceMemory(m) => m.finalizes_independently(),
+            KnownMachine::BlockMachine(m) => m.finalizes_independently(),
+            KnownMachine::DynamicMachine(m) => m.finalizes_independently(),
+            KnownMachine::FixedLookup(m) => m.finalizes_independently(),
+        }
+    }
+
+    fn used_rows(&self) -> Option<DegreeType> {
+        match self {
+            KnownMachine::SecondStageMachine(m) => m.used_rows(),
+            KnownMachine::SortedWitnesses(m) => m.used_rows(),
+            KnownMachine::DoubleSortedWitnesses16(m) => m.used_rows(),
+            KnownMachine::DoubleSortedWitnesses32(m) => m.used_rows(),
+            KnownMachine::WriteOnceMemory(m) => m.used_rows(),
+            KnownMachine::BlockMachine(m) => m.used_rows(),
+            KnownMachine::DynamicMachine(m) => m.used_rows(),
+            KnownMachine::FixedLookup(m) => m.used_rows(),
+        }
+    }
+
     fn identity_ids(&self) -> Vec<u64> {
         match self {
             KnownMachine::SecondStageMachine(m) => m.identity_ids(),
@@ -412,6 +453,19 @@ impl<'a, T: FieldElement> MachineParts<'a, T> {
         self.connections.keys().cloned().collect()
     }
 
+    /// Returns true if any of the identities of this machine is a connection
+    /// to another machine, including bus interactions that send to the bus.
+    pub fn calls_other_machines(&self) -> bool {
+        self.identities.iter().any(|identity| match identity {
+            Identity::Lookup(_)
+            | Identity::Permutation(_)
+            | Identity::PhantomLookup(_)
+            | Identity::PhantomPermutation(_) => true,
+            Identity::PhantomBusInteraction(interaction) => !is_negated(&interaction.multiplicity),
+            _ => false,
+        })
+    }
+
     /// Returns the name of a column.
     pub fn column_name(&self, poly_id: &PolyID) -> &str {
         self.fixed_data.column_name(poly_id)
@@ -445,3 +499,22 @@ pub fn compute_size_and_log(name: &str, used_rows: usize, degree_range: DegreeRa
     }
     size
 }
+
+/// Returns true if the expression is syntactically the negation of another
+/// expression. Bus receives negate their multiplicity (see `std::protocols::bus`),
+/// so any bus interaction whose multiplicity is not negated is treated as a send.
+fn is_negated<T: FieldElement>(expr: &AlgebraicExpression<T>) -> bool {
+    match expr {
+        AlgebraicExpression::Number(n) => !n.is_in_lower_half(),
+        AlgebraicExpression::UnaryOperation(AlgebraicUnaryOperation {
+            op: AlgebraicUnaryOperator::Minus,
+            expr,
+        }) => !is_negated(expr),
+        AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
+            left,
+            op: AlgebraicBinaryOperator::Mul,
+            right,
+        }) => is_negated(left) != is_negated(right),
+        _ => false,
+    }
+}
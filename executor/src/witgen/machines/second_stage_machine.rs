@@ -60,6 +60,10 @@ impl<'a, T: FieldElement> Machine<'a, T> for SecondStageMachine<'a, T> {
         panic!("SecondStageMachine can't be called by other machines!")
     }
 
+    fn finalizes_independently(&self) -> bool {
+        true
+    }
+
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         _mutable_state: &'b MutableState<'a, T, Q>,
@@ -268,6 +268,10 @@ impl<'a, T: FieldElement> Machine<'a, T> for WriteOnceMemory<'a, T> {
         self.process_plookup_internal(identity_id, caller_rows)
     }
 
+    fn finalizes_independently(&self) -> bool {
+        true
+    }
+
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         _mutable_state: &'b MutableState<'a, T, Q>,
@@ -1,4 +1,5 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use bus_accumulator::BusAccumulatorGenerator;
@@ -30,6 +31,7 @@ pub(crate) mod analysis;
 mod block_processor;
 mod bus_accumulator;
 mod data_structures;
+mod diagnostics;
 mod eval_result;
 pub mod evaluators;
 mod global_constraints;
@@ -64,6 +66,7 @@ pub struct WitgenCallbackContext<T> {
     /// Arc was moved one level up... but I have to investigate this further.
     fixed_col_values: Arc<Vec<(String, VariablySizedColumn<T>)>>,
     query_callback: Arc<dyn QueryCallback<T>>,
+    jit_cache_dir: Option<PathBuf>,
 }
 
 impl<T: FieldElement> WitgenCallbackContext<T> {
@@ -75,6 +78,15 @@ impl<T: FieldElement> WitgenCallbackContext<T> {
         Self {
             fixed_col_values,
             query_callback,
+            jit_cache_dir: None,
+        }
+    }
+
+    /// Sets the directory of the cache for JIT-compiled code.
+    pub fn with_jit_cache_dir(self, jit_cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            jit_cache_dir,
+            ..self
         }
     }
 
@@ -119,11 +131,30 @@ impl<T: FieldElement> WitgenCallbackContext<T> {
                 pil,
                 current_witness,
                 &self.fixed_col_values,
-                challenges,
+                challenges.clone(),
             )
             .generate();
 
-            current_witness.iter().cloned().chain(bus_columns).collect()
+            let witness = current_witness
+                .iter()
+                .cloned()
+                .chain(bus_columns)
+                .collect::<Vec<_>>();
+            // Other columns of the stage, e.g. the ones introduced by degree
+            // reduction, are solved by the automatic witgen.
+            let is_complete = witness_column_names(pil, stage)
+                .all(|name| witness.iter().any(|(n, _)| n == &name));
+            if is_complete {
+                return witness;
+            }
+            log::debug!("Using automatic stage-1 witgen for the remaining columns.");
+            let size = current_witness.iter().next().unwrap().1.len() as DegreeType;
+            let fixed_col_values = self.select_fixed_columns(pil, size);
+            WitnessGenerator::new(pil, &fixed_col_values, &*self.query_callback)
+                .with_external_witness_values(&witness)
+                .with_challenges(stage, challenges)
+                .with_jit_cache_dir(self.jit_cache_dir.as_deref())
+                .generate()
         } else {
             log::debug!("Using automatic stage-1 witgen.");
             let size = current_witness.iter().next().unwrap().1.len() as DegreeType;
@@ -131,6 +162,7 @@ impl<T: FieldElement> WitgenCallbackContext<T> {
             WitnessGenerator::new(pil, &fixed_col_values, &*self.query_callback)
                 .with_external_witness_values(current_witness)
                 .with_challenges(stage, challenges)
+                .with_jit_cache_dir(self.jit_cache_dir.as_deref())
                 .generate()
         }
     }
@@ -155,6 +187,7 @@ pub struct WitnessGenerator<'a, 'b, T: FieldElement> {
     external_witness_values: &'b [(String, Vec<T>)],
     stage: u8,
     challenges: BTreeMap<u64, T>,
+    jit_cache_dir: Option<&'b Path>,
 }
 
 impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
@@ -170,6 +203,7 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             external_witness_values: &[],
             stage: 0,
             challenges: BTreeMap::new(),
+            jit_cache_dir: None,
         }
     }
 
@@ -191,9 +225,59 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
         }
     }
 
+    /// Sets the directory of the cache for JIT-compiled code. If `None`, the
+    /// one configured in the environment is used (see [powdr_jit_compiler::call_cargo]).
+    pub fn with_jit_cache_dir(self, jit_cache_dir: Option<&'b Path>) -> Self {
+        WitnessGenerator {
+            jit_cache_dir,
+            ..self
+        }
+    }
+
     /// Generates the committed polynomial values
     /// @returns the values (in source order) and the degree of the polynomials.
     pub fn generate(self) -> Vec<(String, Vec<T>)> {
+        self.generate_with_used_rows().0
+    }
+
+    /// Like [Self::generate], but also returns the number of rows each namespace
+    /// used before padding, for the machines that are sized to fit their used rows.
+    pub fn generate_with_used_rows(self) -> (Vec<(String, Vec<T>)>, BTreeMap<String, DegreeType>) {
+        let analyzed = self.analyzed;
+        let stage = self.stage;
+
+        let mut columns = vec![];
+        let used_rows = self.generate_into(&mut columns);
+        let mut columns = columns.into_iter().collect::<HashMap<_, _>>();
+
+        // Order columns according to the order of declaration.
+        let witness_cols = witness_column_names(analyzed, stage)
+            .map(|name| {
+                let column = columns.remove(&name).unwrap();
+                (name, column)
+            })
+            .collect::<Vec<_>>();
+
+        log::debug!("Publics:");
+        for (name, value) in extract_publics(&witness_cols, analyzed) {
+            log::debug!(
+                "  {name:>30}: {}",
+                value
+                    .map(|value| value.to_string())
+                    .unwrap_or_else(|| "Not yet known at this stage".to_string())
+            );
+        }
+        (witness_cols, used_rows)
+    }
+
+    /// Generates the committed polynomial values and passes them to the sink
+    /// machine by machine, as soon as they are complete. In contrast to
+    /// [Self::generate], the columns of a machine can be dropped by the sink
+    /// before the next machine is finalized.
+    /// Every witness column of the current stage is passed exactly once; the
+    /// columns in each batch are in source order.
+    /// Returns the number of rows each namespace used before padding.
+    pub fn generate_into(self, sink: &mut dyn WitnessSink<T>) -> BTreeMap<String, DegreeType> {
         record_start(OUTER_CODE_NAME);
         let fixed = FixedData::new(
             self.analyzed,
@@ -201,7 +285,8 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             self.external_witness_values,
             self.challenges,
             self.stage,
-        );
+        )
+        .with_jit_cache_dir(self.jit_cache_dir.map(Path::to_path_buf));
         let identities = self
             .analyzed
             .identities
@@ -238,75 +323,166 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             global_constraints::set_global_constraints(fixed, &identities);
         let machines = MachineExtractor::new(&fixed).split_out_machines(retained_identities);
 
-        // Run main machine and extract columns from all machines.
-        let mut columns = MutableState::new(machines.into_iter(), &self.query_callback).run();
+        // Only the columns of the current stage are passed to the sink, in source order.
+        let mut pending_columns = witness_column_names(self.analyzed, self.stage)
+            .enumerate()
+            .map(|(index, name)| (name, index))
+            .collect::<HashMap<_, _>>();
+        let mut multiplicities = RangeConstraintMultiplicities::new(&fixed);
+        let mut emit = |columns: Vec<(String, Vec<T>)>| {
+            let mut columns = columns
+                .into_iter()
+                .filter_map(|(name, values)| {
+                    let index = pending_columns.remove(&name)?;
+                    Some((index, (name, values)))
+                })
+                .collect::<Vec<_>>();
+            if !columns.is_empty() {
+                columns.sort_by_key(|(index, _)| *index);
+                sink.consume(columns.into_iter().map(|(_, column)| column).collect());
+            }
+        };
 
-        Self::range_constraint_multiplicity_witgen(&fixed, &mut columns);
+        // Run main machine and pass on the columns of each machine.
+        let used_rows =
+            MutableState::new(machines.into_iter(), &self.query_callback).run(|columns| {
+                let columns = multiplicities.process(columns);
+                emit(columns);
+            });
+        emit(multiplicities.finish());
 
         record_end(OUTER_CODE_NAME);
         reset_and_print_profile_summary();
 
-        // Order columns according to the order of declaration.
-        let witness_cols = self
-            .analyzed
-            .committed_polys_in_source_order()
-            .filter(|(symbol, _)| symbol.stage.unwrap_or_default() <= self.stage.into())
-            .flat_map(|(p, _)| p.array_elements())
-            .map(|(name, _id)| {
-                let column = columns
-                    .remove(&name)
-                    .unwrap_or_else(|| panic!("No machine generated witness for column: {name}"));
-                (name, column)
-            })
-            .collect::<Vec<_>>();
-
-        log::debug!("Publics:");
-        for (name, value) in extract_publics(&witness_cols, self.analyzed) {
-            log::debug!(
-                "  {name:>30}: {}",
-                value
-                    .map(|value| value.to_string())
-                    .unwrap_or_else(|| "Not yet known at this stage".to_string())
-            );
+        if let Some(name) = pending_columns.keys().min() {
+            panic!("No machine generated witness for column: {name}");
         }
-        witness_cols
+        used_rows
     }
+}
 
-    fn range_constraint_multiplicity_witgen(
-        fixed: &FixedData<T>,
-        columns: &mut HashMap<String, Vec<T>>,
-    ) {
-        record_start(RANGE_CONSTRAINT_MULTIPLICITY_WITGEN);
+/// The names of the witness columns of the given stage and all previous
+/// stages, in source order.
+fn witness_column_names<T>(analyzed: &Analyzed<T>, stage: u8) -> impl Iterator<Item = String> + '_ {
+    analyzed
+        .committed_polys_in_source_order()
+        .filter(move |(symbol, _)| symbol.stage.unwrap_or_default() <= stage.into())
+        .flat_map(|(p, _)| p.array_elements())
+        .map(|(name, _id)| name)
+}
 
-        // Several range constraints might point to the same target
-        let mut multiplicity_columns = BTreeMap::new();
+/// A consumer of witness columns, which receives the columns as soon as they are
+/// complete, instead of all at once at the end of witness generation.
+pub trait WitnessSink<T> {
+    /// Receives a batch of complete witness columns, typically all columns of one machine.
+    fn consume(&mut self, columns: Vec<(String, Vec<T>)>);
+}
+
+impl<T> WitnessSink<T> for Vec<(String, Vec<T>)> {
+    fn consume(&mut self, columns: Vec<(String, Vec<T>)>) {
+        self.extend(columns);
+    }
+}
 
-        // Count multiplicities
-        for (source_id, target) in &fixed.global_range_constraints.phantom_range_constraints {
+/// Computes the multiplicity columns of phantom range constraints, while the
+/// columns of the machines are streamed. The multiplicity columns are withheld
+/// until all their source columns have been seen.
+struct RangeConstraintMultiplicities<'a, T: FieldElement> {
+    fixed: &'a FixedData<'a, T>,
+    /// For each multiplicity column, the counts and the number of source
+    /// columns that have not been processed yet.
+    counts: BTreeMap<PolyID, (Vec<u64>, usize)>,
+}
+
+impl<'a, T: FieldElement> RangeConstraintMultiplicities<'a, T> {
+    fn new(fixed: &'a FixedData<'a, T>) -> Self {
+        let mut counts = BTreeMap::new();
+        // Several range constraints might point to the same target
+        for target in fixed
+            .global_range_constraints
+            .phantom_range_constraints
+            .values()
+        {
             let size = fixed.fixed_cols[&target.column]
                 .values
                 .get_uniquely_sized()
                 .unwrap()
                 .len();
-            let multiplicities = multiplicity_columns
+            let (multiplicities, remaining_sources) = counts
                 .entry(target.multiplicity_column)
-                .or_insert_with(|| vec![0; size]);
+                .or_insert_with(|| (vec![0; size], 0));
             assert_eq!(multiplicities.len(), size);
-            for value in columns.get(fixed.column_name(source_id)).unwrap() {
+            *remaining_sources += 1;
+        }
+        Self { fixed, counts }
+    }
+
+    /// Counts the values of range-constrained columns and returns the given
+    /// columns, minus the multiplicity columns, plus all multiplicity columns
+    /// that became complete.
+    fn process(&mut self, mut columns: HashMap<String, Vec<T>>) -> Vec<(String, Vec<T>)> {
+        record_start(RANGE_CONSTRAINT_MULTIPLICITY_WITGEN);
+
+        for (source_id, target) in &self
+            .fixed
+            .global_range_constraints
+            .phantom_range_constraints
+        {
+            let Some(values) = columns.get(self.fixed.column_name(source_id)) else {
+                continue;
+            };
+            let (multiplicities, remaining_sources) =
+                self.counts.get_mut(&target.multiplicity_column).unwrap();
+            for value in values {
                 let index = value.to_degree() as usize;
                 multiplicities[index] += 1;
             }
+            *remaining_sources -= 1;
         }
 
-        // Convert to field elements and insert into columns
-        for (poly_id, values) in multiplicity_columns {
-            columns.insert(
-                fixed.column_name(&poly_id).to_string(),
-                values.into_iter().map(T::from).collect(),
-            );
+        // The values generated by the machine are replaced by the counted values.
+        for poly_id in self.counts.keys() {
+            columns.remove(self.fixed.column_name(poly_id));
         }
+        let complete = self
+            .counts
+            .iter()
+            .filter(|(_, (_, remaining_sources))| *remaining_sources == 0)
+            .map(|(poly_id, _)| *poly_id)
+            .collect::<Vec<_>>();
+        let result = columns
+            .into_iter()
+            .chain(complete.into_iter().map(|poly_id| self.take(poly_id)))
+            .collect();
 
         record_end(RANGE_CONSTRAINT_MULTIPLICITY_WITGEN);
+        result
+    }
+
+    /// Returns the remaining multiplicity columns.
+    fn finish(self) -> Vec<(String, Vec<T>)> {
+        self.counts
+            .into_iter()
+            .map(|(poly_id, (multiplicities, remaining_sources))| {
+                let name = self.fixed.column_name(&poly_id);
+                assert_eq!(
+                    remaining_sources, 0,
+                    "Not all range-constrained columns counted by {name} were generated."
+                );
+                (
+                    name.to_string(),
+                    multiplicities.into_iter().map(T::from).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn take(&mut self, poly_id: PolyID) -> (String, Vec<T>) {
+        let (multiplicities, _) = self.counts.remove(&poly_id).unwrap();
+        (
+            self.fixed.column_name(&poly_id).to_string(),
+            multiplicities.into_iter().map(T::from).collect(),
+        )
     }
 }
 
@@ -341,6 +517,9 @@ pub struct FixedData<'a, T: FieldElement> {
     global_range_constraints: GlobalConstraints<T>,
     intermediate_definitions: BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
     stage: u8,
+    /// The directory of the cache for JIT-compiled code, if it is not the
+    /// one configured in the environment.
+    jit_cache_dir: Option<PathBuf>,
 }
 
 impl<'a, T: FieldElement> FixedData<'a, T> {
@@ -421,9 +600,23 @@ impl<'a, T: FieldElement> FixedData<'a, T> {
             global_range_constraints,
             intermediate_definitions,
             stage,
+            jit_cache_dir: None,
         }
     }
 
+    pub fn with_jit_cache_dir(self, jit_cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            jit_cache_dir,
+            ..self
+        }
+    }
+
+    /// The directory of the cache for JIT-compiled code, if it is not the one
+    /// configured in the environment.
+    pub fn jit_cache_dir(&self) -> Option<&Path> {
+        self.jit_cache_dir.as_deref()
+    }
+
     pub fn with_global_range_constraints(
         self,
         global_range_constraints: GlobalConstraints<T>,
@@ -18,6 +18,7 @@ use super::{
         column_map::WitnessColumnMap, copy_constraints::CopyConstraints,
         finalizable_data::FinalizableData,
     },
+    diagnostics::StuckRowDiagnostics,
     identity_processor::IdentityProcessor,
     rows::{Row, RowIndex, RowPair, RowUpdater, UnknownStrategy},
     Constraints, EvalError, EvalValue, IncompleteCause, QueryCallback,
@@ -575,6 +576,22 @@ Known values in current row (local: {row_index}, global {global_row_index}):
         self.data.len()
     }
 
+    /// Analyzes why the given identities could not be completed on the given row.
+    pub fn diagnose_row(
+        &self,
+        row_index: usize,
+        identities: impl IntoIterator<Item = &'a Identity<T>>,
+    ) -> StuckRowDiagnostics<'a, T> {
+        StuckRowDiagnostics::new(
+            self.fixed_data,
+            self.parts,
+            (self.row_offset + row_index as DegreeType).into(),
+            &self.data[row_index],
+            &self.data[row_index + 1],
+            identities,
+        )
+    }
+
     pub fn finalize_range(&mut self, range: std::ops::Range<usize>) {
         assert!(
             self.copy_constraints.is_empty(),
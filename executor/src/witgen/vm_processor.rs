@@ -43,6 +43,14 @@ impl<'a, T: FieldElement> CompletableIdentities<'a, T> {
             .iter_mut()
             .map(|(identity, complete)| (*identity, complete))
     }
+
+    /// Yields the identities that are not complete.
+    fn incomplete(&self) -> impl Iterator<Item = &'a Identity<T>> + '_ {
+        self.identities_with_complete
+            .iter()
+            .filter(|(_, complete)| !complete)
+            .map(|(identity, _)| *identity)
+    }
 }
 
 pub struct VmProcessor<'a, 'c, T: FieldElement, Q: QueryCallback<T>> {
@@ -71,6 +79,8 @@ pub struct VmProcessor<'a, 'c, T: FieldElement, Q: QueryCallback<T>> {
     /// If true, we'll periodically check if we are in a loop. If yes, we'll add new rows by
     /// copying the old ones and check the constraints.
     loop_detection: bool,
+    /// The number of rows used before a loop was detected, if any.
+    used_rows: Option<DegreeType>,
 }
 
 impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
@@ -122,6 +132,7 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
             processor,
             progress_bar,
             loop_detection,
+            used_rows: None,
         }
     }
 
@@ -130,9 +141,10 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
         Self { processor, ..self }
     }
 
-    /// Returns the updated data, values for publics, and the length of the block.
-    pub fn finish(self) -> (SolverState<'a, T>, DegreeType) {
-        (self.processor.finish(), self.degree)
+    /// Returns the updated data, values for publics, the length of the block,
+    /// and the number of rows used before a loop was detected, if any.
+    pub fn finish(self) -> (SolverState<'a, T>, DegreeType, Option<DegreeType>) {
+        (self.processor.finish(), self.degree, self.used_rows)
     }
 
     /// Starting out with a single row (at a given offset), iteratively append rows
@@ -197,6 +209,7 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
                         "Found loop with period {p} starting at row {row_index}"
                     );
 
+                    self.used_rows = Some(self.processor.len() as DegreeType);
                     self.degree = compute_size_and_log(
                         &self.machine_name,
                         self.processor.len(),
@@ -318,20 +331,30 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
             log::trace!(
                 "  Checking that remaining identities hold when unknown values are set to 0"
             );
-            self.process_identities(
-                row_index,
-                &mut identities_without_next_ref,
-                UnknownStrategy::Zero,
-            )
-            .and_then(|_| {
-                self.process_identities(
+            let result = self
+                .process_identities(
                     row_index,
-                    &mut identities_with_next_ref,
+                    &mut identities_without_next_ref,
                     UnknownStrategy::Zero,
                 )
-            })
-            .map_err(|e| self.report_failure_and_panic_under_constrained(row_index, e))
-            .unwrap();
+                .and_then(|_| {
+                    self.process_identities(
+                        row_index,
+                        &mut identities_with_next_ref,
+                        UnknownStrategy::Zero,
+                    )
+                });
+            if let Err(e) = result {
+                let incomplete_identities = identities_without_next_ref
+                    .incomplete()
+                    .chain(identities_with_next_ref.incomplete())
+                    .collect();
+                self.report_failure_and_panic_under_constrained(
+                    row_index,
+                    e,
+                    incomplete_identities,
+                );
+            }
         }
 
         log::trace!(
@@ -520,6 +543,7 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
         &self,
         row_index: DegreeType,
         failures: Vec<EvalError<T>>,
+        incomplete_identities: Vec<&'a Identity<T>>,
     ) -> ! {
         log::error!(
             "\nError: Row {} failed. Set RUST_LOG=debug for more information.\n",
@@ -527,6 +551,12 @@ impl<'a, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'c, T, Q> {
         );
         let row_index = row_index as usize;
 
+        log::error!(
+            "{}",
+            self.processor
+                .diagnose_row(row_index, incomplete_identities)
+        );
+
         log::debug!("Some columns could not be determined, but setting them to zero does not satisfy the constraints. This typically means that the system is under-constrained!");
         log::debug!(
             "{}",
@@ -129,14 +129,7 @@ impl<'a, T: FieldElement> CodeGenerator<'a, T> {
                     _ => {
                         let type_scheme = value.type_scheme.as_ref().unwrap();
                         assert!(type_scheme.vars.is_empty());
-                        let ty = if type_scheme.ty == Type::Col {
-                            Type::Function(FunctionType {
-                                params: vec![Type::Int],
-                                value: Box::new(Type::Fe),
-                            })
-                        } else {
-                            type_scheme.ty.clone()
-                        };
+                        let ty = replace_col_type(&type_scheme.ty);
                         // We need a lazy static here because we want symbols to only be
                         // evaluated once and the code is not `const` in the general case.
                         format!(
@@ -273,6 +266,9 @@ impl<'a, T: FieldElement> CodeGenerator<'a, T> {
                     BinaryOperator::Add => {
                         format!("Add::add(({left}).clone(), ({right}).clone())")
                     }
+                    BinaryOperator::Pow => {
+                        format!("Pow::pow(({left}).clone(), ({right}).clone())")
+                    }
                     _ => format!("(({left}).clone() {op} ({right}).clone())"),
                 }
             }
@@ -623,6 +619,22 @@ fn escape_enum_variant(mut s: SymbolPath) -> String {
     }
 }
 
+/// Replaces the column type, also inside arrays, by the function type
+/// (int -> fe) it is implemented as.
+fn replace_col_type(ty: &Type) -> Type {
+    match ty {
+        Type::Col => Type::Function(FunctionType {
+            params: vec![Type::Int],
+            value: Box::new(Type::Fe),
+        }),
+        Type::Array(ArrayType { base, length }) => Type::Array(ArrayType {
+            base: Box::new(replace_col_type(base)),
+            length: *length,
+        }),
+        _ => ty.clone(),
+    }
+}
+
 fn map_type(ty: &Type) -> String {
     match ty {
         Type::Bottom | Type::Bool => format!("{ty}"),
@@ -662,6 +674,10 @@ fn get_builtins<T: FieldElement>() -> &'static HashMap<String, String> {
                 "std::check::panic",
                 "(s: String) -> ! { panic!(\"{s}\"); }".to_string(),
             ),
+            (
+                "std::debug::print",
+                "<T: ToString>(s: T) -> () { print!(\"{}\", s.to_string()); }".to_string(),
+            ),
             (
                 "std::convert::fe",
                 "<T: Into<FieldElement>>(n: T) -> FieldElement { n.into() }".to_string(),
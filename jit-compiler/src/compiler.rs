@@ -1,26 +1,33 @@
 use mktemp::Temp;
 use std::{
+    env,
     fs::{self},
+    io,
+    path::{Path, PathBuf},
     process::Command,
     str::from_utf8,
-    sync::Arc,
+    sync::{Arc, OnceLock},
 };
 
 use powdr_ast::{
     analyzed::Analyzed,
     parsed::{
         display::format_type_scheme_around_name,
-        types::{FunctionType, Type, TypeScheme},
+        types::{ArrayType, FunctionType, Type},
     },
 };
 use powdr_number::{FieldElement, LargeInt};
 
 use crate::{codegen::escape_symbol, CompiledPIL, FixedColFunction};
 
+/// Generates the code that exposes the requested symbols as `extern "C"` functions.
+/// Arrays of columns are exposed element by element.
+/// Returns the code and, for each exposed column, its name and the name of the
+/// extern function.
 pub fn generate_glue_code<T: FieldElement>(
     symbols: &[(&str, String)],
     analyzed: &Analyzed<T>,
-) -> Result<String, String> {
+) -> Result<(String, Vec<(String, String)>), String> {
     if T::BITS > 64 {
         return Err(format!(
             "Fields with more than 64 bits not supported, requested {}",
@@ -28,36 +35,58 @@ pub fn generate_glue_code<T: FieldElement>(
         ));
     }
     let mut glue = String::new();
-    let int_int_fun: TypeScheme = Type::Function(FunctionType {
-        params: vec![Type::Int],
-        value: Box::new(Type::Int),
-    })
-    .into();
+    let mut columns = vec![];
     for (sym, access) in symbols {
         let ty = analyzed.type_of_symbol(sym);
-        if ty != int_int_fun && ty.ty != Type::Col {
+        let elements = match &ty.ty {
+            ty if is_column_function(ty) => vec![(sym.to_string(), access.clone())],
+            Type::Array(ArrayType { base, .. }) if is_column_function(base) => {
+                let (symbol, _) = &analyzed.definitions[*sym];
+                let length = symbol
+                    .length
+                    .ok_or_else(|| format!("Array length of {sym} not known."))?;
+                (0..length)
+                    .map(|i| (symbol.array_element_name(i), format!("({access})[{i}]")))
+                    .collect()
+            }
+            _ => vec![],
+        };
+        if elements.is_empty() || !ty.vars.is_empty() {
             return Err(format!(
-                "Only (int -> int) functions and columns are supported, but requested{}",
+                "Only (int -> int) and (int -> fe) functions, columns and arrays of them are supported, but requested{}",
                 format_type_scheme_around_name(sym, &Some(ty)),
             ));
         }
 
-        // TODO we should use big int instead of u64
-        glue.push_str(&format!(
-            r#"
+        for (name, access) in elements {
+            let extern_name = extern_symbol_name(&name);
+            // TODO we should use big int instead of u64
+            glue.push_str(&format!(
+                r#"
             #[no_mangle]
-            pub extern "C" fn {}(i: u64) -> u64 {{
+            pub extern "C" fn {extern_name}(i: u64) -> u64 {{
                 u64::try_from(({access}).call(ibig::IBig::from(i))).unwrap()
             }}
-            "#,
-            extern_symbol_name(sym)
-        ));
+            "#
+            ));
+            columns.push((name, extern_name));
+        }
     }
 
-    Ok(format!(
-        "{PREAMBLE}\n{}\n{glue}\n",
-        field_specific_preamble::<T>()
-    ))
+    let code = format!("{PREAMBLE}\n{}\n{glue}\n", field_specific_preamble::<T>());
+    Ok((code, columns))
+}
+
+/// Returns true if values of the given type can be used as fixed columns,
+/// i.e. it is a column or a function from int to int or fe.
+fn is_column_function(ty: &Type) -> bool {
+    match ty {
+        Type::Col => true,
+        Type::Function(FunctionType { params, value }) => {
+            params == &[Type::Int] && matches!(value.as_ref(), Type::Int | Type::Fe)
+        }
+        _ => false,
+    }
 }
 
 const PREAMBLE: &str = r#"
@@ -70,7 +99,7 @@ pub extern "C" fn __set_degree(degree: u64) {
     *DEGREE.write().unwrap() = Some(ibig::IBig::from(degree));
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 struct FieldElement(u64);
 impl From<FieldElement> for u64 {
     fn from(x: FieldElement) -> u64 {
@@ -87,6 +116,11 @@ impl From<FieldElement> for ibig::IBig {
         ibig::IBig::from(x.0)
     }
 }
+impl std::fmt::Display for FieldElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 #[derive(Clone)]
 enum Callable<Args, Ret> {
@@ -156,6 +190,14 @@ impl FromLiteral for FieldElement {
     fn from_u64(x: u64) -> Self { FieldElement::from(x) }
 }
 
+trait Pow {
+    fn pow(a: Self, b: ibig::IBig) -> Self;
+}
+
+impl Pow for ibig::IBig {
+    fn pow(a: Self, b: ibig::IBig) -> Self { a.pow(usize::try_from(b).unwrap()) }
+}
+
 "#;
 
 fn field_specific_preamble<T: FieldElement>() -> String {
@@ -174,6 +216,39 @@ fn field_specific_preamble<T: FieldElement>() -> String {
                 Self(u64::try_from((u128::from(a.0) + u128::from(b.0)) % u128::from({modulus}_u64)).unwrap())
             }}
         }}
+        impl std::ops::Sub for FieldElement {{
+            type Output = Self;
+            fn sub(self, b: Self) -> Self {{
+                Self(u64::try_from((u128::from(self.0) + u128::from({modulus}_u64) - u128::from(b.0)) % u128::from({modulus}_u64)).unwrap())
+            }}
+        }}
+        impl std::ops::Mul for FieldElement {{
+            type Output = Self;
+            fn mul(self, b: Self) -> Self {{
+                Self(u64::try_from((u128::from(self.0) * u128::from(b.0)) % u128::from({modulus}_u64)).unwrap())
+            }}
+        }}
+        impl std::ops::Neg for FieldElement {{
+            type Output = Self;
+            fn neg(self) -> Self {{
+                Self(({modulus}_u64 - self.0) % {modulus}_u64)
+            }}
+        }}
+        impl Pow for FieldElement {{
+            fn pow(a: Self, b: ibig::IBig) -> Self {{
+                let mut exponent = u64::try_from(b).unwrap();
+                let mut base = a;
+                let mut result = FieldElement::from(1_u64);
+                while exponent > 0 {{
+                    if exponent & 1 == 1 {{
+                        result = result * base;
+                    }}
+                    base = base * base;
+                    exponent >>= 1;
+                }}
+                result
+            }}
+        }}
         "#
     )
 }
@@ -192,30 +267,65 @@ ibig = { version = "0.3.6", features = [], default-features = false }
 lazy_static = "1.4.0"
 "#;
 
+/// The environment variable that configures the directory of the cache
+/// for compiled libraries, unless it is passed explicitly.
+/// If neither is set, libraries are not cached.
+static JIT_CACHE_DIR_ENV: &str = "POWDR_JIT_CACHE_DIR";
+
+/// Returns the given directory of the cache for compiled libraries,
+/// or the one from <JIT_CACHE_DIR_ENV> if `None`.
+fn resolve_cache_dir(cache_dir: Option<&Path>) -> Option<PathBuf> {
+    cache_dir
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os(JIT_CACHE_DIR_ENV).map(PathBuf::from))
+}
+
 pub struct PathInTempDir {
+    /// The temporary directory the library was compiled in, or `None` if it was
+    /// loaded from the cache.
     #[allow(dead_code)]
-    dir: Temp,
+    dir: Option<Temp>,
     /// The absolute path
     pub path: String,
 }
 
-/// Compiles the given code and returns the path to the
+/// Compiles the given code for the field `T` and returns the path to the
 /// temporary directory containing the compiled library
 /// and the path to the compiled library.
-pub fn call_cargo(code: &str) -> Result<PathInTempDir, String> {
+/// If a cache directory is given (or configured through <JIT_CACHE_DIR_ENV>),
+/// the compiled library is stored in that directory, keyed by the code, the
+/// field and the toolchain, and later calls with the same key use it instead
+/// of compiling again.
+pub fn call_cargo<T: FieldElement>(
+    code: &str,
+    cache_dir: Option<&Path>,
+) -> Result<PathInTempDir, String> {
+    let cache_dir = resolve_cache_dir(cache_dir);
+    let cached_path = cache_dir.as_ref().and_then(|cache_dir| {
+        let toolchain = toolchain_fingerprint()?;
+        Some(cache_dir.join(format!(
+            "{}.{}",
+            cache_key::<T>(code, toolchain),
+            library_extension()
+        )))
+    });
+    if let Some(cached_path) = &cached_path {
+        if cached_path.is_file() {
+            log::debug!("Using cached library at {}", cached_path.display());
+            return Ok(PathInTempDir {
+                dir: None,
+                path: cached_path.to_str().unwrap().to_string(),
+            });
+        }
+    }
+
     let dir = mktemp::Temp::new_dir().unwrap();
     fs::write(dir.join("Cargo.toml"), CARGO_TOML).unwrap();
     fs::create_dir(dir.join("src")).unwrap();
     fs::write(dir.join("src").join("lib.rs"), code).unwrap();
     let output_asm = false;
     let out = Command::new("cargo")
-        .env(
-            "RUSTFLAGS",
-            format!(
-                "-C target-cpu=native{}",
-                if output_asm { " --emit asm" } else { "" }
-            ),
-        )
+        .env("RUSTFLAGS", rust_flags(output_asm))
         .arg("build")
         .arg("--release")
         .current_dir(dir.clone())
@@ -240,41 +350,127 @@ pub fn call_cargo(code: &str) -> Result<PathInTempDir, String> {
             .join("powdr_jit_compiled.s");
         println!("{}", fs::read_to_string(&asm_file).unwrap());
     }
-    let extension = if cfg!(target_os = "windows") {
-        "dll"
-    } else if cfg!(target_os = "macos") {
-        "dylib"
-    } else {
-        "so"
-    };
     let lib_path = dir
         .join("target")
         .join("release")
-        .join(format!("libpowdr_jit_compiled.{extension}"));
+        .join(format!("libpowdr_jit_compiled.{}", library_extension()));
+    if let (Some(cache_dir), Some(cached_path)) = (&cache_dir, &cached_path) {
+        if let Err(e) = store_in_cache(cache_dir, &lib_path, cached_path) {
+            log::warn!(
+                "Could not store the compiled library in the cache at {}: {e}",
+                cache_dir.display()
+            );
+        }
+    }
     Ok(PathInTempDir {
-        dir,
+        dir: Some(dir),
         path: lib_path.to_str().unwrap().to_string(),
     })
 }
 
+fn rust_flags(output_asm: bool) -> String {
+    format!(
+        "-C target-cpu=native{}",
+        if output_asm { " --emit asm" } else { "" }
+    )
+}
+
+fn library_extension() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "dll"
+    } else if cfg!(target_os = "macos") {
+        "dylib"
+    } else {
+        "so"
+    }
+}
+
+/// Returns the key of the compiled library in the cache. It depends on
+/// everything that influences the compiled library: the code, the field,
+/// the crate manifest, the compiler flags and the toolchain fingerprint.
+fn cache_key<T: FieldElement>(code: &str, toolchain: &str) -> String {
+    let field = format!("{:?} {}", T::known_field(), T::modulus());
+    let mut hasher = blake3::Hasher::new();
+    for part in [
+        field.as_str(),
+        CARGO_TOML,
+        &rust_flags(false),
+        toolchain,
+        code,
+    ] {
+        // Prefix each part with its length so that the parts cannot be shifted.
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Returns the version of the Rust compiler together with the host target
+/// and the features of the native CPU, since libraries are compiled with
+/// `target-cpu=native`.
+/// Returns `None` if the compiler cannot be queried, in which case libraries
+/// are not cached.
+fn toolchain_fingerprint() -> Option<&'static str> {
+    static FINGERPRINT: OnceLock<Option<String>> = OnceLock::new();
+    FINGERPRINT
+        .get_or_init(|| {
+            // Run the compiler in the same kind of directory as `cargo build`,
+            // so that the same toolchain is selected.
+            let rustc = |args: &[&str]| {
+                let out = Command::new("rustc")
+                    .args(args)
+                    .current_dir(env::temp_dir())
+                    .output()
+                    .ok()?;
+                out.status
+                    .success()
+                    .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+            };
+            let fingerprint = rustc(&["-vV"]).and_then(|version| {
+                let target = rustc(&["-C", "target-cpu=native", "--print", "cfg"])?;
+                Some(format!("{version}\n{target}"))
+            });
+            if fingerprint.is_none() {
+                log::warn!(
+                    "Could not query the Rust compiler, JIT-compiled libraries are not cached."
+                );
+            }
+            fingerprint
+        })
+        .as_deref()
+}
+
+/// Copies the library into the cache. The library is first copied to a
+/// temporary file and then renamed, so that concurrent processes never see
+/// a partially written library.
+fn store_in_cache(cache_dir: &Path, lib_path: &Path, cached_path: &Path) -> io::Result<()> {
+    fs::create_dir_all(cache_dir)?;
+    let temp_file = mktemp::Temp::new_file_in(cache_dir)?;
+    fs::copy(lib_path, &temp_file)?;
+    fs::rename(&temp_file, cached_path)?;
+    // The file has been moved, so it must not be deleted.
+    temp_file.release();
+    Ok(())
+}
+
 /// Loads the given library and functions.
-pub fn load_library(path: &str, fixed_column_names: &[&str]) -> Result<CompiledPIL, String> {
+/// The columns are given as pairs of the column name and the name of the extern function.
+pub fn load_library(path: &str, columns: &[(String, String)]) -> Result<CompiledPIL, String> {
     let library = Arc::new(
         unsafe { libloading::Library::new(path) }
             .map_err(|e| format!("Error loading library at {path}: {e}"))?,
     );
-    let fixed_columns = fixed_column_names
+    let fixed_columns = columns
         .iter()
-        .map(|&sym| {
-            let extern_sym = extern_symbol_name(sym);
+        .map(|(name, extern_name)| {
             let function =
-                *unsafe { library.get::<extern "C" fn(u64) -> u64>(extern_sym.as_bytes()) }
-                    .map_err(|e| format!("Error accessing symbol {sym}: {e}"))?;
+                *unsafe { library.get::<extern "C" fn(u64) -> u64>(extern_name.as_bytes()) }
+                    .map_err(|e| format!("Error accessing symbol {name}: {e}"))?;
             let fun = FixedColFunction {
                 library: library.clone(),
                 function,
             };
-            Ok((sym.to_string(), fun))
+            Ok((name.clone(), fun))
         })
         .collect::<Result<_, String>>()?;
     let set_degree_fun = *unsafe { library.get::<extern "C" fn(u64)>(b"__set_degree") }
@@ -287,5 +483,25 @@ pub fn load_library(path: &str, fixed_column_names: &[&str]) -> Result<CompiledP
 }
 
 fn extern_symbol_name(sym: &str) -> String {
-    format!("extern_{}", escape_symbol(sym))
+    // Array elements are named `name[index]`.
+    format!(
+        "extern_{}",
+        escape_symbol(sym).replace('[', "__").replace(']', "")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::{BabyBearField, GoldilocksField};
+
+    use super::cache_key;
+
+    #[test]
+    fn cache_key_depends_on_code_field_and_toolchain() {
+        let key = cache_key::<GoldilocksField>("fn f() {}", "rustc 1.0");
+        assert_eq!(key, cache_key::<GoldilocksField>("fn f() {}", "rustc 1.0"));
+        assert_ne!(key, cache_key::<GoldilocksField>("fn g() {}", "rustc 1.0"));
+        assert_ne!(key, cache_key::<BabyBearField>("fn f() {}", "rustc 1.0"));
+        assert_ne!(key, cache_key::<GoldilocksField>("fn f() {}", "rustc 1.1"));
+    }
 }
@@ -4,6 +4,7 @@ mod compiler;
 use std::{
     collections::{HashMap, HashSet},
     fs,
+    path::Path,
     sync::Arc,
 };
 
@@ -50,10 +51,14 @@ impl FixedColFunction {
 
 /// JIT-compiles the given symbols (and their dependencies) and loads the binary
 /// as a shared library.
-/// Only functions of type (int -> int) are supported for now.
+/// Only columns, functions of type (int -> int) or (int -> fe) and arrays of them
+/// are supported for now. The elements of arrays are available under the
+/// names `name[index]`.
+/// See [call_cargo] for how the library is cached in `cache_dir`.
 pub fn compile<T: FieldElement>(
     analyzed: &Analyzed<T>,
     requested_symbols: &[&str],
+    cache_dir: Option<&Path>,
 ) -> Result<CompiledPIL, String> {
     // TODO this should be changed back to Info after the introduction of the ToCol trait.
     log::debug!("JIT-compiling {} symbols...", requested_symbols.len());
@@ -83,9 +88,12 @@ pub fn compile<T: FieldElement>(
         );
     }
 
-    let glue_code = generate_glue_code(&successful_symbols, analyzed)?;
+    let (glue_code, columns) = generate_glue_code(&successful_symbols, analyzed)?;
 
-    let lib_file = call_cargo(&format!("{glue_code}\n{}\n", codegen.generated_code()))?;
+    let lib_file = call_cargo::<T>(
+        &format!("{glue_code}\n{}\n", codegen.generated_code()),
+        cache_dir,
+    )?;
     let metadata = fs::metadata(&lib_file.path).unwrap();
 
     log::info!(
@@ -93,7 +101,7 @@ pub fn compile<T: FieldElement>(
         metadata.len() as f64 / (1024.0 * 1024.0)
     );
 
-    let result = load_library(&lib_file.path, &successful_symbol_names)?;
+    let result = load_library(&lib_file.path, &columns)?;
     log::info!("Done.");
     Ok(result)
 }
@@ -6,7 +6,7 @@ use powdr_pil_analyzer::analyze_string;
 
 fn compile(input: &str, symbols: &[&str]) -> CompiledPIL {
     let analyzed = analyze_string::<GoldilocksField>(input).unwrap();
-    powdr_jit_compiler::compile(&analyzed, symbols)
+    powdr_jit_compiler::compile(&analyzed, symbols, None)
         .map_err(|e| {
             eprintln!("Error jit-compiling:\n{e}");
             e
@@ -52,7 +52,7 @@ fn sqrt() {
 }
 
 #[test]
-#[should_panic = "Only (int -> int) functions and columns are supported, but requested c: int -> bool"]
+#[should_panic = "Only (int -> int) and (int -> fe) functions, columns and arrays of them are supported, but requested c: int -> bool"]
 fn invalid_function() {
     let _ = compile_fun("let c: int -> bool = |i| true;", "c");
 }
@@ -390,3 +390,47 @@ fn generic() {
     assert_eq!(q.call(2), 23);
     assert_eq!(q.call(3), 24);
 }
+
+#[test]
+fn field_arithmetic() {
+    let input = "
+        namespace main;
+            let q: col = |i| if i % 2 == 0 { 7 * 3 - 25 } else { -(2 ** 64) };
+            let r: int -> int = |i| 3 ** i;
+        ";
+    let obj = compile(input, &["main::q", "main::r"]);
+    let q = obj.get_fixed_column("main::q").unwrap();
+    let p = 0xffffffff00000001_u64;
+    assert_eq!(q.call(0), p - 4);
+    // 2**64 = 2**32 - 1 (mod p)
+    assert_eq!(q.call(1), p - 0xffffffff);
+
+    let r = obj.get_fixed_column("main::r").unwrap();
+    assert_eq!(r.call(0), 1);
+    assert_eq!(r.call(4), 81);
+}
+
+#[test]
+fn int_to_fe_function() {
+    let f = compile_fun("let f: int -> fe = |i| if i == 0 { 5 } else { 6 };", "f");
+
+    assert_eq!(f.call(0), 5);
+    assert_eq!(f.call(1), 6);
+}
+
+#[test]
+fn column_array() {
+    let input = "
+        namespace main;
+            let clk: col[3] = [|i| if i % 3 == 0 { 1 } else { 0 }, |i| if i % 3 == 1 { 1 } else { 0 }, |i| if i % 3 == 2 { 1 } else { 0 }];
+        ";
+    let obj = compile(input, &["main::clk"]);
+    for index in 0..3 {
+        let clk = obj
+            .get_fixed_column(&format!("main::clk[{index}]"))
+            .unwrap();
+        for row in 0..6 {
+            assert_eq!(clk.call(row), u64::from(row % 3 == index));
+        }
+    }
+}
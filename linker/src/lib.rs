@@ -3,7 +3,7 @@ use powdr_ast::{
     asm_analysis::{combine_flags, MachineDegree},
     object::{Link, Location, MachineInstanceGraph, Object},
     parsed::{
-        asm::{AbsoluteSymbolPath, Part, SymbolPath},
+        asm::{AbsoluteSymbolPath, LinkConnection, Part, SymbolPath},
         build::{index_access, lookup, namespaced_reference, permutation, selected},
         visitor::{ExpressionVisitable, VisitOrder},
         ArrayLiteral, Expression, FunctionCall, NamespaceDegree, Number, PILFile, PilStatement,
@@ -28,12 +28,16 @@ pub struct LinkerParams {
 
 #[derive(Clone, EnumString, EnumVariantNames, Display, Copy, Default)]
 /// Whether to link the machines natively or via a global bus.
+/// Links can override this in the ASM, e.g. `link bus => ...`.
 pub enum LinkerMode {
     #[default]
     #[strum(serialize = "native")]
     Native,
     #[strum(serialize = "bus")]
     Bus,
+    /// Link machines of the same degree natively, and all others via the bus.
+    #[strum(serialize = "hybrid")]
+    Hybrid,
 }
 
 #[derive(Clone, EnumString, EnumVariantNames, Display, Copy, Default)]
@@ -50,6 +54,8 @@ pub enum DegreeMode {
 struct Linker {
     params: LinkerParams,
     max_degree: Option<Number>,
+    /// the degree of each namespace, used to choose the connection of links in hybrid mode
+    degrees: BTreeMap<String, MachineDegree>,
     /// for each namespace, we store the statements resulting from processing the links separately, because we need to make sure they do not come first.
     namespaces: BTreeMap<String, (Vec<PilStatement>, Vec<PilStatement>)>,
     next_interaction_id: u32,
@@ -82,6 +88,12 @@ impl Linker {
             DegreeMode::Vadcop => None,
         };
 
+        self.degrees = graph
+            .objects
+            .iter()
+            .map(|(location, object)| (location.to_string(), object.degree.clone()))
+            .collect();
+
         let common_definitions = process_definitions(graph.statements);
 
         for (location, object) in graph.objects {
@@ -153,11 +165,38 @@ impl Linker {
         }
     }
 
+    /// Returns how to connect a link between the two namespaces if it does not specify it.
+    fn default_connection(&self, from_namespace: &str, to_namespace: &str) -> LinkConnection {
+        match (self.params.mode, self.params.degree_mode) {
+            (LinkerMode::Native, _) => LinkConnection::Native,
+            (LinkerMode::Bus, _) => LinkConnection::Bus,
+            // all machines have the same degree
+            (LinkerMode::Hybrid, DegreeMode::Monolithic) => LinkConnection::Native,
+            // native links require both machines to always have the same size
+            (LinkerMode::Hybrid, DegreeMode::Vadcop) => {
+                let fixed_degree = |namespace: &str| {
+                    let degree = &self.degrees[namespace];
+                    degree
+                        .min
+                        .as_ref()
+                        .filter(|min| Some(*min) == degree.max.as_ref())
+                };
+                match (fixed_degree(from_namespace), fixed_degree(to_namespace)) {
+                    (Some(from), Some(to)) if from == to => LinkConnection::Native,
+                    _ => LinkConnection::Bus,
+                }
+            }
+        }
+    }
+
     fn process_link(&mut self, link: Link, from_namespace: String) {
         let from = link.from;
         let to = link.to;
 
         let to_namespace = to.machine.location.clone().to_string();
+        let connection = link
+            .connection
+            .unwrap_or_else(|| self.default_connection(&from_namespace, &to_namespace));
 
         let op_id = to.operation.id.iter().cloned().map(|n| n.into());
 
@@ -209,6 +248,7 @@ impl Linker {
 
             self.insert_interaction(
                 InteractionType::Permutation,
+                connection,
                 from_namespace,
                 to_namespace,
                 lhs,
@@ -222,6 +262,7 @@ impl Linker {
 
             self.insert_interaction(
                 InteractionType::Lookup,
+                connection,
                 from_namespace,
                 to_namespace,
                 lhs,
@@ -233,6 +274,7 @@ impl Linker {
     fn insert_interaction(
         &mut self,
         interaction_type: InteractionType,
+        connection: LinkConnection,
         from_namespace: String,
         to_namespace: String,
         lhs: Expression,
@@ -241,8 +283,8 @@ impl Linker {
         // get a new unique interaction id
         let interaction_id = self.next_interaction_id();
 
-        match self.params.mode {
-            LinkerMode::Native => {
+        match connection {
+            LinkConnection::Native => {
                 self.namespaces.entry(from_namespace).or_default().1.push(
                     PilStatement::Expression(
                         SourceRef::unknown(),
@@ -253,7 +295,7 @@ impl Linker {
                     ),
                 );
             }
-            LinkerMode::Bus => {
+            LinkConnection::Bus => {
                 // send in the origin
                 self.namespaces
                     .entry(from_namespace.clone())
@@ -417,6 +459,16 @@ mod test {
         )
     }
 
+    fn link_hybrid(graph: MachineInstanceGraph) -> Result<PILFile, Vec<String>> {
+        super::link(
+            graph,
+            super::LinkerParams {
+                mode: super::LinkerMode::Hybrid,
+                degree_mode: super::DegreeMode::Vadcop,
+            },
+        )
+    }
+
     fn link_native_monolithic(graph: MachineInstanceGraph) -> Result<PILFile, Vec<String>> {
         super::link(
             graph,
@@ -1175,4 +1227,108 @@ namespace main_submachine(32);
         let pil = link_native_monolithic(graph).unwrap();
         assert_eq!(extract_main(&format!("{pil}")), expected);
     }
+
+    #[test]
+    fn hybrid_linking() {
+        let input = r#"
+machine Main with degree: 8 {
+    Same same;
+    Larger larger;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg Z[<=];
+    reg A;
+
+    instr add_same X, Y -> Z link => Z = same.add(X, Y);
+    instr add_larger X, Y -> Z link => Z = larger.add(X, Y);
+    instr add_via_bus X, Y -> Z link bus => Z = same.add(X, Y);
+    instr add_native X, Y -> Z link native => Z = larger.add(X, Y);
+
+    function main {
+        A <== add_same(1, 2);
+        A <== add_larger(1, 2);
+        A <== add_via_bus(1, 2);
+        A <== add_native(1, 2);
+        return;
+    }
+}
+
+machine Same with degree: 8, latch: latch, operation_id: operation_id {
+    operation add<0> x, y -> z;
+    col fixed latch = [1]*;
+    col witness operation_id;
+    col witness x, y, z;
+    z = x + y;
+}
+
+machine Larger with degree: 16, latch: latch, operation_id: operation_id {
+    operation add<0> x, y -> z;
+    col fixed latch = [1]*;
+    col witness operation_id;
+    col witness x, y, z;
+    z = x + y;
+}
+"#;
+        let graph = parse_analyze_and_compile::<GoldilocksField>(input);
+        let pil = link_hybrid(graph).unwrap().to_string();
+        let link_line = |instr: &str| {
+            pil.lines()
+                .find(|line| line.contains(&format!("{instr} $")))
+                .unwrap_or_else(|| panic!("no link for {instr}"))
+                .trim()
+                .to_string()
+        };
+
+        // Links between machines of the same degree are native, all others use the bus,
+        // unless the link overrides it.
+        assert!(link_line("instr_add_same").starts_with("instr_add_same $"));
+        assert!(link_line("instr_add_larger")
+            .starts_with("std::protocols::lookup_via_bus::lookup_send("));
+        assert!(link_line("instr_add_via_bus")
+            .starts_with("std::protocols::lookup_via_bus::lookup_send("));
+        assert!(link_line("instr_add_native").starts_with("instr_add_native $"));
+    }
+
+    #[test]
+    fn hybrid_linking_variable_degrees() {
+        let input = r#"
+machine Main with min_degree: 8, max_degree: 16 {
+    Same same;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg Z[<=];
+    reg A;
+
+    instr add_same X, Y -> Z link => Z = same.add(X, Y);
+
+    function main {
+        A <== add_same(1, 2);
+        return;
+    }
+}
+
+machine Same with min_degree: 8, max_degree: 16, latch: latch, operation_id: operation_id {
+    operation add<0> x, y -> z;
+    col fixed latch = [1]*;
+    col witness operation_id;
+    col witness x, y, z;
+    z = x + y;
+}
+"#;
+        let graph = parse_analyze_and_compile::<GoldilocksField>(input);
+        let pil = link_hybrid(graph).unwrap().to_string();
+        let link_line = pil
+            .lines()
+            .find(|line| line.contains("instr_add_same $"))
+            .unwrap()
+            .trim();
+
+        // The machines could end up with different sizes, so the link uses the bus
+        // even though their degree ranges are the same.
+        assert!(link_line.starts_with("std::protocols::lookup_via_bus::lookup_send("));
+    }
 }
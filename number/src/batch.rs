@@ -0,0 +1,213 @@
+//! Arithmetic on slices of field elements.
+//!
+//! The functions are generic over the element type, so that they can also be
+//! used for elements of extension fields (see [crate::BinomialExtension]).
+//! For base field elements, [FieldElement](crate::FieldElement) provides
+//! `dot_product`, `add_assign_slice`, `mul_assign_slice` and `inverse_slice`,
+//! which the fields specialize: Goldilocks reduces dot products only once,
+//! and the 31-bit fields use the packed (SIMD) arithmetic of Plonky3.
+
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign};
+
+use num_traits::{One, Zero};
+
+/// Adds `b` to `a` element-wise.
+/// Panics if the slices have different lengths.
+pub fn add_assign<T: Copy + AddAssign>(a: &mut [T], b: &[T]) {
+    assert_eq!(a.len(), b.len());
+    for (a, b) in a.iter_mut().zip(b) {
+        *a += *b;
+    }
+}
+
+/// Multiplies `a` by `b` element-wise.
+/// Panics if the slices have different lengths.
+pub fn mul_assign<T: Copy + MulAssign>(a: &mut [T], b: &[T]) {
+    assert_eq!(a.len(), b.len());
+    for (a, b) in a.iter_mut().zip(b) {
+        *a *= *b;
+    }
+}
+
+/// Replaces every element by its inverse, using Montgomery's trick: only a
+/// single inversion is needed, at the cost of three multiplications per element.
+/// Panics if any of the elements is zero.
+pub fn inverse_in_place<T>(values: &mut [T])
+where
+    T: Copy + Zero + One + Mul<Output = T> + Div<Output = T>,
+{
+    // prefix_products[i] is the product of values[0..i].
+    let mut prefix_products = Vec::with_capacity(values.len());
+    let mut product = T::one();
+    for value in values.iter() {
+        assert!(!value.is_zero(), "Cannot invert zero");
+        prefix_products.push(product);
+        product = product * *value;
+    }
+
+    // The inverse of the product of values[0..=i].
+    let mut inverse = T::one() / product;
+    for (value, prefix_product) in values.iter_mut().zip(prefix_products).rev() {
+        let value_inverse = inverse * prefix_product;
+        inverse = inverse * *value;
+        *value = value_inverse;
+    }
+}
+
+/// Evaluates the polynomial with the given coefficients (starting with the
+/// constant one) at `x`, using Horner's method.
+pub fn evaluate_polynomial<T, X>(coefficients: &[T], x: X) -> T
+where
+    T: Copy + Zero + Add<Output = T> + Mul<X, Output = T>,
+    X: Copy,
+{
+    coefficients
+        .iter()
+        .rev()
+        .fold(T::zero(), |acc, coefficient| acc * x + *coefficient)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        BabyBearField, Bn254Field, FieldElement, Fp2, GoldilocksField, KoalaBearField,
+        Mersenne31Field,
+    };
+    use test_log::test;
+
+    fn values<T: FieldElement>(n: i64) -> Vec<T> {
+        (1..=n).map(|i| T::from(i * i - 7 * i + 13)).collect()
+    }
+
+    fn check_dot_product<T: FieldElement>() {
+        let a = values::<T>(100);
+        let b = a.iter().map(|x| -*x).collect::<Vec<_>>();
+        let expected = a
+            .iter()
+            .zip(&b)
+            .fold(T::zero(), |acc, (a, b)| acc + *a * *b);
+        assert_eq!(T::dot_product(&a, &b), expected);
+        assert_eq!(T::dot_product(&[], &[]), T::zero());
+    }
+
+    #[test]
+    fn dot_product() {
+        check_dot_product::<GoldilocksField>();
+        check_dot_product::<BabyBearField>();
+        check_dot_product::<Mersenne31Field>();
+        check_dot_product::<Bn254Field>();
+    }
+
+    #[test]
+    fn dot_product_overflowing_u128() {
+        // (p - 1)^2 is close to 2^128, so the sum overflows.
+        let a = [-GoldilocksField::from(1); 5];
+        assert_eq!(
+            GoldilocksField::dot_product(&a, &a),
+            GoldilocksField::from(5)
+        );
+    }
+
+    #[test]
+    fn inverse() {
+        let mut a = values::<GoldilocksField>(50);
+        let original = a.clone();
+        inverse_in_place(&mut a);
+        for (x, inv) in original.iter().zip(&a) {
+            assert_eq!(*x * *inv, GoldilocksField::from(1));
+        }
+
+        let mut empty: Vec<GoldilocksField> = vec![];
+        inverse_in_place(&mut empty);
+
+        let mut ext = vec![
+            Fp2::new(BabyBearField::from(3), BabyBearField::from(4)),
+            Fp2::new(BabyBearField::from(0), BabyBearField::from(9)),
+        ];
+        let original = ext.clone();
+        inverse_in_place(&mut ext);
+        for (x, inv) in original.iter().zip(&ext) {
+            assert_eq!(*x * *inv, Fp2::one());
+        }
+    }
+
+    fn check_specialized<T: FieldElement>() {
+        // Not a multiple of the packing width, so that some elements are not packed.
+        let a = values::<T>(37);
+        let b = a.iter().map(|x| *x + T::from(5)).collect::<Vec<_>>();
+
+        let mut sum = a.clone();
+        T::add_assign_slice(&mut sum, &b);
+        let mut expected = a.clone();
+        add_assign(&mut expected, &b);
+        assert_eq!(sum, expected);
+
+        let mut product = a.clone();
+        T::mul_assign_slice(&mut product, &b);
+        let expected = a.iter().zip(&b).map(|(a, b)| *a * *b).collect::<Vec<_>>();
+        assert_eq!(product, expected);
+
+        let mut inverses = a.clone();
+        T::inverse_slice(&mut inverses);
+        let mut expected = a.clone();
+        inverse_in_place(&mut expected);
+        assert_eq!(inverses, expected);
+    }
+
+    #[test]
+    fn specialized() {
+        check_specialized::<GoldilocksField>();
+        check_specialized::<BabyBearField>();
+        check_specialized::<KoalaBearField>();
+        check_specialized::<Mersenne31Field>();
+        check_specialized::<Bn254Field>();
+    }
+
+    #[test]
+    #[should_panic = "Cannot invert zero"]
+    fn specialized_inverse_of_zero() {
+        BabyBearField::inverse_slice(&mut [BabyBearField::from(2), BabyBearField::from(0)]);
+    }
+
+    #[test]
+    #[should_panic = "Cannot invert zero"]
+    fn inverse_of_zero() {
+        inverse_in_place(&mut [GoldilocksField::from(2), GoldilocksField::from(0)]);
+    }
+
+    #[test]
+    fn element_wise() {
+        let mut a = values::<BabyBearField>(10);
+        let b = values::<BabyBearField>(10);
+        add_assign(&mut a, &b);
+        assert_eq!(a, b.iter().map(|x| *x + *x).collect::<Vec<_>>());
+        mul_assign(&mut a, &b);
+        assert_eq!(
+            a,
+            b.iter()
+                .map(|x| BabyBearField::from(2) * *x * *x)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn polynomial() {
+        // 3 + 2x + x^2 at x = 5
+        let coefficients = [3u64, 2, 1].map(GoldilocksField::from);
+        assert_eq!(
+            evaluate_polynomial(&coefficients, GoldilocksField::from(5)),
+            GoldilocksField::from(38)
+        );
+        assert_eq!(
+            evaluate_polynomial::<GoldilocksField, _>(&[], GoldilocksField::from(5)),
+            GoldilocksField::from(0)
+        );
+        // Extension field coefficients at a base field point.
+        let coefficients = [Fp2::new(GoldilocksField::from(1), GoldilocksField::from(2)); 2];
+        assert_eq!(
+            evaluate_polynomial(&coefficients, GoldilocksField::from(3)),
+            Fp2::new(GoldilocksField::from(4), GoldilocksField::from(8))
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use std::{
+    fmt::{self, Display},
+    iter::{Product, Sum},
+    ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign},
+};
+
+use num_traits::{One, Zero};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::{BigUint, FieldElement};
+
+/// An element of the degree-`D` binomial extension of `F`, i.e. of `F[X] / (X^D - W)`
+/// with `W = F::EXTENSION_NON_RESIDUE`.
+/// The coefficients `[a_0, ..., a_{D-1}]` represent the polynomial `a_0 + a_1 * X + ...`.
+///
+/// The polynomial `X^D - W` needs to be irreducible over `F` (see [Self::is_irreducible]),
+/// which is the case for the degrees 2 and 4 over BabyBear, KoalaBear, Goldilocks and BN254,
+/// but only for the degree 2 over Mersenne31.
+#[serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(bound(serialize = "F: Serialize", deserialize = "F: Deserialize<'de>"))]
+pub struct BinomialExtension<F, const D: usize>(#[serde_as(as = "[_; D]")] pub [F; D]);
+
+/// An element of the quadratic extension, analogous to `std/math/fp2.asm`.
+pub type Fp2<F> = BinomialExtension<F, 2>;
+
+/// An element of the quartic extension, analogous to `std/math/fp4.asm`.
+pub type Fp4<F> = BinomialExtension<F, 4>;
+
+impl<F: FieldElement> Fp2<F> {
+    pub fn new(a0: F, a1: F) -> Self {
+        Self([a0, a1])
+    }
+}
+
+impl<F: FieldElement> Fp4<F> {
+    pub fn new(a0: F, a1: F, a2: F, a3: F) -> Self {
+        Self([a0, a1, a2, a3])
+    }
+}
+
+impl<F: FieldElement, const D: usize> BinomialExtension<F, D> {
+    /// Returns true if `X^D - W` is irreducible over `F`, i.e. if the extension is a field.
+    /// By Theorem 3.75 of Lidl and Niederreiter, "Finite Fields", this is the case iff
+    /// `W` is not an `r`-th power for any prime `r` dividing `D`, and `p = 1 (mod 4)`
+    /// if `4` divides `D`.
+    pub fn is_irreducible() -> bool {
+        let p_minus_one = F::modulus().to_arbitrary_integer() - BigUint::from(1u32);
+        let w = F::from(F::EXTENSION_NON_RESIDUE);
+        if D % 4 == 0 && &p_minus_one % BigUint::from(4u32) != BigUint::from(0u32) {
+            return false;
+        }
+        prime_factors(D).all(|r| {
+            let r = BigUint::from(r);
+            &p_minus_one % &r == BigUint::from(0u32) && !pow(w, &(&p_minus_one / &r)).is_one()
+        })
+    }
+
+    /// Returns the coefficients, starting with the constant one.
+    pub fn coefficients(&self) -> &[F; D] {
+        &self.0
+    }
+
+    /// Returns true if the element is in the base field.
+    pub fn is_in_base_field(&self) -> bool {
+        self.0[1..].iter().all(|c| c.is_zero())
+    }
+
+    /// Multiplies the element by `X`.
+    fn mul_by_x(self) -> Self {
+        let mut result = [F::zero(); D];
+        result[0] = self.0[D - 1] * F::from(F::EXTENSION_NON_RESIDUE);
+        result[1..].copy_from_slice(&self.0[..D - 1]);
+        Self(result)
+    }
+
+    /// Returns the inverse of the element.
+    /// Panics if the element is zero.
+    pub fn inverse(self) -> Self {
+        assert!(!self.is_zero(), "Cannot invert zero");
+        if D == 2 {
+            let [a0, a1] = [self.0[0], self.0[1]];
+            let inv = F::one() / (a0 * a0 - a1 * a1 * F::from(F::EXTENSION_NON_RESIDUE));
+            let mut result = [F::zero(); D];
+            result[0] = a0 * inv;
+            result[1] = -a1 * inv;
+            return Self(result);
+        }
+
+        // Solve `self * z = 1` for `z` by Gaussian elimination. The columns of
+        // the matrix are the coefficients of `self * X^j`.
+        let mut matrix = [[F::zero(); D]; D];
+        let mut column = self;
+        for j in 0..D {
+            for (i, row) in matrix.iter_mut().enumerate() {
+                row[j] = column.0[i];
+            }
+            column = column.mul_by_x();
+        }
+        let mut rhs = Self::one().0;
+
+        for j in 0..D {
+            let pivot = (j..D)
+                .find(|&i| !matrix[i][j].is_zero())
+                .expect("X^D - W is not irreducible over the base field");
+            matrix.swap(j, pivot);
+            rhs.swap(j, pivot);
+
+            let inv = F::one() / matrix[j][j];
+            for c in &mut matrix[j] {
+                *c = *c * inv;
+            }
+            rhs[j] = rhs[j] * inv;
+
+            for i in (0..D).filter(|&i| i != j) {
+                let factor = matrix[i][j];
+                if factor.is_zero() {
+                    continue;
+                }
+                let pivot_row = matrix[j];
+                for (c, p) in matrix[i].iter_mut().zip(pivot_row) {
+                    *c -= factor * p;
+                }
+                let pivot_rhs = rhs[j];
+                rhs[i] -= factor * pivot_rhs;
+            }
+        }
+        Self(rhs)
+    }
+
+    /// Raises the element to the power of `exponent` using square-and-multiply.
+    pub fn pow(self, mut exponent: u64) -> Self {
+        let mut result = Self::one();
+        let mut base = self;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result *= base;
+            }
+            base *= base;
+            exponent >>= 1;
+        }
+        result
+    }
+}
+
+impl<F: FieldElement, const D: usize> Default for BinomialExtension<F, D> {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl<F: FieldElement, const D: usize> Zero for BinomialExtension<F, D> {
+    fn zero() -> Self {
+        Self([F::zero(); D])
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|c| c.is_zero())
+    }
+}
+
+impl<F: FieldElement, const D: usize> One for BinomialExtension<F, D> {
+    fn one() -> Self {
+        F::one().into()
+    }
+
+    fn is_one(&self) -> bool {
+        self.0[0].is_one() && self.is_in_base_field()
+    }
+}
+
+impl<F: FieldElement, const D: usize> From<F> for BinomialExtension<F, D> {
+    fn from(a: F) -> Self {
+        let mut result = [F::zero(); D];
+        result[0] = a;
+        Self(result)
+    }
+}
+
+impl<F: FieldElement, const D: usize> Add for BinomialExtension<F, D> {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        self += other;
+        self
+    }
+}
+
+impl<F: FieldElement, const D: usize> AddAssign for BinomialExtension<F, D> {
+    fn add_assign(&mut self, other: Self) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+}
+
+impl<F: FieldElement, const D: usize> Sum for BinomialExtension<F, D> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::zero(), Add::add)
+    }
+}
+
+impl<F: FieldElement, const D: usize> Sub for BinomialExtension<F, D> {
+    type Output = Self;
+
+    fn sub(mut self, other: Self) -> Self {
+        self -= other;
+        self
+    }
+}
+
+impl<F: FieldElement, const D: usize> SubAssign for BinomialExtension<F, D> {
+    fn sub_assign(&mut self, other: Self) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a -= b;
+        }
+    }
+}
+
+impl<F: FieldElement, const D: usize> Neg for BinomialExtension<F, D> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.map(|c| -c))
+    }
+}
+
+impl<F: FieldElement, const D: usize> Mul for BinomialExtension<F, D> {
+    type Output = Self;
+
+    /// Multiplies the polynomial representations and reduces modulo `X^D - W`,
+    /// i.e. the coefficient of `X^(D + k)` is added to the one of `X^k`, multiplied by `W`.
+    fn mul(self, other: Self) -> Self {
+        let w = F::from(F::EXTENSION_NON_RESIDUE);
+        let mut result = [F::zero(); D];
+        for (i, a) in self.0.iter().enumerate() {
+            for (j, b) in other.0.iter().enumerate() {
+                let product = *a * *b;
+                if i + j < D {
+                    result[i + j] += product;
+                } else {
+                    result[i + j - D] += product * w;
+                }
+            }
+        }
+        Self(result)
+    }
+}
+
+impl<F: FieldElement, const D: usize> MulAssign for BinomialExtension<F, D> {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+impl<F: FieldElement, const D: usize> Mul<F> for BinomialExtension<F, D> {
+    type Output = Self;
+
+    fn mul(self, other: F) -> Self {
+        Self(self.0.map(|c| c * other))
+    }
+}
+
+impl<F: FieldElement, const D: usize> Product for BinomialExtension<F, D> {
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::one(), |a, b| a * b)
+    }
+}
+
+impl<F: FieldElement, const D: usize> Div for BinomialExtension<F, D> {
+    type Output = Self;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Self) -> Self {
+        self * other.inverse()
+    }
+}
+
+/// Raises `base` to the power of `exponent`, which can be larger than the
+/// integer type of the field.
+fn pow<F: FieldElement>(base: F, exponent: &BigUint) -> F {
+    (0..exponent.bit_len()).rev().fold(F::one(), |result, bit| {
+        let result = result * result;
+        if exponent.bit(bit) {
+            result * base
+        } else {
+            result
+        }
+    })
+}
+
+/// Returns the distinct prime factors of `n`, in ascending order.
+fn prime_factors(mut n: usize) -> impl Iterator<Item = usize> {
+    let mut factors = vec![];
+    let mut factor = 2;
+    while n > 1 {
+        if n % factor == 0 {
+            factors.push(factor);
+            while n % factor == 0 {
+                n /= factor;
+            }
+        }
+        factor += 1;
+    }
+    factors.into_iter()
+}
+
+impl<F: FieldElement, const D: usize> Display for BinomialExtension<F, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, c) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{c}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        BabyBearField, Bn254Field, GoldilocksField, KoalaBearField, Mersenne31Field, ReadWrite,
+    };
+
+    use super::*;
+
+    fn new(a: i64, b: i64) -> Fp2<GoldilocksField> {
+        Fp2::new(GoldilocksField::from(a), GoldilocksField::from(b))
+    }
+
+    fn new4(a: [i64; 4]) -> Fp4<BabyBearField> {
+        BinomialExtension(a.map(BabyBearField::from))
+    }
+
+    fn from_base(x: i64) -> Fp2<GoldilocksField> {
+        GoldilocksField::from(x).into()
+    }
+
+    #[test]
+    fn test_add() {
+        // Test adding 0
+        assert_eq!(from_base(0) + from_base(0), from_base(0));
+        assert_eq!(new(123, 1234) + from_base(0), new(123, 1234));
+        assert_eq!(from_base(0) + new(123, 1234), new(123, 1234));
+
+        // Add arbitrary elements
+        assert_eq!(new(123, 1234) + new(567, 5678), new(690, 6912));
+        assert_eq!(new(-1, -1) + new(3, 4), new(2, 3));
+    }
+
+    #[test]
+    fn test_sub() {
+        // Test subtracting 0
+        assert_eq!(from_base(0) - from_base(0), from_base(0));
+        assert_eq!(new(123, 1234) - from_base(0), new(123, 1234));
+
+        // Subtract arbitrary elements
+        assert_eq!(new(123, 1234) - new(567, 5678), new(123 - 567, 1234 - 5678));
+        assert_eq!(new(-1, -1) - new(0x78000000, 1), new(-0x78000000 - 1, -2));
+        assert_eq!(-new(1, -2), new(-1, 2));
+    }
+
+    #[test]
+    fn test_mul() {
+        // Test multiplication by 1
+        assert_eq!(from_base(1) * from_base(1), from_base(1));
+        assert_eq!(new(123, 1234) * from_base(1), new(123, 1234));
+        assert_eq!(from_base(1) * new(123, 1234), new(123, 1234));
+
+        // Test multiplication by 0
+        assert_eq!(new(123, 1234) * from_base(0), from_base(0));
+        assert_eq!(from_base(0) * new(123, 1234), from_base(0));
+
+        // Multiply arbitrary elements
+        assert_eq!(new(123, 1234) * new(567, 5678), new(77142913, 1398072));
+
+        // Multiplication with field overflow
+        assert_eq!(new(-1, -2) * new(-3, 4), new(3 - 11 * 8, 6 - 4));
+    }
+
+    #[test]
+    fn test_mul_fp4() {
+        // X * X^3 = X^4 = 11
+        assert_eq!(new4([0, 1, 0, 0]) * new4([0, 0, 0, 1]), new4([11, 0, 0, 0]));
+        // (1 + X^2) * (2 + X^3) = 2 + 2 * X^2 + X^3 + X^5 = 2 + 11 * X + 2 * X^2 + X^3
+        assert_eq!(new4([1, 0, 1, 0]) * new4([2, 0, 0, 1]), new4([2, 11, 2, 1]));
+        assert_eq!(new4([1, 2, 3, 4]).pow(3), {
+            let x = new4([1, 2, 3, 4]);
+            x * x * x
+        });
+    }
+
+    #[test]
+    fn test_inverse() {
+        let test_elements = [from_base(1), new(123, 1234), new(-1, 500)];
+        for x in test_elements.iter() {
+            assert_eq!(*x * x.inverse(), Fp2::one());
+        }
+
+        let test_elements = [
+            new4([1, 0, 0, 0]),
+            new4([0, 0, 0, 7]),
+            new4([1, -2, 3, 500]),
+        ];
+        for x in test_elements.iter() {
+            assert_eq!(*x * x.inverse(), Fp4::one());
+            assert_eq!(*x / *x, Fp4::one());
+        }
+    }
+
+    #[test]
+    fn test_irreducible() {
+        assert!(Fp2::<BabyBearField>::is_irreducible());
+        assert!(Fp4::<BabyBearField>::is_irreducible());
+        assert!(Fp2::<KoalaBearField>::is_irreducible());
+        assert!(Fp4::<KoalaBearField>::is_irreducible());
+        assert!(Fp2::<GoldilocksField>::is_irreducible());
+        assert!(Fp4::<GoldilocksField>::is_irreducible());
+        assert!(Fp2::<Bn254Field>::is_irreducible());
+        assert!(Fp4::<Bn254Field>::is_irreducible());
+        assert!(Fp2::<Mersenne31Field>::is_irreducible());
+        // p = 3 (mod 4), so no binomial of degree 4 is irreducible over Mersenne31.
+        assert!(!Fp4::<Mersenne31Field>::is_irreducible());
+        // p - 1 is not divisible by 3, so every element is a cube in KoalaBear.
+        assert!(!BinomialExtension::<KoalaBearField, 3>::is_irreducible());
+        assert!(BinomialExtension::<GoldilocksField, 3>::is_irreducible());
+    }
+
+    #[test]
+    fn test_inverse_koala_bear() {
+        let x = Fp4::<KoalaBearField>::new(1.into(), 2.into(), 3.into(), 4.into());
+        assert_eq!(x * x.inverse(), Fp4::one());
+    }
+
+    #[test]
+    #[should_panic = "Cannot invert zero"]
+    fn test_inverse_of_zero() {
+        Fp4::<BabyBearField>::zero().inverse();
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut buf: Vec<u8> = vec![];
+        let elements = vec![new4([1, -2, 3, 500]), new4([0, 0, 0, 7])];
+        serde_cbor::to_writer(&mut buf, &elements).unwrap();
+        let read: Vec<Fp4<BabyBearField>> = ReadWrite::read(&mut Cursor::new(buf));
+        assert_eq!(read, elements);
+    }
+}
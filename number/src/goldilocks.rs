@@ -402,6 +402,22 @@ impl FieldElement for GoldilocksField {
     fn has_direct_repr() -> bool {
         true
     }
+
+    /// Accumulates the unreduced 128-bit products, counting the overflows, and
+    /// only reduces once at the end.
+    fn dot_product(a: &[Self], b: &[Self]) -> Self {
+        assert_eq!(a.len(), b.len());
+        let mut sum = 0u128;
+        let mut overflows = 0u64;
+        for (a, b) in a.iter().zip(b) {
+            let (new_sum, overflow) = sum.overflowing_add((a.0 as u128) * (b.0 as u128));
+            sum = new_sum;
+            overflows += overflow as u64;
+        }
+        // 2^128 = (2^64)^2 = EPSILON^2 modulo the order.
+        let two_to_128 = reduce128((EPSILON as u128) * (EPSILON as u128));
+        reduce128(sum) + two_to_128 * Self::from_canonical_u64(wrap(overflows))
+    }
 }
 
 impl LowerHex for GoldilocksField {
@@ -2,7 +2,8 @@ use p3_koala_bear::KoalaBear;
 
 use crate::powdr_field_plonky3;
 
-powdr_field_plonky3!(KoalaBearField, KoalaBear);
+// 11 is a square in KoalaBear, so the extensions are defined by `X^D - 3`.
+powdr_field_plonky3!(KoalaBearField, KoalaBear, extension_non_residue = 3);
 
 #[cfg(test)]
 mod test {
@@ -3,7 +3,9 @@
 #[macro_use]
 mod macros;
 mod baby_bear;
+pub mod batch;
 mod bn254;
+mod extension_field;
 mod goldilocks;
 mod koala_bear;
 mod mersenne31;
@@ -17,6 +19,7 @@ pub use serialize::{
 
 pub use baby_bear::BabyBearField;
 pub use bn254::Bn254Field;
+pub use extension_field::{BinomialExtension, Fp2, Fp4};
 pub use goldilocks::GoldilocksField;
 pub use koala_bear::KoalaBearField;
 pub use mersenne31::Mersenne31Field;
@@ -1,6 +1,6 @@
 #[macro_export]
 macro_rules! powdr_field_plonky3 {
-    ($name:ident, $p3_type:ty) => {
+    ($name:ident, $p3_type:ty $(, extension_non_residue = $extension_non_residue:expr)?) => {
         use schemars::{
             schema::{Schema, SchemaObject},
             JsonSchema,
@@ -18,7 +18,7 @@ macro_rules! powdr_field_plonky3 {
         use core::fmt::{self, Debug, Formatter};
         use core::hash::Hash;
 
-        use p3_field::{AbstractField, Field, PrimeField32};
+        use p3_field::{AbstractField, Field, PackedValue, PrimeField32};
 
         #[derive(
             Debug,
@@ -34,6 +34,7 @@ macro_rules! powdr_field_plonky3 {
             Deserialize,
             derive_more::Display,
         )]
+        #[repr(transparent)]
         pub struct $name($p3_type);
 
         impl $name {
@@ -54,6 +55,42 @@ macro_rules! powdr_field_plonky3 {
             pub fn from_inner(e: $p3_type) -> Self {
                 Self(e)
             }
+
+            fn as_inner_slice(values: &[Self]) -> &[$p3_type] {
+                // SAFETY: `Self` is a transparent wrapper of the Plonky3 type.
+                unsafe { std::slice::from_raw_parts(values.as_ptr().cast(), values.len()) }
+            }
+
+            fn as_inner_slice_mut(values: &mut [Self]) -> &mut [$p3_type] {
+                // SAFETY: `Self` is a transparent wrapper of the Plonky3 type.
+                unsafe {
+                    std::slice::from_raw_parts_mut(values.as_mut_ptr().cast(), values.len())
+                }
+            }
+
+            /// Applies `op` to the pairs of elements of `a` and `b`, using the
+            /// packed (SIMD) representation of Plonky3 for all but the last few.
+            fn zip_assign_packed(
+                a: &mut [Self],
+                b: &[Self],
+                packed_op: impl Fn(
+                    &mut <$p3_type as Field>::Packing,
+                    <$p3_type as Field>::Packing,
+                ),
+                op: impl Fn(&mut $p3_type, $p3_type),
+            ) {
+                type Packing = <$p3_type as Field>::Packing;
+                assert_eq!(a.len(), b.len());
+                let (a_packed, a_suffix) =
+                    Packing::pack_slice_with_suffix_mut(Self::as_inner_slice_mut(a));
+                let (b_packed, b_suffix) = Packing::pack_slice_with_suffix(Self::as_inner_slice(b));
+                for (a, b) in a_packed.iter_mut().zip(b_packed) {
+                    packed_op(a, *b);
+                }
+                for (a, b) in a_suffix.iter_mut().zip(b_suffix) {
+                    op(a, *b);
+                }
+            }
         }
 
         impl FieldElement for $name {
@@ -61,6 +98,8 @@ macro_rules! powdr_field_plonky3 {
 
             const BITS: u32 = 31;
 
+            $(const EXTENSION_NON_RESIDUE: u64 = $extension_non_residue;)?
+
             fn to_degree(&self) -> $crate::DegreeType {
                 self.to_canonical_u32() as u64
             }
@@ -122,6 +161,35 @@ macro_rules! powdr_field_plonky3 {
                 // No direct repr, because 'mod' is not always applied.
                 false
             }
+
+            /// The products of the 31-bit canonical values fit into 62 bits, so
+            /// they can be accumulated in a u128 and reduced once at the end.
+            fn dot_product(a: &[Self], b: &[Self]) -> Self {
+                assert_eq!(a.len(), b.len());
+                let sum = a
+                    .iter()
+                    .zip(b)
+                    .map(|(a, b)| (a.to_canonical_u32() as u64) * (b.to_canonical_u32() as u64))
+                    .fold(0u128, |acc, product| acc + product as u128);
+                let p: u32 = <$p3_type>::order().try_into().unwrap();
+                Self::from_canonical_u32((sum % p as u128) as u32)
+            }
+
+            fn add_assign_slice(a: &mut [Self], b: &[Self]) {
+                Self::zip_assign_packed(a, b, |a, b| *a += b, |a, b| *a += b);
+            }
+
+            fn mul_assign_slice(a: &mut [Self], b: &[Self]) {
+                Self::zip_assign_packed(a, b, |a, b| *a *= b, |a, b| *a *= b);
+            }
+
+            /// Uses the batch inversion of Plonky3, which computes the products
+            /// of Montgomery's trick on packed values.
+            fn inverse_slice(values: &mut [Self]) {
+                assert!(!values.contains(&Self::ZERO), "Cannot invert zero");
+                let inverses = p3_field::batch_multiplicative_inverse(Self::as_inner_slice(values));
+                Self::as_inner_slice_mut(values).copy_from_slice(&inverses);
+            }
         }
 
         impl LowerHex for $name {
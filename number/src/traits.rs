@@ -152,6 +152,11 @@ pub trait FieldElement:
     /// Number of bits required to represent elements of this field.
     const BITS: u32;
 
+    /// The constant `W` of the polynomials `X^D - W` defining the binomial
+    /// extensions of this field, see [crate::BinomialExtension].
+    /// It is the same as in `std/math/fp2.asm` unless that one is a square in this field.
+    const EXTENSION_NON_RESIDUE: u64 = 11;
+
     fn to_degree(&self) -> DegreeType;
 
     fn to_integer(&self) -> Self::Integer;
@@ -191,6 +196,40 @@ pub trait FieldElement:
     /// additional fields), i.e. the `to_integer` function can be implemented as
     /// a mem::transmute operation on pointers.
     fn has_direct_repr() -> bool;
+
+    /// Returns the sum of the pairwise products of `a` and `b`.
+    /// Fields can override this to avoid reducing after every step.
+    /// Panics if the slices have different lengths.
+    fn dot_product(a: &[Self], b: &[Self]) -> Self {
+        assert_eq!(a.len(), b.len());
+        a.iter()
+            .zip(b)
+            .fold(Self::zero(), |acc, (a, b)| acc + *a * *b)
+    }
+
+    /// Adds `b` to `a` element-wise.
+    /// Fields can override this to use vectorized arithmetic.
+    /// Panics if the slices have different lengths.
+    fn add_assign_slice(a: &mut [Self], b: &[Self]) {
+        crate::batch::add_assign(a, b);
+    }
+
+    /// Multiplies `a` by `b` element-wise.
+    /// Fields can override this to use vectorized arithmetic.
+    /// Panics if the slices have different lengths.
+    fn mul_assign_slice(a: &mut [Self], b: &[Self]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter_mut().zip(b) {
+            *a = *a * *b;
+        }
+    }
+
+    /// Replaces every element by its inverse, see [crate::batch::inverse_in_place].
+    /// Fields can override this to use vectorized arithmetic.
+    /// Panics if any of the elements is zero.
+    fn inverse_slice(values: &mut [Self]) {
+        crate::batch::inverse_in_place(values);
+    }
 }
 
 #[cfg(test)]
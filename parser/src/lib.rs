@@ -644,6 +644,28 @@ machine Main(a: Byte, b: Byte) {
         assert_eq!(expected.trim(), printed.trim());
     }
 
+    #[test]
+    fn link_connections() {
+        let input = r#"
+machine Main {
+    link bus if flag => z = submachine.foo(x, y);
+    link native ~> submachine.bar(x);
+    link => z = submachine.foo(x, y);
+}
+"#;
+        let printed = format!("{}", parse_asm(Some("input"), input).unwrap_err_to_stderr());
+        assert_eq!(input.trim(), printed.trim());
+    }
+
+    #[test]
+    fn unknown_link_connection() {
+        let input = "machine Main { link fast => submachine.foo(); }";
+        let err = parse_asm(Some("input"), input).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unknown link connection `fast`, expected `native` or `bus`"));
+    }
+
     #[test]
     fn struct_decls() {
         let input = r#"
@@ -800,6 +800,16 @@ fn to_constraint<T: FieldElement>(
                 Value::Array(fields) => fields.iter().map(|f| to_expr(f)).collect(),
                 _ => panic!("Expected array, got {:?}", fields[1]),
             }),
+            accumulator_columns: match fields[2].as_ref() {
+                Value::Array(fields) => fields
+                    .iter()
+                    .map(|f| match to_expr(f) {
+                        AlgebraicExpression::Reference(reference) => reference,
+                        e => panic!("Expected a column reference, got {e}"),
+                    })
+                    .collect(),
+                _ => panic!("Expected array, got {:?}", fields[2]),
+            },
         }
         .into(),
         _ => panic!("Expected constraint but got {constraint}"),
@@ -0,0 +1,216 @@
+//! Common subexpression elimination: extracts algebraic subexpressions that
+//! occur multiple times in the identities of a namespace into intermediate columns.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicExpression, AlgebraicReference, Analyzed, PolyID, PolynomialType, StatementIdentifier,
+    Symbol, SymbolKind,
+};
+use powdr_ast::parsed::visitor::{AllChildren, Children};
+use powdr_number::FieldElement;
+use powdr_parser_util::SourceRef;
+
+use crate::{expression_degree, namespace_degree};
+
+/// The cost model that decides which common subexpressions are extracted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CseConfig {
+    /// The minimum number of operations the extraction of a subexpression has
+    /// to save, i.e. the number of its operations times its occurrences minus one.
+    pub min_savings: usize,
+    /// The maximum constraint degree of the backend, if it has one.
+    /// Subexpressions of a higher degree are not extracted, since their
+    /// intermediate columns could not be turned into witness columns later on.
+    pub max_degree: Option<usize>,
+}
+
+/// Extracts subexpressions that occur multiple times in the identities of a namespace
+/// into new intermediate columns, if this is worth it according to the cost model.
+/// Returns the number of intermediate columns introduced.
+pub fn extract_common_subexpressions<T: FieldElement>(
+    pil_file: &mut Analyzed<T>,
+    config: &CseConfig,
+) -> usize {
+    let intermediates = pil_file.intermediate_definitions();
+    let mut intermediate_degrees = BTreeMap::new();
+
+    // Count the occurrences of all candidates, including those nested in other candidates.
+    let mut counts: BTreeMap<&AlgebraicExpression<T>, usize> = BTreeMap::new();
+    for e in pil_file
+        .identities
+        .iter()
+        .flat_map(|identity| identity.children())
+        .flat_map(|e| e.all_children())
+    {
+        if candidate_namespace(e).is_some() {
+            *counts.entry(e).or_default() += 1;
+        }
+    }
+
+    // Greedily extract the largest subexpressions first. Once a subexpression is
+    // extracted, everything nested in it only occurs once more, inside its definition.
+    let mut candidates = counts.keys().copied().collect::<Vec<_>>();
+    candidates.sort_by_key(|e| std::cmp::Reverse(operation_count(e)));
+    let mut extracted = vec![];
+    for candidate in candidates {
+        let count = counts[candidate];
+        if count < 2 || (count - 1) * operation_count(candidate) < config.min_savings {
+            continue;
+        }
+        if let Some(max_degree) = config.max_degree {
+            if expression_degree(candidate, &intermediates, &mut intermediate_degrees) > max_degree
+            {
+                continue;
+            }
+        }
+        for nested in candidate.all_children().skip(1) {
+            if let Some(nested_count) = counts.get_mut(nested) {
+                *nested_count -= count - 1;
+            }
+        }
+        extracted.push(candidate.clone());
+    }
+    if extracted.is_empty() {
+        return 0;
+    }
+
+    // Create the intermediate columns, the ones of nested subexpressions first,
+    // so that they are defined before they are used.
+    let mut next_id = pil_file
+        .intermediate_columns
+        .values()
+        .map(|(symbol, _)| symbol.id + symbol.length.unwrap_or(1))
+        .max()
+        .unwrap_or_default();
+    let mut replacements = BTreeMap::new();
+    let mut new_columns: BTreeMap<String, Vec<(Symbol, AlgebraicExpression<T>)>> = BTreeMap::new();
+    for expr in extracted.into_iter().rev() {
+        let namespace = candidate_namespace(&expr).unwrap().to_string();
+        let name = (0..)
+            .map(|i| format!("{namespace}::_cse_{i}"))
+            .find(|name| {
+                !pil_file.definitions.contains_key(name)
+                    && !pil_file.intermediate_columns.contains_key(name)
+                    && !new_columns
+                        .values()
+                        .flatten()
+                        .any(|(symbol, _)| &symbol.absolute_name == name)
+            })
+            .unwrap();
+        let symbol = Symbol {
+            id: next_id,
+            source: SourceRef::unknown(),
+            absolute_name: name.clone(),
+            stage: None,
+            kind: SymbolKind::Poly(PolynomialType::Intermediate),
+            length: None,
+            degree: namespace_degree(pil_file, &namespace),
+        };
+        next_id += 1;
+
+        let mut definition = expr.clone();
+        for e in definition.children_mut() {
+            replace_subexpressions(e, &replacements);
+        }
+        replacements.insert(
+            expr,
+            AlgebraicReference {
+                name,
+                poly_id: PolyID::from(&symbol),
+                next: false,
+            },
+        );
+        new_columns
+            .entry(namespace)
+            .or_default()
+            .push((symbol, definition));
+    }
+
+    for identity in &mut pil_file.identities {
+        for e in identity.children_mut() {
+            replace_subexpressions(e, &replacements);
+        }
+    }
+
+    // Insert the new columns after the last definition of their namespace.
+    let count = replacements.len();
+    for (namespace, columns) in new_columns {
+        let position = pil_file
+            .source_order
+            .iter()
+            .rposition(|s| {
+                matches!(s, StatementIdentifier::Definition(name)
+                    if name.rsplit_once("::").map(|(ns, _)| ns) == Some(namespace.as_str()))
+            })
+            .map(|p| p + 1)
+            .unwrap_or(pil_file.source_order.len());
+        let statements = columns
+            .iter()
+            .map(|(symbol, _)| StatementIdentifier::Definition(symbol.absolute_name.clone()))
+            .collect::<Vec<_>>();
+        pil_file.source_order.splice(position..position, statements);
+        for (symbol, definition) in columns {
+            log::debug!(
+                "Extracting common subexpression {definition} into intermediate column {}",
+                symbol.absolute_name
+            );
+            pil_file
+                .intermediate_columns
+                .insert(symbol.absolute_name.clone(), (symbol, vec![definition]));
+        }
+    }
+    pil_file.reassign_ids();
+    log::info!("Extracted {count} common subexpressions into intermediate columns.");
+    count
+}
+
+/// Returns the namespace of a subexpression that can be extracted, i.e. an operation
+/// that only references columns of a single namespace (and at least one of them).
+fn candidate_namespace<T>(e: &AlgebraicExpression<T>) -> Option<&str> {
+    if !matches!(
+        e,
+        AlgebraicExpression::BinaryOperation(_) | AlgebraicExpression::UnaryOperation(_)
+    ) {
+        return None;
+    }
+    let mut namespaces = BTreeSet::new();
+    for e in e.all_children() {
+        match e {
+            AlgebraicExpression::Reference(r) => {
+                namespaces.insert(r.name.rsplit_once("::")?.0);
+            }
+            AlgebraicExpression::PublicReference(_) | AlgebraicExpression::Challenge(_) => {
+                return None
+            }
+            _ => {}
+        }
+    }
+    let namespace = namespaces.pop_first()?;
+    namespaces.is_empty().then_some(namespace)
+}
+
+fn operation_count<T>(e: &AlgebraicExpression<T>) -> usize {
+    e.all_children()
+        .filter(|e| {
+            matches!(
+                e,
+                AlgebraicExpression::BinaryOperation(_) | AlgebraicExpression::UnaryOperation(_)
+            )
+        })
+        .count()
+}
+
+/// Replaces the outermost occurrences of the given subexpressions by references.
+fn replace_subexpressions<T: Ord>(
+    e: &mut AlgebraicExpression<T>,
+    replacements: &BTreeMap<AlgebraicExpression<T>, AlgebraicReference>,
+) {
+    if let Some(reference) = replacements.get(e) {
+        *e = AlgebraicExpression::Reference(reference.clone());
+    } else {
+        for e in e.children_mut() {
+            replace_subexpressions(e, replacements);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Degree reduction: rewrites identities whose degree exceeds the maximum
+//! constraint degree of a backend, by committing to subexpressions in new
+//! witness columns.
+//!
+//! A subexpression `e` is replaced by a new witness column `w` together with
+//! the identity `w = e`, which witness generation solves for `w` like it solves
+//! the definition of an intermediate column. Intermediate columns whose degree
+//! is too high are turned into witness columns in the same way. Powers are
+//! reduced by committing to their base or by expanding them into products.
+//!
+//! The backends prove lookups and permutations with LogUp, which constrains a
+//! helper column `h` by `h * (beta - fingerprint) = selector`. The fingerprint
+//! is linear in the tuple expressions, so those are reduced to one degree below
+//! the maximum, by committing to them as a whole if needed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperation, AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReference,
+    AlgebraicReferenceThin, Analyzed, Identity, LookupIdentity, PermutationIdentity, PolyID,
+    PolynomialIdentity, PolynomialType, StatementIdentifier, Symbol, SymbolKind,
+};
+use powdr_ast::parsed::visitor::{AllChildren, Children};
+use powdr_number::FieldElement;
+use powdr_parser_util::SourceRef;
+
+use crate::{expression_degree, namespace_degree};
+
+/// Rewrites the polynomial identities, lookups and permutations so that all of
+/// their constraints have a degree of at most `max_degree`: polynomial identities
+/// and selectors are reduced to `max_degree`, the tuple expressions of lookups and
+/// permutations to `max_degree - 1`.
+/// Returns the number of witness columns introduced.
+pub fn reduce_degree<T: FieldElement>(pil_file: &mut Analyzed<T>, max_degree: usize) -> usize {
+    assert!(max_degree >= 2, "Cannot reduce the degree below 2.");
+    let mut reducer = DegreeReducer::new(pil_file, max_degree);
+
+    // Intermediate columns referencing other intermediate columns need to be
+    // handled after those.
+    let intermediates = reducer
+        .pil_file
+        .intermediate_polys_in_source_order()
+        .filter(|(symbol, _)| !symbol.is_array())
+        .map(|(symbol, _)| PolyID::from(symbol))
+        .collect::<Vec<_>>();
+    for poly_id in intermediates {
+        reducer.reduce_intermediate(poly_id);
+    }
+
+    for index in 0..reducer.pil_file.identities.len() {
+        let mut identity = reducer.pil_file.identities[index].clone();
+        // The expressions together with the degree they need to be reduced to.
+        let expressions: Vec<(&mut AlgebraicExpression<T>, usize)> = match &mut identity {
+            Identity::Polynomial(identity) => vec![(&mut identity.expression, max_degree)],
+            Identity::Lookup(LookupIdentity { left, right, .. })
+            | Identity::Permutation(PermutationIdentity { left, right, .. }) => [left, right]
+                .into_iter()
+                .flat_map(|selected| {
+                    std::iter::once((&mut selected.selector, max_degree))
+                        .chain(selected.expressions.iter_mut().map(|e| (e, max_degree - 1)))
+                })
+                .collect(),
+            // Phantom identities and bus interactions are not proven by the backends.
+            _ => vec![],
+        };
+        let mut changed = false;
+        for (e, bound) in expressions {
+            if reducer.degree(e) > bound {
+                reducer.reduce(e);
+                if reducer.degree(e) > bound {
+                    reducer.commit(e);
+                }
+                changed = true;
+            }
+        }
+        if changed {
+            reducer.pil_file.identities[index] = identity;
+        }
+    }
+
+    let count = reducer.new_columns;
+    if count > 0 || reducer.converted_intermediates > 0 {
+        pil_file.reassign_ids();
+        log::info!(
+            "Reduced the degree to {max_degree} by introducing {count} witness columns and committing to {} intermediate columns.",
+            reducer.converted_intermediates
+        );
+    }
+    count
+}
+
+struct DegreeReducer<'a, T> {
+    pil_file: &'a mut Analyzed<T>,
+    max_degree: usize,
+    /// The definitions of the intermediate columns.
+    intermediates: BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+    /// The degrees of the intermediate columns, with intermediate columns inlined.
+    intermediate_degrees: BTreeMap<AlgebraicReferenceThin, usize>,
+    /// The ID for the next witness column. IDs are re-assigned in the end.
+    next_witness_id: u64,
+    /// The number of identities before the reduction.
+    original_identity_count: usize,
+    new_columns: usize,
+    converted_intermediates: usize,
+}
+
+impl<'a, T: FieldElement> DegreeReducer<'a, T> {
+    fn new(pil_file: &'a mut Analyzed<T>, max_degree: usize) -> Self {
+        let next_witness_id = pil_file
+            .committed_polys_in_source_order()
+            .map(|(symbol, _)| symbol.id + symbol.length.unwrap_or(1))
+            .max()
+            .unwrap_or_default();
+        let original_identity_count = pil_file.identities.len();
+        let intermediates = pil_file.intermediate_definitions();
+        Self {
+            pil_file,
+            max_degree,
+            intermediates,
+            intermediate_degrees: Default::default(),
+            next_witness_id,
+            original_identity_count,
+            new_columns: 0,
+            converted_intermediates: 0,
+        }
+    }
+
+    /// Turns the intermediate column into a witness column if its degree is too high.
+    fn reduce_intermediate(&mut self, poly_id: PolyID) {
+        let (name, mut definition) = {
+            let (symbol, definitions) = self
+                .pil_file
+                .intermediate_columns
+                .values()
+                .find(|(symbol, _)| PolyID::from(symbol) == poly_id)
+                .unwrap();
+            (symbol.absolute_name.clone(), definitions[0].clone())
+        };
+        if self.degree(&definition) <= self.max_degree {
+            return;
+        }
+        self.reduce(&mut definition);
+
+        let (mut symbol, _) = self.pil_file.intermediate_columns.remove(&name).unwrap();
+        symbol.kind = SymbolKind::Poly(PolynomialType::Committed);
+        symbol.id = self.next_witness_id;
+        symbol.stage = self.stage(&definition);
+        self.next_witness_id += 1;
+        let new_poly_id = PolyID::from(&symbol);
+        self.pil_file
+            .post_visit_expressions_in_identities_mut(&mut |e| {
+                if let AlgebraicExpression::Reference(r) = e {
+                    if r.poly_id == poly_id {
+                        r.poly_id = new_poly_id;
+                    }
+                }
+            });
+        self.pil_file
+            .definitions
+            .insert(name.clone(), (symbol, None));
+        // Degrees of intermediate columns referencing this one are outdated now.
+        self.intermediates = self.pil_file.intermediate_definitions();
+        self.intermediate_degrees.clear();
+        self.converted_intermediates += 1;
+
+        let reference = AlgebraicReference {
+            name: name.clone(),
+            poly_id: new_poly_id,
+            next: false,
+        };
+        let position = self
+            .pil_file
+            .source_order
+            .iter()
+            .position(|s| matches!(s, StatementIdentifier::Definition(n) if n == &name))
+            .unwrap();
+        self.add_identity(reference, definition, position + 1);
+    }
+
+    /// Rewrites the expression until its degree is at most the maximum degree.
+    fn reduce(&mut self, e: &mut AlgebraicExpression<T>) {
+        if self.degree(e) <= self.max_degree {
+            return;
+        }
+        match e {
+            AlgebraicExpression::Reference(r) => {
+                // Only elements of intermediate arrays can still be too large,
+                // so we inline their definition.
+                let definition = self.intermediates[&r.to_thin()].clone();
+                *e = definition;
+                self.reduce(e);
+            }
+            AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
+                left,
+                op: AlgebraicBinaryOperator::Mul,
+                right,
+            }) => {
+                self.reduce(left);
+                self.reduce(right);
+                loop {
+                    let (left_degree, right_degree) = (self.degree(left), self.degree(right));
+                    if left_degree + right_degree <= self.max_degree {
+                        break;
+                    }
+                    if left_degree >= right_degree {
+                        self.commit(left);
+                    } else {
+                        self.commit(right);
+                    }
+                }
+            }
+            AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
+                left,
+                op: AlgebraicBinaryOperator::Pow,
+                right,
+            }) => {
+                let AlgebraicExpression::Number(exponent) = right.as_ref() else {
+                    panic!("Exponent has to be a number, but got {right}.");
+                };
+                let exponent = exponent.to_degree();
+                self.reduce(left);
+                if exponent > 1 && self.degree(left) > 1 {
+                    self.commit(left);
+                }
+                if self.degree(left) * exponent as usize > self.max_degree {
+                    // Expand the power into a product, whose factors can be committed to.
+                    *e = power_product(left.as_ref(), exponent);
+                    self.reduce(e);
+                }
+            }
+            _ => {
+                for e in e.children_mut() {
+                    self.reduce(e);
+                }
+            }
+        }
+    }
+
+    /// Replaces the expression by a new witness column constrained to be equal to it.
+    fn commit(&mut self, e: &mut AlgebraicExpression<T>) {
+        let namespace = e
+            .all_children()
+            .find_map(|e| match e {
+                AlgebraicExpression::Reference(r) => {
+                    r.name.rsplit_once("::").map(|(ns, _)| ns.to_string())
+                }
+                _ => None,
+            })
+            .expect("Expected a namespaced reference in an expression of degree larger than one.");
+        let name = (0..)
+            .map(|i| format!("{namespace}::_degree_reduction_{i}"))
+            .find(|name| {
+                !self.pil_file.definitions.contains_key(name)
+                    && !self.pil_file.intermediate_columns.contains_key(name)
+            })
+            .unwrap();
+        let symbol = Symbol {
+            id: self.next_witness_id,
+            source: SourceRef::unknown(),
+            absolute_name: name.clone(),
+            stage: self.stage(e),
+            kind: SymbolKind::Poly(PolynomialType::Committed),
+            length: None,
+            degree: namespace_degree(self.pil_file, &namespace),
+        };
+        self.next_witness_id += 1;
+        self.new_columns += 1;
+        let reference = AlgebraicReference {
+            name: name.clone(),
+            poly_id: PolyID::from(&symbol),
+            next: false,
+        };
+        self.pil_file
+            .definitions
+            .insert(name.clone(), (symbol, None));
+
+        // Insert the column after the last definition of its namespace, and after
+        // the identities previously added there.
+        let mut position = self
+            .pil_file
+            .source_order
+            .iter()
+            .rposition(|s| {
+                matches!(s, StatementIdentifier::Definition(n)
+                    if n.rsplit_once("::").map(|(ns, _)| ns) == Some(namespace.as_str()))
+            })
+            .map(|p| p + 1)
+            .unwrap_or(self.pil_file.source_order.len());
+        while matches!(self.pil_file.source_order.get(position),
+            Some(StatementIdentifier::ProofItem(i)) if *i >= self.original_identity_count)
+        {
+            position += 1;
+        }
+        self.pil_file
+            .source_order
+            .insert(position, StatementIdentifier::Definition(name));
+
+        let definition = std::mem::replace(e, AlgebraicExpression::Reference(reference.clone()));
+        self.add_identity(reference, definition, position + 1);
+    }
+
+    /// Adds the identity `column = definition` at the given position in the source order.
+    fn add_identity(
+        &mut self,
+        column: AlgebraicReference,
+        definition: AlgebraicExpression<T>,
+        position: usize,
+    ) {
+        let id = self
+            .pil_file
+            .identities
+            .iter()
+            .map(|identity| identity.id())
+            .max()
+            .unwrap_or_default()
+            + 1;
+        self.pil_file
+            .identities
+            .push(Identity::Polynomial(PolynomialIdentity {
+                id,
+                source: SourceRef::unknown(),
+                expression: AlgebraicExpression::Reference(column) - definition,
+            }));
+        self.pil_file.source_order.insert(
+            position,
+            StatementIdentifier::ProofItem(self.pil_file.identities.len() - 1),
+        );
+    }
+
+    /// Returns the degree of the expression, with intermediate columns inlined.
+    fn degree(&mut self, e: &AlgebraicExpression<T>) -> usize {
+        expression_degree(e, &self.intermediates, &mut self.intermediate_degrees)
+    }
+
+    /// Returns the proof stage a witness column committing to the expression needs to be in.
+    fn stage(&self, e: &AlgebraicExpression<T>) -> Option<u32> {
+        let witness_stages = self
+            .pil_file
+            .committed_polys_in_source_order()
+            .flat_map(|(symbol, _)| {
+                symbol
+                    .array_elements()
+                    .map(|(_, poly_id)| (poly_id, symbol.stage.unwrap_or_default()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut stage = 0;
+        let mut visited = BTreeSet::new();
+        let mut to_visit = vec![e.clone()];
+        while let Some(e) = to_visit.pop() {
+            for e in e.all_children() {
+                match e {
+                    AlgebraicExpression::Reference(r) => match r.poly_id.ptype {
+                        PolynomialType::Committed => {
+                            stage = stage.max(witness_stages[&r.poly_id]);
+                        }
+                        PolynomialType::Intermediate => {
+                            if visited.insert(r.to_thin()) {
+                                to_visit.push(self.intermediates[&r.to_thin()].clone());
+                            }
+                        }
+                        PolynomialType::Constant => {}
+                    },
+                    AlgebraicExpression::Challenge(challenge) => {
+                        stage = stage.max(challenge.stage + 1);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        (stage > 0).then_some(stage)
+    }
+}
+
+/// Returns the product of `exponent` copies of `base`, as a balanced tree of
+/// multiplications.
+fn power_product<T: FieldElement>(
+    base: &AlgebraicExpression<T>,
+    exponent: u64,
+) -> AlgebraicExpression<T> {
+    match exponent {
+        0 => AlgebraicExpression::Number(T::one()),
+        1 => base.clone(),
+        _ => power_product(base, exponent / 2) * power_product(base, exponent - exponent / 2),
+    }
+}
@@ -6,18 +6,23 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use itertools::Itertools;
 use powdr_ast::analyzed::{
     AlgebraicBinaryOperation, AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReference,
-    AlgebraicUnaryOperation, AlgebraicUnaryOperator, Analyzed, ConnectIdentity, Expression,
-    FunctionValueDefinition, Identity, LookupIdentity, PermutationIdentity, PhantomLookupIdentity,
-    PhantomPermutationIdentity, PolyID, PolynomialIdentity, PolynomialReference, PolynomialType,
-    Reference, Symbol, SymbolKind,
+    AlgebraicReferenceThin, AlgebraicUnaryOperation, AlgebraicUnaryOperator, Analyzed,
+    ConnectIdentity, DegreeRange, Expression, FunctionValueDefinition, Identity, LookupIdentity,
+    PermutationIdentity, PhantomLookupIdentity, PhantomPermutationIdentity, PolyID,
+    PolynomialIdentity, PolynomialReference, PolynomialType, Reference, Symbol, SymbolKind,
 };
 use powdr_ast::parsed::types::Type;
 use powdr_ast::parsed::visitor::{AllChildren, Children, ExpressionVisitable};
 use powdr_ast::parsed::Number;
 use powdr_number::{BigUint, FieldElement};
 
+mod cse;
+mod degree_reduction;
 pub mod referenced_symbols;
 
+pub use cse::{extract_common_subexpressions, CseConfig};
+pub use degree_reduction::reduce_degree;
+
 use referenced_symbols::{ReferencedSymbols, SymbolReference};
 
 pub fn optimize<T: FieldElement>(mut pil_file: Analyzed<T>) -> Analyzed<T> {
@@ -219,6 +224,66 @@ fn constant_value(function: &FunctionValueDefinition) -> Option<BigUint> {
     }
 }
 
+/// Returns the degree of the expression, with the definitions of intermediate
+/// columns inlined. The degrees of intermediate columns are memoized in `cache`.
+fn expression_degree<T: FieldElement>(
+    e: &AlgebraicExpression<T>,
+    intermediates: &BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+    cache: &mut BTreeMap<AlgebraicReferenceThin, usize>,
+) -> usize {
+    match e {
+        AlgebraicExpression::Reference(r) if r.poly_id.ptype == PolynomialType::Intermediate => {
+            if let Some(degree) = cache.get(&r.to_thin()) {
+                return *degree;
+            }
+            let degree = expression_degree(&intermediates[&r.to_thin()], intermediates, cache);
+            cache.insert(r.to_thin(), degree);
+            degree
+        }
+        AlgebraicExpression::Reference(_) => 1,
+        AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
+            left,
+            op: AlgebraicBinaryOperator::Mul,
+            right,
+        }) => {
+            expression_degree(left, intermediates, cache)
+                + expression_degree(right, intermediates, cache)
+        }
+        AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
+            left,
+            op: AlgebraicBinaryOperator::Pow,
+            right,
+        }) => match right.as_ref() {
+            AlgebraicExpression::Number(exponent) => {
+                expression_degree(left, intermediates, cache) * exponent.to_degree() as usize
+            }
+            _ => panic!("Exponent has to be a number, but got {right}."),
+        },
+        _ => e
+            .children()
+            .map(|e| expression_degree(e, intermediates, cache))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// Returns the degree range of the columns in the given namespace.
+fn namespace_degree<T>(pil_file: &Analyzed<T>, namespace: &str) -> Option<DegreeRange> {
+    pil_file
+        .committed_polys_in_source_order()
+        .chain(pil_file.constant_polys_in_source_order())
+        .map(|(symbol, _)| symbol)
+        .chain(
+            pil_file
+                .intermediate_polys_in_source_order()
+                .map(|(symbol, _)| symbol),
+        )
+        .filter(|symbol| {
+            symbol.absolute_name.rsplit_once("::").map(|(ns, _)| ns) == Some(namespace)
+        })
+        .find_map(|symbol| symbol.degree)
+}
+
 /// Deduplicate fixed columns of the same namespace which share the same value.
 /// This compares the function values, so `|i| i` is different from `|j| j`
 fn extract_namespace(symbol: &Symbol) -> &str {
@@ -1,7 +1,7 @@
 use powdr_number::GoldilocksField;
 use powdr_pil_analyzer::analyze_string;
 
-use powdr_pilopt::optimize;
+use powdr_pilopt::{extract_common_subexpressions, optimize, reduce_degree, CseConfig};
 use pretty_assertions::assert_eq;
 
 #[test]
@@ -392,3 +392,132 @@ fn handle_array_references_in_prover_functions() {
     let optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap()).to_string();
     assert_eq!(optimized, expectation);
 }
+
+#[test]
+fn common_subexpressions() {
+    let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    X * Y + Z = 0;
+    (X * Y + Z) * X = Y;
+    Z' = X * Y + Z;
+    X + Y = Z * Z;
+    X' = X + Y;
+"#;
+    let expectation = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    col _cse_0 = N::X * N::Y + N::Z;
+    N::_cse_0 = 0;
+    N::_cse_0 * N::X = N::Y;
+    N::Z' = N::_cse_0;
+    N::X + N::Y = N::Z * N::Z;
+    N::X' = N::X + N::Y;
+"#;
+    let mut optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap());
+    let config = CseConfig {
+        min_savings: 2,
+        max_degree: Some(3),
+    };
+    assert_eq!(extract_common_subexpressions(&mut optimized, &config), 1);
+    assert_eq!(optimized.to_string(), expectation);
+}
+
+#[test]
+fn common_subexpressions_above_max_degree() {
+    let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    X * Y * Z = 1;
+    Z' = X * Y * Z;
+"#;
+    let mut optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap());
+    let expectation = optimized.to_string();
+    let config = CseConfig {
+        min_savings: 1,
+        max_degree: Some(1),
+    };
+    assert_eq!(extract_common_subexpressions(&mut optimized, &config), 0);
+    assert_eq!(optimized.to_string(), expectation);
+}
+
+#[test]
+fn degree_reduction() {
+    let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    X * Y * Z = 1;
+    X + Y = Z * Z;
+"#;
+    let expectation = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    col witness _degree_reduction_0;
+    N::_degree_reduction_0 = N::X * N::Y;
+    N::_degree_reduction_0 * N::Z = 1;
+    N::X + N::Y = N::Z * N::Z;
+"#;
+    let mut optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap());
+    assert_eq!(reduce_degree(&mut optimized, 2), 1);
+    assert_eq!(optimized.to_string(), expectation);
+}
+
+#[test]
+fn degree_reduction_of_intermediate() {
+    let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    col I = X * Y * Z;
+    I * X = 1;
+    Z' = I + Y;
+"#;
+    let mut optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap());
+    assert_eq!(reduce_degree(&mut optimized, 2), 1);
+    // The intermediate column is committed to as well.
+    assert!(optimized.intermediate_columns.is_empty());
+    assert_eq!(optimized.commitment_count(), 5);
+    assert!(optimized.identities.iter().all(|i| i.degree() <= 2));
+}
+
+#[test]
+fn degree_reduction_in_lookup() {
+    let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col fixed Z = [0, 1]*;
+    [X * Y] in [Z];
+    [X] in [Z];
+"#;
+    let mut optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap());
+    // LogUp multiplies the tuple with a helper column, so a product in a lookup
+    // is committed to even though its degree is 2.
+    assert_eq!(reduce_degree(&mut optimized, 2), 1);
+    let output = optimized.to_string();
+    assert!(output.contains("N::_degree_reduction_0 = N::X * N::Y;"));
+    assert!(output.contains("[N::_degree_reduction_0] in [N::Z];"));
+    assert!(output.contains("[N::X] in [N::Z];"));
+}
+
+#[test]
+fn degree_reduction_of_power() {
+    let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    X**3 = Y;
+    (X * Y)**2 = 1;
+"#;
+    let mut optimized = optimize(analyze_string::<GoldilocksField>(input).unwrap());
+    assert_eq!(reduce_degree(&mut optimized, 2), 2);
+    let output = optimized.to_string();
+    // The power of a column is expanded into a product.
+    assert!(output.contains("N::_degree_reduction_0 = N::X * N::X;"));
+    assert!(output.contains("N::X * N::_degree_reduction_0 = N::Y;"));
+    // The base of a power of a product is committed to.
+    assert!(output.contains("N::_degree_reduction_1 = N::X * N::Y;"));
+}
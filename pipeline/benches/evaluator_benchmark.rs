@@ -131,7 +131,7 @@ fn jit_benchmark(c: &mut Criterion) {
         pipeline.compute_analyzed_pil().unwrap().clone()
     };
 
-    let sqrt_fun = powdr_jit_compiler::compile(&sqrt_analyzed, &["sqrt"])
+    let sqrt_fun = powdr_jit_compiler::compile(&sqrt_analyzed, &["sqrt"], None)
         .unwrap()
         .get_fixed_column("sqrt")
         .unwrap()
@@ -155,7 +155,7 @@ fn jit_benchmark(c: &mut Criterion) {
         let mut pipeline = Pipeline::default().from_asm_string(sort_code.to_string(), None);
         pipeline.compute_analyzed_pil().unwrap().clone()
     };
-    let sort_fun = powdr_jit_compiler::compile(&sort_analyzed, &["sort_int"])
+    let sort_fun = powdr_jit_compiler::compile(&sort_analyzed, &["sort_int"], None)
         .unwrap()
         .get_fixed_column("sort_int")
         .unwrap()
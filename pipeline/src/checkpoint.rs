@@ -0,0 +1,260 @@
+//! On-disk checkpoints of the pipeline stages.
+//!
+//! When checkpoints are enabled, every stage computed by the pipeline is
+//! persisted into a `checkpoints` sub-directory of the output directory,
+//! together with a manifest recording a content hash of each stage file and
+//! the hash of the input it was computed from. A new pipeline pointed to the
+//! same output directory resumes from the latest stage whose chain of hashes
+//! is still valid.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the checkpoint directory, relative to the output directory.
+const CHECKPOINT_DIR: &str = "checkpoints";
+/// Name of the manifest file, relative to the checkpoint directory.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The pipeline stages that can be checkpointed, in the order they are computed.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Stage {
+    OptimizedPil,
+    FixedCols,
+    Witness,
+    Proof,
+}
+
+impl Stage {
+    /// The name of the file the stage is persisted to.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Stage::OptimizedPil => "optimized.pilo",
+            Stage::FixedCols => "constants.bin",
+            Stage::Witness => "commits.bin",
+            Stage::Proof => "proof.bin",
+        }
+    }
+
+    /// The stage this stage is computed from, if any.
+    pub fn previous(&self) -> Option<Stage> {
+        match self {
+            Stage::OptimizedPil => None,
+            Stage::FixedCols => Some(Stage::OptimizedPil),
+            Stage::Witness => Some(Stage::FixedCols),
+            Stage::Proof => Some(Stage::Witness),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StageEntry {
+    /// Hash of the content of the stage file.
+    hash: String,
+    /// Hash of the input the stage was computed from.
+    input_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+struct Manifest {
+    stages: BTreeMap<Stage, StageEntry>,
+}
+
+/// The checkpoints of a pipeline run, backed by a directory.
+#[derive(Clone, Debug)]
+pub struct Checkpoints {
+    dir: PathBuf,
+    manifest: Manifest,
+    /// The stages that were validated or stored through this instance, i.e.
+    /// the ones whose hash corresponds to the artifacts of the current run.
+    current: BTreeSet<Stage>,
+}
+
+impl Checkpoints {
+    /// Opens the checkpoints in the given output directory, creating the
+    /// checkpoint directory if needed. A missing or unreadable manifest is
+    /// treated as an empty one.
+    pub fn open(output_dir: &Path) -> Result<Self, String> {
+        let dir = output_dir.join(CHECKPOINT_DIR);
+        fs::create_dir_all(&dir).map_err(|e| format!("Error creating {}: {e}", dir.display()))?;
+
+        let manifest = fs::File::open(dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default();
+
+        Ok(Self {
+            dir,
+            manifest,
+            current: Default::default(),
+        })
+    }
+
+    /// The directory the checkpoints are stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of the file of the given stage.
+    pub fn path(&self, stage: Stage) -> PathBuf {
+        self.dir.join(stage.file_name())
+    }
+
+    /// Returns the hash of the given stage, if it has been validated or
+    /// stored during the current run.
+    pub fn hash(&self, stage: Stage) -> Option<&str> {
+        if !self.current.contains(&stage) {
+            return None;
+        }
+        self.manifest
+            .stages
+            .get(&stage)
+            .map(|entry| entry.hash.as_str())
+    }
+
+    /// Returns the path of the stage file if the stage was checkpointed from
+    /// the given input and the file content still matches its recorded hash.
+    /// Otherwise, the stage is forgotten and None is returned.
+    pub fn validate(&mut self, stage: Stage, input_hash: &str) -> Option<PathBuf> {
+        let entry = self.manifest.stages.get(&stage)?;
+        let path = self.path(stage);
+        let is_valid =
+            entry.input_hash == input_hash && hash_file(&path).is_ok_and(|hash| hash == entry.hash);
+        if is_valid {
+            self.current.insert(stage);
+            Some(path)
+        } else {
+            log::info!("Discarding invalid checkpoint of stage {stage:?}.");
+            self.current.remove(&stage);
+            self.manifest.stages.remove(&stage);
+            None
+        }
+    }
+
+    /// Forgets the given stage and all the stages computed after it, so that
+    /// they are not resumed anymore.
+    pub fn invalidate(&mut self, stage: Stage) -> Result<(), String> {
+        self.current.retain(|s| *s < stage);
+        self.manifest.stages.retain(|s, _| *s < stage);
+        self.write_manifest()
+    }
+
+    /// Persists a stage computed from the given input. The stage content is
+    /// written by `write` into the provided (temporary) path, which is then
+    /// atomically moved into place before the manifest is updated.
+    pub fn store(
+        &mut self,
+        stage: Stage,
+        input_hash: String,
+        write: impl FnOnce(&Path) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let path = self.path(stage);
+        let tmp_path = path.with_extension("tmp");
+        write(&tmp_path)?;
+        let hash = hash_file(&tmp_path)
+            .map_err(|e| format!("Error hashing {}: {e}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| format!("Error writing {}: {e}", path.display()))?;
+
+        log::info!("Checkpointed stage {stage:?} into {}.", path.display());
+        self.manifest
+            .stages
+            .insert(stage, StageEntry { hash, input_hash });
+        self.current.insert(stage);
+        self.write_manifest()
+    }
+
+    fn write_manifest(&self) -> Result<(), String> {
+        let path = self.dir.join(MANIFEST_FILE);
+        let tmp_path = path.with_extension("tmp");
+        let content = serde_json::to_vec_pretty(&self.manifest).unwrap();
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .map_err(|e| format!("Error writing {}: {e}", path.display()))
+    }
+}
+
+/// Returns the hex-encoded hash of a file.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Returns the hex-encoded hash of the concatenation of the given parts.
+pub fn hash_parts<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for part in parts {
+        // Prefix with the length, so that different splits of the same
+        // bytes result in different hashes.
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn store_and_validate() {
+        let tmp_dir = mktemp::Temp::new_dir().unwrap();
+        let mut checkpoints = Checkpoints::open(&tmp_dir).unwrap();
+
+        checkpoints
+            .store(Stage::FixedCols, "input".to_string(), |path| {
+                fs::write(path, b"fixed").map_err(|e| e.to_string())
+            })
+            .unwrap();
+
+        // A new instance reads the manifest back.
+        let mut checkpoints = Checkpoints::open(&tmp_dir).unwrap();
+        assert!(checkpoints.validate(Stage::FixedCols, "other").is_none());
+
+        let mut checkpoints = Checkpoints::open(&tmp_dir).unwrap();
+        assert_eq!(
+            checkpoints.validate(Stage::FixedCols, "input"),
+            Some(checkpoints.path(Stage::FixedCols))
+        );
+
+        // Tampering with the file invalidates the checkpoint.
+        fs::write(checkpoints.path(Stage::FixedCols), b"changed").unwrap();
+        assert!(checkpoints.validate(Stage::FixedCols, "input").is_none());
+        assert!(checkpoints.hash(Stage::FixedCols).is_none());
+    }
+
+    #[test]
+    fn invalidate_later_stages() {
+        let tmp_dir = mktemp::Temp::new_dir().unwrap();
+        let mut checkpoints = Checkpoints::open(&tmp_dir).unwrap();
+        for stage in [Stage::FixedCols, Stage::Witness, Stage::Proof] {
+            checkpoints
+                .store(stage, "input".to_string(), |path| {
+                    fs::write(path, stage.file_name()).map_err(|e| e.to_string())
+                })
+                .unwrap();
+        }
+
+        checkpoints.invalidate(Stage::Witness).unwrap();
+        assert!(checkpoints.hash(Stage::FixedCols).is_some());
+        assert!(checkpoints.hash(Stage::Witness).is_none());
+
+        let mut checkpoints = Checkpoints::open(&tmp_dir).unwrap();
+        assert!(checkpoints.validate(Stage::FixedCols, "input").is_some());
+        assert!(checkpoints.validate(Stage::Proof, "input").is_none());
+    }
+
+    #[test]
+    fn hash_parts_is_split_sensitive() {
+        assert_ne!(
+            hash_parts([b"ab".as_slice(), b"c"]),
+            hash_parts([b"a".as_slice(), b"bc"])
+        );
+    }
+}
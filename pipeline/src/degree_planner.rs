@@ -0,0 +1,402 @@
+//! Chooses the degree ranges of the machines from the number of rows they use
+//! in executions, so that they do not need to be tuned by hand.
+//!
+//! The row counts can come from the RISC-V executor (see `Execution::machine_rows`)
+//! or from a first witness generation pass (see [crate::Pipeline::machine_rows]).
+//!
+//! A machine with a degree range is prepared (fixed columns and keys) for every
+//! power of two in the range, and each execution is proven at the smallest
+//! degree in the range that fits its rows. The planner estimates both costs by
+//! the number of cells, i.e. the number of columns times the degree, and picks
+//! for every machine the range with the lowest total cost over the given
+//! executions. A wide range makes small executions cheaper to prove, but
+//! needs to be prepared for more degrees. Machines with a fixed degree are
+//! left untouched.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use powdr_ast::{
+    analyzed::{Analyzed, DegreeRange},
+    asm_analysis::MachineDegree,
+    object::MachineInstanceGraph,
+    parsed::Expression,
+};
+use powdr_number::{BigUint, DegreeType};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DegreePlannerParams {
+    /// The log of the smallest degree a machine is given.
+    pub min_degree_log: u8,
+    /// The log of the largest degree a machine can have.
+    pub max_degree_log: u8,
+    /// The number of times a machine can double in size beyond the degree
+    /// needed by the largest execution, to absorb differences between executions.
+    pub headroom_log: u8,
+    /// The cost of preparing a machine for one degree, relative to proving one
+    /// execution at that degree.
+    pub setup_weight: u64,
+}
+
+impl Default for DegreePlannerParams {
+    fn default() -> Self {
+        Self {
+            min_degree_log: 5,
+            max_degree_log: 22,
+            headroom_log: 1,
+            setup_weight: 1,
+        }
+    }
+}
+
+/// The planned degree range of a single machine.
+#[derive(Clone, Debug, Serialize)]
+pub struct MachinePlan {
+    /// The largest number of rows used in any of the executions, if the machine
+    /// was part of one. Machines which were not, or which have a fixed degree,
+    /// are kept at their declared degree range.
+    pub used_rows: Option<DegreeType>,
+    /// The number of witness and fixed columns of the machine.
+    pub columns: usize,
+    /// The degree range declared in the source.
+    pub declared: DegreeRange,
+    /// The degree range to use.
+    pub planned: DegreeRange,
+    /// The estimated cost of preparing the planned range and proving all executions.
+    pub cost: u64,
+}
+
+/// The planned degree ranges of all machines, by namespace.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DegreePlan {
+    pub machines: BTreeMap<String, MachinePlan>,
+}
+
+impl DegreePlan {
+    /// The estimated cost of all machines.
+    pub fn total_cost(&self) -> u64 {
+        self.machines.values().map(|plan| plan.cost).sum()
+    }
+
+    /// Replaces the degrees of the machines in the graph by the planned ranges.
+    pub fn apply(&self, graph: &mut MachineInstanceGraph) {
+        for (location, object) in graph.objects.iter_mut() {
+            if let Some(plan) = self.machines.get(&location.to_string()) {
+                object.degree = MachineDegree {
+                    min: Some(Expression::from(BigUint::from(plan.planned.min))),
+                    max: Some(Expression::from(BigUint::from(plan.planned.max))),
+                };
+            }
+        }
+    }
+}
+
+impl fmt::Display for DegreePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = |r: &DegreeRange| format!("{}..={}", r.min, r.max);
+        writeln!(
+            f,
+            "{:<30} {:>10} {:>8} {:>20} {:>20} {:>14}",
+            "machine", "rows", "columns", "declared", "planned", "cost"
+        )?;
+        for (namespace, plan) in &self.machines {
+            writeln!(
+                f,
+                "{:<30} {:>10} {:>8} {:>20} {:>20} {:>14}",
+                namespace,
+                plan.used_rows
+                    .map(|rows| rows.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                plan.columns,
+                range(&plan.declared),
+                range(&plan.planned),
+                plan.cost
+            )?;
+        }
+        write!(f, "total cost: {}", self.total_cost())
+    }
+}
+
+/// The estimated cost of a machine with the given number of columns and degree
+/// range, which is prepared for every degree in the range and proves an
+/// execution at each of the given degrees.
+fn cost(
+    columns: usize,
+    range: DegreeRange,
+    degrees: &[DegreeType],
+    params: &DegreePlannerParams,
+) -> u64 {
+    let setup = std::iter::successors(Some(range.min), |degree| Some(degree * 2))
+        .take_while(|degree| *degree <= range.max)
+        .sum::<u64>()
+        * params.setup_weight;
+    let proving = degrees
+        .iter()
+        .map(|degree| *degree.max(&range.min))
+        .sum::<u64>();
+    columns as u64 * (setup + proving)
+}
+
+/// Plans the degree ranges of the machines in `pil` from the number of rows
+/// each namespace used in a number of executions.
+pub fn plan_degrees<T>(
+    pil: &Analyzed<T>,
+    executions: &[BTreeMap<String, DegreeType>],
+    params: DegreePlannerParams,
+) -> Result<DegreePlan, Vec<String>> {
+    let min_degree = 1 << params.min_degree_log;
+    let max_degree = 1 << params.max_degree_log;
+
+    // the declared degree range and the number of columns of each namespace
+    let mut machines: BTreeMap<String, (DegreeRange, usize)> = BTreeMap::new();
+    for (symbol, _) in pil
+        .committed_polys_in_source_order()
+        .chain(pil.constant_polys_in_source_order())
+    {
+        let (Some(degree), Some((namespace, _))) =
+            (symbol.degree, symbol.absolute_name.rsplit_once("::"))
+        else {
+            continue;
+        };
+        let (_, columns) = machines.entry(namespace.to_string()).or_insert((degree, 0));
+        *columns += symbol.length.unwrap_or(1) as usize;
+    }
+
+    let errors = executions
+        .iter()
+        .flatten()
+        .filter(|(namespace, rows)| **rows > 0 && !machines.contains_key(*namespace))
+        .map(|(namespace, _)| namespace)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|namespace| format!("No machine found for namespace {namespace}"))
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut errors = vec![];
+    let machines = machines
+        .into_iter()
+        .map(|(namespace, (declared, columns))| {
+            let rows = executions
+                .iter()
+                .filter_map(|execution| execution.get(&namespace).copied())
+                .collect::<Vec<_>>();
+            let used_rows = rows.iter().max().copied();
+            // The degree each execution needs, if the range allows it.
+            let degrees = rows
+                .iter()
+                .map(|rows| {
+                    rows.next_power_of_two()
+                        .max(min_degree)
+                        .clamp(declared.min, declared.max)
+                })
+                .collect::<Vec<_>>();
+            let planned = match (used_rows, degrees.iter().min(), degrees.iter().max()) {
+                (Some(used_rows), Some(&smallest), Some(&largest))
+                    if declared.min != declared.max =>
+                {
+                    if used_rows > declared.max || used_rows > max_degree {
+                        errors.push(format!(
+                            "Machine {namespace} uses {used_rows} rows, which exceeds the maximum degree {}",
+                            declared.max.min(max_degree)
+                        ));
+                    }
+                    let max = (largest << params.headroom_log)
+                        .min(max_degree)
+                        .min(declared.max)
+                        .max(largest);
+                    // Any smallest degree between the ones needed by the
+                    // smallest and the largest execution could be the cheapest.
+                    std::iter::successors(Some(smallest), |min| Some(min * 2))
+                        .take_while(|min| *min <= largest)
+                        .map(|min| DegreeRange { min, max })
+                        .min_by_key(|range| cost(columns, *range, &degrees, &params))
+                        .unwrap()
+                }
+                _ => declared,
+            };
+            let plan = MachinePlan {
+                used_rows,
+                columns,
+                declared,
+                planned,
+                cost: cost(columns, planned, &degrees, &params),
+            };
+            (namespace, plan)
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(DegreePlan { machines })
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use powdr_ast::analyzed::DegreeRange;
+    use powdr_number::GoldilocksField;
+
+    use crate::Pipeline;
+
+    use super::{plan_degrees, DegreePlannerParams};
+
+    const ASM: &str = r#"
+machine Main with min_degree: 32, max_degree: 4096 {
+    Add add;
+    Sub sub;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg Z[<=];
+    reg A;
+
+    instr add X, Y -> Z link => Z = add.add(X, Y);
+    instr sub X, Y -> Z link => Z = sub.sub(X, Y);
+
+    function main {
+        A <== add(1, 2);
+        A <== add(A, 3);
+        A <== add(A, 4);
+        A <== sub(A, 1);
+        return;
+    }
+}
+
+machine Add with min_degree: 32, max_degree: 4096, latch: latch, operation_id: operation_id {
+    operation add<0> x, y -> z;
+    col fixed latch = [1]*;
+    col witness operation_id;
+    col witness x, y, z;
+    z = x + y;
+}
+
+machine Sub with degree: 64, latch: latch, operation_id: operation_id {
+    operation sub<0> x, y -> z;
+    col fixed latch = [1]*;
+    col witness operation_id;
+    col witness x, y, z;
+    z = x - y;
+}
+"#;
+
+    #[test]
+    fn plan() {
+        let mut pipeline =
+            Pipeline::<GoldilocksField>::default().from_asm_string(ASM.to_string(), None);
+        let pil = pipeline.compute_optimized_pil().unwrap();
+
+        let machine_rows = BTreeMap::from([
+            ("main".to_string(), 100),
+            ("main_add".to_string(), 3),
+            ("main_sub".to_string(), 1),
+        ]);
+        let params = DegreePlannerParams {
+            min_degree_log: 4,
+            max_degree_log: 10,
+            headroom_log: 1,
+            setup_weight: 1,
+        };
+        let plan = plan_degrees(&pil, &[machine_rows], params).unwrap();
+
+        let main = &plan.machines["main"];
+        assert_eq!(main.used_rows, Some(100));
+        assert_eq!(main.planned, DegreeRange { min: 128, max: 256 });
+        // the planned degree is clamped to the declared range
+        assert_eq!(
+            plan.machines["main_add"].planned,
+            DegreeRange { min: 32, max: 64 }
+        );
+        // machines with a fixed degree are not resized
+        let sub = &plan.machines["main_sub"];
+        assert_eq!(sub.planned, DegreeRange { min: 64, max: 64 });
+        // the rom was not part of the execution, so it is not resized
+        let rom = &plan.machines["main__rom"];
+        assert_eq!(rom.used_rows, None);
+        assert_eq!(rom.planned, rom.declared);
+        assert_eq!(
+            plan.total_cost(),
+            plan.machines.values().map(|m| m.cost).sum::<u64>()
+        );
+        assert!(plan.to_string().contains("main_add"));
+
+        let too_large = BTreeMap::from([("main".to_string(), 2000)]);
+        assert!(plan_degrees(&pil, &[too_large], params).is_err());
+        let above_declared = BTreeMap::from([("main_add".to_string(), 5000)]);
+        let params = DegreePlannerParams {
+            max_degree_log: 16,
+            ..params
+        };
+        assert!(plan_degrees(&pil, &[above_declared], params).is_err());
+        let unknown = BTreeMap::from([("main_mul".to_string(), 1)]);
+        assert!(plan_degrees(&pil, &[unknown], params).is_err());
+    }
+
+    #[test]
+    fn plan_multiple_executions() {
+        let mut pipeline =
+            Pipeline::<GoldilocksField>::default().from_asm_string(ASM.to_string(), None);
+        let pil = pipeline.compute_optimized_pil().unwrap();
+
+        let execution = |rows| BTreeMap::from([("main".to_string(), rows)]);
+        let params = DegreePlannerParams {
+            min_degree_log: 4,
+            max_degree_log: 10,
+            headroom_log: 1,
+            setup_weight: 1,
+        };
+
+        // many small executions: proving them at a small degree pays for
+        // preparing the machine for all degrees up to the largest execution
+        let executions = [execution(10), execution(10), execution(10), execution(200)];
+        let plan = plan_degrees(&pil, &executions, params).unwrap();
+        let main = &plan.machines["main"];
+        assert_eq!(main.used_rows, Some(200));
+        assert_eq!(main.planned, DegreeRange { min: 32, max: 512 });
+        // 32 + 64 + ... + 512 for the setup, 3 * 32 + 256 for proving
+        assert_eq!(main.cost, main.columns as u64 * (992 + 352));
+
+        // when the setup is expensive, the small execution is padded instead
+        let params = DegreePlannerParams {
+            setup_weight: 4,
+            ..params
+        };
+        let executions = [execution(10), execution(200)];
+        let plan = plan_degrees(&pil, &executions, params).unwrap();
+        assert_eq!(
+            plan.machines["main"].planned,
+            DegreeRange { min: 256, max: 512 }
+        );
+    }
+
+    #[test]
+    fn apply_plan() {
+        let mut pipeline =
+            Pipeline::<GoldilocksField>::default().from_asm_string(ASM.to_string(), None);
+        let pil = pipeline.compute_optimized_pil().unwrap();
+        pipeline.compute_witness().unwrap();
+
+        // the used rows, not the padded length of the witness
+        let machine_rows = pipeline.machine_rows().unwrap().clone();
+        assert_eq!(machine_rows["main_add"], 3);
+
+        let plan = plan_degrees(&pil, &[machine_rows], Default::default()).unwrap();
+        let mut pipeline = Pipeline::<GoldilocksField>::default()
+            .from_asm_string(ASM.to_string(), None)
+            .with_degree_plan(plan);
+        let pil = pipeline.compute_optimized_pil().unwrap();
+        assert!(pil
+            .degree_ranges()
+            .contains(&DegreeRange { min: 32, max: 64 }));
+        pipeline.compute_witness().unwrap();
+    }
+}
@@ -1,5 +1,7 @@
 //! The main powdr lib, used to compile from assembly to PIL
 
+mod checkpoint;
+pub mod degree_planner;
 pub mod pipeline;
 pub mod test_runner;
 pub mod test_util;
@@ -133,6 +135,214 @@ pub fn serde_data_to_query_callback<T: FieldElement>(
     }
 }
 
+/// A host-provided handler for an interactive channel. It receives the bytes
+/// the guest wrote to the channel since its last read and returns the bytes
+/// the guest reads next.
+pub type ChannelHandler = dyn Fn(&[u8]) -> Vec<u8> + Send + Sync;
+
+/// The state of a channel during an execution.
+#[derive(Clone, Default)]
+struct ChannelPosition {
+    /// The position in the transcript of the next request.
+    next: usize,
+    /// The bytes written by the guest since the last response was computed.
+    request: Vec<u8>,
+    /// The response to the last request, if it was already computed.
+    response: Option<Vec<u8>>,
+}
+
+impl ChannelPosition {
+    /// Returns the response to the current request, replaying it from the
+    /// transcript if it was already recorded and invoking the handler otherwise.
+    fn respond(
+        &mut self,
+        transcript: &mut Vec<(Vec<u8>, Vec<u8>)>,
+        handler: &ChannelHandler,
+    ) -> Result<Vec<u8>, String> {
+        let request = std::mem::take(&mut self.request);
+        let next = self.next;
+        self.next += 1;
+        match transcript.get(next) {
+            Some((recorded, response)) if *recorded == request => Ok(response.clone()),
+            Some(_) => Err(format!(
+                "request {next} differs from the one of the first execution"
+            )),
+            None => {
+                let response = handler(&request);
+                transcript.push((request, response.clone()));
+                Ok(response)
+            }
+        }
+    }
+}
+
+struct ChannelState {
+    /// The requests and responses of the channel, in the order of the first execution.
+    transcript: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The position of the current execution, or `None` if the start of its
+    /// chunk was never recorded.
+    position: Option<ChannelPosition>,
+    /// The position at the start of each chunk of an execution with
+    /// continuations. The first chunk starts at the beginning of the program.
+    chunk_starts: Vec<ChannelPosition>,
+    /// The chunk that the following executions run.
+    chunk: usize,
+}
+
+impl ChannelState {
+    fn new() -> Self {
+        Self {
+            transcript: vec![],
+            position: Some(Default::default()),
+            chunk_starts: vec![Default::default()],
+            chunk: 0,
+        }
+    }
+
+    /// Starts a new execution at the beginning of the current chunk.
+    fn clear(&mut self) {
+        self.position = self.chunk_starts.get(self.chunk).cloned();
+    }
+
+    /// Records the current position as the start of the next chunk.
+    fn checkpoint(&mut self) {
+        if let Some(position) = &self.position {
+            self.chunk_starts.truncate(self.chunk + 1);
+            self.chunk_starts.push(position.clone());
+        }
+    }
+}
+
+/// Returns a query callback that serves `Input` queries on `channel` on demand.
+/// `Output` queries on the channel are collected into a request. The response
+/// is computed when the guest reads the length of the data (index 0) for the
+/// first time after writing a request, and it is served until the guest writes
+/// the next request.
+///
+/// The program is executed several times (e.g. once by the executor and once by
+/// witness generation), so the requests and responses of the first execution
+/// are recorded and later executions are served from the recording. The
+/// position in the recording is controlled by the following queries, which the
+/// pipeline passes to all query callbacks (see [chain_session_callbacks]):
+/// - `Clear` starts a new execution at the beginning of the current chunk.
+/// - `Chunk(i)` makes the following executions run chunk `i` of an execution
+///   with continuations.
+/// - `Checkpoint` records the current position as the start of the next chunk.
+///   It is issued by the executor before the last row of a chunk, which is
+///   executed again at the start of the next chunk.
+/// - `Reset` discards the recording, e.g. because the inputs changed.
+pub fn channel_handler_to_query_callback<T: FieldElement>(
+    channel: u32,
+    handler: Arc<ChannelHandler>,
+) -> impl QueryCallback<T> {
+    let state = Mutex::new(ChannelState::new());
+    move |query: &str| -> Result<Option<T>, String> {
+        let (id, data) = parse_query(query)?;
+        let mut state = state.lock().unwrap();
+        match (id, &data[..]) {
+            ("Clear", []) => {
+                state.clear();
+                return Ok(Some(0.into()));
+            }
+            ("Chunk", [chunk]) => {
+                state.chunk = chunk
+                    .parse::<usize>()
+                    .map_err(|e| format!("Error parsing chunk: {e})"))?;
+                return Ok(Some(0.into()));
+            }
+            ("Checkpoint", []) => {
+                state.checkpoint();
+                return Ok(Some(0.into()));
+            }
+            ("Reset", []) => {
+                *state = ChannelState::new();
+                return Ok(Some(0.into()));
+            }
+            _ => {}
+        }
+        let [cb_channel, value] = data[..] else {
+            return Err(format!("Unsupported query: {query}"));
+        };
+        let cb_channel = cb_channel
+            .parse::<u32>()
+            .map_err(|e| format!("Error parsing callback data channel: {e})"))?;
+        if channel != cb_channel {
+            return Err("Callback channel mismatch".to_string());
+        }
+
+        let ChannelState {
+            transcript,
+            position,
+            chunk,
+            ..
+        } = &mut *state;
+        let position = position.as_mut().ok_or_else(|| {
+            format!("The position of channel {channel} at the start of chunk {chunk} is unknown")
+        })?;
+        match id {
+            "Input" => {
+                let index = value
+                    .parse::<usize>()
+                    .map_err(|e| format!("Error parsing index: {e})"))?;
+                if position.response.is_none() {
+                    if index != 0 {
+                        return Err(format!(
+                            "Channel {channel} was read before its length was queried"
+                        ));
+                    }
+                    let response = position
+                        .respond(transcript, handler.as_ref())
+                        .map_err(|e| format!("Channel {channel}: {e}"))?;
+                    position.response = Some(response);
+                }
+                let response = position.response.as_ref().unwrap();
+
+                // query index 0 means the length
+                Ok(Some(match index {
+                    0 => (response.len() as u64).into(),
+                    index => (*response.get(index - 1).ok_or_else(|| {
+                        format!("Index {index} out of bounds for channel {channel}")
+                    })? as u64)
+                        .into(),
+                }))
+            }
+            "Output" => {
+                let byte = value
+                    .parse::<u8>()
+                    .map_err(|e| format!("Invalid byte to write: {e}"))?;
+                // Writing starts a new request, so the old response is stale.
+                position.response = None;
+                position.request.push(byte);
+                Ok(Some(0.into()))
+            }
+            _ => Err(format!("Unsupported query: {query}")),
+        }
+    }
+}
+
+/// The queries that start, move or reset the executions of stateful query
+/// callbacks, see [channel_handler_to_query_callback].
+const SESSION_QUERIES: [&str; 4] = ["Clear", "Chunk", "Checkpoint", "Reset"];
+
+/// Chains two query callbacks like [powdr_executor::witgen::chain_callbacks], but passes the session
+/// queries to both callbacks, since each of them might have a state to update.
+/// Succeeds if any of the callbacks accepts a session query.
+pub fn chain_session_callbacks<T: FieldElement>(
+    c1: Arc<dyn QueryCallback<T>>,
+    c2: Arc<dyn QueryCallback<T>>,
+) -> impl QueryCallback<T> {
+    move |query: &str| {
+        let (id, _) = parse_query(query)?;
+        if SESSION_QUERIES.contains(&id) {
+            let r1 = c1(query);
+            let r2 = c2(query);
+            r1.or(r2)
+        } else {
+            c1(query).or_else(|_| c2(query))
+        }
+    }
+}
+
 pub fn dict_data_to_query_callback<T: FieldElement>(
     dict: BTreeMap<u32, Vec<T>>,
 ) -> impl QueryCallback<T> {
@@ -201,3 +411,142 @@ pub fn handle_simple_queries_callback<'a, T: FieldElement>() -> impl QueryCallba
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use powdr_number::GoldilocksField;
+
+    use super::channel_handler_to_query_callback;
+
+    #[test]
+    fn channel_handler_serves_requests() {
+        // Responds with the reversed request, each byte incremented.
+        let handler = |request: &[u8]| request.iter().rev().map(|b| b + 1).collect::<Vec<_>>();
+        let callback = channel_handler_to_query_callback::<GoldilocksField>(3, Arc::new(handler));
+        let query = |q: &str| callback(q).unwrap().unwrap().to_degree();
+
+        for byte in [1, 2] {
+            query(&format!("std::prelude::Query::Output(3, {byte})"));
+        }
+        assert_eq!(query("std::prelude::Query::Input(3, 0)"), 2);
+        // Querying the length again does not invoke the handler again.
+        assert_eq!(query("std::prelude::Query::Input(3, 0)"), 2);
+        assert_eq!(query("std::prelude::Query::Input(3, 1)"), 3);
+        assert_eq!(query("std::prelude::Query::Input(3, 2)"), 2);
+
+        query("std::prelude::Query::Output(3, 7)");
+        assert_eq!(query("std::prelude::Query::Input(3, 0)"), 1);
+        assert_eq!(query("std::prelude::Query::Input(3, 1)"), 8);
+
+        assert!(callback("std::prelude::Query::Input(0, 0)").is_err());
+        assert!(callback("std::prelude::Query::Hint(5)").is_err());
+    }
+
+    #[test]
+    fn channel_handler_replays_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler = {
+            let calls = calls.clone();
+            move |request: &[u8]| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                vec![request[0] * 2]
+            }
+        };
+        let callback = channel_handler_to_query_callback::<GoldilocksField>(3, Arc::new(handler));
+        let query = |q: &str| callback(q).unwrap().unwrap().to_degree();
+        let request = |byte: u8| {
+            query(&format!("std::prelude::Query::Output(3, {byte})"));
+            assert_eq!(query("std::prelude::Query::Input(3, 0)"), 1);
+            query("std::prelude::Query::Input(3, 1)")
+        };
+
+        // The first execution invokes the handler.
+        query("Clear");
+        assert_eq!(request(1), 2);
+        assert_eq!(request(2), 4);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // A re-execution is served from the recording.
+        query("Clear");
+        assert_eq!(request(1), 2);
+        assert_eq!(request(2), 4);
+        assert_eq!(request(3), 6);
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        // A re-execution has to send the recorded requests.
+        query("Clear");
+        callback("std::prelude::Query::Output(3, 5)").unwrap();
+        assert!(callback("std::prelude::Query::Input(3, 0)").is_err());
+
+        // Inputs changed, so the handler is invoked again.
+        query("Reset");
+        query("Clear");
+        assert_eq!(request(1), 2);
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn channel_handler_resumes_chunks() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler = {
+            let calls = calls.clone();
+            move |request: &[u8]| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                vec![request.iter().sum()]
+            }
+        };
+        let callback = channel_handler_to_query_callback::<GoldilocksField>(3, Arc::new(handler));
+        let query = |q: &str| callback(q).unwrap().unwrap().to_degree();
+        let write = |byte: u8| query(&format!("std::prelude::Query::Output(3, {byte})"));
+        let read = || {
+            assert_eq!(query("std::prelude::Query::Input(3, 0)"), 1);
+            query("std::prelude::Query::Input(3, 1)")
+        };
+
+        // The first chunk ends while the second request is written. Its last
+        // row writes a byte, which is written again by the next chunk.
+        query("Chunk(0)");
+        query("Clear");
+        write(1);
+        write(2);
+        assert_eq!(read(), 3);
+        write(4);
+        query("Checkpoint");
+        write(5);
+
+        // The second chunk ends after the length of the response was read.
+        query("Chunk(1)");
+        query("Clear");
+        write(5);
+        assert_eq!(query("std::prelude::Query::Input(3, 0)"), 1);
+        query("Checkpoint");
+        assert_eq!(query("std::prelude::Query::Input(3, 1)"), 9);
+
+        query("Chunk(2)");
+        query("Clear");
+        assert_eq!(query("std::prelude::Query::Input(3, 1)"), 9);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // The chunks are executed again, e.g. by witness generation, in any order.
+        query("Chunk(1)");
+        query("Clear");
+        write(5);
+        assert_eq!(read(), 9);
+        query("Chunk(0)");
+        query("Clear");
+        write(1);
+        write(2);
+        assert_eq!(read(), 3);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // The start of a chunk that was not executed by the executor is unknown.
+        query("Chunk(3)");
+        query("Clear");
+        assert!(callback("std::prelude::Query::Output(3, 1)").is_err());
+    }
+}
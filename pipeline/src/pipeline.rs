@@ -23,24 +23,33 @@ use powdr_backend::{Backend, BackendOptions, BackendType, Proof};
 use powdr_executor::{
     constant_evaluator::{self, VariablySizedColumn},
     witgen::{
-        chain_callbacks, extract_publics, unused_query_callback, QueryCallback, WitgenCallback,
-        WitgenCallbackContext, WitnessGenerator,
+        extract_publics, unused_query_callback, QueryCallback, WitgenCallback,
+        WitgenCallbackContext, WitnessGenerator, WitnessSink,
     },
 };
 pub use powdr_linker::{DegreeMode, LinkerMode, LinkerParams};
-use powdr_number::{write_polys_csv_file, CsvRenderMode, FieldElement, ReadWrite};
+use powdr_number::{write_polys_csv_file, CsvRenderMode, DegreeType, FieldElement, ReadWrite};
+use powdr_pilopt::CseConfig;
 use powdr_schemas::SerializedAnalyzed;
 
 use crate::{
+    chain_session_callbacks, channel_handler_to_query_callback,
+    checkpoint::{hash_file, hash_parts, Checkpoints, Stage},
+    degree_planner::DegreePlan,
     dict_data_to_query_callback, handle_simple_queries_callback, inputs_to_query_callback,
     serde_data_to_query_callback,
     util::{FixedPolySet, WitnessPolySet},
+    ChannelHandler,
 };
 use std::collections::BTreeMap;
 
 pub type Columns<T> = Vec<(String, Vec<T>)>;
 pub type VariablySizedColumns<T> = Vec<(String, VariablySizedColumn<T>)>;
 
+/// The content of the witness checkpoint: the witness and, if it was computed
+/// by witness generation, the number of rows used by each machine.
+type WitnessCheckpoint<T> = (Columns<T>, Option<BTreeMap<String, DegreeType>>);
+
 #[derive(Default)]
 pub struct Artifacts<T: FieldElement> {
     /// The path to a single .asm file.
@@ -76,6 +85,8 @@ pub struct Artifacts<T: FieldElement> {
     fixed_cols: Option<Arc<VariablySizedColumns<T>>>,
     /// Generated witnesses.
     witness: Option<Arc<Columns<T>>>,
+    /// The number of rows each namespace used in witness generation, before padding.
+    machine_rows: Option<BTreeMap<String, DegreeType>>,
     /// Instantiated backend.
     backend: Option<Box<dyn Backend<T>>>,
     /// The proof (if successful).
@@ -101,12 +112,30 @@ struct Arguments<T: FieldElement> {
     external_witness_values: Vec<(String, Vec<T>)>,
     /// Callback for queries for witness generation.
     query_callback: Option<Arc<dyn QueryCallback<T>>>,
+    /// The serialized data served by the query callbacks, which is part of the
+    /// hash of the witness checkpoint.
+    query_inputs: Vec<Vec<u8>>,
+    /// Whether a query callback was added whose data is not in `query_inputs`.
+    opaque_query_callback: bool,
+    /// Identifies the data of the query callbacks not in `query_inputs` in the
+    /// hash of the witness checkpoint.
+    input_key: Option<String>,
     /// Backend to use for proving. If None, proving will fail.
     backend: Option<BackendType>,
     /// Backend options
     backend_options: BackendOptions,
     /// Linker options
     linker_params: LinkerParams,
+    /// Degree ranges replacing the declared ones of the machines, if any.
+    degree_plan: Option<DegreePlan>,
+    /// The cost model for common subexpression elimination, if it is enabled.
+    cse_config: Option<CseConfig>,
+    /// Overrides the maximum constraint degree the optimized PIL is reduced to.
+    /// If None, the maximum degree of the backend is used.
+    max_constraint_degree: Option<Option<usize>>,
+    /// The directory of the cache for JIT-compiled libraries. If None, the
+    /// environment variable `POWDR_JIT_CACHE_DIR` is used.
+    jit_cache_dir: Option<PathBuf>,
     /// CSV render mode for witness generation.
     csv_render_mode: CsvRenderMode,
     /// Whether to export the witness as a CSV file.
@@ -142,6 +171,8 @@ pub struct Pipeline<T: FieldElement> {
     force_overwrite: bool,
     /// Whether to output the serialized pil object (.pilo)
     pilo: bool,
+    /// On-disk checkpoints of the computed stages, if enabled.
+    checkpoints: Option<Checkpoints>,
     /// The log level to use for this pipeline.
     log_level: Level,
     /// Optional arguments for various stages of the pipeline.
@@ -168,6 +199,7 @@ impl<T: FieldElement> Clone for Artifacts<T> {
             optimized_pil: self.optimized_pil.clone(),
             fixed_cols: self.fixed_cols.clone(),
             witness: self.witness.clone(),
+            machine_rows: self.machine_rows.clone(),
             proof: self.proof.clone(),
             // Backend is not cloneable, so we clear it instead
             backend: None,
@@ -191,12 +223,15 @@ where
             name: None,
             force_overwrite: false,
             pilo: false,
+            checkpoints: None,
             arguments: Arguments::default(),
             host_context: ctx,
         }
         // We add the basic callback functionalities to support PrintChar and Hint.
-        .add_query_callback(Arc::new(handle_simple_queries_callback()))
-        .add_query_callback(cb)
+        // They only serve data written by the guest, so they do not make the
+        // inputs opaque.
+        .chain_query_callback(Arc::new(handle_simple_queries_callback()))
+        .chain_query_callback(cb)
     }
 }
 
@@ -313,18 +348,68 @@ impl<T: FieldElement> Pipeline<T> {
         self
     }
 
+    /// Adds a query callback for witness generation.
+    ///
+    /// The data served by the callback is unknown to the pipeline, so with
+    /// checkpoints enabled, the witness is not resumed unless an input key is
+    /// set (see [Self::with_input_key]).
     pub fn add_query_callback(mut self, query_callback: Arc<dyn QueryCallback<T>>) -> Self {
+        self.arguments.opaque_query_callback = true;
+        self.chain_query_callback(query_callback)
+    }
+
+    fn chain_query_callback(mut self, query_callback: Arc<dyn QueryCallback<T>>) -> Self {
+        self.reset_query_callbacks();
         let query_callback = match self.arguments.query_callback {
-            Some(old_callback) => Arc::new(chain_callbacks(old_callback, query_callback)),
+            Some(old_callback) => Arc::new(chain_session_callbacks(old_callback, query_callback)),
             None => query_callback,
         };
         self.arguments.query_callback = Some(query_callback);
         self
     }
 
+    /// Adds a query callback serving the given data, which is recorded for the
+    /// hash of the witness checkpoint.
+    fn chain_query_callback_with_inputs(
+        mut self,
+        query_callback: Arc<dyn QueryCallback<T>>,
+        inputs: Vec<u8>,
+    ) -> Self {
+        self.arguments.query_inputs.push(inputs);
+        self.chain_query_callback(query_callback)
+    }
+
     pub fn add_data<S: serde::Serialize>(self, channel: u32, data: &S) -> Self {
         let bytes = serde_cbor::to_vec(&data).unwrap();
-        self.add_query_callback(Arc::new(serde_data_to_query_callback(channel, bytes)))
+        let inputs = serde_cbor::to_vec(&(channel, &bytes)).unwrap();
+        self.chain_query_callback_with_inputs(
+            Arc::new(serde_data_to_query_callback(channel, bytes)),
+            inputs,
+        )
+    }
+
+    /// Registers a handler that serves the guest's reads from `channel` on demand.
+    /// The bytes the guest writes to the channel are passed to the handler as a
+    /// request, and the returned bytes are what the guest reads next.
+    /// See [channel_handler_to_query_callback] for how the responses are replayed
+    /// when the program is executed again.
+    ///
+    /// Like for [Self::add_query_callback], the responses of the handler are
+    /// unknown to the pipeline, so the witness is only resumed from a checkpoint
+    /// if an input key is set.
+    pub fn add_channel_handler(mut self, channel: u32, handler: Arc<ChannelHandler>) -> Self {
+        self.arguments.opaque_query_callback = true;
+        self.reset_query_callbacks();
+        let handler_callback: Arc<dyn QueryCallback<T>> =
+            Arc::new(channel_handler_to_query_callback(channel, handler));
+        // The handler has to see the guest's writes to the channel before the
+        // host context, which accepts writes to any channel.
+        let query_callback = match self.arguments.query_callback {
+            Some(old_callback) => Arc::new(chain_session_callbacks(handler_callback, old_callback)),
+            None => handler_callback,
+        };
+        self.arguments.query_callback = Some(query_callback);
+        self
     }
 
     pub fn add_data_vec<S: serde::Serialize + 'static>(self, data: &[(u32, S)]) -> Self {
@@ -333,11 +418,30 @@ impl<T: FieldElement> Pipeline<T> {
     }
 
     pub fn with_prover_inputs(self, inputs: Vec<T>) -> Self {
-        self.add_query_callback(Arc::new(inputs_to_query_callback(inputs)))
+        let serialized = serde_cbor::to_vec(&inputs).unwrap();
+        self.chain_query_callback_with_inputs(
+            Arc::new(inputs_to_query_callback(inputs)),
+            serialized,
+        )
     }
 
     pub fn with_prover_dict_inputs(self, inputs: BTreeMap<u32, Vec<T>>) -> Self {
-        self.add_query_callback(Arc::new(dict_data_to_query_callback(inputs)))
+        let serialized = serde_cbor::to_vec(&inputs).unwrap();
+        self.chain_query_callback_with_inputs(
+            Arc::new(dict_data_to_query_callback(inputs)),
+            serialized,
+        )
+    }
+
+    /// Sets a key identifying the data served by query callbacks and channel
+    /// handlers, which the pipeline cannot hash itself. The key is part of the
+    /// hash of the witness checkpoint, which allows resuming the witness of such
+    /// a pipeline. The caller is responsible for changing the key whenever the
+    /// data changes.
+    pub fn with_input_key(mut self, input_key: String) -> Self {
+        self.reset_query_callbacks();
+        self.arguments.input_key = Some(input_key);
+        self
     }
 
     pub fn with_linker_params(mut self, linker_params: LinkerParams) -> Self {
@@ -345,10 +449,47 @@ impl<T: FieldElement> Pipeline<T> {
         self
     }
 
+    /// Replaces the declared degree ranges of the machines by the planned ones
+    /// before linking.
+    pub fn with_degree_plan(mut self, degree_plan: DegreePlan) -> Self {
+        self.arguments.degree_plan = Some(degree_plan);
+        self
+    }
+
+    /// Sets the backend to prove with. Unless overridden by
+    /// `with_max_constraint_degree`, this also reduces the degree of the
+    /// constraints to the maximum the backend supports.
     pub fn with_backend(mut self, backend: BackendType, options: Option<BackendOptions>) -> Self {
+        let previous = self.pil_optimization_arguments();
         self.arguments.backend = Some(backend);
         self.arguments.backend_options = options.unwrap_or_default();
         self.artifact.backend = None;
+        self.reset_optimized_pil_if_changed(previous);
+        self
+    }
+
+    /// Enables common subexpression elimination with the given cost model,
+    /// or disables it if `None`. It is disabled by default.
+    pub fn with_cse_config(mut self, cse_config: Option<CseConfig>) -> Self {
+        let previous = self.pil_optimization_arguments();
+        self.arguments.cse_config = cse_config;
+        self.reset_optimized_pil_if_changed(previous);
+        self
+    }
+
+    /// Overrides the maximum constraint degree the PIL is reduced to, or
+    /// disables the degree reduction if `None`, regardless of the backend.
+    pub fn with_max_constraint_degree(mut self, max_constraint_degree: Option<usize>) -> Self {
+        let previous = self.pil_optimization_arguments();
+        self.arguments.max_constraint_degree = Some(max_constraint_degree);
+        self.reset_optimized_pil_if_changed(previous);
+        self
+    }
+
+    /// Sets the directory in which JIT-compiled libraries are cached across runs.
+    /// If `None`, the environment variable `POWDR_JIT_CACHE_DIR` is used instead.
+    pub fn with_jit_cache_dir(mut self, jit_cache_dir: Option<PathBuf>) -> Self {
+        self.arguments.jit_cache_dir = jit_cache_dir;
         self
     }
 
@@ -394,6 +535,28 @@ impl<T: FieldElement> Pipeline<T> {
         self
     }
 
+    /// Enables checkpoints in the output directory, which must be set.
+    ///
+    /// Every computed stage (optimized PIL, fixed columns, witness, proof and,
+    /// for composite backends, the proof of each machine) is persisted with a
+    /// content hash, and stages persisted by a previous run from the same
+    /// inputs are loaded instead of recomputed. This allows resuming a run
+    /// that was interrupted.
+    ///
+    /// The witness is only resumed if the data served to witness generation is
+    /// known, i.e. if it was provided through the prover inputs or data methods
+    /// of the pipeline, or if an input key is set (see [Self::with_input_key]).
+    /// Note that the data written by the guest to the host context is not
+    /// restored when the witness is resumed.
+    pub fn with_checkpoints(mut self) -> Result<Self, Vec<String>> {
+        let output_dir = self
+            .output_dir
+            .as_ref()
+            .ok_or_else(|| vec!["Checkpoints require an output directory".to_string()])?;
+        self.checkpoints = Some(Checkpoints::open(output_dir).map_err(|e| vec![e])?);
+        Ok(self)
+    }
+
     pub fn from_file(self, asm_file: PathBuf) -> Self {
         match asm_file.extension() {
             Some(ext) if ext.to_str().unwrap() == "asm" => self.from_asm_file(asm_file),
@@ -681,8 +844,162 @@ impl<T: FieldElement> Pipeline<T> {
     // The previous alternative to this was cloning the entire pipeline.
     pub fn rollback_from_witness(&mut self) {
         self.artifact.witness = None;
+        self.artifact.machine_rows = None;
         self.artifact.proof = None;
         self.arguments.external_witness_values.clear();
+        // Stateful query callbacks might serve different data for the next
+        // witness, which is not part of the checkpoint hashes.
+        if let Some(checkpoints) = self.checkpoints.as_mut() {
+            if let Err(e) = checkpoints.invalidate(Stage::Witness) {
+                log::warn!("Could not invalidate checkpoints: {e}");
+            }
+        }
+    }
+
+    /// The arguments the optimized PIL is computed with, besides the source.
+    fn pil_optimization_arguments(&self) -> (Option<CseConfig>, Option<usize>) {
+        (self.arguments.cse_config, self.max_constraint_degree())
+    }
+
+    /// The maximum constraint degree the optimized PIL is reduced to, if any.
+    fn max_constraint_degree(&self) -> Option<usize> {
+        self.arguments.max_constraint_degree.unwrap_or_else(|| {
+            self.arguments
+                .backend
+                .and_then(|backend| backend.max_constraint_degree())
+        })
+    }
+
+    /// Discards the optimized PIL and the artifacts computed from it if the
+    /// arguments it is computed with changed since `previous`, so that it is
+    /// recomputed from the source. A PIL read from a .pilo file has no source
+    /// and is kept.
+    fn reset_optimized_pil_if_changed(&mut self, previous: (Option<CseConfig>, Option<usize>)) {
+        let has_source = self.artifact.asm_file_path.is_some()
+            || self.artifact.asm_string.is_some()
+            || self.artifact.pil_file_path.is_some()
+            || self.artifact.pil_string.is_some();
+        if self.artifact.optimized_pil.is_none()
+            || !has_source
+            || previous == self.pil_optimization_arguments()
+        {
+            return;
+        }
+        self.artifact.optimized_pil = None;
+        self.artifact.fixed_cols = None;
+        self.artifact.witness = None;
+        self.artifact.machine_rows = None;
+        self.artifact.backend = None;
+        self.artifact.proof = None;
+    }
+
+    // ===== Checkpoints =====
+
+    /// Returns the hash of the source the pipeline was created from, if any.
+    fn source_hash(&self) -> Option<String> {
+        let source = if let Some((_, asm_string)) = &self.artifact.asm_string {
+            asm_string.as_bytes().to_vec()
+        } else if let Some(path) = &self.artifact.asm_file_path {
+            fs::read(path).ok()?
+        } else if let Some(pil_string) = &self.artifact.pil_string {
+            pil_string.as_bytes().to_vec()
+        } else if let Some(path) = &self.artifact.pil_file_path {
+            fs::read(path).ok()?
+        } else {
+            return None;
+        };
+        let linker_params = self.arguments.linker_params;
+        Some(hash_parts([
+            source.as_slice(),
+            std::any::type_name::<T>().as_bytes(),
+            linker_params.mode.to_string().as_bytes(),
+            linker_params.degree_mode.to_string().as_bytes(),
+            &serde_cbor::to_vec(&self.arguments.degree_plan).unwrap(),
+        ]))
+    }
+
+    /// Returns the hash of the input the given stage is computed from, if
+    /// checkpoints are enabled and the input is known. The input of the witness
+    /// is unknown if a query callback with unknown data was added and no input
+    /// key is set.
+    fn checkpoint_input_hash(&self, stage: Stage) -> Option<String> {
+        let checkpoints = self.checkpoints.as_ref()?;
+        let previous = match stage.previous() {
+            Some(previous) => checkpoints.hash(previous)?.to_string(),
+            None => self.source_hash()?,
+        };
+        Some(match stage {
+            Stage::OptimizedPil => hash_parts([
+                previous.as_bytes(),
+                format!("{:?}", self.arguments.cse_config).as_bytes(),
+                format!("{:?}", self.max_constraint_degree()).as_bytes(),
+            ]),
+            Stage::FixedCols => previous,
+            Stage::Witness => {
+                if self.arguments.opaque_query_callback && self.arguments.input_key.is_none() {
+                    return None;
+                }
+                hash_parts([
+                    previous.as_bytes(),
+                    &serde_cbor::to_vec(&self.arguments.external_witness_values).unwrap(),
+                    &serde_cbor::to_vec(&self.arguments.query_inputs).unwrap(),
+                    self.arguments
+                        .input_key
+                        .as_deref()
+                        .unwrap_or_default()
+                        .as_bytes(),
+                ])
+            }
+            Stage::Proof => {
+                let existing_proof_hash = match &self.arguments.existing_proof_file {
+                    Some(path) => hash_file(path).ok()?,
+                    None => String::new(),
+                };
+                hash_parts([
+                    previous.as_bytes(),
+                    self.arguments.backend?.to_string().as_bytes(),
+                    self.arguments.backend_options.as_bytes(),
+                    existing_proof_hash.as_bytes(),
+                ])
+            }
+        })
+    }
+
+    /// Reads a stage from its checkpoint, if it was persisted from the given input.
+    fn load_checkpoint<A>(
+        &mut self,
+        stage: Stage,
+        input_hash: Option<&str>,
+        read: impl FnOnce(&Path) -> Result<A, String>,
+    ) -> Option<A> {
+        let checkpoints = self.checkpoints.as_mut()?;
+        let path = checkpoints.validate(stage, input_hash?)?;
+        match read(&path) {
+            Ok(artifact) => {
+                self.log(&format!("Resuming {stage:?} from {}", path.display()));
+                Some(artifact)
+            }
+            Err(e) => {
+                log::warn!("Could not read checkpoint {}: {e}", path.display());
+                checkpoints.invalidate(stage).ok();
+                None
+            }
+        }
+    }
+
+    /// Persists a stage computed from the given input, if checkpoints are enabled.
+    fn store_checkpoint(
+        &mut self,
+        stage: Stage,
+        input_hash: Option<String>,
+        write: impl FnOnce(&Path) -> Result<(), String>,
+    ) -> Result<(), Vec<String>> {
+        match (self.checkpoints.as_mut(), input_hash) {
+            (Some(checkpoints), Some(input_hash)) => checkpoints
+                .store(stage, input_hash, write)
+                .map_err(|e| vec![e]),
+            _ => Ok(()),
+        }
     }
 
     // ===== Compute and retrieve artifacts =====
@@ -860,7 +1177,11 @@ impl<T: FieldElement> Pipeline<T> {
         if self.artifact.parsed_pil_file.is_none() {
             self.artifact.parsed_pil_file = Some({
                 self.compute_linked_machine_graph()?;
-                let graph = self.artifact.linked_machine_graph.take().unwrap();
+                let mut graph = self.artifact.linked_machine_graph.take().unwrap();
+                if let Some(degree_plan) = &self.arguments.degree_plan {
+                    log::debug!("Applying degree plan:\n{degree_plan}");
+                    degree_plan.apply(&mut graph);
+                }
 
                 self.log("Run linker");
                 let linked = powdr_linker::link(graph, self.arguments.linker_params)?;
@@ -948,13 +1269,32 @@ impl<T: FieldElement> Pipeline<T> {
             return Ok(optimized_pil.clone());
         }
 
+        let input_hash = self.checkpoint_input_hash(Stage::OptimizedPil);
+        if let Some(optimized) =
+            self.load_checkpoint(Stage::OptimizedPil, input_hash.as_deref(), |path| {
+                SerializedAnalyzed::deserialize_from(path.to_path_buf())?.try_into()
+            })
+        {
+            self.artifact.optimized_pil = Some(Arc::new(optimized));
+            return Ok(self.artifact.optimized_pil.as_ref().unwrap().clone());
+        }
+
         self.compute_analyzed_pil()?;
         let analyzed_pil = self.artifact.analyzed_pil.take().unwrap();
 
         self.log("Optimizing pil...");
-        let optimized = powdr_pilopt::optimize(analyzed_pil);
+        let mut optimized = powdr_pilopt::optimize(analyzed_pil);
+        if let Some(cse_config) = &self.arguments.cse_config {
+            powdr_pilopt::extract_common_subexpressions(&mut optimized, cse_config);
+        }
+        if let Some(max_degree) = self.max_constraint_degree() {
+            powdr_pilopt::reduce_degree(&mut optimized, max_degree);
+        }
         self.maybe_write_pil(&optimized, "_opt")?;
         self.maybe_write_pil_object(&optimized, "_opt")?;
+        self.store_checkpoint(Stage::OptimizedPil, input_hash, |path| {
+            SerializedAnalyzed::try_from(&optimized)?.serialize_to(path.to_path_buf())
+        })?;
 
         self.artifact.optimized_pil = Some(Arc::new(optimized));
 
@@ -972,14 +1312,30 @@ impl<T: FieldElement> Pipeline<T> {
 
         let pil = self.compute_optimized_pil()?;
 
+        let input_hash = self.checkpoint_input_hash(Stage::FixedCols);
+        if let Some(fixed_cols) =
+            self.load_checkpoint(Stage::FixedCols, input_hash.as_deref(), |path| {
+                FixedPolySet::<T>::read(path.parent().unwrap())
+            })
+        {
+            self.artifact.fixed_cols = Some(Arc::new(fixed_cols));
+            return Ok(self.artifact.fixed_cols.as_ref().unwrap().clone());
+        }
+
         self.log("Evaluating fixed columns...");
         let start = Instant::now();
-        let fixed_cols = constant_evaluator::generate(&pil);
+        let fixed_cols = constant_evaluator::generate_with_jit_cache_dir(
+            &pil,
+            self.arguments.jit_cache_dir.as_deref(),
+        );
         self.log(&format!(
             "Fixed column generation took {}s",
             start.elapsed().as_secs_f32()
         ));
         self.maybe_write_constants(&fixed_cols)?;
+        self.store_checkpoint(Stage::FixedCols, input_hash, |path| {
+            fixed_cols.write(path).map_err(|e| e.to_string())
+        })?;
 
         self.artifact.fixed_cols = Some(Arc::new(fixed_cols));
 
@@ -995,42 +1351,62 @@ impl<T: FieldElement> Pipeline<T> {
             return Ok(witness.clone());
         }
 
-        self.host_context.clear();
+        self.clear_query_callbacks();
 
         let pil = self.compute_optimized_pil()?;
         let fixed_cols = self.compute_fixed_cols()?;
 
         assert_eq!(pil.constant_count(), fixed_cols.len());
 
+        let input_hash = self.checkpoint_input_hash(Stage::Witness);
+        if let Some((witness, machine_rows)) =
+            self.load_checkpoint(Stage::Witness, input_hash.as_deref(), |path| {
+                let file = fs::File::open(path).map_err(|e| e.to_string())?;
+                serde_cbor::from_reader::<WitnessCheckpoint<T>, _>(BufReader::new(file))
+                    .map_err(|e| e.to_string())
+            })
+        {
+            self.arguments.external_witness_values.clear();
+            self.artifact.witness = Some(Arc::new(witness));
+            self.artifact.machine_rows = machine_rows;
+            self.artifact.proof = None;
+            return Ok(self.artifact.witness.as_ref().unwrap().clone());
+        }
+
         let witness_cols: Vec<_> = pil
             .committed_polys_in_source_order()
             .flat_map(|(s, _)| s.array_elements().map(|(name, _)| name))
             .collect();
 
-        let mut external_witness_values =
-            std::mem::take(&mut self.arguments.external_witness_values);
-        // witgen needs external witness columns sorted by source order
-        external_witness_values
-            .sort_by_key(|(name, _)| witness_cols.iter().position(|n| n == name).unwrap());
+        let external_witness_values = self.take_external_witness_values(&pil);
 
         if witness_cols
             .iter()
             .all(|name| external_witness_values.iter().any(|(e, _)| e == name))
         {
             self.log("All witness columns externally provided, skipping witness generation.");
+            self.store_checkpoint(Stage::Witness, input_hash, |path| {
+                (
+                    &external_witness_values,
+                    None::<BTreeMap<String, DegreeType>>,
+                )
+                    .write(path)
+                    .map_err(|e| e.to_string())
+            })?;
             self.artifact.witness = Some(Arc::new(external_witness_values));
         } else {
             self.log("Deducing witness columns...");
             let start = Instant::now();
-
             let query_callback = self
                 .arguments
                 .query_callback
                 .clone()
                 .unwrap_or_else(|| Arc::new(unused_query_callback()));
-            let witness = WitnessGenerator::new(&pil, &fixed_cols, query_callback.borrow())
-                .with_external_witness_values(&external_witness_values)
-                .generate();
+            let (witness, machine_rows) =
+                WitnessGenerator::new(&pil, &fixed_cols, query_callback.borrow())
+                    .with_external_witness_values(&external_witness_values)
+                    .with_jit_cache_dir(self.arguments.jit_cache_dir.as_deref())
+                    .generate_with_used_rows();
 
             self.log(&format!(
                 "Witness generation took {}s",
@@ -1038,18 +1414,48 @@ impl<T: FieldElement> Pipeline<T> {
             ));
 
             self.maybe_write_witness(&fixed_cols, &witness)?;
+            self.store_checkpoint(Stage::Witness, input_hash, |path| {
+                (&witness, Some(&machine_rows))
+                    .write(path)
+                    .map_err(|e| e.to_string())
+            })?;
 
             self.artifact.witness = Some(Arc::new(witness));
+            self.artifact.machine_rows = Some(machine_rows);
         }
         self.artifact.proof = None;
 
         Ok(self.artifact.witness.as_ref().unwrap().clone())
     }
 
+    /// Takes the external witness values, sorted by source order as needed by witgen.
+    fn take_external_witness_values(&mut self, pil: &Analyzed<T>) -> Columns<T> {
+        let witness_cols: Vec<_> = pil
+            .committed_polys_in_source_order()
+            .flat_map(|(s, _)| s.array_elements().map(|(name, _)| name))
+            .collect();
+
+        let mut external_witness_values =
+            std::mem::take(&mut self.arguments.external_witness_values);
+        external_witness_values
+            .sort_by_key(|(name, _)| witness_cols.iter().position(|n| n == name).unwrap());
+        external_witness_values
+    }
+
     pub fn witness(&self) -> Result<Arc<Columns<T>>, Vec<String>> {
         Ok(self.artifact.witness.as_ref().unwrap().clone())
     }
 
+    /// Returns the number of rows each namespace used in witness generation,
+    /// before the columns were padded to their size, e.g. as input for
+    /// [crate::degree_planner::plan_degrees].
+    /// Only available if the witness was generated by witness generation (also
+    /// when it was resumed from a checkpoint), and only includes the machines
+    /// that are sized to fit the rows they use.
+    pub fn machine_rows(&self) -> Option<&BTreeMap<String, DegreeType>> {
+        self.artifact.machine_rows.as_ref()
+    }
+
     pub fn publics(&self) -> Result<Vec<(String, Option<T>)>, Vec<String>> {
         let pil = self.optimized_pil()?;
         let witness = self.witness()?;
@@ -1060,7 +1466,8 @@ impl<T: FieldElement> Pipeline<T> {
         let ctx = WitgenCallbackContext::new(
             self.compute_fixed_cols()?,
             self.arguments.query_callback.as_ref().cloned(),
-        );
+        )
+        .with_jit_cache_dir(self.arguments.jit_cache_dir.clone());
         Ok(WitgenCallback::new(Arc::new(
             move |pil, current_witness, challenges, stage| {
                 ctx.next_stage_witness(pil, current_witness, challenges, stage)
@@ -1113,7 +1520,7 @@ impl<T: FieldElement> Pipeline<T> {
         // Create the backend
         let start = Instant::now();
         self.log(&format!("Backend setup for {backend}..."));
-        let backend = factory
+        let mut backend = factory
             .create(
                 pil.clone(),
                 fixed_cols.clone(),
@@ -1127,6 +1534,13 @@ impl<T: FieldElement> Pipeline<T> {
             .unwrap();
         self.log(&format!("Setup took {}s", start.elapsed().as_secs_f32()));
 
+        if let Some(checkpoints) = &self.checkpoints {
+            match backend.enable_checkpoints(checkpoints.dir().join("machine_proofs")) {
+                Ok(()) | Err(powdr_backend::Error::NoCheckpointsAvailable) => {}
+                Err(e) => return Err(vec![e.to_string()]),
+            }
+        }
+
         self.artifact.backend = Some(backend);
         Ok(self.artifact.backend.as_deref_mut().unwrap())
     }
@@ -1139,6 +1553,14 @@ impl<T: FieldElement> Pipeline<T> {
         let witness = self.compute_witness()?;
         let witgen_callback = self.witgen_callback()?;
 
+        let input_hash = self.checkpoint_input_hash(Stage::Proof);
+        if let Some(proof) = self.load_checkpoint(Stage::Proof, input_hash.as_deref(), |path| {
+            fs::read(path).map_err(|e| e.to_string())
+        }) {
+            self.artifact.proof = Some(proof);
+            return Ok(self.artifact.proof.as_ref().unwrap());
+        }
+
         // Reads the existing proof file, if set.
         let existing_proof = self
             .arguments
@@ -1166,7 +1588,84 @@ impl<T: FieldElement> Pipeline<T> {
         self.log(&format!("Proof size: {} bytes", proof.len()));
 
         self.maybe_write_proof(&proof)?;
+        self.store_checkpoint(Stage::Proof, input_hash, |path| {
+            fs::write(path, &proof).map_err(|e| e.to_string())
+        })?;
+
+        self.artifact.proof = Some(proof);
+
+        Ok(self.artifact.proof.as_ref().unwrap())
+    }
+
+    /// Computes the proof like [Self::compute_proof], but streams the witness
+    /// into the backend machine by machine while it is generated, so that the
+    /// witness of all machines never needs to be kept in memory at once.
+    /// As a consequence, the witness is neither written to the output directory
+    /// nor available as an artifact afterwards.
+    ///
+    /// Falls back to [Self::compute_proof] if the witness has already been
+    /// computed, if checkpoints are enabled (which persist the witness) or if
+    /// the backend does not support streaming.
+    pub fn compute_proof_streaming(&mut self) -> Result<&Proof, Vec<String>> {
+        if self.artifact.proof.is_some() {
+            return Ok(self.artifact.proof.as_ref().unwrap());
+        }
+        if self.artifact.witness.is_some() || self.checkpoints.is_some() {
+            return self.compute_proof();
+        }
+
+        self.clear_query_callbacks();
+
+        let pil = self.compute_optimized_pil()?;
+        let fixed_cols = self.compute_fixed_cols()?;
+        let witgen_callback = self.witgen_callback()?;
+
+        // Reads the existing proof file, if set.
+        let existing_proof = self
+            .arguments
+            .existing_proof_file
+            .as_ref()
+            .map(|path| fs::read(path).unwrap());
+
+        self.setup_backend()?;
+
+        let external_witness_values = self.take_external_witness_values(&pil);
+        let query_callback = self
+            .arguments
+            .query_callback
+            .clone()
+            .unwrap_or_else(|| Arc::new(unused_query_callback()));
 
+        self.log("Deducing witness columns and computing proof...");
+        let start = Instant::now();
+        let result = {
+            let mut generate_witness = |sink: &mut dyn WitnessSink<T>| {
+                WitnessGenerator::new(&pil, &fixed_cols, query_callback.borrow())
+                    .with_external_witness_values(&external_witness_values)
+                    .with_jit_cache_dir(self.arguments.jit_cache_dir.as_deref())
+                    .generate_into(sink);
+            };
+            self.backend()?
+                .prove_streaming(&mut generate_witness, existing_proof, witgen_callback)
+        };
+        let proof = match result {
+            Ok(proof) => proof,
+            Err(powdr_backend::Error::NoStreamingAvailable) => {
+                self.arguments.external_witness_values = external_witness_values;
+                return self.compute_proof();
+            }
+            Err(powdr_backend::Error::BackendError(e)) => {
+                return Err(vec![e.to_string()]);
+            }
+            Err(e) => panic!("{}", e),
+        };
+        self.log(&format!(
+            "Witness generation and proof generation took {}s",
+            start.elapsed().as_secs_f32()
+        ));
+        self.log(&format!("Proof size: {} bytes", proof.len()));
+
+        self.maybe_write_proof(&proof)?;
         self.artifact.proof = Some(proof);
 
         Ok(self.artifact.proof.as_ref().unwrap())
@@ -1188,6 +1687,33 @@ impl<T: FieldElement> Pipeline<T> {
         self.name.as_ref().unwrap()
     }
 
+    /// Resets the state of the query callbacks before a new execution, i.e. the
+    /// data written by the guest and the position in interactive channels.
+    fn clear_query_callbacks(&mut self) {
+        self.host_context.clear();
+        self.session_query("Clear");
+    }
+
+    /// Makes the following executions run the given chunk of an execution with
+    /// continuations, for query callbacks whose state depends on the position in
+    /// the execution (see [channel_handler_to_query_callback]).
+    pub fn set_query_chunk(&self, chunk: usize) {
+        self.session_query(&format!("Chunk({chunk})"));
+    }
+
+    /// Discards the data recorded by the query callbacks, because the inputs
+    /// of the program changed.
+    fn reset_query_callbacks(&self) {
+        self.session_query("Reset");
+    }
+
+    fn session_query(&self, query: &str) {
+        if let Some(query_callback) = &self.arguments.query_callback {
+            // Callbacks without any state reject the query, so the result is ignored.
+            let _ = query_callback(query);
+        }
+    }
+
     pub fn data_callback(&self) -> Option<&dyn QueryCallback<T>> {
         self.arguments.query_callback.as_deref()
     }
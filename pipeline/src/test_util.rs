@@ -312,6 +312,63 @@ pub fn gen_halo2_proof(pipeline: Pipeline<Bn254Field>, backend: BackendVariant)
 #[cfg(not(feature = "halo2"))]
 pub fn gen_halo2_proof(_pipeline: Pipeline<Bn254Field>, _backend: BackendVariant) {}
 
+/// Generates a Poseidon proof and compresses it into an aggregated proof, which is then
+/// verified against the public inputs of the program.
+#[cfg(feature = "halo2")]
+pub fn gen_halo2_aggr_proof(pipeline: Pipeline<Bn254Field>) {
+    use powdr_backend::BackendType;
+    use powdr_number::buffered_write_file;
+    use std::io::Write;
+
+    let output_dir = pipeline.output_dir().clone().unwrap();
+
+    // The aggregation circuit needs a larger setup, which is shared with the app proof
+    let setup_file_path = output_dir.join("params.bin");
+    buffered_write_file(&setup_file_path, |writer| {
+        BackendType::Halo2
+            .factory::<Bn254Field>()
+            .generate_setup(1 << 20, writer)
+            .unwrap()
+    })
+    .unwrap();
+    let pipeline = pipeline.with_setup_file(Some(setup_file_path));
+
+    // Poseidon proof of the program
+    let mut pipeline_app = pipeline
+        .clone()
+        .with_backend(BackendType::Halo2, Some("poseidon".to_string()));
+    let proof_app_file_path = output_dir.join("proof_app.bin");
+    let proof_app = pipeline_app.compute_proof().unwrap().clone();
+    buffered_write_file(&proof_app_file_path, |writer| {
+        writer.write_all(&proof_app).unwrap()
+    })
+    .unwrap();
+    let vkey_app_file_path = output_dir.join("verification_key_app.bin");
+    buffered_write_file(&vkey_app_file_path, |writer| {
+        pipeline_app.export_verification_key(writer).unwrap()
+    })
+    .unwrap();
+
+    // Aggregated proof
+    let mut pipeline_aggr = pipeline
+        .with_backend(BackendType::Halo2, Some("snark_aggr".to_string()))
+        .with_vkey_app_file(Some(vkey_app_file_path))
+        .with_existing_proof_file(Some(proof_app_file_path));
+    let proof = pipeline_aggr.compute_proof().unwrap().clone();
+
+    let publics: Vec<Bn254Field> = pipeline_aggr
+        .publics()
+        .unwrap()
+        .iter()
+        .map(|(_name, v)| v.expect("all publics should be known since we created a proof"))
+        .collect();
+
+    pipeline_aggr.verify(&proof, &[publics]).unwrap();
+}
+
+#[cfg(not(feature = "halo2"))]
+pub fn gen_halo2_aggr_proof(_pipeline: Pipeline<Bn254Field>) {}
+
 #[cfg(feature = "plonky3")]
 pub fn test_plonky3_with_backend_variant<T: FieldElement>(
     file_name: &str,
@@ -1,14 +1,21 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use powdr_executor::constant_evaluator;
 use powdr_linker::LinkerParams;
 use powdr_number::{FieldElement, GoldilocksField};
 use powdr_pipeline::{
+    inputs_to_query_callback,
     test_util::{
         asm_string_to_pil, make_prepared_pipeline, make_simple_prepared_pipeline,
         regular_test_all_fields, regular_test_gl, resolve_test_file,
         run_pilcom_with_backend_variant, test_mock_backend, test_pilcom, test_plonky3_pipeline,
-        BackendVariant,
+        test_plonky3_with_backend_variant, BackendVariant,
     },
     Pipeline,
 };
@@ -43,6 +50,29 @@ fn challenges_asm() {
     test_plonky3_pipeline(pipeline);
 }
 
+#[test]
+fn degree_reduction_in_later_stage() {
+    let make_pipeline = |f: &str| {
+        Pipeline::<GoldilocksField>::default()
+            .with_tmp_output()
+            .from_file(resolve_test_file(f))
+            .with_max_constraint_degree(Some(2))
+    };
+
+    let mut pipeline = make_pipeline("asm/challenges_degree_reduction.asm");
+    let optimized = pipeline.compute_optimized_pil().unwrap().to_string();
+    assert!(optimized.contains("col witness stage(1) _degree_reduction_0;"));
+    pipeline.compute_witness().unwrap();
+    test_mock_backend(pipeline.clone());
+    test_plonky3_pipeline(pipeline);
+
+    // The bus accumulators are generated by the hand-written bus witgen, the
+    // columns introduced by degree reduction by the automatic one.
+    let mut pipeline = make_pipeline("asm/block_to_block_with_bus.asm");
+    pipeline.compute_witness().unwrap();
+    test_mock_backend(pipeline);
+}
+
 #[test]
 fn simple_sum_asm() {
     let f = "asm/simple_sum.asm";
@@ -50,6 +80,84 @@ fn simple_sum_asm() {
     regular_test_all_fields(f, &i);
 }
 
+#[test]
+fn simple_sum_resume_from_checkpoints() {
+    let f = "asm/simple_sum.asm";
+    let output_dir = mktemp::Temp::new_dir().unwrap();
+    let make_pipeline = |inputs: &[i32]| {
+        Pipeline::<GoldilocksField>::default()
+            .with_output(output_dir.to_path_buf(), true)
+            .from_file(resolve_test_file(f))
+            .with_prover_inputs(slice_to_vec(inputs))
+            .with_checkpoints()
+            .unwrap()
+            .with_backend(powdr_backend::BackendType::Mock, None)
+    };
+
+    let inputs = [16, 4, 1, 2, 8, 5];
+    let mut pipeline = make_pipeline(&inputs);
+    pipeline.compute_proof().unwrap();
+    let witness = pipeline.witness().unwrap();
+
+    // The prover inputs are part of the witness checkpoint hash, so changed
+    // inputs force the witness to be recomputed.
+    let changed_inputs = [17, 4, 1, 2, 8, 6];
+    let mut changed = make_pipeline(&changed_inputs);
+    let changed_witness = changed.compute_witness().unwrap();
+    assert_ne!(changed_witness, witness);
+    changed.compute_proof().unwrap();
+
+    // Unchanged inputs resume the witness.
+    let mut resumed = make_pipeline(&changed_inputs);
+    assert_eq!(resumed.compute_witness().unwrap(), changed_witness);
+    // The rows used by the machines are resumed with the witness.
+    assert!(changed.machine_rows().is_some());
+    assert_eq!(resumed.machine_rows(), changed.machine_rows());
+    resumed.compute_proof().unwrap();
+
+    // The data served by a query callback is unknown, so the witness is not
+    // resumed unless an input key is set.
+    let with_callback = |inputs: &[i32]| {
+        make_pipeline(&[])
+            .add_query_callback(Arc::new(inputs_to_query_callback(slice_to_vec(inputs))))
+    };
+    assert_eq!(with_callback(&inputs).compute_witness().unwrap(), witness);
+    assert_eq!(
+        with_callback(&changed_inputs).compute_witness().unwrap(),
+        changed_witness
+    );
+    let mut keyed = with_callback(&inputs).with_input_key("inputs".to_string());
+    assert_eq!(keyed.compute_witness().unwrap(), witness);
+    // Without any inputs, the witness can only be obtained by resuming.
+    let mut keyed_resumed = make_pipeline(&[]).with_input_key("inputs".to_string());
+    assert_eq!(keyed_resumed.compute_witness().unwrap(), witness);
+}
+
+#[test]
+fn parallel_finalization_is_deterministic() {
+    // Machines that finalize independently are finalized in parallel. Running
+    // witness generation on a single thread finalizes them sequentially, which
+    // has to result in the same witness.
+    let compute_witness = |f: &str, num_threads: usize| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap()
+            .install(|| {
+                make_simple_prepared_pipeline::<GoldilocksField>(f)
+                    .compute_witness()
+                    .unwrap()
+            })
+    };
+    for f in [
+        "asm/dynamic_vadcop.asm",
+        "asm/mem_read_write.asm",
+        "asm/block_to_block_with_bus.asm",
+    ] {
+        assert_eq!(compute_witness(f, 1), compute_witness(f, 4), "{f}");
+    }
+}
+
 #[test]
 #[should_panic = "Witness generation failed."]
 fn secondary_machine_plonk() {
@@ -157,6 +265,17 @@ fn block_to_block() {
     regular_test_all_fields(f, &[]);
 }
 
+#[test]
+#[cfg(feature = "plonky3")]
+fn block_to_block_streaming() {
+    let f = "asm/block_to_block.asm";
+    let mut pipeline: Pipeline<GoldilocksField> = make_simple_prepared_pipeline(f)
+        .with_backend(powdr_backend::BackendType::Plonky3Composite, None);
+
+    let proof = pipeline.compute_proof_streaming().cloned().unwrap();
+    pipeline.verify(&proof, &[vec![]]).unwrap();
+}
+
 #[test]
 fn block_to_block_empty_submachine() {
     let f = "asm/block_to_block_empty_submachine.asm";
@@ -191,22 +310,106 @@ fn block_to_block_with_bus_different_sizes() {
     test_plonky3_pipeline(pipeline);
 }
 
-#[cfg(feature = "halo2")]
 #[test]
-#[should_panic = "called `Result::unwrap()` on an `Err` value: [\"Circuit was not satisfied\"]"]
 fn block_to_block_with_bus_composite() {
-    // This currently fails because of #1608 ("Emulate shared challenges in CompositeBackend"):
-    // - `CompositeBackend::prove` correctly gets the challenges of each machine and accumulates them.
-    //   The shared challenges are used during witness generation.
-    // - `CompositeBackend::verify` simply verifies each machine proof independently, using the local
-    //   challenges. As a result, the challenges during verification differ and the constraints are
-    //   not satisfied.
-
-    use powdr_pipeline::test_util::test_halo2_with_backend_variant;
     let f = "asm/block_to_block_with_bus.asm";
-    let pipeline = make_simple_prepared_pipeline(f);
-    test_mock_backend(pipeline.clone());
-    test_halo2_with_backend_variant(pipeline, BackendVariant::Composite);
+    let pipeline: Pipeline<GoldilocksField> = make_simple_prepared_pipeline(f);
+    test_mock_backend(pipeline);
+    test_plonky3_with_backend_variant::<GoldilocksField>(f, vec![], BackendVariant::Composite);
+}
+
+#[test]
+fn bus_balance_mock() {
+    // Sends the values of `x` and receives those of `y`, which balances iff
+    // `y` is a permutation of `x`.
+    let program = |y: &str| {
+        format!(
+            r#"
+use std::protocols::bus::bus_receive;
+use std::protocols::bus::bus_send;
+
+machine Main with
+    degree: 8,
+    latch: latch,
+    operation_id: operation_id
+{{
+    operation main<0>;
+
+    col fixed operation_id = [0]*;
+    col fixed latch = [0, 0, 0, 1]*;
+    col fixed x(i) {{ i }};
+    col fixed y(i) {{ {y} }};
+
+    // Can't have a challenge without a witness column, so add one here
+    col witness dummy;
+    dummy = dummy';
+
+    bus_send(42, [x], 1);
+    bus_receive(42, [y], 1);
+}}
+"#
+        )
+    };
+    let prove = |y: &str| {
+        Pipeline::<GoldilocksField>::default()
+            .from_asm_string(program(y), None)
+            .with_backend(powdr_backend::BackendType::Mock, None)
+            .compute_proof()
+            .cloned()
+    };
+
+    prove("(i + 1) % 8").unwrap();
+    // The accumulators are consistent with the tuples, but do not add up to zero.
+    assert!(prove("i + 1").is_err());
+}
+
+#[test]
+fn channel_handler() {
+    let asm = r#"
+use std::prelude::Query;
+
+machine Main with degree: 16 {
+    reg pc[@pc];
+    reg X[<=];
+    reg A;
+
+    instr assert_zero X { X = 0 }
+
+    function main {
+        A <=X= ${ Query::Output(5, 3) };
+        A <=X= ${ Query::Input(5, 0) } - 1;
+        assert_zero A;
+        A <=X= ${ Query::Input(5, 1) } - 9;
+        assert_zero A;
+        return;
+    }
+}
+"#;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let handler = {
+        let calls = calls.clone();
+        move |request: &[u8]| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            request.iter().map(|b| b * b).collect()
+        }
+    };
+    let mut pipeline = Pipeline::<GoldilocksField>::default()
+        .from_asm_string(asm.to_string(), None)
+        .add_channel_handler(5, Arc::new(handler))
+        .with_backend(powdr_backend::BackendType::Mock, None);
+    pipeline.compute_proof().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    // Computing the witness again replays the response.
+    pipeline.rollback_from_witness();
+    pipeline.compute_proof().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    // The inputs changed, so the handler is invoked again.
+    let mut pipeline = pipeline.add_data(0, &0u32);
+    pipeline.rollback_from_witness();
+    pipeline.compute_proof().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
 }
 
 #[test]
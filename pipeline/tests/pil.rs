@@ -4,9 +4,10 @@ use powdr_pipeline::{
         assert_proofs_fail_for_invalid_witnesses, assert_proofs_fail_for_invalid_witnesses_estark,
         assert_proofs_fail_for_invalid_witnesses_mock,
         assert_proofs_fail_for_invalid_witnesses_pilcom,
-        assert_proofs_fail_for_invalid_witnesses_stwo, make_prepared_pipeline,
-        make_simple_prepared_pipeline, regular_test_all_fields, regular_test_gl,
-        test_halo2_with_backend_variant, test_mock_backend, test_pilcom, test_stwo, BackendVariant,
+        assert_proofs_fail_for_invalid_witnesses_stwo, gen_halo2_aggr_proof, gen_halo2_proof,
+        make_prepared_pipeline, make_simple_prepared_pipeline, regular_test_all_fields,
+        regular_test_gl, test_halo2_with_backend_variant, test_mock_backend, test_pilcom,
+        test_plonky3_with_backend_variant, test_stwo, BackendVariant,
     },
     Pipeline,
 };
@@ -43,6 +44,53 @@ fn lookup_with_selector() {
     let witness = vec![("main::w".to_string(), vec![0, 42, 4, 17])];
     assert_proofs_fail_for_invalid_witnesses_mock(f, &witness);
     assert_proofs_fail_for_invalid_witnesses_pilcom(f, &witness);
+    assert_proofs_fail_for_invalid_witnesses_stwo(f, &witness);
+}
+
+#[test]
+fn lookup_with_selector_mock_report() {
+    use powdr_pipeline::test_util::resolve_test_file;
+
+    // Invalid witness: 0 is not in the set {2, 4}
+    let witness = [0, 42, 4, 17];
+    let mut pipeline = Pipeline::default()
+        .with_tmp_output()
+        .from_file(resolve_test_file("pil/lookup_with_selector.pil"))
+        .set_witness(vec![(
+            "main::w".to_string(),
+            witness.iter().cloned().map(GoldilocksField::from).collect(),
+        )])
+        // Options of other backends are ignored.
+        .with_backend(
+            powdr_backend::BackendType::Mock,
+            Some("stark_gl,report=json".to_string()),
+        );
+    assert!(pipeline.compute_proof().is_err());
+
+    let report_path = pipeline
+        .output_dir()
+        .as_ref()
+        .unwrap()
+        .join("constraint_report.json");
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(report_path).unwrap()).unwrap();
+
+    let failures = report["connection_failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0]["kind"], "lookup");
+
+    let missing = &failures[0]["missing_tuples"][0];
+    assert_eq!(missing["row"], 0);
+    assert_eq!(missing["values"], serde_json::json!(["0"]));
+    assert!(missing["columns"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({"column": "main::w", "row": "0", "next_row": "42"})));
+    // The closest tuple of the lookup table is (2), on row 1.
+    assert_eq!(
+        missing["nearest"][0],
+        serde_json::json!({"row": 1, "values": ["2"], "mismatches": 1})
+    );
 }
 
 #[test]
@@ -83,6 +131,7 @@ fn permutation_with_selector() {
     let witness = vec![("main::w".to_string(), vec![0, 42, 4, 17])];
     assert_proofs_fail_for_invalid_witnesses_mock(f, &witness);
     assert_proofs_fail_for_invalid_witnesses_pilcom(f, &witness);
+    assert_proofs_fail_for_invalid_witnesses_stwo(f, &witness);
 }
 
 #[test]
@@ -108,10 +157,45 @@ fn fibonacci() {
 
 #[test]
 fn fibonacci_with_public() {
-    // Public references are not supported by the backends yet, but we can test witness generation.
+    // Halo2 does not support public references in identities, see `stwo_fibonacci_with_public`
+    // for a proof with the Stwo backend.
     let f = "pil/fibonacci_with_public.pil";
-    let mut pipeline: Pipeline<GoldilocksField> = make_prepared_pipeline(f, vec![], vec![]);
-    pipeline.compute_witness().unwrap();
+    let pipeline: Pipeline<GoldilocksField> = make_prepared_pipeline(f, vec![], vec![]);
+    test_mock_backend(pipeline);
+    test_plonky3_with_backend_variant::<GoldilocksField>(f, vec![], BackendVariant::Monolithic);
+}
+
+#[test]
+fn halo2_poseidon_with_publics() {
+    let f = "pil/fib_arrays.pil";
+    let pipeline = make_simple_prepared_pipeline(f);
+    gen_halo2_proof(pipeline, BackendVariant::Monolithic);
+}
+
+#[test]
+#[cfg(feature = "halo2")]
+fn halo2_snark_single_with_publics() {
+    let f = "pil/fib_arrays.pil";
+    let mut pipeline = make_simple_prepared_pipeline::<powdr_number::Bn254Field>(f).with_backend(
+        powdr_backend::BackendType::Halo2,
+        Some("snark_single".to_string()),
+    );
+    let proof = pipeline.compute_proof().cloned().unwrap();
+    let publics = pipeline
+        .publics()
+        .unwrap()
+        .iter()
+        .map(|(_, v)| v.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(publics.len(), 1);
+    pipeline.verify(&proof, &[publics]).unwrap();
+}
+
+#[test]
+fn halo2_aggregation_with_publics() {
+    let f = "pil/fib_arrays.pil";
+    let pipeline = make_simple_prepared_pipeline(f);
+    gen_halo2_aggr_proof(pipeline);
 }
 
 #[test]
@@ -302,6 +386,97 @@ fn stwo_constant_next_test() {
     test_stwo(f, Default::default());
 }
 
+#[test]
+fn stwo_fibonacci_with_public() {
+    let f = "pil/fibonacci_with_public.pil";
+    test_stwo(f, Default::default());
+}
+
+#[test]
+#[cfg(feature = "stwo")]
+fn stwo_proof_with_invalid_size_or_commitments() {
+    use powdr_number::Mersenne31Field;
+    use powdr_pipeline::test_util::resolve_test_file;
+    use powdr_pipeline::Pipeline;
+
+    let f = "pil/fibonacci_with_public.pil";
+    let mut pipeline = Pipeline::<Mersenne31Field>::default()
+        .with_tmp_output()
+        .from_file(resolve_test_file(f))
+        .with_backend(powdr_backend::BackendType::Stwo, None);
+    let mut proof = pipeline.compute_proof().cloned().unwrap();
+    let publics = pipeline
+        .publics()
+        .clone()
+        .unwrap()
+        .iter()
+        .map(|(_, v)| v.unwrap())
+        .collect::<Vec<_>>();
+    pipeline.verify(&proof, &[publics.clone()]).unwrap();
+
+    // The commitments are serialized first, as a length followed by 32-byte
+    // hashes. Dropping the commitment of the witness has to be rejected.
+    let mut truncated = 1u64.to_le_bytes().to_vec();
+    truncated.extend(&proof[8..40]);
+    truncated.extend(&proof[72..]);
+    assert!(pipeline.verify(&truncated, &[publics.clone()]).is_err());
+
+    // The log size is serialized last. The machine has a fixed degree of 4.
+    let len = proof.len();
+    proof[len - 4..].copy_from_slice(&3u32.to_le_bytes());
+    assert!(pipeline.verify(&proof, &[publics]).is_err());
+}
+
+#[test]
+#[cfg(feature = "stwo")]
+fn stwo_different_degrees() {
+    use powdr_number::Mersenne31Field;
+    use powdr_pipeline::test_util::resolve_test_file;
+    use powdr_pipeline::Pipeline;
+
+    // Because machines have different lengths, this can only be proven
+    // with a composite proof.
+    let f = "pil/different_degrees.pil";
+    let mut pipeline = Pipeline::<Mersenne31Field>::default()
+        .with_tmp_output()
+        .from_file(resolve_test_file(f))
+        .with_backend(powdr_backend::BackendType::StwoComposite, None);
+    let proof = pipeline.compute_proof().cloned().unwrap();
+    pipeline.verify(&proof, &[vec![]]).unwrap();
+}
+
+#[test]
+#[cfg(feature = "stwo")]
+fn stwo_lookup_and_permutation_with_selector() {
+    use powdr_number::Mersenne31Field;
+    use powdr_pipeline::test_util::resolve_test_file;
+    use powdr_pipeline::Pipeline;
+
+    // witness[0] and witness[2] have to be in {2, 4}
+    let witness = [2, 42, 4, 17];
+    for f in [
+        "pil/lookup_with_selector.pil",
+        "pil/permutation_with_selector.pil",
+    ] {
+        let mut pipeline = Pipeline::default()
+            .with_tmp_output()
+            .from_file(resolve_test_file(f))
+            .set_witness(vec![(
+                "main::w".to_string(),
+                witness.iter().cloned().map(Mersenne31Field::from).collect(),
+            )])
+            .with_backend(powdr_backend::BackendType::Stwo, None);
+        let proof = pipeline.compute_proof().cloned().unwrap();
+        pipeline.verify(&proof, &[vec![]]).unwrap();
+    }
+}
+
+#[test]
+fn stwo_lookup_with_product() {
+    let f = "pil/lookup_with_product.pil";
+    test_stwo(f, Default::default());
+}
+
 #[test]
 fn fibonacci_invalid_witness_stwo() {
     let f = "pil/fibo_no_publics.pil";
@@ -433,6 +608,78 @@ fn serialize_deserialize_optimized_pil() {
     assert_eq!(input_pil_file, output_pil_file);
 }
 
+#[test]
+fn cse_only_when_enabled() {
+    let pil = r#"namespace N(8);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    (X * Y + Z) * X = Y;
+    Z' = X * Y + Z;
+    X' = X * Y + Z;
+"#;
+    let mut pipeline = Pipeline::<GoldilocksField>::default().from_pil_string(pil.to_string());
+    let optimized = pipeline.compute_optimized_pil().unwrap();
+    assert!(!optimized.to_string().contains("_cse_"));
+
+    // Changing the config after the PIL was optimized recomputes it.
+    let mut pipeline = pipeline.with_cse_config(Some(powdr_pilopt::CseConfig {
+        min_savings: 2,
+        max_degree: None,
+    }));
+    let optimized = pipeline.compute_optimized_pil().unwrap();
+    assert!(optimized.to_string().contains("_cse_0"));
+}
+
+#[test]
+fn degree_reduction_witgen() {
+    let pil = r#"namespace N(8);
+    col fixed FIRST = [1] + [0]*;
+    col witness x;
+    col witness y;
+    FIRST * (x - 2) = 0;
+    (1 - FIRST') * (x' - x - 1) = 0;
+    y = x * x * x;
+"#;
+    let mut pipeline = Pipeline::<GoldilocksField>::default()
+        .from_pil_string(pil.to_string())
+        .with_max_constraint_degree(Some(2));
+    let optimized = pipeline.compute_optimized_pil().unwrap();
+    assert!(optimized.to_string().contains("N::_degree_reduction_0"));
+
+    // Witness generation has to solve the new column for the proof to succeed.
+    let witness = pipeline.compute_witness().unwrap();
+    let column = |name: &str| &witness.iter().find(|(n, _)| n == name).unwrap().1;
+    assert_eq!(
+        column("N::_degree_reduction_0"),
+        &column("N::x").iter().map(|x| *x * *x).collect::<Vec<_>>()
+    );
+    test_mock_backend(pipeline);
+}
+
+#[test]
+fn degree_reduction_after_proof() {
+    let pil = r#"namespace N(8);
+    col fixed FIRST = [1] + [0]*;
+    col witness x;
+    col witness y;
+    FIRST * (x - 2) = 0;
+    (1 - FIRST') * (x' - x - 1) = 0;
+    y = x * x * x;
+"#;
+    let mut pipeline = Pipeline::<GoldilocksField>::default()
+        .from_pil_string(pil.to_string())
+        .with_backend(powdr_backend::BackendType::Mock, None);
+    pipeline.compute_proof().unwrap();
+
+    // Reducing the degree afterwards discards everything computed from the
+    // previous PIL, including the fixed columns and the backend.
+    let mut pipeline = pipeline.with_max_constraint_degree(Some(2));
+    let witness = pipeline.compute_witness().unwrap();
+    assert!(witness.iter().any(|(n, _)| n == "N::_degree_reduction_0"));
+    pipeline.compute_proof().unwrap();
+}
+
 mod reparse {
     use powdr_pipeline::test_util::run_reparse_test;
     use test_log::test;
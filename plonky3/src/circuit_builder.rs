@@ -18,7 +18,8 @@ use p3_maybe_rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use tracing::info_span;
 
 use crate::{
-    params::{Commitment, FieldElementMap, Plonky3Field, ProverData},
+    logup::{helper_column_name, multiplicity_column_name, ExtensionArithmetic, Logup},
+    params::{Challenge, Commitment, FieldElementMap, Plonky3Field, ProverData},
     AirStage,
 };
 use p3_air::{Air, AirBuilder, BaseAir, PairBuilder};
@@ -31,8 +32,8 @@ use powdr_ast::analyzed::{
 use crate::{CallbackResult, MultiStageAir, MultistageAirBuilder};
 use powdr_ast::parsed::visitor::ExpressionVisitable;
 
-use powdr_executor_utils::WitgenCallback;
-use powdr_number::{FieldElement, LargeInt};
+use powdr_executor_utils::{VariablySizedColumn, WitgenCallback};
+use powdr_number::LargeInt;
 
 /// A description of the constraint system.
 /// All of the data is derived from the analyzed PIL, but is materialized
@@ -51,13 +52,22 @@ pub struct ConstraintSystem<T> {
     // for each stage, the number of witness columns. There is always a least one stage, possibly empty
     stage_widths: Vec<usize>,
     challenges_by_stage: Vec<Vec<u64>>,
+    // the lookups and permutations, proven with LogUp
+    logup: Option<Logup<T>>,
 }
 
-impl<T: FieldElement> From<&Analyzed<T>> for ConstraintSystem<T> {
-    fn from(analyzed: &Analyzed<T>) -> Self {
+impl<T: FieldElementMap> TryFrom<&Analyzed<T>> for ConstraintSystem<T>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    type Error = String;
+
+    /// Fails if the lookups and permutations cannot be proven.
+    fn try_from(analyzed: &Analyzed<T>) -> Result<Self, String> {
         let identities = analyzed.identities.clone();
         let constant_count = analyzed.constant_count();
-        let stage_widths = (0..analyzed.stage_count() as u32)
+        let mut stage_widths = (0..analyzed.stage_count() as u32)
             .map(|stage| {
                 analyzed
                     .definitions_in_source_order(PolynomialType::Committed)
@@ -93,8 +103,17 @@ impl<T: FieldElement> From<&Analyzed<T>> for ConstraintSystem<T> {
             })
             .collect();
 
+        // lookups and permutations add columns to the first two stages
+        let logup = Logup::new(
+            &identities,
+            &intermediates,
+            &witness_columns,
+            &mut stage_widths,
+        )?;
+        let stage_count = stage_widths.len();
+
         // we use a set to collect all used challenges
-        let mut challenges_by_stage = vec![BTreeSet::new(); analyzed.stage_count()];
+        let mut challenges_by_stage = vec![BTreeSet::new(); stage_count];
         for identity in &identities {
             identity.pre_visit_expressions(&mut |expr| {
                 if let AlgebraicExpression::Challenge(challenge) = expr {
@@ -102,6 +121,9 @@ impl<T: FieldElement> From<&Analyzed<T>> for ConstraintSystem<T> {
                 }
             });
         }
+        if let Some(logup) = &logup {
+            challenges_by_stage[0].extend(logup.challenge_ids());
+        }
 
         // finally, we convert the set to a vector
         let challenges_by_stage = challenges_by_stage
@@ -110,14 +132,14 @@ impl<T: FieldElement> From<&Analyzed<T>> for ConstraintSystem<T> {
             .collect();
 
         let publics_by_stage = analyzed.get_publics().into_iter().fold(
-            vec![vec![]; analyzed.stage_count()],
+            vec![vec![]; stage_count],
             |mut acc, (name, column_name, id, row, stage)| {
                 acc[stage as usize].push((name, column_name, id, row));
                 acc
             },
         );
 
-        Self {
+        Ok(Self {
             identities,
             publics_by_stage,
             constant_count,
@@ -126,8 +148,37 @@ impl<T: FieldElement> From<&Analyzed<T>> for ConstraintSystem<T> {
             fixed_columns,
             intermediates,
             challenges_by_stage,
-        }
+            logup,
+        })
+    }
+}
+
+impl<T> ConstraintSystem<T> {
+    /// The number of stages, including the ones added for lookups and permutations.
+    pub fn stage_count(&self) -> usize {
+        self.stage_widths.len()
     }
+
+    /// Adds empty stages so that the table has `stage_count` stages.
+    pub fn set_stage_count(&mut self, stage_count: usize) {
+        assert!(stage_count >= self.stage_count());
+        self.stage_widths.resize(stage_count, 0);
+        self.challenges_by_stage.resize(stage_count, vec![]);
+        self.publics_by_stage.resize(stage_count, vec![]);
+    }
+}
+
+/// The IDs of the challenges drawn after the given stage for any of the constraint
+/// systems. The challenges of a stage are drawn in the order of their IDs.
+pub(crate) fn challenge_ids<'b, T: 'b>(
+    constraint_systems: impl IntoIterator<Item = &'b ConstraintSystem<T>>,
+    stage: usize,
+) -> BTreeSet<u64> {
+    constraint_systems
+        .into_iter()
+        .flat_map(|constraint_system| &constraint_system.challenges_by_stage[stage])
+        .copied()
+        .collect()
 }
 
 pub struct PowdrCircuit<'a, T: FieldElementMap>
@@ -139,6 +190,8 @@ where
     pub split: &'a BTreeMap<String, (Analyzed<T>, ConstraintSystem<T>)>,
     /// Callback to augment the witness in the later stages
     witgen_callback: Option<WitgenCallback<T>>,
+    /// The values of the fixed columns, required for lookups and permutations
+    fixed: Option<&'a [(String, VariablySizedColumn<T>)]>,
 }
 
 impl<'a, T: FieldElementMap> PowdrCircuit<'a, T>
@@ -150,6 +203,7 @@ where
         Self {
             split,
             witgen_callback: None,
+            fixed: None,
         }
     }
 
@@ -190,6 +244,98 @@ where
             ..self
         }
     }
+
+    pub fn with_fixed(self, fixed: &'a [(String, VariablySizedColumn<T>)]) -> Self {
+        Self {
+            fixed: Some(fixed),
+            ..self
+        }
+    }
+
+    /// Appends the multiplicity columns of the lookups to the first-stage witness.
+    /// Fails if a lookup is not satisfied.
+    pub fn add_lookup_multiplicities(
+        &self,
+        witness_by_machine: &mut BTreeMap<String, Vec<(String, Vec<T>)>>,
+    ) -> Result<(), String> {
+        for (machine_name, machine_witness) in witness_by_machine.iter_mut() {
+            let (pil, constraint_system) = &self.split[machine_name];
+            if let Some(logup) = &constraint_system.logup {
+                let multiplicities = logup.multiplicities(
+                    &self.columns_by_id(pil, machine_witness),
+                    &constraint_system.intermediates,
+                )?;
+                machine_witness.extend(
+                    multiplicities
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, values)| (multiplicity_column_name(machine_name, i), values)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the second-stage columns of the lookups and permutations of a table.
+    fn logup_helper_columns(
+        &self,
+        machine_name: &str,
+        machine_witness: &[(String, Vec<T>)],
+        challenges: &BTreeMap<u64, T>,
+    ) -> Vec<(String, Vec<T>)> {
+        let (pil, constraint_system) = &self.split[machine_name];
+        let Some(logup) = &constraint_system.logup else {
+            return vec![];
+        };
+        let multiplicities = (0..logup.multiplicity_count())
+            .map(|i| {
+                let name = multiplicity_column_name(machine_name, i);
+                machine_witness
+                    .iter()
+                    .find_map(|(n, values)| (n == &name).then_some(values.as_slice()))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        logup
+            .helper_columns(
+                &self.columns_by_id(pil, machine_witness),
+                &constraint_system.intermediates,
+                &multiplicities,
+                challenges,
+            )
+            .into_iter()
+            .enumerate()
+            .map(|(i, values)| (helper_column_name(machine_name, i), values))
+            .collect()
+    }
+
+    /// Returns the values of the witness and fixed columns of a table, by ID.
+    fn columns_by_id<'b>(
+        &'b self,
+        pil: &Analyzed<T>,
+        witness: &'b [(String, Vec<T>)],
+    ) -> BTreeMap<PolyID, &'b [T]> {
+        let size = witness[0].1.len() as u64;
+        let fixed = self
+            .fixed
+            .expect("The fixed columns are required for lookups and permutations");
+        let values_by_name = witness
+            .iter()
+            .map(|(name, values)| (name, values.as_slice()))
+            .chain(
+                fixed
+                    .iter()
+                    .filter_map(|(name, column)| column.get_by_size(size).map(|v| (name, v))),
+            )
+            .collect::<BTreeMap<_, _>>();
+        pil.committed_polys_in_source_order()
+            .chain(pil.constant_polys_in_source_order())
+            .flat_map(|(symbol, _)| symbol.array_elements())
+            .filter_map(|(name, poly_id)| {
+                values_by_name.get(&name).map(|values| (poly_id, *values))
+            })
+            .collect()
+    }
 }
 
 pub(crate) struct PowdrTable<'a, T: FieldElementMap>
@@ -454,9 +600,8 @@ where
 
                     builder.assert_zero(e);
                 }
-                Identity::Lookup(..) => unimplemented!("Plonky3 does not support plookup"),
-                Identity::Permutation(..) => {
-                    unimplemented!("Plonky3 does not support permutations")
+                Identity::Lookup(..) | Identity::Permutation(..) => {
+                    // proven with LogUp below
                 }
                 Identity::Connect(..) => unimplemented!("Plonky3 does not support connections"),
                 Identity::PhantomPermutation(_)
@@ -466,6 +611,104 @@ where
                 }
             }
         }
+
+        // lookups and permutations, with extension field elements given by their coordinates
+        if let Some(logup) = &self.constraint_system.logup {
+            let extension = ExtensionArithmetic::new::<Challenge<T>>();
+            let challenge = |ids: &[u64]| {
+                ids.iter()
+                    .map(|id| challenges_by_stage[0][id].clone().into())
+                    .collect::<Vec<AB::Expr>>()
+            };
+            let (alpha, beta) = (challenge(&logup.alpha_ids), challenge(&logup.beta_ids));
+            let degree = alpha.len();
+            let first_stage = traces_by_stage[0].row_slice(0);
+            let (helpers, helpers_next) = (
+                traces_by_stage[1].row_slice(0),
+                traces_by_stage[1].row_slice(1),
+            );
+            let helper = |row: &[AB::Var], index: usize| {
+                row[index..index + degree]
+                    .iter()
+                    .map(|v| (*v).into())
+                    .collect::<Vec<AB::Expr>>()
+            };
+
+            let mut sum = vec![AB::Expr::zero(); degree];
+            for argument in &logup.arguments {
+                let [left, right] = [
+                    (&argument.left, argument.left_helper),
+                    (&argument.right, argument.right_helper),
+                ]
+                .map(|(selected, index)| {
+                    let selector = self.to_plonky3_expr::<AB>(
+                        &selected.selector,
+                        &traces_by_stage,
+                        &fixed,
+                        &mut intermediate_cache,
+                        &public_vals_by_name,
+                        &challenges_by_stage,
+                    );
+                    // fp = id + alpha * e_0 + alpha^2 * e_1 + ...
+                    let (fingerprint, _) = selected.expressions.iter().fold(
+                        (
+                            extension.embed(AB::Expr::from(T::from(argument.id).into_p3_field())),
+                            alpha.clone(),
+                        ),
+                        |(fingerprint, power), e| {
+                            let e = self.to_plonky3_expr::<AB>(
+                                e,
+                                &traces_by_stage,
+                                &fixed,
+                                &mut intermediate_cache,
+                                &public_vals_by_name,
+                                &challenges_by_stage,
+                            );
+                            let fingerprint = fingerprint
+                                .into_iter()
+                                .zip(&power)
+                                .map(|(f, p)| f + p.clone() * e.clone())
+                                .collect();
+                            (fingerprint, extension.mul(&power, &alpha))
+                        },
+                    );
+
+                    // h * (beta - fp) = selector
+                    let h = helper(&*helpers, index);
+                    let denominator = beta
+                        .iter()
+                        .zip(fingerprint)
+                        .map(|(b, f)| b.clone() - f)
+                        .collect::<Vec<_>>();
+                    for (product, selector) in extension
+                        .mul(&h, &denominator)
+                        .into_iter()
+                        .zip(extension.embed(selector))
+                    {
+                        builder.assert_zero(product - selector);
+                    }
+                    h
+                });
+
+                let multiplicity: Option<AB::Expr> =
+                    argument.multiplicity.map(|index| first_stage[index].into());
+                for ((sum, left), right) in sum.iter_mut().zip(left).zip(right) {
+                    *sum += match &multiplicity {
+                        Some(m) => left - m.clone() * right,
+                        None => left - right,
+                    };
+                }
+            }
+
+            // acc' = acc + sum, also from the last row to the first
+            for ((accumulator_next, accumulator), sum) in helper(&*helpers_next, logup.accumulator)
+                .into_iter()
+                .zip(helper(&*helpers, logup.accumulator))
+                .zip(sum)
+            {
+                builder.assert_zero(accumulator_next - accumulator - sum);
+            }
+        }
     }
 }
 
@@ -508,27 +751,44 @@ where
     Commitment<T>: Send,
 {
     /// Computes the stage data for stage number `trace_stage` based on `new_challenge_values` drawn at the end of stage `trace_stage - 1`.
+    /// The witgen callback can replace these challenges by ones shared with other proofs,
+    /// so the challenges the stage was computed with are returned as well.
     pub fn compute_stage(
         &self,
         trace_stage: u8,
         new_challenge_values: &[Plonky3Field<T>],
         witness_by_machine: &mut BTreeMap<String, Vec<(String, Vec<T>)>>,
     ) -> CallbackResult<Plonky3Field<T>> {
-        let previous_stage_challenges: BTreeSet<&u64> = self
-            .split
-            .values()
-            .flat_map(|(_, constraint_system)| {
-                &constraint_system.challenges_by_stage[trace_stage as usize - 1]
-            })
-            .collect();
+        let previous_stage_challenges = challenge_ids(
+            self.split
+                .values()
+                .map(|(_, constraint_system)| constraint_system),
+            trace_stage as usize - 1,
+        );
 
         assert_eq!(previous_stage_challenges.len(), new_challenge_values.len());
         let challenge_map = previous_stage_challenges
-            .into_iter()
+            .iter()
             .zip(new_challenge_values)
             .map(|(c, v)| (*c, T::from_p3_field(*v)))
             .collect::<BTreeMap<_, _>>();
 
+        // only stages computed by the witgen callback can depend on shared challenges
+        let uses_callback = self
+            .split
+            .values()
+            .any(|(pil, _)| (trace_stage as usize) < pil.stage_count());
+        let challenge_map = match &self.witgen_callback {
+            Some(witgen_callback) if uses_callback => {
+                let shared = witgen_callback.share_challenges(challenge_map, trace_stage);
+                previous_stage_challenges
+                    .iter()
+                    .map(|id| (*id, shared[id]))
+                    .collect()
+            }
+            _ => challenge_map,
+        };
+
         // remember the columns we already know about
         let columns_before: BTreeSet<String> = witness_by_machine
             .iter()
@@ -540,12 +800,36 @@ where
             witness_by_machine
                 .par_iter()
                 .map(|(machine_name, machine_witness)| {
-                    let new_witness = self.witgen_callback.as_ref().unwrap().next_stage_witness(
-                        &self.split[machine_name].0,
-                        machine_witness,
-                        challenge_map.clone(),
-                        trace_stage,
-                    );
+                    let pil = &self.split[machine_name].0;
+                    // witgen only knows about the columns of the PIL, so we set aside the
+                    // columns added for lookups and permutations
+                    let pil_columns = pil
+                        .committed_polys_in_source_order()
+                        .flat_map(|(symbol, _)| symbol.array_elements())
+                        .map(|(name, _)| name)
+                        .collect::<BTreeSet<_>>();
+                    let (pil_witness, extra_witness): (Vec<_>, Vec<_>) = machine_witness
+                        .iter()
+                        .cloned()
+                        .partition(|(name, _)| pil_columns.contains(name));
+
+                    let mut new_witness = if (trace_stage as usize) < pil.stage_count() {
+                        self.witgen_callback.as_ref().unwrap().next_stage_witness(
+                            pil,
+                            &pil_witness,
+                            challenge_map.clone(),
+                            trace_stage,
+                        )
+                    } else {
+                        pil_witness
+                    };
+                    new_witness.extend(extra_witness);
+
+                    if trace_stage == 1 {
+                        let helper_columns =
+                            self.logup_helper_columns(machine_name, &new_witness, &challenge_map);
+                        new_witness.extend(helper_columns);
+                    }
                     (machine_name.clone(), new_witness)
                 })
                 .collect()
@@ -577,6 +861,12 @@ where
             .collect();
 
         // return the next stage for each table
-        CallbackResult { air_stages }
+        CallbackResult {
+            air_stages,
+            challenge_values: challenge_map
+                .into_values()
+                .map(|value| value.into_p3_field())
+                .collect(),
+        }
     }
 }
@@ -4,6 +4,7 @@ extern crate alloc;
 
 mod circuit_builder;
 mod folder;
+mod logup;
 mod params;
 mod proof;
 mod prover;
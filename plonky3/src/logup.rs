@@ -0,0 +1,426 @@
+//! Lookups and permutations within a table, proven with LogUp.
+//!
+//! Every lookup `sel_l $ [a] in sel_r $ [b]` adds a column `m` to the first
+//! stage of its table, which counts how often each row of `b` is looked up.
+//! Permutations use `m = 1` instead. The tuples are compressed to
+//! `fp(t) = id + alpha * t_0 + alpha^2 * t_1 + ...`, where `id` is the ID of the
+//! identity, and the second stage holds the fractions `h_l = sel_l / (beta - fp(a))`
+//! and `h_r = sel_r / (beta - fp(b))` of each identity together with an
+//! accumulator constrained by `acc' = acc + sum(h_l - m * h_r)` in every row,
+//! including the last one, which wraps around to the first row.
+//!
+//! `alpha`, `beta`, the fractions and the accumulator are elements of the
+//! extension field of the proof system, represented by their coordinates over
+//! the base field: a challenge per coordinate, drawn like the challenger draws
+//! an extension field element, and a column per coordinate.
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, vec, vec::Vec};
+use core::iter::once;
+
+use p3_field::{batch_multiplicative_inverse, AbstractExtensionField, AbstractField, Field};
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperation, AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReferenceThin,
+    AlgebraicUnaryOperation, AlgebraicUnaryOperator, Identity, LookupIdentity, PermutationIdentity,
+    PolyID, PolynomialType, SelectedExpressions,
+};
+use powdr_ast::parsed::visitor::AllChildren;
+use powdr_executor_utils::lookup_multiplicities;
+use powdr_number::FieldElement;
+
+use crate::params::{Challenge, Commitment, FieldElementMap, Plonky3Field, ProverData};
+
+/// A lookup or permutation, together with the columns allocated for it.
+pub(crate) struct LogupArgument<T> {
+    pub(crate) id: u64,
+    pub(crate) left: SelectedExpressions<T>,
+    pub(crate) right: SelectedExpressions<T>,
+    /// For lookups, the index of the multiplicity column in the first stage
+    pub(crate) multiplicity: Option<usize>,
+    /// The index of the first coordinate of `h_l` in the second stage
+    pub(crate) left_helper: usize,
+    /// The index of the first coordinate of `h_r` in the second stage
+    pub(crate) right_helper: usize,
+}
+
+/// The lookups and permutations of a table.
+pub(crate) struct Logup<T> {
+    pub(crate) arguments: Vec<LogupArgument<T>>,
+    /// The index of the first coordinate of the accumulator in the second stage
+    pub(crate) accumulator: usize,
+    /// The IDs of the challenges holding the coordinates of `alpha`
+    pub(crate) alpha_ids: Vec<u64>,
+    /// The IDs of the challenges holding the coordinates of `beta`
+    pub(crate) beta_ids: Vec<u64>,
+}
+
+impl<T: FieldElementMap> Logup<T>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    /// Allocates the columns and challenges for the lookups and permutations among
+    /// the identities, if there are any. The columns are appended to the given
+    /// stage widths.
+    /// Fails if an identity cannot be evaluated before the second stage.
+    pub(crate) fn new(
+        identities: &[Identity<T>],
+        intermediates: &BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+        witness_columns: &BTreeMap<PolyID, (usize, usize)>,
+        stage_widths: &mut Vec<usize>,
+    ) -> Result<Option<Self>, String> {
+        let arguments = identities
+            .iter()
+            .filter_map(|identity| match identity {
+                Identity::Lookup(LookupIdentity {
+                    id, left, right, ..
+                }) => Some((*id, true, left, right)),
+                Identity::Permutation(PermutationIdentity {
+                    id, left, right, ..
+                }) => Some((*id, false, left, right)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if arguments.is_empty() {
+            return Ok(None);
+        }
+
+        for (id, _, left, right) in &arguments {
+            [*left, *right]
+                .into_iter()
+                .flat_map(|selected| once(&selected.selector).chain(&selected.expressions))
+                .try_for_each(|e| check_expression(*id, e, intermediates, witness_columns))?;
+        }
+
+        let degree = extension_degree::<T>();
+        if stage_widths.len() < 2 {
+            stage_widths.resize(2, 0);
+        }
+        let mut allocate = |stage: usize, count: usize| {
+            stage_widths[stage] += count;
+            stage_widths[stage] - count
+        };
+
+        let arguments = arguments
+            .into_iter()
+            .map(|(id, is_lookup, left, right)| LogupArgument {
+                id,
+                left: left.clone(),
+                right: right.clone(),
+                multiplicity: is_lookup.then(|| allocate(0, 1)),
+                left_helper: allocate(1, degree),
+                right_helper: allocate(1, degree),
+            })
+            .collect();
+        let accumulator = allocate(1, degree);
+
+        // the largest IDs, so that they do not collide with the challenges of the PIL
+        let first_id = u64::MAX - 2 * degree as u64 + 1;
+        let alpha_ids = (first_id..).take(degree).collect();
+        let beta_ids = (first_id + degree as u64..=u64::MAX).collect();
+
+        Ok(Some(Self {
+            arguments,
+            accumulator,
+            alpha_ids,
+            beta_ids,
+        }))
+    }
+
+    /// The IDs of the challenges drawn after the first stage.
+    pub(crate) fn challenge_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.alpha_ids.iter().chain(&self.beta_ids).copied()
+    }
+
+    /// The number of multiplicity columns in the first stage.
+    pub(crate) fn multiplicity_count(&self) -> usize {
+        self.arguments
+            .iter()
+            .filter(|argument| argument.multiplicity.is_some())
+            .count()
+    }
+
+    /// Computes the multiplicity columns, in the order of their allocation.
+    /// Fails if a lookup is not satisfied.
+    pub(crate) fn multiplicities(
+        &self,
+        columns: &BTreeMap<PolyID, &[T]>,
+        intermediates: &BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+    ) -> Result<Vec<Vec<T>>, String> {
+        let evaluator = Evaluator {
+            columns,
+            intermediates,
+        };
+
+        self.arguments
+            .iter()
+            .filter(|argument| argument.multiplicity.is_some())
+            .map(|argument| {
+                lookup_multiplicities(
+                    argument.id,
+                    &argument.left,
+                    &argument.right,
+                    height(columns),
+                    |e, row| evaluator.evaluate(e, row),
+                )
+            })
+            .collect()
+    }
+
+    /// Computes the second-stage columns, in the order of their allocation.
+    pub(crate) fn helper_columns(
+        &self,
+        columns: &BTreeMap<PolyID, &[T]>,
+        intermediates: &BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+        multiplicities: &[&[T]],
+        challenges: &BTreeMap<u64, T>,
+    ) -> Vec<Vec<T>> {
+        let size = height(columns);
+        let evaluator = Evaluator {
+            columns,
+            intermediates,
+        };
+        let challenge = |ids: &[u64]| {
+            Challenge::<T>::from_base_slice(
+                &ids.iter()
+                    .map(|id| challenges[id].into_p3_field())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let (alpha, beta) = (challenge(&self.alpha_ids), challenge(&self.beta_ids));
+
+        // h = selector / (beta - fp(tuple)) for one side of an argument
+        let fractions = |id: u64, selected: &SelectedExpressions<T>| {
+            let (selectors, denominators): (Vec<_>, Vec<_>) = (0..size)
+                .map(|row| {
+                    let (fingerprint, _) = selected.expressions.iter().fold(
+                        (
+                            Challenge::<T>::from_base(T::from(id).into_p3_field()),
+                            alpha,
+                        ),
+                        |(fingerprint, power), e| {
+                            (
+                                fingerprint + power * evaluator.evaluate(e, row).into_p3_field(),
+                                power * alpha,
+                            )
+                        },
+                    );
+                    (
+                        evaluator.evaluate(&selected.selector, row).into_p3_field(),
+                        beta - fingerprint,
+                    )
+                })
+                .unzip();
+            selectors
+                .into_iter()
+                .zip(batch_multiplicative_inverse(&denominators))
+                .map(|(selector, inverse)| inverse * selector)
+                .collect::<Vec<_>>()
+        };
+
+        let mut helper_columns = vec![];
+        let mut sum = vec![Challenge::<T>::zero(); size];
+        let mut multiplicities = multiplicities.iter();
+        for argument in &self.arguments {
+            let left = fractions(argument.id, &argument.left);
+            let right = fractions(argument.id, &argument.right);
+            let multiplicity = argument
+                .multiplicity
+                .map(|_| multiplicities.next().unwrap());
+            for (row, sum) in sum.iter_mut().enumerate() {
+                *sum += match multiplicity {
+                    Some(m) => left[row] - right[row] * m[row].into_p3_field(),
+                    None => left[row] - right[row],
+                };
+            }
+            helper_columns.extend(coordinate_columns::<T>(&left));
+            helper_columns.extend(coordinate_columns::<T>(&right));
+        }
+
+        // acc[0] = 0 and acc[i + 1] = acc[i] + sum[i]
+        let accumulator = sum
+            .iter()
+            .scan(Challenge::<T>::zero(), |acc, sum| {
+                let current = *acc;
+                *acc += *sum;
+                Some(current)
+            })
+            .collect::<Vec<_>>();
+        helper_columns.extend(coordinate_columns::<T>(&accumulator));
+        helper_columns
+    }
+}
+
+/// Arithmetic on extension field elements given by their coordinates, for
+/// expressing extension field constraints as base field constraints.
+pub(crate) struct ExtensionArithmetic<F> {
+    /// The coordinates of one
+    one: Vec<F>,
+    /// The coordinates of the product of the i-th and the j-th basis element
+    products: Vec<Vec<Vec<F>>>,
+}
+
+impl<F: Field> ExtensionArithmetic<F> {
+    pub(crate) fn new<EF: AbstractExtensionField<F>>() -> Self {
+        let products = (0..EF::D)
+            .map(|i| {
+                (0..EF::D)
+                    .map(|j| (EF::monomial(i) * EF::monomial(j)).as_base_slice().to_vec())
+                    .collect()
+            })
+            .collect();
+        Self {
+            one: EF::one().as_base_slice().to_vec(),
+            products,
+        }
+    }
+
+    /// Returns the coordinates of a base field element.
+    pub(crate) fn embed<E: AbstractField + From<F>>(&self, e: E) -> Vec<E> {
+        self.one.iter().map(|c| scale(e.clone(), *c)).collect()
+    }
+
+    /// Returns the coordinates of the product of two extension field elements.
+    pub(crate) fn mul<E: AbstractField + From<F>>(&self, a: &[E], b: &[E]) -> Vec<E> {
+        let mut product = vec![E::zero(); self.one.len()];
+        for (a, products) in a.iter().zip(&self.products) {
+            for (b, products) in b.iter().zip(products) {
+                for (product, c) in product.iter_mut().zip(products) {
+                    *product += scale(a.clone() * b.clone(), *c);
+                }
+            }
+        }
+        product
+    }
+}
+
+/// Multiplies the expression with a constant, skipping the common cases of zero and one.
+fn scale<F: Field, E: AbstractField + From<F>>(e: E, c: F) -> E {
+    if c == F::zero() {
+        E::zero()
+    } else if c == F::one() {
+        e
+    } else {
+        e * E::from(c)
+    }
+}
+
+/// The number of coordinates of an extension field element.
+fn extension_degree<T: FieldElementMap>() -> usize
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    <Challenge<T> as AbstractExtensionField<Plonky3Field<T>>>::D
+}
+
+/// Splits a column of extension field elements into a column per coordinate.
+fn coordinate_columns<T: FieldElementMap>(values: &[Challenge<T>]) -> Vec<Vec<T>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    (0..extension_degree::<T>())
+        .map(|i| {
+            values
+                .iter()
+                .map(|value| T::from_p3_field(value.as_base_slice()[i]))
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns an error if the expression cannot be evaluated when the multiplicities
+/// and the helper columns are computed, i.e. if it references a challenge, a
+/// public value or a witness column of a later stage, or raises to a power that
+/// is not a number.
+fn check_expression<T>(
+    id: u64,
+    e: &AlgebraicExpression<T>,
+    intermediates: &BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+    witness_columns: &BTreeMap<PolyID, (usize, usize)>,
+) -> Result<(), String> {
+    e.all_children().try_for_each(|e| match e {
+        AlgebraicExpression::Reference(r) => match r.poly_id.ptype {
+            PolynomialType::Committed if witness_columns[&r.poly_id].0 > 0 => Err(format!(
+                "Lookup or permutation {id} references {}, which is not a first-stage column",
+                r.name
+            )),
+            PolynomialType::Intermediate => check_expression(
+                id,
+                &intermediates[&r.to_thin()],
+                intermediates,
+                witness_columns,
+            ),
+            _ => Ok(()),
+        },
+        AlgebraicExpression::PublicReference(name) => Err(format!(
+            "Lookup or permutation {id} references the public {name}, which is not supported"
+        )),
+        AlgebraicExpression::Challenge(_) => Err(format!(
+            "Lookup or permutation {id} references a challenge, which is not supported"
+        )),
+        AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation {
+            op: AlgebraicBinaryOperator::Pow,
+            right,
+            ..
+        }) if !matches!(**right, AlgebraicExpression::Number(_)) => Err(format!(
+            "Lookup or permutation {id} has a power with a non-constant exponent, which is not supported"
+        )),
+        _ => Ok(()),
+    })
+}
+
+/// The name of a multiplicity column added to the first stage of a table.
+pub(crate) fn multiplicity_column_name(table: &str, index: usize) -> String {
+    format!("{table}::__logup_multiplicity_{index}")
+}
+
+/// The name of a column added to the second stage of a table.
+pub(crate) fn helper_column_name(table: &str, index: usize) -> String {
+    format!("{table}::__logup_helper_{index}")
+}
+
+fn height<T>(columns: &BTreeMap<PolyID, &[T]>) -> usize {
+    columns.values().next().map(|v| v.len()).unwrap()
+}
+
+/// Evaluates expressions on the rows of a trace.
+struct Evaluator<'a, T> {
+    columns: &'a BTreeMap<PolyID, &'a [T]>,
+    intermediates: &'a BTreeMap<AlgebraicReferenceThin, AlgebraicExpression<T>>,
+}
+
+impl<'a, T: FieldElement> Evaluator<'a, T> {
+    fn evaluate(&self, e: &AlgebraicExpression<T>, row: usize) -> T {
+        match e {
+            AlgebraicExpression::Reference(r) => match r.poly_id.ptype {
+                PolynomialType::Committed | PolynomialType::Constant => {
+                    let column = self.columns[&r.poly_id];
+                    column[(row + r.next as usize) % column.len()]
+                }
+                PolynomialType::Intermediate => {
+                    self.evaluate(&self.intermediates[&r.to_thin()], row)
+                }
+            },
+            AlgebraicExpression::Number(n) => *n,
+            AlgebraicExpression::BinaryOperation(AlgebraicBinaryOperation { left, op, right }) => {
+                let left = self.evaluate(left, row);
+                match op {
+                    AlgebraicBinaryOperator::Add => left + self.evaluate(right, row),
+                    AlgebraicBinaryOperator::Sub => left - self.evaluate(right, row),
+                    AlgebraicBinaryOperator::Mul => left * self.evaluate(right, row),
+                    AlgebraicBinaryOperator::Pow => match **right {
+                        AlgebraicExpression::Number(n) => left.pow(n.to_integer()),
+                        _ => unreachable!("rejected when the constraint system is built"),
+                    },
+                }
+            }
+            AlgebraicExpression::UnaryOperation(AlgebraicUnaryOperation { op, expr }) => match op {
+                AlgebraicUnaryOperator::Minus => -self.evaluate(expr, row),
+            },
+            AlgebraicExpression::PublicReference(_) | AlgebraicExpression::Challenge(_) => {
+                unreachable!("rejected when the constraint system is built")
+            }
+        }
+    }
+}
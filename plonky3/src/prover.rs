@@ -15,7 +15,7 @@ use p3_maybe_rayon::prelude::*;
 use p3_util::log2_strict_usize;
 use tracing::{info_span, instrument};
 
-use crate::circuit_builder::{generate_matrix, PowdrCircuit, PowdrTable};
+use crate::circuit_builder::{challenge_ids, generate_matrix, PowdrCircuit, PowdrTable};
 use crate::params::{Challenge, Challenger, Pcs};
 use crate::proof::{OpenedValues, StageOpenedValues};
 use crate::symbolic_builder::{
@@ -396,6 +396,10 @@ where
 /// Prove a program execution.
 /// Note that `witness_by_machine` might not have all the machines, empty ones are expected
 /// to be removed already.
+/// Returns the proof and, for each stage, the challenges drawn after it by their IDs.
+/// These differ from the challenges the verifier derives if the witgen callback shares
+/// them with other proofs.
+/// Fails if a lookup is not satisfied by the witness.
 #[instrument(skip_all)]
 #[allow(clippy::multiple_bound_locations)] // cfg not supported in where clauses?
 pub fn prove<T: FieldElementMap>(
@@ -403,11 +407,13 @@ pub fn prove<T: FieldElementMap>(
     program: &PowdrCircuit<T>,
     witness_by_machine: &mut BTreeMap<String, Vec<(String, Vec<T>)>>,
     challenger: &mut Challenger<T>,
-) -> Proof<T::Config>
+) -> Result<(Proof<T::Config>, Vec<BTreeMap<u64, T>>), String>
 where
     ProverData<T>: Send,
     Commitment<T>: Send,
 {
+    program.add_lookup_multiplicities(witness_by_machine)?;
+
     let (tables, stage_0): (BTreeMap<_, _>, BTreeMap<_, _>) = witness_by_machine
         .iter()
         .map(|(name, columns)| {
@@ -499,8 +505,12 @@ where
     for stage_id in 1..stage_count {
         // get the challenges drawn at the end of the previous stage
         let local_challenges = &state.processed_stages.last().unwrap().challenge_values;
-        let CallbackResult { air_stages } =
-            program.compute_stage(stage_id, local_challenges, witness_by_machine);
+        let CallbackResult {
+            air_stages,
+            challenge_values,
+        } = program.compute_stage(stage_id, local_challenges, witness_by_machine);
+        // the quotient is computed with the challenges the stage was computed with
+        state.processed_stages.last_mut().unwrap().challenge_values = challenge_values;
 
         assert_eq!(air_stages.len(), multi_table.table_count());
 
@@ -521,6 +531,23 @@ where
     // sanity check that we processed as many stages as expected
     assert_eq!(state.processed_stages.len() as u8, stage_count);
 
+    let challenges = state
+        .processed_stages
+        .iter()
+        .enumerate()
+        .map(|(stage, processed_stage)| {
+            challenge_ids(program.split.values().map(|(_, c)| c), stage)
+                .into_iter()
+                .zip_eq(
+                    processed_stage
+                        .challenge_values
+                        .iter()
+                        .map(|value| T::from_p3_field(*value)),
+                )
+                .collect()
+        })
+        .collect();
+
     let (quotient_commit, quotient_data) =
         multi_table.compute_and_commit_to_quotient(&mut state, proving_key);
 
@@ -535,11 +562,12 @@ where
 
     let (opened_values, opening_proof) = multi_table.open(&mut state, proving_key, quotient_data);
 
-    Proof {
+    let proof = Proof {
         commitments,
         opened_values,
         opening_proof,
-    }
+    };
+    Ok((proof, challenges))
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -739,4 +767,6 @@ pub struct Stage<F> {
 pub struct CallbackResult<T> {
     /// the next stage for each air
     pub(crate) air_stages: BTreeMap<String, AirStage<T>>,
+    /// the challenges the next stage was computed with
+    pub(crate) challenge_values: Vec<T>,
 }
@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
@@ -13,12 +14,12 @@ use p3_matrix::dense::RowMajorMatrixView;
 use p3_matrix::stack::VerticalPair;
 use tracing::instrument;
 
-use crate::circuit_builder::PowdrTable;
+use crate::circuit_builder::{challenge_ids, PowdrTable};
 use crate::params::{Challenge, Challenger, Commitment, Pcs, ProverData};
 use crate::symbolic_builder::{get_log_quotient_degree, SymbolicAirBuilder};
 use crate::{
-    ConstraintSystem, FieldElementMap, MultiStageAir, Proof, StageOpenedValues, StarkVerifyingKey,
-    TableOpenedValues, TableVerifyingKeyCollection, VerifierConstraintFolder,
+    Com, Commitments, ConstraintSystem, FieldElementMap, MultiStageAir, Proof, StageOpenedValues,
+    StarkVerifyingKey, TableOpenedValues, TableVerifyingKeyCollection, VerifierConstraintFolder,
 };
 use p3_uni_stark::{Domain, PcsError, StarkGenericConfig, Val};
 
@@ -82,144 +83,89 @@ where
     ProverData<T>: Send,
     Commitment<T>: Send,
 {
-    let public_inputs = public_inputs
-        .into_iter()
-        .map(|(name, values)| {
-            (
-                name,
-                values
-                    .into_iter()
-                    .map(|values| values.into_iter().map(|v| v.into_p3_field()).collect_vec())
-                    .collect_vec(),
-            )
-        })
-        .collect::<BTreeMap<_, _>>();
+    verify_inner(verifying_key, split, challenger, proof, public_inputs, None)
+}
+
+/// Like [verify], but the constraints are evaluated with the given challenges for
+/// each stage, by their IDs, instead of the ones drawn from the proof. This is used
+/// when the challenges are shared with other proofs, in which case the caller must
+/// check that they were derived from the commitments of all of these proofs.
+#[instrument(skip_all)]
+pub fn verify_with_challenges<T: FieldElementMap>(
+    verifying_key: Option<&StarkVerifyingKey<T::Config>>,
+    split: &BTreeMap<&String, &ConstraintSystem<T>>,
+    challenger: &mut Challenger<T>,
+    proof: &Proof<T::Config>,
+    // Machine name -> (stage -> public values)
+    public_inputs: BTreeMap<String, Vec<Vec<T>>>,
+    challenges: &[BTreeMap<u64, T>],
+) -> Result<(), VerificationError<PcsError<T::Config>>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    verify_inner(
+        verifying_key,
+        split,
+        challenger,
+        proof,
+        public_inputs,
+        Some(challenges),
+    )
+}
+
+fn verify_inner<T: FieldElementMap>(
+    verifying_key: Option<&StarkVerifyingKey<T::Config>>,
+    split: &BTreeMap<&String, &ConstraintSystem<T>>,
+    challenger: &mut Challenger<T>,
+    proof: &Proof<T::Config>,
+    public_inputs: BTreeMap<String, Vec<Vec<T>>>,
+    challenges: Option<&[BTreeMap<u64, T>]>,
+) -> Result<(), VerificationError<PcsError<T::Config>>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    let public_inputs = into_p3_public_inputs(public_inputs);
+    let tables = tables(verifying_key, split, proof, &public_inputs)?;
 
     let Proof {
         commitments,
-        opened_values,
         opening_proof,
+        ..
     } = proof;
 
-    // Filters out machines that are not included in the proof.
-    // With a sound bus argument, the prover can only do this if they don't interact
-    // with the bus, i.e., are empty.
-    let split = split
-        .iter()
-        .filter_map(|(k, v)| opened_values.contains_key(*k).then_some((*k, v)))
-        .collect::<BTreeMap<_, _>>();
-    let public_inputs = public_inputs
-        .iter()
-        .filter_map(|(k, v)| {
-            if opened_values.contains_key(k) {
-                Some((k, v))
-            } else {
-                for stage_publics in v {
-                    // TODO: This will fail once we expose the accumulators as publics...
-                    // If we machine is removed, we want to use an accumulator value of 0.
-                    assert!(stage_publics.is_empty());
-                }
-                None
-            }
-        })
-        .collect::<BTreeMap<_, _>>();
-
-    // sanity check that the two maps have the same keys
-    itertools::assert_equal(split.keys(), public_inputs.keys());
-
-    // error out if the opened values do not have the same keys as the tables
-    if !itertools::equal(split.keys().cloned(), opened_values.keys()) {
-        return Err(VerificationError::InvalidProofShape(
-            "Opened values do not have the same keys as the tables".to_string(),
-        ));
-    }
-
-    let tables: BTreeMap<&String, Table<_>> = split
-        .values()
-        .zip_eq(public_inputs.iter())
-        .zip_eq(opened_values.values())
-        .map(
-            |((constraints, (name, public_values_by_stage)), opened_values)| {
-                (
-                    *name,
-                    Table {
-                        air: PowdrTable::new(constraints),
-                        opened_values,
-                        public_values_by_stage,
-                        preprocessed: verifying_key
-                            .as_ref()
-                            .and_then(|vk| vk.preprocessed.get(*name)),
-                    },
-                )
-            },
-        )
-        .collect();
-
     let config = T::get_config();
 
     let pcs = config.pcs();
 
-    // TODO: Instead of hashing each commit separately, we could hash a summary of all the commitments,
-    // like a hash that is precomputed at setup phase.
-    for table in tables.values() {
-        if let Some(preprocessed_commit) = table.preprocessed_commit() {
-            challenger.observe(preprocessed_commit.clone());
-        }
-    }
-
-    // Observe the instances.
-    for table in tables.values() {
-        challenger.observe(Val::<T::Config>::from_canonical_usize(
-            table.opened_values.log_degree,
-        ));
+    let mut challenges_by_stage = observe_stages(&tables, challenger, commitments);
+    if let Some(challenges) = challenges {
+        challenges_by_stage = challenges_by_stage
+            .into_iter()
+            .enumerate()
+            .map(|(stage, drawn)| {
+                let ids = challenge_ids(split.values().copied(), stage);
+                if ids.is_empty() {
+                    return Ok(drawn);
+                }
+                ids.iter()
+                    .map(|id| {
+                        challenges
+                            .get(stage)
+                            .and_then(|challenges| challenges.get(id))
+                            .map(|value| value.into_p3_field())
+                            .ok_or_else(|| {
+                                VerificationError::InvalidProofShape(format!(
+                                    "Missing challenge {id} of stage {stage}"
+                                ))
+                            })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
     }
-    // TODO: Might be best practice to include other instance data here in the transcript, like some
-    // encoding of the AIR. This protects against transcript collisions between distinct instances.
-    // Practically speaking though, the only related known attack is from failing to include public
-    // values. It's not clear if failing to include other instance data could enable a transcript
-    // collision, since most such changes would completely change the set of satisfying witnesses.
-
-    let stage_count = tables
-        .values()
-        .map(|i| &i.air)
-        .map(<_ as MultiStageAir<SymbolicAirBuilder<_>>>::stage_count)
-        .max()
-        .unwrap();
-
-    let challenge_count_by_stage: Vec<usize> = (0..stage_count)
-        .map(|stage_id| {
-            tables
-                .values()
-                .map(|table| {
-                    <_ as MultiStageAir<SymbolicAirBuilder<_>>>::stage_challenge_count(
-                        &table.air, stage_id,
-                    )
-                })
-                .max()
-                .unwrap()
-        })
-        .collect();
-
-    let challenges_by_stage = commitments
-        .traces_by_stage
-        .iter()
-        .zip_eq((0..stage_count).map(|i| {
-            tables
-                .values()
-                .map(|table| &table.public_values_by_stage[i as usize])
-                .collect_vec()
-        }))
-        .zip_eq(challenge_count_by_stage)
-        .map(|((commitment, public_values_by_stage), challenge_count)| {
-            challenger.observe(commitment.clone());
-            for public_values in &public_values_by_stage {
-                challenger.observe_slice(public_values);
-            }
-            (0..challenge_count)
-                .map(|_| challenger.sample())
-                .collect_vec()
-        })
-        .collect_vec();
+    let stage_count = challenges_by_stage.len();
 
     let alpha: Challenge<T> = challenger.sample_ext_element();
     challenger.observe(commitments.quotient_chunks.clone());
@@ -258,7 +204,7 @@ where
         .commitments
         .traces_by_stage
         .iter()
-        .zip_eq((0..stage_count as usize).map(|i| {
+        .zip_eq((0..stage_count).map(|i| {
             tables
                 .values()
                 .map(|table| &table.opened_values.traces_by_stage[i])
@@ -395,6 +341,197 @@ where
     Ok(())
 }
 
+/// Derives the challenges drawn after each stage of the proof by their IDs, in the
+/// same way the verifier does. The proof itself is not verified.
+pub fn stage_challenges<T: FieldElementMap>(
+    verifying_key: Option<&StarkVerifyingKey<T::Config>>,
+    split: &BTreeMap<&String, &ConstraintSystem<T>>,
+    challenger: &mut Challenger<T>,
+    proof: &Proof<T::Config>,
+    // Machine name -> (stage -> public values)
+    public_inputs: BTreeMap<String, Vec<Vec<T>>>,
+) -> Result<Vec<BTreeMap<u64, T>>, VerificationError<PcsError<T::Config>>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    let public_inputs = into_p3_public_inputs(public_inputs);
+    let tables = tables(verifying_key, split, proof, &public_inputs)?;
+    Ok(observe_stages(&tables, challenger, &proof.commitments)
+        .into_iter()
+        .enumerate()
+        .map(|(stage, values)| {
+            challenge_ids(split.values().copied(), stage)
+                .into_iter()
+                .zip_eq(values.into_iter().map(T::from_p3_field))
+                .collect()
+        })
+        .collect())
+}
+
+fn into_p3_public_inputs<T: FieldElementMap>(
+    public_inputs: BTreeMap<String, Vec<Vec<T>>>,
+) -> BTreeMap<String, Vec<Vec<Val<T::Config>>>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    public_inputs
+        .into_iter()
+        .map(|(name, values)| {
+            (
+                name,
+                values
+                    .into_iter()
+                    .map(|values| values.into_iter().map(|v| v.into_p3_field()).collect_vec())
+                    .collect_vec(),
+            )
+        })
+        .collect()
+}
+
+/// Builds the tables of the machines included in the proof.
+fn tables<'a, T: FieldElementMap>(
+    verifying_key: Option<&'a StarkVerifyingKey<T::Config>>,
+    split: &BTreeMap<&'a String, &'a ConstraintSystem<T>>,
+    proof: &'a Proof<T::Config>,
+    public_inputs: &'a BTreeMap<String, Vec<Vec<Val<T::Config>>>>,
+) -> Result<BTreeMap<&'a String, Table<'a, T>>, VerificationError<PcsError<T::Config>>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    let opened_values = &proof.opened_values;
+
+    // Filters out machines that are not included in the proof.
+    // With a sound bus argument, the prover can only do this if they don't interact
+    // with the bus, i.e., are empty.
+    let split = split
+        .iter()
+        .filter_map(|(k, v)| opened_values.contains_key(*k).then_some((*k, *v)))
+        .collect::<BTreeMap<_, _>>();
+    let public_inputs = public_inputs
+        .iter()
+        .filter_map(|(k, v)| {
+            if opened_values.contains_key(k) {
+                Some((k, v))
+            } else {
+                for stage_publics in v {
+                    // TODO: This will fail once we expose the accumulators as publics...
+                    // If we machine is removed, we want to use an accumulator value of 0.
+                    assert!(stage_publics.is_empty());
+                }
+                None
+            }
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    // sanity check that the two maps have the same keys
+    itertools::assert_equal(split.keys(), public_inputs.keys());
+
+    // error out if the opened values do not have the same keys as the tables
+    if !itertools::equal(split.keys().cloned(), opened_values.keys()) {
+        return Err(VerificationError::InvalidProofShape(
+            "Opened values do not have the same keys as the tables".to_string(),
+        ));
+    }
+
+    Ok(split
+        .into_values()
+        .zip_eq(public_inputs)
+        .zip_eq(opened_values.values())
+        .map(
+            |((constraints, (name, public_values_by_stage)), opened_values)| {
+                (
+                    name,
+                    Table {
+                        air: PowdrTable::new(constraints),
+                        opened_values,
+                        public_values_by_stage,
+                        preprocessed: verifying_key
+                            .as_ref()
+                            .and_then(|vk| vk.preprocessed.get(name)),
+                    },
+                )
+            },
+        )
+        .collect())
+}
+
+/// Observes the preprocessed commitments, the instances and the trace commitments
+/// and public values of each stage, returning the challenges drawn after each stage.
+fn observe_stages<T: FieldElementMap>(
+    tables: &BTreeMap<&String, Table<'_, T>>,
+    challenger: &mut Challenger<T>,
+    commitments: &Commitments<Com<T::Config>>,
+) -> Vec<Vec<Val<T::Config>>>
+where
+    ProverData<T>: Send,
+    Commitment<T>: Send,
+{
+    // TODO: Instead of hashing each commit separately, we could hash a summary of all the commitments,
+    // like a hash that is precomputed at setup phase.
+    for table in tables.values() {
+        if let Some(preprocessed_commit) = table.preprocessed_commit() {
+            challenger.observe(preprocessed_commit.clone());
+        }
+    }
+
+    // Observe the instances.
+    for table in tables.values() {
+        challenger.observe(Val::<T::Config>::from_canonical_usize(
+            table.opened_values.log_degree,
+        ));
+    }
+    // TODO: Might be best practice to include other instance data here in the transcript, like some
+    // encoding of the AIR. This protects against transcript collisions between distinct instances.
+    // Practically speaking though, the only related known attack is from failing to include public
+    // values. It's not clear if failing to include other instance data could enable a transcript
+    // collision, since most such changes would completely change the set of satisfying witnesses.
+
+    let stage_count = tables
+        .values()
+        .map(|i| &i.air)
+        .map(<_ as MultiStageAir<SymbolicAirBuilder<_>>>::stage_count)
+        .max()
+        .unwrap();
+
+    let challenge_count_by_stage: Vec<usize> = (0..stage_count)
+        .map(|stage_id| {
+            tables
+                .values()
+                .map(|table| {
+                    <_ as MultiStageAir<SymbolicAirBuilder<_>>>::stage_challenge_count(
+                        &table.air, stage_id,
+                    )
+                })
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    commitments
+        .traces_by_stage
+        .iter()
+        .zip_eq((0..stage_count).map(|i| {
+            tables
+                .values()
+                .map(|table| &table.public_values_by_stage[i as usize])
+                .collect_vec()
+        }))
+        .zip_eq(challenge_count_by_stage)
+        .map(|((commitment, public_values_by_stage), challenge_count)| {
+            challenger.observe(commitment.clone());
+            for public_values in &public_values_by_stage {
+                challenger.observe_slice(public_values);
+            }
+            (0..challenge_count)
+                .map(|_| challenger.sample())
+                .collect_vec()
+        })
+        .collect_vec()
+}
+
 fn verify_opening_shape<T: FieldElementMap>(
     table: &Table<'_, T>,
 ) -> Result<(), VerificationError<PcsError<T::Config>>>
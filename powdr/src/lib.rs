@@ -13,26 +13,45 @@ pub use powdr_pipeline::Pipeline;
 
 pub use powdr_number::Bn254Field;
 pub use powdr_number::GoldilocksField;
-pub use powdr_number::{FieldElement, LargeInt};
+pub use powdr_number::{BabyBearField, KoalaBearField, Mersenne31Field};
+pub use powdr_number::{FieldElement, KnownField, LargeInt};
 
+use powdr_number::FieldSize;
 use riscv::{CompilerOptions, RuntimeLibs};
 
 use std::fs::{self, File};
+use std::marker::PhantomData;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Instant;
 
-#[derive(Default)]
-pub struct SessionBuilder {
+/// Builds a [Session] over the field `F`. The field can be changed using
+/// [SessionBuilder::with_field].
+pub struct SessionBuilder<F: FieldElement = GoldilocksField> {
     guest_path: String,
     out_path: String,
     asm_file: Option<String>,
     chunk_size_log2: Option<u8>,
     precompiles: RuntimeLibs,
+    _field: PhantomData<F>,
 }
 
-pub struct Session {
-    pipeline: Pipeline<GoldilocksField>,
+impl<F: FieldElement> Default for SessionBuilder<F> {
+    fn default() -> Self {
+        Self {
+            guest_path: Default::default(),
+            out_path: Default::default(),
+            asm_file: None,
+            chunk_size_log2: None,
+            precompiles: RuntimeLibs::new(),
+            _field: PhantomData,
+        }
+    }
+}
+
+pub struct Session<F: FieldElement = GoldilocksField> {
+    pipeline: Pipeline<F>,
     out_path: String,
 }
 
@@ -45,11 +64,11 @@ const DEFAULT_MAX_DEGREE_LOG: u8 = 20;
 // Minimum acceptable max degree.
 const DEFAULT_MIN_MAX_DEGREE_LOG: u8 = 18;
 
-impl SessionBuilder {
+impl<F: FieldElement> SessionBuilder<F> {
     /// Builds a session with the given parameters.
-    pub fn build(self) -> Session {
+    pub fn build(self) -> Session<F> {
         let pipeline = match self.asm_file {
-            Some(asm_file) => Pipeline::<GoldilocksField>::default()
+            Some(asm_file) => Pipeline::<F>::default()
                 .from_asm_file(asm_file.into())
                 .with_output(Path::new(&self.out_path).to_path_buf(), true),
             None => pipeline_from_guest(
@@ -64,7 +83,22 @@ impl SessionBuilder {
             pipeline,
             out_path: self.out_path,
         }
-        .with_backend(powdr_backend::BackendType::Plonky3)
+        .with_backend(default_backend(F::known_field().unwrap()))
+    }
+
+    /// Sets the field the guest program is compiled for and proven in.
+    /// The default is the Goldilocks field; for the fields smaller than 32 bits
+    /// (BabyBear, KoalaBear and Mersenne31), precompiles and continuations are
+    /// not supported yet, so the whole execution is proven in a single chunk.
+    pub fn with_field<G: FieldElement>(self) -> SessionBuilder<G> {
+        SessionBuilder {
+            guest_path: self.guest_path,
+            out_path: self.out_path,
+            asm_file: self.asm_file,
+            chunk_size_log2: self.chunk_size_log2,
+            precompiles: self.precompiles,
+            _field: PhantomData,
+        }
     }
 
     /// Sets the path to the guest program.
@@ -103,18 +137,19 @@ impl SessionBuilder {
 }
 
 impl Session {
+    /// Returns a builder for a session over the Goldilocks field.
+    /// Use [SessionBuilder::with_field] to choose a different field.
     pub fn builder() -> SessionBuilder {
-        SessionBuilder {
-            precompiles: RuntimeLibs::new(),
-            ..SessionBuilder::default()
-        }
+        SessionBuilder::default()
     }
+}
 
-    pub fn into_pipeline(self) -> Pipeline<GoldilocksField> {
+impl<F: FieldElement> Session<F> {
+    pub fn into_pipeline(self) -> Pipeline<F> {
         self.pipeline
     }
 
-    pub fn pipeline(&self) -> &Pipeline<GoldilocksField> {
+    pub fn pipeline(&self) -> &Pipeline<F> {
         &self.pipeline
     }
 
@@ -132,6 +167,32 @@ impl Session {
         }
     }
 
+    /// Serves the guest's reads from `channel` on demand: each value the guest
+    /// writes to the channel is passed to `handler`, and the returned value is
+    /// what the guest reads next, e.g.
+    /// `powdr_riscv_runtime::io::write(fd, key); let value = io::read(fd);`.
+    /// The guest is executed several times, and later executions replay the
+    /// responses of the first one, so the handler is invoked once per request.
+    pub fn with_channel_handler<Req, Resp, H>(self, channel: u32, handler: H) -> Self
+    where
+        Req: serde::de::DeserializeOwned,
+        Resp: serde::Serialize,
+        H: Fn(Req) -> Resp + Send + Sync + 'static,
+    {
+        let handler = move |request: &[u8]| {
+            let request = serde_cbor::from_slice(request).unwrap_or_else(|e| {
+                panic!("Error deserializing request on channel {channel}: {e}")
+            });
+            serde_cbor::to_vec(&handler(request)).unwrap()
+        };
+        Session {
+            pipeline: self
+                .pipeline
+                .add_channel_handler(channel, Arc::new(handler)),
+            ..self
+        }
+    }
+
     pub fn run(&mut self) {
         run(&mut self.pipeline);
     }
@@ -205,6 +266,9 @@ impl Session {
         self.pipeline.export_verification_key(file).unwrap();
     }
 
+    /// Returns the eight 32-bit words committed to by the guest.
+    /// In fields smaller than 32 bits, each word is exposed as two public
+    /// values, its lower and its upper 16 bits.
     pub fn publics(&self) -> [u32; 8] {
         let pubs: Vec<u32> = self
             .pipeline
@@ -213,7 +277,19 @@ impl Session {
             .iter()
             .map(|(_, v)| v.unwrap().to_integer().try_into_u32().unwrap())
             .collect();
-        pubs.try_into().expect("There should be exactly 8 publics")
+        let words: Vec<u32> = match F::known_field().unwrap().field_size() {
+            FieldSize::Large => pubs,
+            FieldSize::Small => pubs
+                .chunks(2)
+                .map(|limbs| {
+                    let [low, high] = limbs else {
+                        panic!("Expected an even number of publics");
+                    };
+                    low | (high << 16)
+                })
+                .collect(),
+        };
+        words.try_into().expect("There should be exactly 8 publics")
     }
 
     pub fn stdout<S: serde::de::DeserializeOwned>(&self) -> S {
@@ -240,26 +316,82 @@ pub fn build_guest(
     max_degree_log: u8,
     precompiles: RuntimeLibs,
 ) -> (PathBuf, String) {
-    let options = CompilerOptions::new_gl()
-        .with_runtime_libs(precompiles)
-        .with_continuations()
-        .with_min_degree_log(min_degree_log)
-        .with_max_degree_log(max_degree_log);
+    build_guest_for_field(
+        KnownField::GoldilocksField,
+        guest_path,
+        out_path,
+        min_degree_log,
+        max_degree_log,
+        precompiles,
+    )
+}
+
+/// Builds the guest program for the given field.
+/// Continuations are only used in fields of at least 32 bits.
+pub fn build_guest_for_field(
+    field: KnownField,
+    guest_path: &str,
+    out_path: &Path,
+    min_degree_log: u8,
+    max_degree_log: u8,
+    precompiles: RuntimeLibs,
+) -> (PathBuf, String) {
+    let options = CompilerOptions::new(
+        field,
+        runtime_libs(field, precompiles),
+        uses_continuations(field),
+    )
+    .with_min_degree_log(min_degree_log)
+    .with_max_degree_log(max_degree_log);
     riscv::compile_rust(guest_path, options, out_path, true, None)
         .ok_or_else(|| vec!["could not compile rust".to_string()])
         .unwrap()
 }
 
-pub fn pipeline_from_guest(
+/// Returns true if guest programs in the given field are executed with
+/// continuations. The bootloader is not available in fields smaller than
+/// 32 bits yet.
+fn uses_continuations(field: KnownField) -> bool {
+    matches!(field.field_size(), FieldSize::Large)
+}
+
+/// Returns the default backend for proving in the given field.
+fn default_backend(field: KnownField) -> backend::BackendType {
+    match field {
+        KnownField::Bn254Field => backend::BackendType::Halo2,
+        KnownField::GoldilocksField
+        | KnownField::BabyBearField
+        | KnownField::KoalaBearField
+        | KnownField::Mersenne31Field => backend::BackendType::Plonky3,
+    }
+}
+
+/// Returns the runtime libraries to use for the given field.
+/// Precompiles are not available in fields smaller than 32 bits yet.
+fn runtime_libs(field: KnownField, precompiles: RuntimeLibs) -> RuntimeLibs {
+    match field.field_size() {
+        FieldSize::Large => precompiles,
+        FieldSize::Small => {
+            assert!(
+                !(precompiles.arith || precompiles.keccak || precompiles.poseidon2),
+                "Precompiles are not supported in the {field} field yet."
+            );
+            RuntimeLibs::new()
+        }
+    }
+}
+
+pub fn pipeline_from_guest<F: FieldElement>(
     guest_path: &str,
     out_path: &Path,
     min_degree_log: u8,
     max_degree_log: u8,
     precompiles: RuntimeLibs,
-) -> Pipeline<GoldilocksField> {
+) -> Pipeline<F> {
     println!("Compiling guest program...");
 
-    let (asm_file_path, asm_contents) = build_guest(
+    let (asm_file_path, asm_contents) = build_guest_for_field(
+        F::known_field().unwrap(),
         guest_path,
         out_path,
         min_degree_log,
@@ -268,12 +400,23 @@ pub fn pipeline_from_guest(
     );
 
     // Create a pipeline from the asm program
-    Pipeline::<GoldilocksField>::default()
+    Pipeline::<F>::default()
         .from_asm_string(asm_contents.clone(), Some(asm_file_path.clone()))
         .with_output(out_path.into(), true)
 }
 
-pub fn run(pipeline: &mut Pipeline<GoldilocksField>) {
+pub fn run<F: FieldElement>(pipeline: &mut Pipeline<F>) {
+    if !uses_continuations(F::known_field().unwrap()) {
+        // The fast executor is not available in small fields yet,
+        // so the execution is checked via witness generation.
+        println!("Running witness generation...");
+        let start = Instant::now();
+        pipeline.compute_witness().unwrap();
+        let duration = start.elapsed();
+        println!("Witness generation took: {duration:?}");
+        return;
+    }
+
     println!("Running powdr-riscv executor in fast mode...");
     let start = Instant::now();
 
@@ -285,6 +428,7 @@ pub fn run(pipeline: &mut Pipeline<GoldilocksField>) {
         pipeline.data_callback().unwrap(),
         &riscv::continuations::bootloader::default_input(&[]),
         None,
+        None,
     );
 
     let duration = start.elapsed();
@@ -292,7 +436,22 @@ pub fn run(pipeline: &mut Pipeline<GoldilocksField>) {
     println!("Trace length: {trace_len}");
 }
 
-pub fn prove(pipeline: &mut Pipeline<GoldilocksField>) {
+pub fn prove<F: FieldElement>(pipeline: &mut Pipeline<F>) {
+    if !uses_continuations(F::known_field().unwrap()) {
+        let start = Instant::now();
+        log::info!("Generating witness...");
+        pipeline.compute_witness().unwrap();
+        let duration = start.elapsed();
+        log::info!("Generating witness took: {duration:?}");
+
+        println!("Generating proof...");
+        let start = Instant::now();
+        pipeline.compute_proof().unwrap();
+        let duration = start.elapsed();
+        println!("Proof generation took: {duration:?}");
+        return;
+    }
+
     log::info!("Running powdr-riscv executor in trace mode for continuations...");
     let start = Instant::now();
 
@@ -305,7 +464,7 @@ pub fn prove(pipeline: &mut Pipeline<GoldilocksField>) {
     // TODO how do we skip PIL compilation and fixed column generation if not needed?
     // We can check whether they exist and not generate it, but what if the asm changed?
     // Maybe one solution is to at least compile asm to PIL and see if that changed.
-    let generate_proof = |pipeline: &mut Pipeline<GoldilocksField>| -> Result<(), Vec<String>> {
+    let generate_proof = |pipeline: &mut Pipeline<F>| -> Result<(), Vec<String>> {
         let start = Instant::now();
         log::info!("Generating witness...");
         pipeline.compute_witness()?;
@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use powdr::{BabyBearField, Session};
+
+#[test]
+#[ignore = "Too slow"]
+fn babybear_session() {
+    let out_dir = mktemp::Temp::new_dir().unwrap();
+    let mut session = Session::builder()
+        .with_field::<BabyBearField>()
+        .guest_path("../riscv/tests/riscv_data/trivial")
+        .out_path(out_dir.to_str().unwrap())
+        .build();
+
+    session.run();
+    session.prove();
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn channel_handler_session() {
+    let out_dir = mktemp::Temp::new_dir().unwrap();
+    let mut session = Session::builder()
+        .guest_path("../riscv/tests/riscv_data/channel_handler")
+        .out_path(out_dir.to_str().unwrap())
+        .build()
+        .with_channel_handler(5, |n: u32| n * n);
+
+    // The handler is queried by the executor, and again when proving with
+    // continuations, where the guest is executed several times.
+    session.run();
+    session.prove();
+
+    let sum: u32 = session.stdout();
+    assert_eq!(sum, 1 + 4 + 9);
+}
+
+/// Builds a session over BabyBear from a program that exposes the given
+/// values as public values, in order.
+fn publics_session(values: &[u32], out_dir: &Path) -> Session<BabyBearField> {
+    let values_list = values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let publics = (0..values.len())
+        .map(|i| format!("    public p{i:02} = values({i});\n"))
+        .collect::<String>();
+    let asm = format!(
+        "machine Main with degree: {} {{
+    col fixed VALUES = [{values_list}] + [0]*;
+    col witness values;
+    values = VALUES;
+{publics}}}
+",
+        values.len().next_power_of_two()
+    );
+    let asm_file = out_dir.join("publics.asm");
+    std::fs::write(&asm_file, asm).unwrap();
+
+    Session::builder()
+        .with_field::<BabyBearField>()
+        .asm_file(asm_file.to_str().unwrap())
+        .out_path(out_dir.to_str().unwrap())
+        .build()
+}
+
+#[test]
+fn small_field_publics() {
+    let out_dir = mktemp::Temp::new_dir().unwrap();
+    let words: [u32; 8] = [0x12345678, 0x9abcdef0, 1, 2, 3, 4, 5, 0xffff0000];
+    // each word is exposed as its lower and its upper 16 bits
+    let limbs = words
+        .iter()
+        .flat_map(|w| [w & 0xffff, w >> 16])
+        .collect::<Vec<_>>();
+    let mut session = publics_session(&limbs, &out_dir);
+
+    session.run();
+    assert_eq!(session.publics(), words);
+}
+
+#[test]
+#[should_panic = "Expected an even number of publics"]
+fn small_field_odd_number_of_publics() {
+    let out_dir = mktemp::Temp::new_dir().unwrap();
+    let mut session = publics_session(&[1; 15], &out_dir);
+
+    session.run();
+    session.publics();
+}
@@ -0,0 +1,598 @@
+//! A GDB stub, which allows controlling the execution of a program from GDB
+//! through the remote serial protocol over a local TCP socket.
+//!
+//! The program counter exposed to GDB is the address of the RISC-V instruction
+//! being executed, as recorded by the `.debug insn` directives the translation
+//! from RISC-V emits for every instruction. While the runtime executes a system
+//! call, this is the address of the `ecall`. Programs without these directives,
+//! e.g. written in powdr-asm directly, expose the powdr-asm PC (i.e., the index
+//! of the instruction batch) instead.
+//!
+//! Labels and source locations are also available through `monitor` commands:
+//! `monitor where` shows the current function and source location, and
+//! `monitor break <label>` sets a breakpoint at a label, e.g. the name of a
+//! function.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use powdr_number::FieldElement;
+
+use crate::{builder::TraceBuilder, Elem};
+
+/// The registers exposed to GDB, in the order of GDB's RV32 register file:
+/// x0 to x31, followed by the PC.
+const REGISTER_COUNT: u32 = 33;
+const PC_REGISTER: u32 = 32;
+
+/// The number of instructions executed between two checks for an interrupt
+/// request (Ctrl-C) from GDB.
+const INTERRUPT_CHECK_INTERVAL: u32 = 1 << 12;
+
+/// The stop reply for a stopped program: stopped by SIGTRAP.
+const STOP_REPLY: &str = "S05";
+
+/// The state of the executed program, as accessed by the debugger.
+pub(crate) trait Target {
+    /// Reads one of x0 to x31.
+    fn read_register(&mut self, idx: u32) -> u32;
+    /// Writes one of x1 to x31.
+    fn write_register(&mut self, idx: u32, value: u32);
+    /// Reads the memory word at a (4-byte aligned) address.
+    fn read_memory_word(&self, addr: u32) -> u32;
+}
+
+impl<F: FieldElement> Target for TraceBuilder<'_, F> {
+    fn read_register(&mut self, idx: u32) -> u32 {
+        self.get_reg_mem(idx).as_i64_from_lower_bytes() as u32
+    }
+
+    fn write_register(&mut self, idx: u32, value: u32) {
+        self.set_reg_mem(idx, Elem::Binary(value as i64));
+    }
+
+    fn read_memory_word(&self, addr: u32) -> u32 {
+        self.peek_mem(addr)
+    }
+}
+
+/// Listens on the given local port and waits for GDB to connect.
+pub(crate) fn wait_for_connection(port: u16) -> io::Result<TcpStream> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    log::info!("Waiting for GDB to connect on port {port} (\"target remote :{port}\")...");
+    let (stream, peer) = listener.accept()?;
+    log::info!("GDB connected from {peer}.");
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RunState {
+    /// Waiting for commands from GDB.
+    Stopped,
+    /// Stop again before the next instruction.
+    Stepping,
+    /// Run until a breakpoint is hit or GDB interrupts the execution.
+    Running,
+    /// GDB detached or disconnected, run until the end.
+    Detached,
+}
+
+/// The result of handling a packet.
+enum Response {
+    Reply(String),
+    Resume(RunState),
+    Kill,
+}
+
+pub(crate) struct GdbStub<'a> {
+    stream: TcpStream,
+    state: RunState,
+    breakpoints: BTreeSet<u32>,
+    instructions_until_interrupt_check: u32,
+    /// The PC of the instruction that is executed next.
+    pc: u32,
+    /// file number to (dir,name)
+    debug_files: &'a [(&'a str, &'a str)],
+    /// function label to PC
+    function_starts: BTreeMap<u32, &'a str>,
+    /// .debug loc to PC
+    location_starts: BTreeMap<u32, (usize, usize)>,
+    /// label to PC
+    labels: HashMap<&'a str, u32>,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(
+        stream: TcpStream,
+        debug_files: &'a [(&'a str, &'a str)],
+        function_starts: BTreeMap<u32, &'a str>,
+        location_starts: BTreeMap<u32, (usize, usize)>,
+        labels: HashMap<&'a str, u32>,
+    ) -> Self {
+        Self {
+            stream,
+            state: RunState::Stopped,
+            breakpoints: Default::default(),
+            instructions_until_interrupt_check: INTERRUPT_CHECK_INTERVAL,
+            pc: 0,
+            debug_files,
+            function_starts,
+            location_starts,
+            labels,
+        }
+    }
+
+    /// Called before the instruction at `pc` is executed. Blocks while GDB
+    /// keeps the program stopped. Returns false if GDB requested to kill the
+    /// program.
+    pub fn on_instruction(&mut self, pc: u32, target: &mut impl Target) -> bool {
+        self.pc = pc;
+        let stop = match self.state {
+            // Initially, GDB queries the stop reason itself.
+            RunState::Stopped => false,
+            RunState::Stepping => true,
+            RunState::Running => self.breakpoints.contains(&pc) || self.check_interrupt(),
+            RunState::Detached => return true,
+        };
+        if stop {
+            self.state = RunState::Stopped;
+            if let Err(e) = self.send_packet(STOP_REPLY) {
+                self.disconnected(e);
+                return true;
+            }
+        }
+        if self.state != RunState::Stopped {
+            return true;
+        }
+
+        match self.serve(target) {
+            Ok(state) => {
+                self.state = state;
+                true
+            }
+            Err(None) => {
+                self.state = RunState::Detached;
+                false
+            }
+            Err(Some(e)) => {
+                self.disconnected(e);
+                true
+            }
+        }
+    }
+
+    /// Called when the program terminated.
+    pub fn on_exit(&mut self) {
+        if self.state != RunState::Detached {
+            // Exited with status 0.
+            if let Err(e) = self.send_packet("W00") {
+                self.disconnected(e);
+            }
+        }
+    }
+
+    /// Handles packets until GDB resumes the execution. Returns the new state,
+    /// or Err(None) if the program should be killed.
+    fn serve(&mut self, target: &mut impl Target) -> Result<RunState, Option<io::Error>> {
+        loop {
+            let packet = self.receive_packet().map_err(Some)?;
+            match self.handle(&packet, target) {
+                Response::Reply(reply) => self.send_packet(&reply).map_err(Some)?,
+                Response::Resume(state) => return Ok(state),
+                Response::Kill => return Err(None),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str, target: &mut impl Target) -> Response {
+        let reply = |s: &str| Response::Reply(s.to_string());
+
+        if let Some(command) = packet.strip_prefix("qRcmd,") {
+            return match decode_hex(command).and_then(|c| String::from_utf8(c).ok()) {
+                Some(command) => {
+                    let output = self.monitor(command.trim());
+                    match self.send_packet(&format!("O{}", encode_hex(output.as_bytes()))) {
+                        Ok(()) => reply("OK"),
+                        Err(_) => reply("E01"),
+                    }
+                }
+                None => reply("E01"),
+            };
+        }
+        if packet.starts_with("qSupported") {
+            return reply("PacketSize=4000");
+        }
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            // All threads share the same action, since there is only one.
+            return match actions.chars().next() {
+                Some('c' | 'C') => Response::Resume(RunState::Running),
+                Some('s' | 'S') => Response::Resume(RunState::Stepping),
+                _ => reply("E01"),
+            };
+        }
+
+        match packet {
+            "?" => return reply(STOP_REPLY),
+            "qAttached" => return reply("1"),
+            "qC" => return reply("QC1"),
+            "qfThreadInfo" => return reply("m1"),
+            "qsThreadInfo" => return reply("l"),
+            "vCont?" => return reply("vCont;c;C;s;S"),
+            "g" => {
+                let registers = (0..REGISTER_COUNT)
+                    .map(|idx| encode_register(read_register(target, self.pc, idx)))
+                    .collect::<String>();
+                return Response::Reply(registers);
+            }
+            "k" | "vKill;1" => return Response::Kill,
+            "D" | "D;1" => {
+                self.send_packet("OK").ok();
+                return Response::Resume(RunState::Detached);
+            }
+            _ => {}
+        }
+
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let response = match command {
+            // Thread selection and liveness: there is only one thread.
+            "H" | "T" => Some("OK".to_string()),
+            // Resuming at a different address is not supported.
+            "c" if args.is_empty() => return Response::Resume(RunState::Running),
+            "s" if args.is_empty() => return Response::Resume(RunState::Stepping),
+            "G" => write_registers(target, self.pc, args),
+            "p" => u32::from_str_radix(args, 16)
+                .ok()
+                .filter(|idx| *idx < REGISTER_COUNT)
+                .map(|idx| encode_register(read_register(target, self.pc, idx))),
+            "P" => args.split_once('=').and_then(|(idx, value)| {
+                let idx = u32::from_str_radix(idx, 16).ok()?;
+                let value = decode_register(value)?;
+                write_register(target, self.pc, idx, value)
+            }),
+            "m" => parse_address_and_length(args).map(|(addr, len)| {
+                let bytes = (0..len)
+                    .map(|offset| {
+                        let addr = addr.wrapping_add(offset);
+                        let word = target.read_memory_word(addr & !3);
+                        (word >> (8 * (addr & 3))) as u8
+                    })
+                    .collect::<Vec<_>>();
+                encode_hex(&bytes)
+            }),
+            "Z" | "z" => {
+                // Software and hardware breakpoints are the same for us.
+                match args.split_once(',') {
+                    Some(("0" | "1", location)) => {
+                        parse_address_and_length(location).map(|(addr, _kind)| {
+                            if command == "Z" {
+                                self.breakpoints.insert(addr);
+                            } else {
+                                self.breakpoints.remove(&addr);
+                            }
+                            "OK".to_string()
+                        })
+                    }
+                    // Watchpoints are not supported.
+                    _ => return reply(""),
+                }
+            }
+            // Unsupported packets get an empty reply.
+            _ => return reply(""),
+        };
+        Response::Reply(response.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    /// Executes a `monitor` command and returns its output.
+    fn monitor(&mut self, command: &str) -> String {
+        match command.split_once(' ').unwrap_or((command, "")) {
+            ("where", _) => format!("{}\n", self.describe_location(self.pc)),
+            ("break", label) if !label.is_empty() => match self.labels.get(label.trim()) {
+                Some(pc) => {
+                    self.breakpoints.insert(*pc);
+                    format!("Breakpoint at pc {pc:#x}\n")
+                }
+                None => format!("Unknown label: {label}\n"),
+            },
+            _ => "Available commands:\n  \
+                  where          show the current function and source location\n  \
+                  break <label>  set a breakpoint at a label\n"
+                .to_string(),
+        }
+    }
+
+    /// Returns the function and source location of the given pc.
+    fn describe_location(&self, pc: u32) -> String {
+        let function = self
+            .function_starts
+            .range(..=pc)
+            .next_back()
+            .map(|(_, function)| format!(" in {}", rustc_demangle::demangle(function)))
+            .unwrap_or_default();
+        let location = self
+            .location_starts
+            .range(..=pc)
+            .next_back()
+            .map(|(_, (file, line))| {
+                let (dir, file) = self.debug_files[file - 1];
+                format!(" at {dir}/{file}:{line}")
+            })
+            .unwrap_or_default();
+        format!("pc {pc:#x}{function}{location}")
+    }
+
+    /// Returns true if GDB sent an interrupt request since the last check.
+    /// Only actually checks every INTERRUPT_CHECK_INTERVAL calls.
+    fn check_interrupt(&mut self) -> bool {
+        self.instructions_until_interrupt_check -= 1;
+        if self.instructions_until_interrupt_check > 0 {
+            return false;
+        }
+        self.instructions_until_interrupt_check = INTERRUPT_CHECK_INTERVAL;
+
+        let mut byte = [0u8];
+        let result = self
+            .stream
+            .set_nonblocking(true)
+            .and_then(|_| self.stream.read(&mut byte));
+        self.stream.set_nonblocking(false).ok();
+        match result {
+            Ok(0) => {
+                self.disconnected(io::ErrorKind::UnexpectedEof.into());
+                false
+            }
+            Ok(_) => byte[0] == 0x03,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => false,
+            Err(e) => {
+                self.disconnected(e);
+                false
+            }
+        }
+    }
+
+    fn disconnected(&mut self, error: io::Error) {
+        log::warn!("Lost connection to GDB ({error}), continuing without debugger.");
+        self.state = RunState::Detached;
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Receives a packet, acknowledging it if the checksum matches.
+    fn receive_packet(&mut self) -> io::Result<String> {
+        loop {
+            // Skip everything up to the start of a packet, e.g. acknowledgements
+            // or interrupt requests while the program is stopped anyway.
+            while self.read_byte()? != b'$' {}
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(compute_checksum(&data));
+
+            if valid {
+                self.stream.write_all(b"+")?;
+                return Ok(String::from_utf8_lossy(&data).into_owned());
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    /// Sends a packet, retransmitting it until GDB acknowledges it.
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let data = escape(data.as_bytes());
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(format!("#{:02x}", compute_checksum(&data)).as_bytes());
+        loop {
+            self.stream.write_all(&packet)?;
+            match self.read_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+fn read_register(target: &mut impl Target, pc: u32, idx: u32) -> u32 {
+    if idx == PC_REGISTER {
+        pc
+    } else {
+        target.read_register(idx)
+    }
+}
+
+/// Writes a register, returning the reply.
+/// Writes to x0 are ignored, and the PC cannot be changed.
+fn write_register(target: &mut impl Target, pc: u32, idx: u32, value: u32) -> Option<String> {
+    match idx {
+        0 => {}
+        PC_REGISTER if value == pc => {}
+        idx if idx < PC_REGISTER => target.write_register(idx, value),
+        _ => return None,
+    }
+    Some("OK".to_string())
+}
+
+fn write_registers(target: &mut impl Target, pc: u32, values: &str) -> Option<String> {
+    let values = (0..REGISTER_COUNT as usize)
+        .map(|i| decode_register(values.get(8 * i..8 * (i + 1))?))
+        .collect::<Option<Vec<_>>>()?;
+    for (idx, value) in values.into_iter().enumerate() {
+        write_register(target, pc, idx as u32, value)?;
+    }
+    Some("OK".to_string())
+}
+
+/// Parses "<addr>,<length>" with both numbers in hex.
+fn parse_address_and_length(args: &str) -> Option<(u32, u32)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u32::from_str_radix(addr, 16).ok()?,
+        u32::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Registers are transferred in target byte order, i.e. little endian.
+fn encode_register(value: u32) -> String {
+    encode_hex(&value.to_le_bytes())
+}
+
+fn decode_register(hex: &str) -> Option<u32> {
+    Some(u32::from_le_bytes(decode_hex(hex)?.try_into().ok()?))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn compute_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Escapes the characters that have a special meaning in packets.
+fn escape(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .flat_map(|b| match b {
+            b'$' | b'#' | b'}' | b'*' => vec![b'}', b ^ 0x20],
+            _ => vec![*b],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct MockTarget {
+        registers: [u32; 32],
+        memory: HashMap<u32, u32>,
+    }
+
+    impl Target for MockTarget {
+        fn read_register(&mut self, idx: u32) -> u32 {
+            self.registers[idx as usize]
+        }
+
+        fn write_register(&mut self, idx: u32, value: u32) {
+            self.registers[idx as usize] = value;
+        }
+
+        fn read_memory_word(&self, addr: u32) -> u32 {
+            *self.memory.get(&addr).unwrap_or(&0)
+        }
+    }
+
+    fn send(stream: &mut TcpStream, data: &str) {
+        let packet = format!("${data}#{:02x}", compute_checksum(data.as_bytes()));
+        stream.write_all(packet.as_bytes()).unwrap();
+        let mut ack = [0u8];
+        stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+    }
+
+    fn receive(stream: &mut TcpStream) -> String {
+        let mut bytes = stream.by_ref().bytes().map(Result::unwrap);
+        assert_eq!(bytes.next(), Some(b'$'));
+        let data = bytes
+            .by_ref()
+            .take_while(|b| *b != b'#')
+            .collect::<Vec<_>>();
+        let checksum = bytes.by_ref().take(2).collect::<Vec<_>>();
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(),
+            compute_checksum(&data)
+        );
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn registers_memory_and_breakpoints() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut request = |data: &str| {
+                send(&mut stream, data);
+                receive(&mut stream)
+            };
+            assert_eq!(request("?"), "S05");
+            let registers = request("g");
+            assert_eq!(&registers[8..16], "78563412");
+            assert_eq!(&registers[256..264], "00100000");
+            assert_eq!(request("m100,6"), "efbeadde0100");
+            assert_eq!(request("P2=01000000"), "OK");
+            assert_eq!(request("P20=00000000"), "E01");
+            assert_eq!(request("Z0,1004,4"), "OK");
+            assert_eq!(request("vMustReplyEmpty"), "");
+
+            // Breakpoints at labels use the address of the labeled instruction.
+            let command = encode_hex(b"break main");
+            let output = request(&format!("qRcmd,{command}"));
+            assert_eq!(
+                decode_hex(&output[1..]).unwrap(),
+                b"Breakpoint at pc 0x1008\n"
+            );
+            assert_eq!(receive(&mut stream), "OK");
+
+            // Continue until each breakpoint is hit, then kill the program.
+            send(&mut stream, "c");
+            assert_eq!(receive(&mut stream), "S05");
+            send(&mut stream, "c");
+            assert_eq!(receive(&mut stream), "S05");
+            send(&mut stream, "k");
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(
+            stream,
+            &[],
+            Default::default(),
+            Default::default(),
+            [("main", 0x1008)].into_iter().collect(),
+        );
+        let mut registers = [0; 32];
+        registers[1] = 0x12345678;
+        let mut target = MockTarget {
+            registers,
+            memory: [(0x100, 0xdeadbeef), (0x104, 1)].into_iter().collect(),
+        };
+
+        let mut executed = vec![];
+        let mut pc = 0x1000;
+        while stub.on_instruction(pc, &mut target) {
+            executed.push(pc);
+            pc += 4;
+        }
+        client.join().unwrap();
+
+        assert_eq!(executed, vec![0x1000, 0x1004]);
+        assert_eq!(target.registers[2], 1);
+    }
+}
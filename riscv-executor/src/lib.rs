@@ -12,6 +12,8 @@
 use std::{
     collections::{BTreeMap, HashMap},
     fmt::{self, Display, Formatter},
+    io,
+    net::TcpStream,
     path::Path,
     sync::Arc,
     time::Instant,
@@ -29,10 +31,13 @@ use powdr_ast::{
     },
 };
 use powdr_executor::constant_evaluator::VariablySizedColumn;
-use powdr_number::{write_polys_csv_file, FieldElement, LargeInt};
+use powdr_number::{write_polys_csv_file, DegreeType, FieldElement, LargeInt};
 pub use profiler::ProfilerOptions;
+use serde::{Deserialize, Serialize};
+pub use step_trace::{StepTraceFormat, StepTraceOptions};
 
 pub mod arith;
+mod gdb;
 mod poseidon2_gl;
 pub mod poseidon_gl;
 mod profiler;
@@ -41,8 +46,11 @@ use submachines::*;
 mod memory;
 use memory::*;
 mod pil;
+pub mod step_trace;
 
+use crate::gdb::GdbStub;
 use crate::profiler::Profiler;
+use crate::step_trace::StepTraceWriter;
 
 // TODO: we don't do anything with these yet. Idea is to keep info that is to be given to witgen
 #[allow(unused)]
@@ -343,7 +351,7 @@ impl<F: FieldElement> Display for Elem<F> {
 pub type MemoryState = HashMap<u32, u32>;
 pub type RegisterMemoryState<F> = HashMap<u32, F>;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MemOperationKind {
     Read,
     Write,
@@ -499,15 +507,21 @@ impl<F: FieldElement> RegisterMemory<F> {
 }
 
 mod builder {
-    use std::{cell::RefCell, cmp, collections::HashMap, time::Instant};
+    use std::{
+        cell::RefCell,
+        cmp,
+        collections::{BTreeMap, HashMap},
+        time::Instant,
+    };
 
     use powdr_ast::{
         analyzed::{Analyzed, DegreeRange},
         asm_analysis::{Machine, RegisterTy},
     };
-    use powdr_number::FieldElement;
+    use powdr_number::{DegreeType, FieldElement};
     use rayon::iter::{ParallelBridge, ParallelIterator};
 
+    use crate::step_trace::{TraceMemOp, TraceRegWrite, TraceStep};
     use crate::{
         pil, BinaryMachine, Elem, ExecMode, Execution, ExecutionTrace, MachineInstance, MainOp,
         MemOperation, MemOperationKind, MemoryMachine, MemoryState, PoseidonGlMachine,
@@ -575,6 +589,9 @@ mod builder {
         /// Fast: do not save the register's trace and memory accesses.
         /// Trace: save everything - needed for continuations.
         mode: ExecMode,
+
+        /// The step being recorded for the step trace, if any.
+        trace_step: Option<TraceStep>,
     }
 
     impl<'a, 'b, F: FieldElement> TraceBuilder<'b, F> {
@@ -670,6 +687,7 @@ mod builder {
                 mem,
                 reg_mem: Default::default(),
                 mode,
+                trace_step: None,
             };
 
             if ret.has_enough_rows() || ret.set_next_pc().is_none() {
@@ -864,6 +882,13 @@ mod builder {
                     address: addr,
                 });
             }
+            if let Some(step) = &mut self.trace_step {
+                step.mem_ops.push(TraceMemOp {
+                    kind: MemOperationKind::Write,
+                    address: addr,
+                    value: val,
+                });
+            }
 
             self.mem.insert(addr, val);
         }
@@ -883,15 +908,49 @@ mod builder {
                     address: addr,
                 });
             }
+            if let Some(step) = &mut self.trace_step {
+                step.mem_ops.push(TraceMemOp {
+                    kind: MemOperationKind::Read,
+                    address: addr,
+                    value: val,
+                });
+            }
             val
         }
 
+        /// get the value of a memory word, without recording the access.
+        pub(crate) fn peek_mem(&self, addr: u32) -> u32 {
+            *self.mem.get(&addr).unwrap_or(&0)
+        }
+
         pub(crate) fn set_reg_mem(&mut self, addr: u32, val: Elem<F>) {
             if addr != 0 {
                 self.reg_mem.last.insert(addr, val);
+                if let Some(step) = &mut self.trace_step {
+                    step.reg_writes.push(TraceRegWrite {
+                        reg: addr,
+                        value: val.as_i64_from_lower_bytes() as u32,
+                    });
+                }
             }
         }
 
+        /// Starts recording the register writes and memory accesses of a
+        /// statement, for the step trace.
+        pub(crate) fn begin_trace_step(&mut self, pc: u32, instruction: String) {
+            self.trace_step = Some(TraceStep {
+                pc,
+                instruction,
+                reg_writes: vec![],
+                mem_ops: vec![],
+            });
+        }
+
+        /// Stops recording and returns the recorded step, if any.
+        pub(crate) fn take_trace_step(&mut self) -> Option<TraceStep> {
+            self.trace_step.take()
+        }
+
         pub(crate) fn get_reg_mem(&mut self, addr: u32) -> Elem<F> {
             let zero: Elem<F> = 0u32.into();
             if addr == 0 {
@@ -909,6 +968,7 @@ mod builder {
             if let ExecMode::Fast = self.mode {
                 return Execution {
                     trace_len: self.trace.len,
+                    machine_rows: [("main".to_string(), self.trace.len as DegreeType)].into(),
                     memory: self.mem,
                     memory_accesses: Vec::new(),
                     trace: HashMap::new(),
@@ -918,6 +978,8 @@ mod builder {
 
             let pil = opt_pil.unwrap();
 
+            let mut machine_rows =
+                BTreeMap::from([("main".to_string(), self.main_columns_len() as DegreeType)]);
             let main_degree = {
                 let range = namespace_degree_range(pil, "main");
                 std::cmp::max(
@@ -953,15 +1015,16 @@ mod builder {
                 })
                 // handle submachines in parallel
                 .par_bridge()
-                .flat_map(|(m, mut machine, ops)| {
+                .map(|(m, mut machine, ops)| {
                     // apply the operations to the submachine
                     ops.into_iter().for_each(|op| {
                         let selector = pil::selector_for_link(&links, op.identity_id);
                         machine.add_operation(selector.as_deref(), &op.lookup_args, &op.extra);
                     });
+                    let used_rows = machine.len() as DegreeType;
 
                     // finalize and extend the submachine traces and add to full trace
-                    if machine.len() > 0 {
+                    let cols = if machine.len() > 0 {
                         let range = namespace_degree_range(pil, machine.namespace());
                         // extend with dummy blocks up to the required machine degree
                         let machine_degree =
@@ -980,10 +1043,14 @@ mod builder {
                     } else {
                         // keep machine columns empty
                         machine.finish(0)
-                    }
+                    };
+                    (machine.namespace().to_string(), used_rows, cols)
                 })
                 .collect::<Vec<_>>();
-            self.trace.cols.extend(cols);
+            for (namespace, used_rows, cols) in cols {
+                machine_rows.insert(namespace, used_rows);
+                self.trace.cols.extend(cols);
+            }
             log::debug!(
                 "Generating submachine traces took {}s",
                 start.elapsed().as_secs_f64(),
@@ -991,6 +1058,7 @@ mod builder {
 
             Execution {
                 trace_len: self.trace.len,
+                machine_rows,
                 memory: self.mem,
                 memory_accesses: std::mem::take(&mut self.trace.mem_ops),
                 trace: self.trace.cols,
@@ -1004,6 +1072,12 @@ mod builder {
             self.trace.len >= self.max_rows
         }
 
+        /// Is the current row the last one before the maximum number of rows
+        /// is reached?
+        pub fn is_last_row(&self) -> bool {
+            self.trace.len == self.max_rows
+        }
+
         /// Optimistically increment PC, but the execution might rewrite it.
         ///
         /// Only do it when running the last statement of a batch.
@@ -1052,6 +1126,22 @@ struct PreprocessedMain<'a, F: FieldElement> {
     function_starts: BTreeMap<usize, &'a str>,
     /// .debug loc to batch number
     location_starts: BTreeMap<usize, (usize, usize)>,
+    /// batch number to the address of the RISC-V instruction starting in it
+    instruction_addresses: BTreeMap<usize, u32>,
+}
+
+/// Returns the address of the RISC-V instruction starting at the given
+/// statement, if it is a `.debug insn "<address in hex>: <instruction>"`
+/// directive, as emitted by the translation from RISC-V.
+fn instruction_address(statement: &FunctionStatement) -> Option<u32> {
+    let FunctionStatement::DebugDirective(d) = statement else {
+        return None;
+    };
+    let DebugDirective::OriginalInstruction(insn) = &d.directive else {
+        return None;
+    };
+    let (address, _) = insn.split_once(": ")?;
+    u32::from_str_radix(address, 16).ok()
 }
 
 /// Returns the list of instructions, directly indexable by PC, the map from
@@ -1069,6 +1159,7 @@ fn preprocess_main_function<F: FieldElement>(machine: &Machine) -> PreprocessedM
     let mut debug_files = Vec::new();
     let mut function_starts = BTreeMap::new();
     let mut location_starts = BTreeMap::new();
+    let mut instruction_addresses = BTreeMap::new();
 
     for (batch_idx, batch) in orig_statements.iter_batches().enumerate() {
         batch_to_line_map.push(statements.len() as u32);
@@ -1094,6 +1185,11 @@ fn preprocess_main_function<F: FieldElement>(machine: &Machine) -> PreprocessedM
                             statements.push(s);
                         }
                         DebugDirective::OriginalInstruction(_) => {
+                            if let Some(address) = instruction_address(s) {
+                                instruction_addresses
+                                    .entry(batch_idx + PC_INITIAL_VAL)
+                                    .or_insert(address);
+                            }
                             // keep debug locs for debugging purposes
                             statements.push(s);
                         }
@@ -1123,6 +1219,7 @@ fn preprocess_main_function<F: FieldElement>(machine: &Machine) -> PreprocessedM
         debug_files,
         function_starts,
         location_starts,
+        instruction_addresses,
     }
 }
 
@@ -2640,6 +2737,9 @@ pub type FixedColumns<F> = Arc<Vec<(String, VariablySizedColumn<F>)>>;
 pub struct Execution<F: FieldElement> {
     /// number of rows used by the execution (not of the full main columns, which are extended to a power-of-two)
     pub trace_len: usize,
+    /// number of rows used by each machine, by namespace, before padding.
+    /// In ExecMode::Fast, only the main machine is included.
+    pub machine_rows: BTreeMap<String, DegreeType>,
     /// witness columns
     pub trace: HashMap<String, Vec<F>>,
     /// final memory state
@@ -2664,6 +2764,7 @@ pub fn execute_fast<F: FieldElement>(
     prover_ctx: &Callback<F>,
     bootloader_inputs: &[F],
     profiling: Option<ProfilerOptions>,
+    step_trace: Option<StepTraceOptions>,
 ) -> usize {
     log::info!("Executing...");
     execute_inner(
@@ -2676,10 +2777,41 @@ pub fn execute_fast<F: FieldElement>(
         usize::MAX,
         ExecMode::Fast,
         profiling,
+        step_trace,
+        None,
     )
     .trace_len
 }
 
+/// Execute a Powdr/RISCV assembly program, without generating a witness, under
+/// the control of GDB. Waits for GDB to connect through the remote serial
+/// protocol on the given local TCP port before starting the execution.
+/// Returns the execution trace length.
+pub fn execute_fast_with_gdb<F: FieldElement>(
+    asm: &AnalysisASMFile,
+    initial_memory: MemoryState,
+    prover_ctx: &Callback<F>,
+    bootloader_inputs: &[F],
+    gdb_port: u16,
+) -> io::Result<usize> {
+    let gdb_stream = gdb::wait_for_connection(gdb_port)?;
+    log::info!("Executing...");
+    Ok(execute_inner(
+        asm,
+        None,
+        None,
+        initial_memory,
+        prover_ctx,
+        bootloader_inputs,
+        usize::MAX,
+        ExecMode::Fast,
+        None,
+        None,
+        Some(gdb_stream),
+    )
+    .trace_len)
+}
+
 /// Execute and generate a valid witness for a Powdr/RISCV assembly program.
 #[allow(clippy::too_many_arguments)]
 pub fn execute<F: FieldElement>(
@@ -2704,6 +2836,8 @@ pub fn execute<F: FieldElement>(
         max_steps_to_execute.unwrap_or(usize::MAX),
         ExecMode::Trace,
         profiling,
+        None,
+        None,
     )
 }
 
@@ -2718,6 +2852,8 @@ fn execute_inner<F: FieldElement>(
     max_steps_to_execute: usize,
     mode: ExecMode,
     profiling: Option<ProfilerOptions>,
+    step_trace: Option<StepTraceOptions>,
+    gdb_stream: Option<TcpStream>,
 ) -> Execution<F> {
     let start = Instant::now();
     let main_machine = get_main_machine(asm);
@@ -2729,6 +2865,7 @@ fn execute_inner<F: FieldElement>(
         debug_files,
         function_starts,
         location_starts,
+        instruction_addresses,
     } = preprocess_main_function(main_machine);
 
     let witness_cols: Vec<String> = opt_pil
@@ -2778,6 +2915,37 @@ fn execute_inner<F: FieldElement>(
 
     let pil_links = opt_pil.map(pil::links_from_pil).unwrap_or_default();
 
+    let mut debugger = gdb_stream.map(|stream| {
+        // The debugger uses the addresses of the RISC-V instructions, or the
+        // powdr-asm PCs in programs which were not translated from RISC-V.
+        let to_pc = |batch: usize| {
+            if instruction_addresses.is_empty() {
+                Some(batch as u32)
+            } else {
+                instruction_addresses.get(&batch).copied()
+            }
+        };
+        let labels = label_map
+            .iter()
+            .filter_map(|(label, pc)| Some((*label, to_pc(pc.u() as usize)?)))
+            .collect();
+        let function_starts = function_starts
+            .iter()
+            .filter_map(|(batch, function)| Some((to_pc(*batch)?, *function)))
+            .collect();
+        let location_starts = location_starts
+            .iter()
+            .filter_map(|(batch, location)| Some((to_pc(*batch)?, *location)))
+            .collect();
+        GdbStub::new(
+            stream,
+            &debug_files[..],
+            function_starts,
+            location_starts,
+            labels,
+        )
+    });
+
     // We clear the QueryCallback's virtual FS before the execution.
     (prover_ctx)("Clear").unwrap();
     let mut e = Executor {
@@ -2798,12 +2966,56 @@ fn execute_inner<F: FieldElement>(
     let mut profiler =
         profiling.map(|opt| Profiler::new(opt, &debug_files[..], function_starts, location_starts));
 
+    let mut step_tracer = step_trace.map(|opt| {
+        StepTraceWriter::create(&opt).unwrap_or_else(|e| {
+            panic!(
+                "Could not create step trace file {}: {e}",
+                opt.path.display()
+            )
+        })
+    });
+
     let mut curr_pc = 0u32;
+    // The address of the last RISC-V instruction that started executing.
+    let mut curr_address = None;
     let mut last = Instant::now();
     let mut count = 0;
+    let mut checkpointed = false;
     loop {
+        if !checkpointed && e.proc.is_last_row() {
+            // With continuations, the next chunk starts by executing this row
+            // again, so stateful query callbacks record their state before it.
+            // Callbacks without any state reject the query.
+            let _ = (e.inputs)("Checkpoint");
+            checkpointed = true;
+        }
+
         let stm = statements[curr_pc as usize];
 
+        let instruction_start = if debugger.is_some() || step_tracer.is_some() {
+            instruction_address(stm)
+        } else {
+            None
+        };
+        curr_address = instruction_start.or(curr_address);
+
+        if let Some(debugger) = &mut debugger {
+            // Stop before each RISC-V instruction, or before each batch in
+            // programs which were not translated from RISC-V.
+            let stop_pc = if instruction_addresses.is_empty() {
+                let pc = e.proc.get_pc().u();
+                (batch_to_line_map[pc as usize] == curr_pc).then_some(pc)
+            } else {
+                instruction_start
+            };
+            if let Some(pc) = stop_pc {
+                if !debugger.on_instruction(pc, &mut e.proc) {
+                    log::info!("Execution killed by GDB.");
+                    break;
+                }
+            }
+        }
+
         log::trace!("l {curr_pc}: {stm}",);
 
         e.step += 4;
@@ -2819,6 +3031,16 @@ fn execute_inner<F: FieldElement>(
             }
         }
 
+        if step_tracer.is_some()
+            && matches!(
+                stm,
+                FunctionStatement::Assignment(_) | FunctionStatement::Instruction(_)
+            )
+        {
+            let pc = curr_address.unwrap_or_else(|| e.proc.get_pc().u());
+            e.proc.begin_trace_step(pc, stm.to_string());
+        }
+
         match stm {
             FunctionStatement::Assignment(a) => {
                 e.proc.push_row();
@@ -2933,6 +3155,14 @@ fn execute_inner<F: FieldElement>(
             }
         };
 
+        if let Some(step) = e.proc.take_trace_step() {
+            step_tracer
+                .as_mut()
+                .unwrap()
+                .write(&step)
+                .expect("Could not write to the step trace file");
+        }
+
         curr_pc = match e.proc.advance() {
             Some(pc) => {
                 // We set pc_update=PC here, after the PC has been updated but before "pushing" the next row
@@ -2947,6 +3177,16 @@ fn execute_inner<F: FieldElement>(
         p.finish();
     }
 
+    if let Some(tracer) = step_tracer {
+        tracer
+            .finish()
+            .expect("Could not write to the step trace file");
+    }
+
+    if let Some(mut debugger) = debugger {
+        debugger.on_exit();
+    }
+
     if let ExecMode::Trace = mode {
         let sink_id = e.sink_id();
 
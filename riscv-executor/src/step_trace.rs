@@ -0,0 +1,262 @@
+//! Instruction-level execution traces.
+//!
+//! A step trace records, for every executed powdr-asm statement, the address of
+//! the RISC-V instruction it belongs to, the statement itself, the writes to
+//! RISC-V registers and the memory accesses.
+//! Traces are written either as JSON lines or in a compact binary format, and
+//! can be read back to compare the executions of two builds of a program.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::MemOperationKind;
+
+/// Magic bytes at the start of a binary step trace.
+const BINARY_MAGIC: &[u8; 8] = b"powdrst\x01";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepTraceFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// bincode-encoded steps, after a magic header.
+    Binary,
+}
+
+#[derive(Clone, Debug)]
+pub struct StepTraceOptions {
+    pub path: PathBuf,
+    pub format: StepTraceFormat,
+}
+
+/// The execution of a single powdr-asm statement.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    /// The address of the RISC-V instruction the statement belongs to. The
+    /// statements of the runtime belong to the instruction which called them,
+    /// e.g. an `ecall`. Statements executed before the first instruction, and
+    /// all statements of programs which were not translated from RISC-V, use
+    /// the powdr-asm PC of their batch instead.
+    pub pc: u32,
+    /// The executed statement.
+    pub instruction: String,
+    /// Writes to the RISC-V registers, in execution order.
+    pub reg_writes: Vec<TraceRegWrite>,
+    /// Memory accesses, in execution order.
+    pub mem_ops: Vec<TraceMemOp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceRegWrite {
+    pub reg: u32,
+    pub value: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceMemOp {
+    pub kind: MemOperationKind,
+    pub address: u32,
+    pub value: u32,
+}
+
+/// Writes the steps of an execution to a file.
+pub struct StepTraceWriter {
+    output: BufWriter<File>,
+    format: StepTraceFormat,
+}
+
+impl StepTraceWriter {
+    pub fn create(options: &StepTraceOptions) -> io::Result<Self> {
+        let mut output = BufWriter::new(File::create(&options.path)?);
+        if options.format == StepTraceFormat::Binary {
+            output.write_all(BINARY_MAGIC)?;
+        }
+        Ok(Self {
+            output,
+            format: options.format,
+        })
+    }
+
+    pub fn write(&mut self, step: &TraceStep) -> io::Result<()> {
+        match self.format {
+            StepTraceFormat::Jsonl => {
+                serde_json::to_writer(&mut self.output, step)?;
+                self.output.write_all(b"\n")
+            }
+            StepTraceFormat::Binary => {
+                bincode::serialize_into(&mut self.output, step).map_err(io::Error::other)
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Reads the steps of a trace written by [StepTraceWriter], detecting the
+/// format from the content.
+pub struct StepTraceReader {
+    input: BufReader<File>,
+    format: StepTraceFormat,
+}
+
+impl StepTraceReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let is_binary = input.fill_buf()?.starts_with(BINARY_MAGIC);
+        let format = if is_binary {
+            input.consume(BINARY_MAGIC.len());
+            StepTraceFormat::Binary
+        } else {
+            StepTraceFormat::Jsonl
+        };
+        Ok(Self { input, format })
+    }
+
+    pub fn format(&self) -> StepTraceFormat {
+        self.format
+    }
+
+    fn read_step(&mut self) -> io::Result<Option<TraceStep>> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        match self.format {
+            StepTraceFormat::Jsonl => {
+                let mut line = String::new();
+                self.input.read_line(&mut line)?;
+                Ok(Some(serde_json::from_str(&line)?))
+            }
+            StepTraceFormat::Binary => bincode::deserialize_from(&mut self.input)
+                .map(Some)
+                .map_err(io::Error::other),
+        }
+    }
+}
+
+impl Iterator for StepTraceReader {
+    type Item = io::Result<TraceStep>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_step().transpose()
+    }
+}
+
+/// The first step at which two traces differ.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the step.
+    pub step: usize,
+    /// The step in the first trace, or None if the trace ended.
+    pub left: Option<TraceStep>,
+    /// The step in the second trace, or None if the trace ended.
+    pub right: Option<TraceStep>,
+}
+
+/// Compares two traces step by step and returns the first step at which they
+/// differ, or None if they are identical.
+pub fn first_divergence(
+    left: impl IntoIterator<Item = io::Result<TraceStep>>,
+    right: impl IntoIterator<Item = io::Result<TraceStep>>,
+) -> io::Result<Option<Divergence>> {
+    let mut left = left.into_iter();
+    let mut right = right.into_iter();
+    for step in 0.. {
+        let (l, r) = (left.next().transpose()?, right.next().transpose()?);
+        if l.is_none() && r.is_none() {
+            return Ok(None);
+        }
+        if l != r {
+            return Ok(Some(Divergence {
+                step,
+                left: l,
+                right: r,
+            }));
+        }
+    }
+    unreachable!()
+}
+
+/// Compares the traces stored in two files, see [first_divergence].
+pub fn first_divergence_in_files(left: &Path, right: &Path) -> io::Result<Option<Divergence>> {
+    first_divergence(StepTraceReader::open(left)?, StepTraceReader::open(right)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn step(pc: u32, value: u32) -> TraceStep {
+        TraceStep {
+            pc,
+            instruction: format!("affine 1, 2, 0, {value};"),
+            reg_writes: vec![TraceRegWrite { reg: 2, value }],
+            mem_ops: vec![TraceMemOp {
+                kind: MemOperationKind::Write,
+                address: 0x100,
+                value,
+            }],
+        }
+    }
+
+    fn write_trace(path: &Path, format: StepTraceFormat, steps: &[TraceStep]) {
+        let mut writer = StepTraceWriter::create(&StepTraceOptions {
+            path: path.to_path_buf(),
+            format,
+        })
+        .unwrap();
+        for step in steps {
+            writer.write(step).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let dir = mktemp::Temp::new_dir().unwrap();
+        let steps = vec![step(2, 7), step(3, 8)];
+        for format in [StepTraceFormat::Jsonl, StepTraceFormat::Binary] {
+            let path = dir.join("trace");
+            write_trace(&path, format, &steps);
+
+            let reader = StepTraceReader::open(&path).unwrap();
+            assert_eq!(reader.format(), format);
+            let read = reader.collect::<io::Result<Vec<_>>>().unwrap();
+            assert_eq!(read, steps);
+        }
+    }
+
+    #[test]
+    fn find_first_divergence() {
+        let dir = mktemp::Temp::new_dir().unwrap();
+        let (left, right) = (dir.join("left"), dir.join("right"));
+        write_trace(
+            &left,
+            StepTraceFormat::Jsonl,
+            &[step(2, 7), step(3, 8), step(4, 9)],
+        );
+        write_trace(
+            &right,
+            StepTraceFormat::Binary,
+            &[step(2, 7), step(3, 1), step(4, 9)],
+        );
+
+        let divergence = first_divergence_in_files(&left, &right).unwrap().unwrap();
+        assert_eq!(divergence.step, 1);
+        assert_eq!(divergence.left, Some(step(3, 8)));
+        assert_eq!(divergence.right, Some(step(3, 1)));
+
+        assert_eq!(first_divergence_in_files(&left, &left).unwrap(), None);
+
+        // A trace that is a prefix of the other diverges where it ends.
+        write_trace(&right, StepTraceFormat::Jsonl, &[step(2, 7)]);
+        let divergence = first_divergence_in_files(&left, &right).unwrap().unwrap();
+        assert_eq!(divergence.step, 1);
+        assert_eq!(divergence.right, None);
+    }
+}
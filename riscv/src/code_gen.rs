@@ -70,9 +70,25 @@ impl From<&str> for Register {
 }
 
 pub enum Statement<'a, L: AsRef<str>, A: InstructionArgs> {
-    DebugLoc { file: u64, line: u64, col: u64 },
+    DebugLoc {
+        file: u64,
+        line: u64,
+        col: u64,
+    },
     Label(L),
-    Instruction { op: &'a str, args: A },
+    Instruction {
+        /// The address of the instruction in the executable.
+        address: u32,
+        op: &'a str,
+        args: A,
+    },
+}
+
+/// The debug directive emitted before the translation of each instruction.
+/// It records the address of the instruction, which the executor reports as
+/// the PC (e.g. to GDB) instead of the powdr-asm PC.
+pub fn original_instruction_directive(address: u32, op: &str) -> String {
+    format!(".debug insn \"{address:08x}: {op}\";")
 }
 
 pub struct MemEntry {
@@ -109,6 +109,7 @@ where
                     .unwrap();
 
                 pipeline.rollback_from_witness();
+                pipeline.set_query_chunk(i);
 
                 // The `jump_to_shutdown_routine` column indicates when the execution should jump to the shutdown routine.
                 // In that row, the normal PC update is ignored and the PC is set to the address of the shutdown routine.
@@ -136,6 +137,7 @@ where
             },
         )
         .collect::<Result<Vec<_>, E>>()?;
+    pipeline.set_query_chunk(0);
     Ok(())
 }
 
@@ -237,6 +239,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
     // TODO: commit to the merkle_tree root in the verifier.
 
     log::info!("Initial execution...");
+    pipeline.set_query_chunk(0);
     let full_exec = powdr_riscv_executor::execute::<F>(
         &asm,
         &pil,
@@ -339,6 +342,9 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
         );
 
         log::info!("Simulating chunk execution...");
+        // The executor records the state of the query callbacks at the start
+        // of the next chunk before executing the last row of this chunk.
+        pipeline.set_query_chunk(chunk_index);
         let chunk_exec = powdr_riscv_executor::execute::<F>(
             &asm,
             &pil,
@@ -506,6 +512,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
 
         chunk_index += 1;
     }
+    pipeline.set_query_chunk(0);
     DryRunResult {
         bootloader_inputs: bootloader_inputs_and_num_rows,
         trace_len: full_trace_length,
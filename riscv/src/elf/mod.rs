@@ -354,6 +354,7 @@ impl RiscVProgram for ElfProgram {
                     }
                     Either::Right((_, Either::Right(insn))) => {
                         Box::new(std::iter::once(Statement::Instruction {
+                            address: insn.loc.address,
                             op: insn.op,
                             args: WrappedArgs {
                                 args: &insn.args,
@@ -1131,6 +1132,24 @@ impl Iterator for RiscVInstructionIterator<'_> {
                 address: self.curr_address,
                 insn: match bin_instruction.decode(Isa::Rv32) {
                     Ok(c_insn) => UnimpOrInstruction::Instruction(to_32bit_equivalent(c_insn)),
+                    Err(_) if expand_compressed(bin_instruction).is_some() => {
+                        // The decoder does not accept some valid RV32C encodings
+                        // (e.g. the HINTs, which write to x0), so we expand the
+                        // instruction to its 32-bit encoding and decode that.
+                        let mut insn = expand_compressed(bin_instruction)
+                            .unwrap()
+                            .decode(Isa::Rv32)
+                            .unwrap_or_else(|err| {
+                                panic!(
+                                    "Failed to decode expanded 16-bit instruction at {:08x}: {err:?}",
+                                    self.curr_address
+                                )
+                            });
+                        // Keep track that this is a 16-bit instruction, so that
+                        // its size is correctly computed.
+                        insn.extension = Extensions::C;
+                        UnimpOrInstruction::Instruction(insn)
+                    }
                     Err(raki::decode::DecodingError::IllegalInstruction) => {
                         // Although not a real RISC-V instruction, sometimes 0x0000
                         // is used on purpose as an illegal instruction (it even has
@@ -1286,6 +1305,190 @@ fn to_32bit_equivalent(mut insn: Ins) -> Ins {
     insn
 }
 
+/// Expands a 16-bit "C" extension instruction into the 32-bit instruction
+/// encoding it is equivalent to, as given by the RVC expansion table of the
+/// RISC-V specification.
+///
+/// Returns `None` for illegal and reserved encodings, and for instructions
+/// that do not exist in RV32 without floating point support.
+fn expand_compressed(insn: u16) -> Option<u32> {
+    const OP_IMM: u32 = 0x13;
+    const OP: u32 = 0x33;
+    const LOAD: u32 = 0x03;
+    const STORE: u32 = 0x23;
+    const LUI: u32 = 0x37;
+    const BRANCH: u32 = 0x63;
+    const JALR: u32 = 0x67;
+    const JAL: u32 = 0x6f;
+    const EBREAK: u32 = 0x0010_0073;
+
+    let insn = insn as u32;
+    // Extracts `len` bits starting at bit `lo`.
+    let bits = |lo: u32, len: u32| (insn >> lo) & ((1 << len) - 1);
+    // Sign extends the lowest `len` bits of `value`.
+    let sext = |value: u32, len: u32| ((value << (32 - len)) as i32 >> (32 - len)) as u32;
+
+    let i_type = |imm: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32| {
+        (imm & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+    };
+    let s_type = |imm: u32, rs2: u32, rs1: u32, funct3: u32| {
+        (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | STORE
+    };
+    let r_type = |funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32| {
+        funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | OP
+    };
+    let b_type = |imm: u32, rs1: u32, funct3: u32| {
+        (imm >> 12 & 1) << 31
+            | (imm >> 5 & 0x3f) << 25
+            | rs1 << 15
+            | funct3 << 12
+            | (imm >> 1 & 0xf) << 8
+            | (imm >> 11 & 1) << 7
+            | BRANCH
+    };
+    let j_type = |imm: u32, rd: u32| {
+        (imm >> 20 & 1) << 31
+            | (imm >> 1 & 0x3ff) << 21
+            | (imm >> 11 & 1) << 20
+            | (imm >> 12 & 0xff) << 12
+            | rd << 7
+            | JAL
+    };
+
+    // Register fields: the full 5-bit ones and the 3-bit ones, which refer to
+    // registers x8 to x15.
+    let rd = bits(7, 5);
+    let rs2 = bits(2, 5);
+    let rd_prime = bits(2, 3) + 8;
+    let rs1_prime = bits(7, 3) + 8;
+
+    // Immediate of CI format instructions (6 bits, sign extended).
+    let ci_imm = sext(bits(12, 1) << 5 | bits(2, 5), 6);
+    // Offset of C.LW and C.SW.
+    let cl_offset = bits(10, 3) << 3 | bits(6, 1) << 2 | bits(5, 1) << 6;
+    // Offset of C.J and C.JAL.
+    let cj_offset = sext(
+        bits(12, 1) << 11
+            | bits(11, 1) << 4
+            | bits(9, 2) << 8
+            | bits(8, 1) << 10
+            | bits(7, 1) << 6
+            | bits(6, 1) << 7
+            | bits(3, 3) << 1
+            | bits(2, 1) << 5,
+        12,
+    );
+    // Offset of C.BEQZ and C.BNEZ.
+    let cb_offset = sext(
+        bits(12, 1) << 8 | bits(10, 2) << 3 | bits(5, 2) << 6 | bits(3, 2) << 1 | bits(2, 1) << 5,
+        9,
+    );
+
+    let expanded = match (bits(0, 2), bits(13, 3)) {
+        // C.ADDI4SPN
+        (0b00, 0b000) => {
+            let imm = bits(11, 2) << 4 | bits(7, 4) << 6 | bits(6, 1) << 2 | bits(5, 1) << 3;
+            if imm == 0 {
+                // Also covers the all zeros illegal instruction.
+                return None;
+            }
+            i_type(imm, 2, 0b000, rd_prime, OP_IMM)
+        }
+        // C.LW
+        (0b00, 0b010) => i_type(cl_offset, rs1_prime, 0b010, rd_prime, LOAD),
+        // C.SW
+        (0b00, 0b110) => s_type(cl_offset, rd_prime, rs1_prime, 0b010),
+        // C.NOP and C.ADDI
+        (0b01, 0b000) => i_type(ci_imm, rd, 0b000, rd, OP_IMM),
+        // C.JAL
+        (0b01, 0b001) => j_type(cj_offset, 1),
+        // C.LI
+        (0b01, 0b010) => i_type(ci_imm, 0, 0b000, rd, OP_IMM),
+        // C.ADDI16SP
+        (0b01, 0b011) if rd == 2 => {
+            let imm = sext(
+                bits(12, 1) << 9
+                    | bits(6, 1) << 4
+                    | bits(5, 1) << 6
+                    | bits(3, 2) << 7
+                    | bits(2, 1) << 5,
+                10,
+            );
+            if imm == 0 {
+                return None;
+            }
+            i_type(imm, 2, 0b000, 2, OP_IMM)
+        }
+        // C.LUI
+        (0b01, 0b011) => {
+            if ci_imm == 0 {
+                return None;
+            }
+            ci_imm << 12 | rd << 7 | LUI
+        }
+        (0b01, 0b100) => {
+            let rd = rs1_prime;
+            match bits(10, 2) {
+                // C.SRLI and C.SRAI, where shamt[5] must be zero in RV32.
+                0b00 | 0b01 if bits(12, 1) != 0 => return None,
+                0b00 => i_type(bits(2, 5), rd, 0b101, rd, OP_IMM),
+                0b01 => i_type(0x400 | bits(2, 5), rd, 0b101, rd, OP_IMM),
+                // C.ANDI
+                0b10 => i_type(ci_imm, rd, 0b111, rd, OP_IMM),
+                // C.SUBW and C.ADDW, which are RV64 only.
+                _ if bits(12, 1) != 0 => return None,
+                // C.SUB, C.XOR, C.OR and C.AND
+                _ => match bits(5, 2) {
+                    0b00 => r_type(0x20, rd_prime, rd, 0b000, rd),
+                    0b01 => r_type(0, rd_prime, rd, 0b100, rd),
+                    0b10 => r_type(0, rd_prime, rd, 0b110, rd),
+                    _ => r_type(0, rd_prime, rd, 0b111, rd),
+                },
+            }
+        }
+        // C.J
+        (0b01, 0b101) => j_type(cj_offset, 0),
+        // C.BEQZ
+        (0b01, 0b110) => b_type(cb_offset, rs1_prime, 0b000),
+        // C.BNEZ
+        (0b01, 0b111) => b_type(cb_offset, rs1_prime, 0b001),
+        // C.SLLI, where shamt[5] must be zero in RV32.
+        (0b10, 0b000) if bits(12, 1) != 0 => return None,
+        (0b10, 0b000) => i_type(bits(2, 5), rd, 0b001, rd, OP_IMM),
+        // C.LWSP
+        (0b10, 0b010) => {
+            if rd == 0 {
+                return None;
+            }
+            let offset = bits(12, 1) << 5 | bits(4, 3) << 2 | bits(2, 2) << 6;
+            i_type(offset, 2, 0b010, rd, LOAD)
+        }
+        (0b10, 0b100) => match (bits(12, 1), rd, rs2) {
+            // C.JR with x0 is reserved.
+            (0, 0, 0) => return None,
+            // C.JR
+            (0, rs1, 0) => i_type(0, rs1, 0b000, 0, JALR),
+            // C.MV
+            (0, rd, rs2) => r_type(0, rs2, 0, 0b000, rd),
+            // C.EBREAK
+            (_, 0, 0) => EBREAK,
+            // C.JALR
+            (_, rs1, 0) => i_type(0, rs1, 0b000, 1, JALR),
+            // C.ADD
+            (_, rd, rs2) => r_type(0, rs2, rd, 0b000, rd),
+        },
+        // C.SWSP
+        (0b10, 0b110) => {
+            let offset = bits(9, 4) << 2 | bits(7, 2) << 6;
+            s_type(offset, rs2, 2, 0b010)
+        }
+        // Floating point and RV64 only instructions.
+        _ => return None,
+    };
+
+    Some(expanded)
+}
+
 /// Helper trait for function `try_map_two_by_two`.
 ///
 /// Provides the methods to try to map two elements into one first, and one to
@@ -1323,3 +1526,36 @@ fn try_map_two_by_two<E, R>(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::expand_compressed;
+
+    #[test]
+    fn expand_compressed_instructions() {
+        // c.addi4spn a0, sp, 1020 => addi a0, sp, 1020
+        assert_eq!(expand_compressed(0x1fe8), Some(0x3fc10513));
+        // c.li a5, -16 => addi a5, x0, -16
+        assert_eq!(expand_compressed(0x57c1), Some(0xff000793));
+        // c.lui s0, 0xfffe1 => lui s0, 0xfffe1
+        assert_eq!(expand_compressed(0x7405), Some(0xfffe1437));
+        // c.nop => addi x0, x0, 0
+        assert_eq!(expand_compressed(0x0001), Some(0x00000013));
+        // c.jr ra => jalr x0, 0(ra)
+        assert_eq!(expand_compressed(0x8082), Some(0x00008067));
+        // c.mv a0, a1 => add a0, x0, a1
+        assert_eq!(expand_compressed(0x852e), Some(0x00b00533));
+        // c.ebreak => ebreak
+        assert_eq!(expand_compressed(0x9002), Some(0x00100073));
+    }
+
+    #[test]
+    fn reject_illegal_compressed_instructions() {
+        // The all zeros instruction is illegal.
+        assert_eq!(expand_compressed(0x0000), None);
+        // c.lwsp with x0 as destination is reserved.
+        assert_eq!(expand_compressed(0x4002), None);
+        // c.jr with x0 is reserved.
+        assert_eq!(expand_compressed(0x8002), None);
+    }
+}
@@ -9,7 +9,8 @@ use powdr_number::KnownField;
 use crate::continuations::bootloader::{bootloader_and_shutdown_routine, bootloader_preamble};
 
 use crate::code_gen::{
-    InstructionArgs, MemEntry, Register, RiscVProgram, SourceFileInfo, Statement,
+    original_instruction_directive, InstructionArgs, MemEntry, Register, RiscVProgram,
+    SourceFileInfo, Statement,
 };
 use crate::CompilerOptions;
 
@@ -164,11 +165,12 @@ fn translate_program_impl(
                 statements.push(format!(".debug loc {file} {line} {col};"))
             }
             Statement::Label(l) => statements.push(format!("{}:", escape_label(l.as_ref()))),
-            Statement::Instruction { op, args } => {
+            Statement::Instruction { address, op, args } => {
                 let processed_instr = match process_instruction(op, args, runtime) {
                     Ok(s) => s,
                     Err(e) => panic!("Failed to process instruction '{op}'. {e}"),
                 };
+                statements.push(original_instruction_directive(address, op));
                 statements.extend(processed_instr.into_iter().map(|s| "  ".to_string() + &s))
             }
         }
@@ -1810,6 +1812,68 @@ fn process_instruction<A: InstructionArgs>(
             .concat()
         }
 
+        insn if insn.starts_with("amo") => {
+            let (rd, rs2, rs1) = args.rrr2()?;
+
+            // The original memory value is loaded into tmp1, and the value to
+            // be stored is computed into tmp2.
+            let compute_stored_value = match insn.split('.').next().unwrap() {
+                "amoswap" => vec![format!(
+                    "affine {}, {}, 0, 1, 0, 0;",
+                    rs2.addr(),
+                    tmp2.addr()
+                )],
+                op @ ("amoand" | "amoor" | "amoxor") => vec![format!(
+                    "{} {}, {}, 0, 0, {};",
+                    &op[3..],
+                    tmp1.addr(),
+                    rs2.addr(),
+                    tmp2.addr()
+                )],
+                op @ ("amomin" | "amominu" | "amomax" | "amomaxu") => {
+                    let comparison = if op.ends_with('u') {
+                        "is_greater_or_equal"
+                    } else {
+                        "is_greater_or_equal_signed"
+                    };
+                    let (greater, lesser) = if op.starts_with("amomin") {
+                        (rs2, tmp1)
+                    } else {
+                        (tmp1, rs2)
+                    };
+                    vec![
+                        // tmp3 is 1 if the loaded value is the one to be kept
+                        format!(
+                            "{comparison} {}, {}, {};",
+                            greater.addr(),
+                            lesser.addr(),
+                            tmp3.addr()
+                        ),
+                        format!("affine {}, {}, 0, 1, 0, 0;", rs2.addr(), tmp2.addr()),
+                        format!("skip_if_equal {}, 0, 0, 0, 1;", tmp3.addr()),
+                        format!("affine {}, {}, 0, 1, 0, 0;", tmp1.addr(), tmp2.addr()),
+                    ]
+                }
+                _ => panic!("Unknown instruction: {instr}"),
+            };
+
+            [
+                vec![format!(
+                    "mload {}, 0, 0, {}, {};",
+                    rs1.addr(),
+                    tmp1.addr(),
+                    tmp2.addr()
+                )],
+                compute_stored_value,
+                vec![format!("mstore {}, 0, 0, 0, {};", rs1.addr(), tmp2.addr())],
+                only_if_no_write_to_zero(
+                    rd,
+                    format!("affine {}, {}, 0, 1, 0, 0;", tmp1.addr(), rd.addr()),
+                ),
+            ]
+            .concat()
+        }
+
         insn if insn.starts_with("lr.w") => {
             // Very similar to "lw":
             let (rd, rs) = args.rr()?;
@@ -41,6 +41,7 @@ pub fn verify_riscv_asm_string<T: FieldElement, S: serde::Serialize + Send + Syn
             pipeline.data_callback().unwrap(),
             &[],
             None,
+            None,
         );
     }
 
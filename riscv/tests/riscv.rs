@@ -88,6 +88,7 @@ fn bn254_sanity_check() {
         // Assume the RISC-V program was compiled without a bootloader, otherwise this will fail.
         &[],
         Default::default(),
+        None,
     );
     run_pilcom_with_backend_variant(pipeline, BackendVariant::Composite).unwrap();
 }
@@ -716,6 +717,7 @@ fn profiler_sanity_check() {
         pipeline.data_callback().unwrap(),
         &[],
         Some(profiler_opt),
+        None,
     );
 
     // check files were created in temp dir, and that they are not empty
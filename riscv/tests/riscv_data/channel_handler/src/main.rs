@@ -0,0 +1,17 @@
+#![no_main]
+#![no_std]
+
+use powdr_riscv_runtime::io::{read, write};
+
+#[no_mangle]
+pub fn main() {
+    let mut sum = 0u32;
+    for i in 1..=3u32 {
+        // The host answers each request with the square of the number.
+        write(5, i);
+        let square: u32 = read(5);
+        assert_eq!(square, i * i);
+        sum += square;
+    }
+    write(1, sum);
+}
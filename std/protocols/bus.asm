@@ -33,10 +33,6 @@ let bus_interaction: expr, expr[], expr -> () = constr |id, tuple, multiplicity|
 
     std::check::assert(required_extension_size() <= 2, || "Invalid extension size");
 
-    // Add phantom bus interaction
-    let full_tuple = [id] + tuple;
-    Constr::PhantomBusInteraction(multiplicity, full_tuple);
-
     // Alpha is used to compress the LHS and RHS arrays.
     let alpha = fp2_from_array(array::new(required_extension_size(), |i| challenge(0, i + 1)));
     // Beta is used to update the accumulator.
@@ -73,6 +69,11 @@ let bus_interaction: expr, expr[], expr -> () = constr |id, tuple, multiplicity|
 
     let acc = array::new(required_extension_size(), |i| std::prover::new_witness_col_at_stage("acc", 1));
     let acc_ext = fp2_from_array(acc);
+
+    // Add phantom bus interaction
+    let full_tuple = [id] + tuple;
+    Constr::PhantomBusInteraction(multiplicity, full_tuple, acc);
+
     let next_acc = next_ext(acc_ext);
 
     let is_first: col = std::well_known::is_first;
@@ -6,10 +6,8 @@ use std::prelude::Query;
 use std::prover::challenge;
 
 // Like block_to_block.asm, but also adds a bus to both machines.
-// This is still flawed currently, because:
-// - The Arith machine does not receive the tuple from the bus yet
-// - Final accumulator values are not exposed as public yet
-// In the future, this can be used to test that challenges can be shared between machines.
+// When proving the machines separately, the challenges are shared between them
+// and the final accumulator values are exposed as publics.
 
 let ARITH_INTERACTION_ID = 1234;
 
@@ -26,8 +24,6 @@ machine Arith with
 
     bus_receive(ARITH_INTERACTION_ID, [0, x, y, z], latch * used);
 
-    // TODO: Expose final value of acc as public.
-
     col fixed operation_id = [0]*;
     col fixed latch = [1]*;
     col witness x;
@@ -55,8 +51,6 @@ machine Main with
 
     bus_send(ARITH_INTERACTION_ID, [0, x, y, z], instr_add);
 
-    // TODO: Expose final value of acc as public.
-
     col fixed operation_id = [0]*;
     col fixed x(i) { i / 4 };
     col fixed y(i) { i / 4 + 1 };
@@ -0,0 +1,15 @@
+use std::prover::challenge;
+
+machine Main with degree: 4 {
+
+    col fixed foo = [1, 2, 3, 4]*;
+
+    col witness bar;
+    bar = foo + 3;
+
+    // Stage-1 witness column of degree 4, which degree reduction splits by
+    // introducing stage-1 witness columns.
+    col witness stage(1) bar4;
+    let alpha: expr = challenge(0, 1);
+    bar4 = (bar + alpha)**4;
+}